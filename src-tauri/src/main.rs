@@ -8,9 +8,13 @@ mod pointcloud;
 use commands::{save_file, load_file, execute_shell};
 use api_server::{ApiServerState, find_free_port, write_discovery_file, remove_discovery_file, start_server};
 use pointcloud::commands::{
-    pointcloud_open, pointcloud_get_progress, pointcloud_get_nodes,
+    pointcloud_open, pointcloud_validate, pointcloud_get_metadata, pointcloud_get_progress,
+    pointcloud_get_vlrs, pointcloud_get_nodes,
     pointcloud_get_nodes_binary, pointcloud_get_visible_nodes,
+    pointcloud_export, pointcloud_export_tiles, pointcloud_get_export_progress,
     pointcloud_close, pointcloud_list,
+    pointcloud_get_cache_info, pointcloud_set_cache_limit, pointcloud_clear_cache,
+    pointcloud_get_memory_budget, pointcloud_set_memory_budget,
 };
 use pointcloud::manager::PointcloudManager;
 use std::sync::Arc;
@@ -59,12 +63,23 @@ fn main() {
             execute_shell,
             api_eval_callback,
             pointcloud_open,
+            pointcloud_validate,
+            pointcloud_get_metadata,
             pointcloud_get_progress,
+            pointcloud_get_vlrs,
             pointcloud_get_nodes,
             pointcloud_get_nodes_binary,
             pointcloud_get_visible_nodes,
+            pointcloud_export,
+            pointcloud_export_tiles,
+            pointcloud_get_export_progress,
             pointcloud_close,
-            pointcloud_list
+            pointcloud_list,
+            pointcloud_get_cache_info,
+            pointcloud_set_cache_limit,
+            pointcloud_clear_cache,
+            pointcloud_get_memory_budget,
+            pointcloud_set_memory_budget
         ])
         .setup(move |app| {
             // Get the main window
@@ -0,0 +1,553 @@
+use std::fs::File;
+use std::path::Path;
+
+use memmap2::Mmap;
+use rayon::prelude::*;
+
+use super::format::PointcloudFormat;
+use super::reader::{base_metadata, PointReader, ValueScale};
+use super::types::{AsciiColumns, BoundingBox3D, PointBuffer, PointRecord, PointcloudMetadata};
+
+/// Highest number of columns a line is split into
+const MAX_COLUMNS: usize = 32;
+
+/// Data lines sampled to detect the column layout and value ranges
+const SAMPLE_LINES: usize = 1000;
+
+/// Number of header lines before each PTX scan's points
+const PTX_HEADER_LINES: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Delimiter {
+    /// Any run of spaces or tabs
+    Whitespace,
+    Char(char),
+}
+
+/// Column indices of the fields we read
+#[derive(Debug, Clone)]
+struct ColumnMap {
+    x: usize,
+    y: usize,
+    z: usize,
+    intensity: Option<usize>,
+    color: Option<[usize; 3]>,
+    classification: Option<usize>,
+}
+
+impl ColumnMap {
+    fn from_options(columns: &AsciiColumns) -> Self {
+        let color = match (columns.red, columns.green, columns.blue) {
+            (Some(r), Some(g), Some(b)) => Some([r, g, b]),
+            _ => None,
+        };
+        Self {
+            x: columns.x,
+            y: columns.y,
+            z: columns.z,
+            intensity: columns.intensity,
+            color,
+            classification: columns.classification,
+        }
+    }
+
+    /// Guess the layout from the column count, as Cyclone/CloudCompare exports use it
+    fn from_count(count: usize) -> Self {
+        let (intensity, color) = match count {
+            0..=3 => (None, None),
+            4 | 5 => (Some(3), None),
+            6 => (None, Some([3, 4, 5])),
+            _ => (Some(3), Some([4, 5, 6])),
+        };
+        Self { x: 0, y: 1, z: 2, intensity, color, classification: None }
+    }
+
+    /// Map a header row such as `//X,Y,Z,R,G,B,Intensity`
+    fn from_header(names: &[String]) -> Option<Self> {
+        let find = |candidates: &[&str]| names.iter().position(|n| candidates.contains(&n.as_str()));
+        let color = match (find(&["r", "red"]), find(&["g", "green"]), find(&["b", "blue"])) {
+            (Some(r), Some(g), Some(b)) => Some([r, g, b]),
+            _ => None,
+        };
+        Some(Self {
+            x: find(&["x"])?,
+            y: find(&["y"])?,
+            z: find(&["z"])?,
+            intensity: find(&["i", "intensity", "scalar_intensity"]),
+            color,
+            classification: find(&["classification", "class", "scalar_classification"]),
+        })
+    }
+
+    /// Number of leading columns that must be split to read every field
+    fn width(&self) -> usize {
+        [Some(self.x), Some(self.y), Some(self.z), self.intensity, self.classification]
+            .into_iter()
+            .chain(self.color.into_iter().flatten().map(Some))
+            .flatten()
+            .max()
+            .unwrap_or(0)
+            + 1
+    }
+}
+
+/// Everything needed to turn a line into a point
+#[derive(Debug, Clone)]
+struct LineFormat {
+    delimiter: Delimiter,
+    columns: ColumnMap,
+    width: usize,
+    color_scale: ValueScale,
+    intensity_scale: ValueScale,
+    /// PTX rows hold 0 0 0 for missing returns
+    skip_origin: bool,
+}
+
+impl LineFormat {
+    /// Split the leading fields of a line into `values`; returns the number of fields read
+    fn split(&self, text: &str, values: &mut [f64; MAX_COLUMNS]) -> usize {
+        fn fill<'a>(fields: impl Iterator<Item = &'a str>, values: &mut [f64], width: usize) -> usize {
+            let mut count = 0;
+            for (value, field) in values.iter_mut().zip(fields).take(width) {
+                *value = field.trim().parse().unwrap_or(f64::NAN);
+                count += 1;
+            }
+            count
+        }
+        match self.delimiter {
+            Delimiter::Whitespace => fill(text.split_ascii_whitespace(), values, self.width),
+            Delimiter::Char(c) => fill(text.split(c), values, self.width),
+        }
+    }
+
+    /// Parse one data line. Comments, short lines and unparsable positions yield None.
+    fn parse(&self, line: &[u8], transform: Option<&Transform>) -> Option<PointRecord> {
+        let text = std::str::from_utf8(line).ok()?.trim();
+        if text.is_empty() || text.starts_with('#') || text.starts_with("//") {
+            return None;
+        }
+
+        let mut values = [f64::NAN; MAX_COLUMNS];
+        self.split(text, &mut values);
+        let c = &self.columns;
+        let (x, y, z) = (values[c.x], values[c.y], values[c.z]);
+        if !(x.is_finite() && y.is_finite() && z.is_finite()) {
+            return None;
+        }
+        if self.skip_origin && x == 0.0 && y == 0.0 && z == 0.0 {
+            return None;
+        }
+
+        // PTX matrices multiply row vectors: the translation is the last row
+        let [x, y, z] = match transform {
+            Some(m) => [
+                x * m[0][0] + y * m[1][0] + z * m[2][0] + m[3][0],
+                x * m[0][1] + y * m[1][1] + z * m[2][1] + m[3][1],
+                x * m[0][2] + y * m[1][2] + z * m[2][2] + m[3][2],
+            ],
+            None => [x, y, z],
+        };
+
+        let mut point = PointRecord { x, y, z, ..Default::default() };
+        if let Some([r, g, b]) = c.color {
+            point.r = self.color_scale.to_u16(values[r]);
+            point.g = self.color_scale.to_u16(values[g]);
+            point.b = self.color_scale.to_u16(values[b]);
+        }
+        if let Some(i) = c.intensity {
+            point.intensity = self.intensity_scale.to_u16(values[i]);
+        }
+        if let Some(i) = c.classification {
+            if values[i].is_finite() {
+                point.classification = values[i] as u8;
+            }
+        }
+        Some(point)
+    }
+}
+
+/// Split off the line starting at `pos`, without its line terminator
+pub fn next_line<'a>(data: &'a [u8], pos: &mut usize) -> Option<&'a [u8]> {
+    if *pos >= data.len() {
+        return None;
+    }
+    let start = *pos;
+    let end = data[start..].iter().position(|&b| b == b'\n').map_or(data.len(), |i| start + i);
+    *pos = end + 1;
+    let line = &data[start..end];
+    Some(line.strip_suffix(b"\r").unwrap_or(line))
+}
+
+fn line_text(line: &[u8]) -> &str {
+    std::str::from_utf8(line).unwrap_or("").trim()
+}
+
+fn is_numeric(field: &str) -> bool {
+    field.trim().parse::<f64>().is_ok()
+}
+
+/// Row-major 4x4 transform of a PTX scan
+type Transform = [[f64; 4]; 4];
+
+/// A PTX scan header: point count and the scan-to-world transform
+fn parse_ptx_header(data: &[u8], pos: &mut usize) -> Result<Option<(u64, Transform)>, String> {
+    let mut lines = Vec::with_capacity(PTX_HEADER_LINES);
+    while lines.len() < PTX_HEADER_LINES {
+        match next_line(data, pos) {
+            Some(line) if line_text(line).is_empty() => continue,
+            Some(line) => lines.push(line_text(line)),
+            None if lines.is_empty() => return Ok(None),
+            None => return Err("PTX file ends inside a scan header".into()),
+        }
+    }
+
+    let columns: u64 = lines[0].parse().map_err(|_| format!("Invalid PTX column count: {}", lines[0]))?;
+    let rows: u64 = lines[1].parse().map_err(|_| format!("Invalid PTX row count: {}", lines[1]))?;
+
+    // Lines 2-5 hold the scanner position and axes, 6-9 the 4x4 transform
+    let mut transform = [[0.0; 4]; 4];
+    for (row, line) in transform.iter_mut().zip(&lines[6..]) {
+        for (value, field) in row.iter_mut().zip(line.split_ascii_whitespace()) {
+            *value = field.parse().map_err(|_| format!("Invalid PTX transform row: {}", line))?;
+        }
+    }
+
+    let count = columns
+        .checked_mul(rows)
+        .ok_or_else(|| format!("Invalid PTX scan size: {} x {}", columns, rows))?;
+    Ok(Some((count, transform)))
+}
+
+/// Reader for ASCII point formats: PTS, PTX and XYZ/CSV/TXT
+pub struct AsciiReader {
+    mmap: Mmap,
+    format: PointcloudFormat,
+    line_format: LineFormat,
+    /// Byte offset of the first point line (of the first scan header for PTX)
+    data_start: usize,
+    estimated_points: u64,
+}
+
+impl AsciiReader {
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        format: PointcloudFormat,
+        columns: Option<&AsciiColumns>,
+    ) -> Result<Self, String> {
+        let file = File::open(path.as_ref()).map_err(|e| format!("Failed to open file: {}", e))?;
+        let mmap = unsafe { Mmap::map(&file) }.map_err(|e| format!("Failed to mmap file: {}", e))?;
+
+        // Find the first point line
+        let mut pos = 0;
+        let mut declared_points = None;
+        let mut header_names = None;
+        let mut points_start = 0;
+        match (format, columns.and_then(|c| c.skip_lines)) {
+            (PointcloudFormat::Ptx, _) => {
+                if parse_ptx_header(&mmap, &mut pos)?.is_none() {
+                    return Err("PTX file has no scans".into());
+                }
+                points_start = pos;
+            }
+            (_, Some(skip)) => {
+                for _ in 0..skip {
+                    next_line(&mmap, &mut pos);
+                }
+                points_start = pos;
+            }
+            _ => loop {
+                let mut after = points_start;
+                let text = match next_line(&mmap, &mut after) {
+                    Some(line) => line_text(line),
+                    None => break,
+                };
+                if text.is_empty() || text.starts_with('#') {
+                    points_start = after;
+                    continue;
+                }
+                // Header row, e.g. CloudCompare's "//X,Y,Z,R,G,B"
+                if header_names.is_none() && (text.starts_with("//") || !is_numeric_row(text)) {
+                    header_names = Some(text);
+                    points_start = after;
+                    continue;
+                }
+                // PTS files start with the point count on a line of its own
+                if format == PointcloudFormat::Pts && declared_points.is_none() {
+                    if let Ok(count) = text.parse::<u64>() {
+                        declared_points = Some(count);
+                        points_start = after;
+                        continue;
+                    }
+                }
+                break;
+            },
+        }
+        let data_start = if format == PointcloudFormat::Ptx { 0 } else { points_start };
+
+        // Sample the first point lines
+        let mut sample = Vec::with_capacity(SAMPLE_LINES);
+        let mut sample_bytes = 0;
+        let mut pos = points_start;
+        while sample.len() < SAMPLE_LINES {
+            let start = pos;
+            let line = match next_line(&mmap, &mut pos) {
+                Some(line) => line_text(line),
+                None => break,
+            };
+            if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+                continue;
+            }
+            sample_bytes += pos - start;
+            sample.push(line);
+        }
+        let first = *sample.first().ok_or("File holds no points")?;
+
+        let delimiter = match columns.and_then(|c| c.delimiter) {
+            Some(c) if c.is_whitespace() => Delimiter::Whitespace,
+            Some(c) => Delimiter::Char(c),
+            None => detect_delimiter(first, format),
+        };
+        let split_names = |text: &str| -> Vec<String> {
+            let fields: Vec<&str> = match delimiter {
+                Delimiter::Whitespace => text.split_ascii_whitespace().collect(),
+                Delimiter::Char(c) => text.split(c).collect(),
+            };
+            fields
+                .iter()
+                .map(|f| f.trim_matches(|c: char| c == '/' || c == '#' || c == '"' || c.is_whitespace()).to_lowercase())
+                .collect()
+        };
+        let column_map = match columns {
+            Some(c) => ColumnMap::from_options(c),
+            None => header_names
+                .and_then(|h| ColumnMap::from_header(&split_names(h)))
+                .unwrap_or_else(|| ColumnMap::from_count(split_names(first).len())),
+        };
+        let width = column_map.width();
+        if width > MAX_COLUMNS {
+            return Err(format!("Column index {} is beyond the supported {} columns", width - 1, MAX_COLUMNS));
+        }
+
+        let mut line_format = LineFormat {
+            delimiter,
+            columns: column_map,
+            width,
+            color_scale: ValueScale::Byte,
+            intensity_scale: ValueScale::Byte,
+            skip_origin: format == PointcloudFormat::Ptx,
+        };
+
+        // Detect the value ranges of color and intensity from the sample
+        let mut color_range = (f64::MAX, f64::MIN, false);
+        let mut intensity_range = (f64::MAX, f64::MIN, false);
+        let mut values = [f64::NAN; MAX_COLUMNS];
+        let track = |range: &mut (f64, f64, bool), v: f64| {
+            if v.is_finite() {
+                range.0 = range.0.min(v);
+                range.1 = range.1.max(v);
+                range.2 |= v.fract() != 0.0;
+            }
+        };
+        for line in &sample {
+            line_format.split(line, &mut values);
+            if let Some(color) = line_format.columns.color {
+                for i in color {
+                    track(&mut color_range, values[i]);
+                }
+            }
+            if let Some(i) = line_format.columns.intensity {
+                track(&mut intensity_range, values[i]);
+            }
+        }
+        if color_range.0 <= color_range.1 {
+            line_format.color_scale = ValueScale::detect(color_range.0.max(0.0), color_range.1, color_range.2);
+        }
+        if intensity_range.0 <= intensity_range.1 {
+            line_format.intensity_scale = ValueScale::detect(intensity_range.0, intensity_range.1, intensity_range.2);
+        }
+
+        let average_line = (sample_bytes / sample.len()).max(1);
+        let estimated_points = declared_points
+            .unwrap_or_else(|| (mmap.len().saturating_sub(points_start) / average_line) as u64);
+
+        Ok(Self { mmap, format, line_format, data_start, estimated_points })
+    }
+
+    /// Parse up to `count` lines from `pos` (to the end of the file when None),
+    /// handing the points to the callback a batch at a time.
+    /// Returns false when the callback asked to stop.
+    fn stream_lines(
+        &self,
+        pos: &mut usize,
+        count: Option<u64>,
+        transform: Option<&Transform>,
+        batch_size: usize,
+        streamed: &mut u64,
+        callback: &mut dyn FnMut(&PointBuffer, u64) -> bool,
+    ) -> bool {
+        let mut remaining = count.unwrap_or(u64::MAX);
+        let mut lines = Vec::with_capacity(batch_size);
+
+        while remaining > 0 {
+            lines.clear();
+            while lines.len() < batch_size && remaining > 0 {
+                match next_line(&self.mmap, pos) {
+                    Some(line) => lines.push(line),
+                    None => break,
+                }
+                remaining -= 1;
+            }
+            if lines.is_empty() {
+                break;
+            }
+
+            let points: PointBuffer = lines
+                .par_iter()
+                .filter_map(|line| self.line_format.parse(line, transform))
+                .collect::<Vec<_>>()
+                .into();
+            if !points.is_empty() {
+                if !callback(&points, *streamed) {
+                    return false;
+                }
+                *streamed += points.len() as u64;
+            }
+            if lines.len() < batch_size && count.is_none() {
+                break;
+            }
+        }
+        true
+    }
+}
+
+/// Whether every field of a row parses as a number
+fn is_numeric_row(text: &str) -> bool {
+    let text = text.trim_start_matches('/');
+    text.split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+        .filter(|f| !f.is_empty())
+        .all(is_numeric)
+}
+
+fn detect_delimiter(line: &str, format: PointcloudFormat) -> Delimiter {
+    if line.contains(';') {
+        Delimiter::Char(';')
+    } else if line.contains(',') || format == PointcloudFormat::Csv {
+        Delimiter::Char(',')
+    } else {
+        Delimiter::Whitespace
+    }
+}
+
+impl PointReader for AsciiReader {
+    fn metadata(&self, id: &str, file_path: &str) -> PointcloudMetadata {
+        let columns = &self.line_format.columns;
+        PointcloudMetadata {
+            has_color: columns.color.is_some(),
+            has_intensity: columns.intensity.is_some(),
+            has_classification: columns.classification.is_some(),
+            ..base_metadata(id, file_path, self.format, self.estimated_points, None)
+        }
+    }
+
+    /// Declared count for PTS files, otherwise estimated from the sampled line length
+    fn total_points(&self) -> u64 {
+        self.estimated_points
+    }
+
+    fn bounds(&self) -> Option<BoundingBox3D> {
+        None
+    }
+
+    fn attribute_mask(&self) -> u32 {
+        0
+    }
+
+    fn stream_points(
+        &self,
+        batch_size: u64,
+        callback: &mut dyn FnMut(&PointBuffer, u64) -> bool,
+    ) -> Result<(), String> {
+        let batch_size = batch_size.max(1) as usize;
+        let mut streamed = 0u64;
+        let mut pos = self.data_start;
+
+        if self.format != PointcloudFormat::Ptx {
+            self.stream_lines(&mut pos, None, None, batch_size, &mut streamed, callback);
+            return Ok(());
+        }
+
+        // PTX: a header before every scan, then columns x rows point lines
+        while let Some((count, transform)) = parse_ptx_header(&self.mmap, &mut pos)? {
+            if !self.stream_lines(&mut pos, Some(count), Some(&transform), batch_size, &mut streamed, callback) {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointcloud::reader::read_temp_file;
+
+    /// Open an ASCII file with these contents and read all its points
+    fn read_ascii(name: &str, format: PointcloudFormat, contents: &str) -> Result<Vec<PointRecord>, String> {
+        read_temp_file(name, contents.as_bytes(), |path| AsciiReader::open(path, format, None))
+    }
+
+    /// A PTX scan header with an identity transform shifted by `dx` in x
+    fn ptx_header(columns: &str, rows: &str, dx: f64) -> String {
+        format!(
+            "{}\n{}\n0 0 0\n1 0 0\n0 1 0\n0 0 1\n1 0 0 0\n0 1 0 0\n0 0 1 0\n{} 0 0 1\n",
+            columns, rows, dx
+        )
+    }
+
+    #[test]
+    fn xyz_header_row_names_the_columns() {
+        let file = "//X,Y,Z,R,G,B\n1.5,2.5,3.5,255,128,0\n4,5,6,0,0,255\n";
+        let points = read_ascii("header.txt", PointcloudFormat::Xyz, file).unwrap();
+        assert_eq!(points.len(), 2);
+        assert_eq!((points[0].x, points[0].y, points[0].z), (1.5, 2.5, 3.5));
+        assert_eq!((points[0].r >> 8, points[0].g >> 8, points[0].b >> 8), (255, 128, 0));
+    }
+
+    #[test]
+    fn pts_count_line_is_skipped() {
+        let file = "2\n1 2 3 -100 10 20 30\n4 5 6 100 40 50 60\n";
+        let points = read_ascii("count.pts", PointcloudFormat::Pts, file).unwrap();
+        assert_eq!(points.len(), 2);
+        assert_eq!((points[1].x, points[1].y, points[1].z), (4.0, 5.0, 6.0));
+        assert_eq!(points[1].g >> 8, 50);
+    }
+
+    #[test]
+    fn ptx_scans_are_transformed() {
+        // Two scans of one point each; the zero point marks a missing return
+        let mut file = ptx_header("2", "1", 10.0);
+        file.push_str("1 2 3 0.5\n0 0 0 0.5\n");
+        file.push_str(&ptx_header("1", "1", 20.0));
+        file.push_str("1 1 1 0.5\n");
+        let points = read_ascii("scans.ptx", PointcloudFormat::Ptx, &file).unwrap();
+        assert_eq!(points.len(), 2);
+        assert_eq!((points[0].x, points[0].y, points[0].z), (11.0, 2.0, 3.0));
+        assert_eq!((points[1].x, points[1].y, points[1].z), (21.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn malformed_files_are_rejected() {
+        let cases = [
+            ("empty.xyz", PointcloudFormat::Xyz, String::new()),
+            ("comments.xyz", PointcloudFormat::Xyz, "# only\n# comments\n".to_string()),
+            ("empty.ptx", PointcloudFormat::Ptx, String::new()),
+            ("short.ptx", PointcloudFormat::Ptx, "2\n1\n0 0 0\n".to_string()),
+            ("columns.ptx", PointcloudFormat::Ptx, ptx_header("two", "1", 0.0)),
+            ("transform.ptx", PointcloudFormat::Ptx, ptx_header("1", "1", 0.0).replace("0 1 0 0", "0 x 0 0")),
+            ("size.ptx", PointcloudFormat::Ptx, ptx_header(&u64::MAX.to_string(), "2", 0.0)),
+        ];
+        for (name, format, file) in cases {
+            assert!(read_ascii(name, format, &file).is_err(), "{} was accepted", name);
+        }
+    }
+}
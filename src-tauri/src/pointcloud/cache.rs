@@ -0,0 +1,585 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use memmap2::Mmap;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::disk_octree::remove_stale_work_dirs;
+use super::hierarchy::HierarchyNode;
+use super::octree::{
+    collect_nodes, node_info, pack_chunk, select_visible, BuiltOctree, NodeSource, NodeTable,
+};
+use super::types::{
+    point_attributes, BoundingBox3D, CacheInfo, CameraState, OctreeNodeInfo, OpenOptions, PointBuffer, PointChunk,
+    PointColumns, PointRecord, PointcloudMetadata, VlrInfo, WavePacket,
+};
+
+const MAGIC: &[u8; 8] = b"OPSOCTRE";
+const VERSION: u32 = 4;
+const EXTENSION: &str = "octree";
+
+/// Extension of the file next to an entry holding when it was last used
+const USED_EXTENSION: &str = "used";
+
+/// Extension of an entry while it is written
+const PARTIAL_EXTENSION: &str = "partial";
+
+/// Cache size limit until one is set
+const DEFAULT_LIMIT: u64 = 20 << 30;
+
+/// Leading bytes of the source file hashed into the key: the header and VLRs
+const HEADER_HASH_BYTES: u64 = 64 * 1024;
+
+/// Size of a cached point without extra attributes: position(24) + gps time(8)
+/// + scan angle(4) + intensity, point source ID, NIR, RGB(12) + six u8 fields
+const RECORD_SIZE: usize = 54;
+
+/// Size of a cached wave packet, as in a LAS record
+const WAVE_PACKET_SIZE: usize = 29;
+
+/// Size of a node entry besides its ID: level(1) + bounds(48) + point count(4)
+/// + payload offset(8)
+const NODE_ENTRY_SIZE: usize = 61;
+
+/// What `write_entry` writes besides the payloads
+struct EntryLayout {
+    attribute_mask: u32,
+    extra_count: usize,
+    additive: bool,
+    /// Nodes, parents first, with the offset of their points in the payloads
+    index: Vec<(OctreeNodeInfo, u64)>,
+    payload_size: u64,
+}
+
+/// What an entry records about the source besides its octree
+#[derive(Serialize, Deserialize)]
+struct CachedInfo {
+    metadata: PointcloudMetadata,
+    vlrs: Vec<VlrInfo>,
+}
+
+/// Built octrees on disk, so reopening a file skips reading and indexing it.
+/// Entries are keyed by the file's path, size, modification time and a hash
+/// of its header, and evicted least recently used first beyond the size limit.
+pub struct OctreeCache {
+    dir: PathBuf,
+    limit: AtomicU64,
+}
+
+impl OctreeCache {
+    /// Use the cache in `dir`, removing the partly written entries a crashed
+    /// run left behind
+    pub fn new(dir: PathBuf) -> Self {
+        let cache = Self {
+            dir,
+            limit: AtomicU64::new(DEFAULT_LIMIT),
+        };
+        if let Ok(files) = fs::read_dir(&cache.dir) {
+            for file in files.flatten() {
+                if file.path().extension().is_some_and(|ext| ext == PARTIAL_EXTENSION) {
+                    let _ = fs::remove_file(file.path());
+                }
+            }
+        }
+        cache
+    }
+
+    /// Octree folder in the platform's cache directory
+    pub fn default_dir() -> Option<PathBuf> {
+        let base = if cfg!(windows) {
+            PathBuf::from(std::env::var("LOCALAPPDATA").ok()?).join("OpenPointcloudStudio")
+        } else if cfg!(target_os = "macos") {
+            PathBuf::from(std::env::var("HOME").ok()?).join("Library/Caches/open-pointcloud-studio")
+        } else {
+            match std::env::var("XDG_CACHE_HOME") {
+                Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+                _ => PathBuf::from(std::env::var("HOME").ok()?).join(".cache"),
+            }
+            .join("open-pointcloud-studio")
+        };
+        Some(base.join("octrees"))
+    }
+
+    /// The cached octree of a file with its metadata and VLRs, if the file
+    /// has not changed since it was cached
+    pub fn load(&self, file_path: &str, options: &OpenOptions) -> Option<(CachedOctree, PointcloudMetadata, Vec<VlrInfo>)> {
+        let key = cache_key(file_path, options).ok()?;
+        let path = self.entry_path(&key);
+        let file = File::open(&path).ok()?;
+        let data = unsafe { Mmap::map(&file) }.ok()?;
+
+        match CachedOctree::parse(data, &key) {
+            Ok(entry) => {
+                mark_used(&path);
+                Some(entry)
+            }
+            Err(e) => {
+                eprintln!("Discarding octree cache {}: {}", path.display(), e);
+                remove_entry(&path);
+                None
+            }
+        }
+    }
+
+    /// Write a built octree, then evict the oldest entries beyond the limit
+    pub fn store(
+        &self,
+        file_path: &str,
+        options: &OpenOptions,
+        octree: &BuiltOctree,
+        metadata: &PointcloudMetadata,
+        vlrs: &[VlrInfo],
+    ) -> Result<(), String> {
+        let extra_count = metadata.extra_attributes.len();
+        match octree {
+            BuiltOctree::Memory(octree) => {
+                let columns = record_columns(octree.attribute_mask, extra_count);
+                // Payloads follow the index in node order
+                let mut payload_size = 0u64;
+                let index: Vec<(OctreeNodeInfo, u64)> = octree
+                    .nodes
+                    .iter()
+                    .map(|node| {
+                        let offset = payload_size;
+                        payload_size += (node.data.len() * record_size(columns)) as u64;
+                        (node.info.clone(), offset)
+                    })
+                    .collect();
+                let entry = EntryLayout {
+                    attribute_mask: octree.attribute_mask,
+                    extra_count,
+                    additive: octree.additive,
+                    index,
+                    payload_size,
+                };
+                self.write_entry(file_path, options, metadata, vlrs, entry, |out| {
+                    let mut buffer = Vec::new();
+                    for node in octree.nodes.iter() {
+                        buffer.clear();
+                        encode_points(&node.data, columns, &mut buffer);
+                        out.write_all(&buffer)?;
+                    }
+                    Ok(())
+                })
+            }
+            BuiltOctree::Disk(octree) => {
+                // The node file is copied as is; its offsets stay valid after the index
+                let index = collect_nodes(octree.root())
+                    .into_iter()
+                    .map(|n| (node_info(n, octree.additive()), n.data))
+                    .collect();
+                let entry = EntryLayout {
+                    attribute_mask: octree.attribute_mask(),
+                    extra_count,
+                    additive: octree.additive(),
+                    index,
+                    payload_size: octree.payload_size(),
+                };
+                self.write_entry(file_path, options, metadata, vlrs, entry, |out| {
+                    io::copy(&mut File::open(octree.payload_path())?, out).map(|_| ())
+                })
+            }
+        }
+    }
+
+    /// Write an entry: header, node index, then the payloads `write_payload`
+    /// writes. Skipped when the entry alone would exceed the limit.
+    fn write_entry(
+        &self,
+        file_path: &str,
+        options: &OpenOptions,
+        metadata: &PointcloudMetadata,
+        vlrs: &[VlrInfo],
+        entry: EntryLayout,
+        write_payload: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+    ) -> Result<(), String> {
+        let key = cache_key(file_path, options).map_err(|e| format!("Failed to read file: {}", e))?;
+        let info = serde_json::to_vec(&CachedInfo { metadata: metadata.clone(), vlrs: vlrs.to_vec() })
+            .map_err(|e| format!("Failed to encode metadata: {}", e))?;
+
+        let index_size: usize = entry.index.iter().map(|(n, _)| 1 + n.node_id.len() + NODE_ENTRY_SIZE).sum();
+        let header_size = 8 + 4 * 7 + key.len() + info.len();
+        if (header_size + index_size) as u64 + entry.payload_size > self.limit.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        fs::create_dir_all(&self.dir).map_err(|e| format!("Failed to create cache folder: {}", e))?;
+        let path = self.entry_path(&key);
+        let partial = path.with_extension(PARTIAL_EXTENSION);
+        let file = File::create(&partial).map_err(|e| format!("Failed to create cache file: {}", e))?;
+        let mut out = BufWriter::new(file);
+
+        let mut head = Vec::with_capacity(header_size + index_size);
+        head.extend_from_slice(MAGIC);
+        head.extend_from_slice(&VERSION.to_le_bytes());
+        head.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        head.extend_from_slice(key.as_bytes());
+        head.extend_from_slice(&(info.len() as u32).to_le_bytes());
+        head.extend_from_slice(&info);
+        head.extend_from_slice(&entry.attribute_mask.to_le_bytes());
+        head.extend_from_slice(&(entry.extra_count as u32).to_le_bytes());
+        head.extend_from_slice(&(entry.additive as u32).to_le_bytes());
+        head.extend_from_slice(&(entry.index.len() as u32).to_le_bytes());
+
+        // Nodes are listed parents first; offsets are relative to the end of the index
+        let payload_start = (header_size + index_size) as u64;
+        for (node, offset) in &entry.index {
+            head.push(node.node_id.len() as u8);
+            head.extend_from_slice(node.node_id.as_bytes());
+            head.push(node.level);
+            let b = &node.bounds;
+            for v in [b.min_x, b.min_y, b.min_z, b.max_x, b.max_y, b.max_z] {
+                head.extend_from_slice(&v.to_le_bytes());
+            }
+            head.extend_from_slice(&node.point_count.to_le_bytes());
+            head.extend_from_slice(&(payload_start + offset).to_le_bytes());
+        }
+
+        let result = out.write_all(&head).and_then(|_| write_payload(&mut out)).and_then(|_| out.flush());
+        if let Err(e) = result {
+            let _ = fs::remove_file(&partial);
+            return Err(format!("Failed to write cache file: {}", e));
+        }
+        drop(out);
+        fs::rename(&partial, &path).map_err(|e| format!("Failed to write cache file: {}", e))?;
+        mark_used(&path);
+
+        self.evict();
+        Ok(())
+    }
+
+    /// Folder for the temporary files of out-of-core builds, next to the cache
+    pub fn work_dir(&self) -> PathBuf {
+        self.dir.with_file_name("indexing")
+    }
+
+    /// Location, size and limit of the cache
+    pub fn info(&self) -> CacheInfo {
+        let entries = self.entries();
+        CacheInfo {
+            directory: self.dir.to_string_lossy().into_owned(),
+            size_bytes: entries.iter().map(|e| e.1).sum(),
+            limit_bytes: self.limit.load(Ordering::Relaxed),
+            entries: entries.len() as u32,
+        }
+    }
+
+    /// Change the size limit, evicting entries beyond it
+    pub fn set_limit(&self, bytes: u64) {
+        self.limit.store(bytes, Ordering::Relaxed);
+        self.evict();
+    }
+
+    /// Remove every entry and the work folders crashed builds left behind.
+    /// Returns the bytes freed.
+    pub fn clear(&self) -> u64 {
+        let entries: u64 = self
+            .entries()
+            .into_iter()
+            .filter(|(path, _, _)| remove_entry(path))
+            .map(|(_, size, _)| size)
+            .sum();
+        entries + remove_stale_work_dirs(&self.work_dir())
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{:016x}.{}", fnv1a(key.as_bytes()), EXTENSION))
+    }
+
+    /// Cache files with their size and when they were last used
+    fn entries(&self) -> Vec<(PathBuf, u64, SystemTime)> {
+        let Ok(dir) = fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        dir.flatten()
+            .filter(|e| e.path().extension().is_some_and(|ext| ext == EXTENSION))
+            .filter_map(|e| {
+                let meta = e.metadata().ok()?;
+                let used = last_used(&e.path()).or_else(|| meta.modified().ok()).unwrap_or(UNIX_EPOCH);
+                Some((e.path(), meta.len(), used))
+            })
+            .collect()
+    }
+
+    fn evict(&self) {
+        let limit = self.limit.load(Ordering::Relaxed);
+        let mut entries = self.entries();
+        let mut size: u64 = entries.iter().map(|e| e.1).sum();
+        entries.sort_by_key(|e| e.2);
+        for (path, entry_size, _) in entries {
+            if size <= limit {
+                break;
+            }
+            if remove_entry(&path) {
+                size -= entry_size;
+            }
+        }
+    }
+}
+
+/// Record that an entry was used now. Kept in a file of its own, as the
+/// entry is memory-mapped and access times are often not kept.
+fn mark_used(entry: &Path) {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0);
+    let _ = fs::write(entry.with_extension(USED_EXTENSION), now.to_le_bytes());
+}
+
+/// When an entry was last used, if recorded
+fn last_used(entry: &Path) -> Option<SystemTime> {
+    let bytes = fs::read(entry.with_extension(USED_EXTENSION)).ok()?;
+    let millis = u64::from_le_bytes(bytes.try_into().ok()?);
+    Some(UNIX_EPOCH + Duration::from_millis(millis))
+}
+
+/// Remove an entry with its use record. Returns whether the entry was removed.
+fn remove_entry(entry: &Path) -> bool {
+    let _ = fs::remove_file(entry.with_extension(USED_EXTENSION));
+    fs::remove_file(entry).is_ok()
+}
+
+/// Identifies the file as it is now and the options it is read with
+fn cache_key(file_path: &str, options: &OpenOptions) -> std::io::Result<String> {
+    let path = fs::canonicalize(file_path)?;
+    let meta = fs::metadata(&path)?;
+    let modified = meta.modified()?.duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+    let mut head = Vec::new();
+    File::open(&path)?.take(HEADER_HASH_BYTES).read_to_end(&mut head)?;
+    Ok(format!(
+        "{}|{}|{}|{:016x}|{:?}",
+        path.display(),
+        meta.len(),
+        modified,
+        fnv1a(&head),
+        options
+    ))
+}
+
+/// 64-bit FNV-1a, stable across builds unlike the std hasher
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// Columns stored in the records of a cloud with these attributes
+pub fn record_columns(attribute_mask: u32, extra_count: usize) -> PointColumns {
+    PointColumns {
+        wave_packets: attribute_mask & point_attributes::WAVE_PACKETS != 0,
+        extra_count,
+    }
+}
+
+/// Size of a cached point with `columns`
+pub fn record_size(columns: PointColumns) -> usize {
+    let wave_size = if columns.wave_packets { WAVE_PACKET_SIZE } else { 0 };
+    RECORD_SIZE + wave_size + columns.extra_count * 8
+}
+
+/// Append points in the cache's record layout. Points without a column
+/// the layout has get its default.
+pub fn encode_points(points: &PointBuffer, columns: PointColumns, out: &mut Vec<u8>) {
+    out.reserve(points.len() * record_size(columns));
+    for (i, p) in points.points.iter().enumerate() {
+        for v in [p.x, p.y, p.z, p.gps_time] {
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        out.extend_from_slice(&p.scan_angle.to_le_bytes());
+        for v in [p.intensity, p.point_source_id, p.nir, p.r, p.g, p.b] {
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        out.extend_from_slice(&[
+            p.classification,
+            p.return_number,
+            p.number_of_returns,
+            p.flags,
+            p.scanner_channel,
+            p.user_data,
+        ]);
+        if columns.wave_packets {
+            let w = points.wave_packets.as_ref().map_or_else(WavePacket::default, |w| w[i]);
+            out.push(w.descriptor_index);
+            out.extend_from_slice(&w.byte_offset.to_le_bytes());
+            out.extend_from_slice(&w.size.to_le_bytes());
+            for v in [w.return_point_location, w.dx, w.dy, w.dz] {
+                out.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        for e in 0..columns.extra_count {
+            let value = points.extra_attributes.get(e).map_or(f64::NAN, |v| v[i]);
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+}
+
+/// Read points written by `encode_points`
+pub fn decode_points(bytes: &[u8], columns: PointColumns) -> PointBuffer {
+    let size = record_size(columns);
+    let records = bytes.chunks_exact(size);
+    let mut points = PointBuffer::with_capacity(columns, records.len());
+    for rec in records {
+        let f64_at = |o: usize| f64::from_le_bytes(rec[o..o + 8].try_into().unwrap());
+        let f32_at = |o: usize| f32::from_le_bytes(rec[o..o + 4].try_into().unwrap());
+        let u16_at = |o: usize| u16::from_le_bytes([rec[o], rec[o + 1]]);
+        let mut extra_start = RECORD_SIZE;
+        if let Some(packets) = &mut points.wave_packets {
+            let w = RECORD_SIZE;
+            packets.push(WavePacket {
+                descriptor_index: rec[w],
+                byte_offset: u64::from_le_bytes(rec[w + 1..w + 9].try_into().unwrap()),
+                size: u32::from_le_bytes(rec[w + 9..w + 13].try_into().unwrap()),
+                return_point_location: f32_at(w + 13),
+                dx: f32_at(w + 17),
+                dy: f32_at(w + 21),
+                dz: f32_at(w + 25),
+            });
+            extra_start += WAVE_PACKET_SIZE;
+        }
+        for (i, values) in points.extra_attributes.iter_mut().enumerate() {
+            values.push(f64_at(extra_start + i * 8));
+        }
+        points.points.push(PointRecord {
+            x: f64_at(0),
+            y: f64_at(8),
+            z: f64_at(16),
+            gps_time: f64_at(24),
+            scan_angle: f32_at(32),
+            intensity: u16_at(36),
+            point_source_id: u16_at(38),
+            nir: u16_at(40),
+            r: u16_at(42),
+            g: u16_at(44),
+            b: u16_at(46),
+            classification: rec[48],
+            return_number: rec[49],
+            number_of_returns: rec[50],
+            flags: rec[51],
+            scanner_channel: rec[52],
+            user_data: rec[53],
+        });
+    }
+    points
+}
+
+/// An octree read back from the cache. The file is memory-mapped and node
+/// points are decoded when requested.
+pub struct CachedOctree {
+    data: Mmap,
+    /// Node data is the byte offset of the node's points
+    root: HierarchyNode<u64>,
+    nodes: NodeTable<u64>,
+    attribute_mask: u32,
+    additive: bool,
+    columns: PointColumns,
+}
+
+impl CachedOctree {
+    /// Check the entry belongs to `key` and read its index
+    fn parse(data: Mmap, key: &str) -> Result<(Self, PointcloudMetadata, Vec<VlrInfo>), String> {
+        let mut pos = 0usize;
+        let take = |pos: &mut usize, len: usize| -> Result<&[u8], String> {
+            let bytes = data.get(*pos..*pos + len).ok_or("Cache file is truncated")?;
+            *pos += len;
+            Ok(bytes)
+        };
+        let u32_at = |pos: &mut usize| take(pos, 4).map(|b| u32::from_le_bytes(b.try_into().unwrap()));
+
+        if take(&mut pos, 8)? != MAGIC || u32_at(&mut pos)? != VERSION {
+            return Err("Not a cache file of this version".into());
+        }
+        let key_len = u32_at(&mut pos)? as usize;
+        if take(&mut pos, key_len)? != key.as_bytes() {
+            return Err("Cache file belongs to another source".into());
+        }
+        let info_len = u32_at(&mut pos)? as usize;
+        let info: CachedInfo = serde_json::from_slice(take(&mut pos, info_len)?)
+            .map_err(|e| format!("Failed to parse cached metadata: {}", e))?;
+        let attribute_mask = u32_at(&mut pos)?;
+        let extra_count = u32_at(&mut pos)? as usize;
+        let additive = u32_at(&mut pos)? != 0;
+        let node_count = u32_at(&mut pos)? as usize;
+
+        // Nodes come parents first; attach them to their parents last to first
+        let mut nodes: Vec<Option<HierarchyNode<u64>>> = Vec::with_capacity(node_count);
+        let mut index_of = HashMap::new();
+        for _ in 0..node_count {
+            let id_len = take(&mut pos, 1)?[0] as usize;
+            let node_id = String::from_utf8_lossy(take(&mut pos, id_len)?).into_owned();
+            let entry = take(&mut pos, NODE_ENTRY_SIZE)?;
+            let f64_at = |o: usize| f64::from_le_bytes(entry[o..o + 8].try_into().unwrap());
+            index_of.insert(node_id.clone(), nodes.len());
+            nodes.push(Some(HierarchyNode {
+                node_id,
+                level: entry[0],
+                bounds: BoundingBox3D {
+                    min_x: f64_at(1),
+                    min_y: f64_at(9),
+                    min_z: f64_at(17),
+                    max_x: f64_at(25),
+                    max_y: f64_at(33),
+                    max_z: f64_at(41),
+                },
+                point_count: u32::from_le_bytes(entry[49..53].try_into().unwrap()),
+                data: u64::from_le_bytes(entry[53..61].try_into().unwrap()),
+                children: Default::default(),
+            }));
+        }
+        for i in (1..nodes.len()).rev() {
+            let node = nodes[i].take().unwrap();
+            // Child IDs are their parent's followed by the octant digit
+            let octant = node.node_id.bytes().last().filter(|b| (b'0'..=b'7').contains(b));
+            let octant = octant.ok_or("Invalid cache node ID")?;
+            let parent_id = &node.node_id[..node.node_id.len() - 1];
+            let parent = index_of.get(parent_id).and_then(|&p| nodes[p].as_mut()).ok_or("Cache node has no parent")?;
+            parent.children[(octant - b'0') as usize] = Some(Box::new(node));
+        }
+        let root = nodes.first_mut().and_then(Option::take).ok_or("Cache file has no nodes")?;
+
+        let columns = record_columns(attribute_mask, extra_count);
+        let record_size = record_size(columns) as u64;
+        let file_size = data.len() as u64;
+        let fits = |n: &HierarchyNode<u64>| {
+            let size = (n.point_count as u64).checked_mul(record_size);
+            size.and_then(|size| size.checked_add(n.data)).is_some_and(|end| end <= file_size)
+        };
+        if !collect_nodes(&root).into_iter().all(fits) {
+            return Err("Cache file is truncated".into());
+        }
+
+        let nodes = NodeTable::new(&root, additive, |n| n.data);
+        let octree = Self { data, root, nodes, attribute_mask, additive, columns };
+        Ok((octree, info.metadata, info.vlrs))
+    }
+
+    fn node_chunk(&self, node_id: &str) -> Option<PointChunk> {
+        let node = self.nodes.find(node_id).filter(|n| n.info.point_count > 0)?;
+        let (info, start) = (&node.info, node.data as usize);
+        let bytes = &self.data[start..start + info.point_count as usize * record_size(self.columns)];
+        let points = decode_points(bytes, self.columns);
+        Some(pack_chunk(node_id, &info.bounds, info.level, node.spacing, &points, self.attribute_mask))
+    }
+}
+
+impl NodeSource for CachedOctree {
+    fn visible_nodes(&self, camera: &CameraState, point_budget: u32) -> Vec<OctreeNodeInfo> {
+        select_visible(&self.root, camera, point_budget, self.additive)
+            .into_iter()
+            .map(|n| node_info(n, self.additive))
+            .collect()
+    }
+
+    /// Decode the requested nodes in parallel
+    fn node_chunks(&self, node_ids: &[String]) -> Result<Vec<PointChunk>, String> {
+        Ok(node_ids.par_iter().filter_map(|id| self.node_chunk(id)).collect())
+    }
+
+    fn nodes(&self) -> Vec<OctreeNodeInfo> {
+        self.nodes.infos()
+    }
+
+    fn is_additive(&self) -> bool {
+        self.additive
+    }
+}
@@ -3,16 +3,36 @@ use tauri::State;
 use tauri::ipc::Response;
 
 use super::manager::PointcloudManager;
-use super::types::{CameraState, IndexProgress, OctreeNodeInfo, PointChunk, PointcloudMetadata};
+use super::types::{
+    CacheInfo, CameraState, ExportOptions, HeaderIssue, IndexProgress, OctreeNodeInfo, OpenOptions, PointChunk,
+    PointcloudMetadata, TilesExportOptions, VlrInfo,
+};
 
 /// Open a pointcloud file, parse header and start async octree indexing.
 /// Returns metadata immediately; octree builds in background.
 #[tauri::command]
 pub fn pointcloud_open(
     file_path: String,
+    options: Option<OpenOptions>,
     state: State<'_, Arc<PointcloudManager>>,
 ) -> Result<PointcloudMetadata, String> {
-    state.inner().open(&file_path)
+    state.inner().open(&file_path, &options.unwrap_or_default())
+}
+
+/// Check a file header for inconsistencies without opening it
+#[tauri::command]
+pub fn pointcloud_validate(file_path: String) -> Result<Vec<HeaderIssue>, String> {
+    PointcloudManager::validate(&file_path)
+}
+
+/// Get the metadata of an open pointcloud, including validation findings
+/// added while indexing
+#[tauri::command]
+pub fn pointcloud_get_metadata(
+    id: String,
+    state: State<'_, Arc<PointcloudManager>>,
+) -> Result<PointcloudMetadata, String> {
+    state.get_metadata(&id).ok_or_else(|| "Pointcloud not found".into())
 }
 
 /// Get octree construction progress (0.0 - 1.0)
@@ -24,6 +44,15 @@ pub fn pointcloud_get_progress(
     state.get_progress(&id).ok_or_else(|| "Pointcloud not found".into())
 }
 
+/// List the VLR and EVLR records in a pointcloud's file header
+#[tauri::command]
+pub fn pointcloud_get_vlrs(
+    id: String,
+    state: State<'_, Arc<PointcloudManager>>,
+) -> Result<Vec<VlrInfo>, String> {
+    state.get_vlrs(&id).ok_or_else(|| "Pointcloud not found".into())
+}
+
 /// Load point data for specific octree nodes
 #[tauri::command]
 pub fn pointcloud_get_nodes(
@@ -47,11 +76,32 @@ pub fn pointcloud_get_nodes(
 ///     [4 bytes]                level (u32 LE)
 ///     [4 bytes]                spacing (f32 LE)
 ///     [4 bytes]                point_count (u32 LE)
+///     [4 bytes]                attribute_mask (u32 LE, see `point_attributes`)
 ///     [point_count * 12 bytes] positions: f32 LE (x,y,z)
 ///     [point_count * 3 bytes]  colors: u8 (r,g,b)
 ///     [point_count * 2 bytes]  intensities: u16 LE
 ///     [point_count * 1 byte]   classifications: u8
 ///     [0-3 bytes]              padding to 4-byte alignment
+///     Optional arrays, in this order, each only if its mask bit is set and
+///     each followed by padding to 4-byte alignment:
+///     [point_count * 1 byte]   return_numbers: u8            (RETURNS)
+///     [point_count * 1 byte]   number_of_returns: u8         (RETURNS)
+///     [point_count * 1 byte]   flags: u8, see `point_flags`  (FLAGS)
+///     [point_count * 1 byte]   scanner_channels: u8          (SCANNER_CHANNEL)
+///     [point_count * 4 bytes]  scan_angles: f32 LE, degrees  (SCAN_ANGLE)
+///     [point_count * 1 byte]   user_data: u8                 (USER_DATA)
+///     [point_count * 2 bytes]  point_source_ids: u16 LE      (POINT_SOURCE_ID)
+///     [point_count * 8 bytes]  gps_times: f64 LE             (GPS_TIME)
+///     [point_count * 2 bytes]  nir: u16 LE                   (NIR)
+///     Wave packets, each array followed by padding           (WAVE_PACKETS):
+///     [point_count * 1 byte]   wave_descriptor_indices: u8
+///     [point_count * 8 bytes]  wave_byte_offsets: u64 LE
+///     [point_count * 4 bytes]  wave_sizes: u32 LE
+///     [point_count * 4 bytes]  wave_return_locations: f32 LE, picoseconds
+///     [point_count * 12 bytes] wave_directions: f32 LE (x,y,z)
+///     [4 bytes]                extra_count (u32 LE)
+///     [extra_count * point_count * 4 bytes] extra attributes: f32 LE, one array
+///                              per `PointcloudMetadata::extra_attributes` entry
 #[tauri::command]
 pub fn pointcloud_get_nodes_binary(
     id: String,
@@ -64,22 +114,47 @@ pub fn pointcloud_get_nodes_binary(
 }
 
 fn pack_chunks_binary(chunks: &[PointChunk]) -> Vec<u8> {
+    let align4 = |n: usize| (n + 3) & !3;
+
     // Pre-calculate total size for a single allocation
     let mut total_size = 4usize; // chunk_count
     for chunk in chunks {
-        let id_bytes = chunk.node_id.as_bytes().len();
-        let id_padded = (id_bytes + 3) & !3; // round up to 4-byte alignment
-        let data_size = (chunk.point_count as usize) * 12  // positions f32*3
-                      + (chunk.point_count as usize) * 3   // colors u8*3
-                      + (chunk.point_count as usize) * 2   // intensities u16
-                      + (chunk.point_count as usize) * 1;  // classifications u8
-        let chunk_size = 4 + id_padded + 24 + 4 + 4 + 4 + data_size; // +4 level, +4 spacing
-        let chunk_padded = (chunk_size + 3) & !3;
-        total_size += chunk_padded;
+        let n = chunk.point_count as usize;
+        let id_padded = align4(chunk.node_id.len()); // round up to 4-byte alignment
+        let data_size = n * 12  // positions f32*3
+                      + n * 3   // colors u8*3
+                      + n * 2   // intensities u16
+                      + n;      // classifications u8
+        let optional_size = align4(chunk.return_numbers.len())
+                          + align4(chunk.number_of_returns.len())
+                          + align4(chunk.flags.len())
+                          + align4(chunk.scanner_channels.len())
+                          + chunk.scan_angles.len() * 4
+                          + align4(chunk.user_data.len())
+                          + align4(chunk.point_source_ids.len() * 2)
+                          + chunk.gps_times.len() * 8
+                          + align4(chunk.nir.len() * 2)
+                          + align4(chunk.wave_descriptor_indices.len())
+                          + chunk.wave_byte_offsets.len() * 8
+                          + chunk.wave_sizes.len() * 4
+                          + chunk.wave_return_locations.len() * 4
+                          + chunk.wave_directions.len() * 4
+                          + 4 + chunk.extra_attributes.iter().map(|a| a.len() * 4).sum::<usize>();
+        // +4 level, +4 spacing, +4 point_count, +4 attribute_mask
+        let chunk_size = 4 + id_padded + 24 + 4 + 4 + 4 + 4 + align4(data_size) + optional_size;
+        total_size += chunk_size;
     }
 
     let mut buf = Vec::with_capacity(total_size);
 
+    // Pad to 4-byte alignment
+    let pad = |buf: &mut Vec<u8>| {
+        let remainder = buf.len() % 4;
+        if remainder != 0 {
+            buf.resize(buf.len() + (4 - remainder), 0);
+        }
+    };
+
     buf.extend_from_slice(&(chunks.len() as u32).to_le_bytes());
 
     for chunk in chunks {
@@ -88,10 +163,7 @@ fn pack_chunks_binary(chunks: &[PointChunk]) -> Vec<u8> {
         buf.extend_from_slice(&id_len.to_le_bytes());
         buf.extend_from_slice(id_bytes);
         // Pad node_id to 4-byte alignment
-        let padding = (4 - (id_bytes.len() % 4)) % 4;
-        for _ in 0..padding {
-            buf.push(0);
-        }
+        pad(&mut buf);
 
         // Center: 3x f64 LE
         for &val in &chunk.center {
@@ -107,6 +179,9 @@ fn pack_chunks_binary(chunks: &[PointChunk]) -> Vec<u8> {
         // Point count
         buf.extend_from_slice(&chunk.point_count.to_le_bytes());
 
+        // Attribute mask
+        buf.extend_from_slice(&chunk.attribute_mask.to_le_bytes());
+
         // Positions: f32 LE
         for &val in &chunk.positions {
             buf.extend_from_slice(&val.to_le_bytes());
@@ -122,12 +197,50 @@ fn pack_chunks_binary(chunks: &[PointChunk]) -> Vec<u8> {
 
         // Classifications: raw u8
         buf.extend_from_slice(&chunk.classifications);
+        pad(&mut buf);
 
-        // Pad to 4-byte alignment for next chunk
-        let remainder = buf.len() % 4;
-        if remainder != 0 {
-            for _ in 0..(4 - remainder) {
-                buf.push(0);
+        // Optional attributes: empty arrays contribute nothing
+        buf.extend_from_slice(&chunk.return_numbers);
+        pad(&mut buf);
+        buf.extend_from_slice(&chunk.number_of_returns);
+        pad(&mut buf);
+        buf.extend_from_slice(&chunk.flags);
+        pad(&mut buf);
+        buf.extend_from_slice(&chunk.scanner_channels);
+        pad(&mut buf);
+        for &val in &chunk.scan_angles {
+            buf.extend_from_slice(&val.to_le_bytes());
+        }
+        buf.extend_from_slice(&chunk.user_data);
+        pad(&mut buf);
+        for &val in &chunk.point_source_ids {
+            buf.extend_from_slice(&val.to_le_bytes());
+        }
+        pad(&mut buf);
+        for &val in &chunk.gps_times {
+            buf.extend_from_slice(&val.to_le_bytes());
+        }
+        for &val in &chunk.nir {
+            buf.extend_from_slice(&val.to_le_bytes());
+        }
+        pad(&mut buf);
+        buf.extend_from_slice(&chunk.wave_descriptor_indices);
+        pad(&mut buf);
+        for &val in &chunk.wave_byte_offsets {
+            buf.extend_from_slice(&val.to_le_bytes());
+        }
+        for &val in &chunk.wave_sizes {
+            buf.extend_from_slice(&val.to_le_bytes());
+        }
+        for &val in chunk.wave_return_locations.iter().chain(&chunk.wave_directions) {
+            buf.extend_from_slice(&val.to_le_bytes());
+        }
+
+        // Extra Bytes attributes: f32 LE arrays
+        buf.extend_from_slice(&(chunk.extra_attributes.len() as u32).to_le_bytes());
+        for values in &chunk.extra_attributes {
+            for &val in values {
+                buf.extend_from_slice(&val.to_le_bytes());
             }
         }
     }
@@ -146,6 +259,39 @@ pub fn pointcloud_get_visible_nodes(
     state.get_visible_nodes(&id, &camera, budget)
 }
 
+/// Start writing a pointcloud, or a filtered subset, to a LAS, LAZ or COPC file.
+/// Runs in the background; poll `pointcloud_get_export_progress`.
+#[tauri::command]
+pub fn pointcloud_export(
+    id: String,
+    output_path: String,
+    options: ExportOptions,
+    state: State<'_, Arc<PointcloudManager>>,
+) -> Result<(), String> {
+    state.inner().export(&id, &output_path, options)
+}
+
+/// Start writing a pointcloud's octree as a 3D Tiles tileset into a folder.
+/// Runs in the background; poll `pointcloud_get_export_progress`.
+#[tauri::command]
+pub fn pointcloud_export_tiles(
+    id: String,
+    output_dir: String,
+    options: Option<TilesExportOptions>,
+    state: State<'_, Arc<PointcloudManager>>,
+) -> Result<(), String> {
+    state.inner().export_tiles(&id, &output_dir, options.unwrap_or_default())
+}
+
+/// Get the progress of a pointcloud's export (0.0 - 1.0)
+#[tauri::command]
+pub fn pointcloud_get_export_progress(
+    id: String,
+    state: State<'_, Arc<PointcloudManager>>,
+) -> Result<IndexProgress, String> {
+    state.get_export_progress(&id).ok_or_else(|| "No export for this pointcloud".into())
+}
+
 /// Close a pointcloud and free memory
 #[tauri::command]
 pub fn pointcloud_close(
@@ -162,3 +308,45 @@ pub fn pointcloud_list(
 ) -> Vec<PointcloudMetadata> {
     state.list()
 }
+
+/// Get the location, size and limit of the on-disk octree cache
+#[tauri::command]
+pub fn pointcloud_get_cache_info(
+    state: State<'_, Arc<PointcloudManager>>,
+) -> Result<CacheInfo, String> {
+    state.cache_info().ok_or_else(|| "No octree cache folder".into())
+}
+
+/// Set the octree cache size limit in bytes; older entries beyond it are removed
+#[tauri::command]
+pub fn pointcloud_set_cache_limit(
+    limit_bytes: u64,
+    state: State<'_, Arc<PointcloudManager>>,
+) -> Result<(), String> {
+    state.set_cache_limit(limit_bytes)
+}
+
+/// Remove every cached octree. Returns the number of bytes freed.
+#[tauri::command]
+pub fn pointcloud_clear_cache(
+    state: State<'_, Arc<PointcloudManager>>,
+) -> u64 {
+    state.clear_cache()
+}
+
+/// Get the bytes of points an octree build may hold in memory
+#[tauri::command]
+pub fn pointcloud_get_memory_budget(
+    state: State<'_, Arc<PointcloudManager>>,
+) -> u64 {
+    state.memory_budget()
+}
+
+/// Set the memory budget of octree builds; larger clouds are built on disk
+#[tauri::command]
+pub fn pointcloud_set_memory_budget(
+    budget_bytes: u64,
+    state: State<'_, Arc<PointcloudManager>>,
+) {
+    state.set_memory_budget(budget_bytes)
+}
@@ -0,0 +1,268 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use rayon::prelude::*;
+
+use super::format::PointcloudFormat;
+use super::hierarchy::{build_hierarchy, HierarchyNode, VoxelKey};
+use super::octree::{
+    estimate_spacing, node_info, pack_chunk, select_visible, NodeSource, NodeTable,
+};
+use super::parser::PointcloudParser;
+use super::reader::PointReader;
+use super::types::{
+    BoundingBox3D, CameraState, HeaderIssue, OctreeNodeInfo, OpenOptions, PointBuffer, PointChunk,
+    PointcloudMetadata, VlrInfo,
+};
+
+/// Size of a hierarchy page entry: key(16) + offset(8) + byte size(4) + point count(4)
+pub const HIERARCHY_ENTRY_SIZE: usize = 32;
+
+/// Reader for Cloud Optimized Point Clouds. Nodes are served straight from the
+/// file's hierarchy and decompressed when requested, so nothing is indexed on open.
+pub struct CopcReader {
+    parser: PointcloudParser,
+    /// Node data is the offset and byte size of the node's LAZ chunk
+    root: HierarchyNode<(u64, u64)>,
+    nodes: NodeTable<(u64, u64)>,
+}
+
+impl CopcReader {
+    pub fn open<P: AsRef<Path>>(path: P, options: &OpenOptions) -> Result<Self, String> {
+        let parser = PointcloudParser::open(path, options)?;
+        let info = parser.vlr_data("copc", 1).ok_or("COPC info VLR not found")?;
+        if info.len() < 56 {
+            return Err("COPC info VLR is truncated".into());
+        }
+
+        let f64_at = |o: usize| f64::from_le_bytes(info[o..o + 8].try_into().unwrap());
+        let u64_at = |o: usize| u64::from_le_bytes(info[o..o + 8].try_into().unwrap());
+        let center = [f64_at(0), f64_at(8), f64_at(16)];
+        let halfsize = f64_at(24);
+        let root_offset = u64_at(40);
+        let root_size = u64_at(48);
+
+        let mut entries = HashMap::new();
+        read_hierarchy(parser.file_data(), root_offset, root_size, &mut entries)?;
+
+        let cube = BoundingBox3D {
+            min_x: center[0] - halfsize,
+            min_y: center[1] - halfsize,
+            min_z: center[2] - halfsize,
+            max_x: center[0] + halfsize,
+            max_y: center[1] + halfsize,
+            max_z: center[2] + halfsize,
+        };
+        let root = build_hierarchy((0, 0, 0, 0), "r".into(), cube, &entries)
+            .ok_or("COPC hierarchy has no root node")?;
+
+        let nodes = NodeTable::new(&*root, true, |n| n.data);
+        Ok(Self { parser, root: *root, nodes })
+    }
+
+    fn node_chunk(&self, node_id: &str) -> Result<Option<PointChunk>, String> {
+        let (node, (offset, byte_size)) = match self.nodes.find(node_id) {
+            Some(n) if n.info.point_count > 0 => (&n.info, n.data),
+            _ => return Ok(None),
+        };
+        let points = self.parser.decompress_chunk(offset, byte_size, node.point_count as u64)?;
+        let spacing = estimate_spacing(&node.bounds, points.len());
+        Ok(Some(pack_chunk(node_id, &node.bounds, node.level, spacing, &points, self.parser.attribute_mask())))
+    }
+}
+
+/// Collect the point count, chunk offset and chunk size of every node in the hierarchy
+/// page at `offset` and in the pages it references. Pages are read from a work queue,
+/// each at most once, so pages that reference each other cannot loop.
+fn read_hierarchy(
+    data: &[u8],
+    offset: u64,
+    size: u64,
+    entries: &mut HashMap<VoxelKey, (u32, (u64, u64))>,
+) -> Result<(), String> {
+    let mut pages = vec![(offset, size)];
+    let mut visited = HashSet::new();
+
+    while let Some((offset, size)) = pages.pop() {
+        if !visited.insert(offset) {
+            continue;
+        }
+        let page = offset
+            .checked_add(size)
+            .filter(|&end| end <= data.len() as u64)
+            .and_then(|end| data.get(offset as usize..end as usize))
+            .ok_or("COPC hierarchy page lies outside the file")?;
+
+        for e in page.chunks_exact(HIERARCHY_ENTRY_SIZE) {
+            let i32_at = |o: usize| i32::from_le_bytes(e[o..o + 4].try_into().unwrap());
+            let key = (i32_at(0), i32_at(4), i32_at(8), i32_at(12));
+            let chunk_offset = u64::from_le_bytes(e[16..24].try_into().unwrap());
+            let byte_size = i32_at(24);
+            let point_count = i32_at(28);
+
+            // A point count of -1 marks a child page rather than point data
+            if point_count < 0 {
+                pages.push((chunk_offset, byte_size.max(0) as u64));
+            } else {
+                entries.insert(key, (point_count as u32, (chunk_offset, byte_size.max(0) as u64)));
+            }
+        }
+    }
+    Ok(())
+}
+
+impl NodeSource for CopcReader {
+    fn visible_nodes(&self, camera: &CameraState, point_budget: u32) -> Vec<OctreeNodeInfo> {
+        select_visible(&self.root, camera, point_budget, true).into_iter().map(|n| node_info(n, true)).collect()
+    }
+
+    /// Decompress the requested chunks in parallel
+    fn node_chunks(&self, node_ids: &[String]) -> Result<Vec<PointChunk>, String> {
+        let chunks = node_ids
+            .par_iter()
+            .map(|id| self.node_chunk(id))
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(chunks.into_iter().flatten().collect())
+    }
+
+    fn nodes(&self) -> Vec<OctreeNodeInfo> {
+        self.nodes.infos()
+    }
+
+    fn is_additive(&self) -> bool {
+        true
+    }
+}
+
+impl PointReader for CopcReader {
+    fn metadata(&self, id: &str, file_path: &str) -> PointcloudMetadata {
+        PointcloudMetadata {
+            format: PointcloudFormat::Copc.name().into(),
+            ..self.parser.metadata(id, file_path)
+        }
+    }
+
+    fn vlr_infos(&self) -> Vec<VlrInfo> {
+        self.parser.vlr_infos()
+    }
+
+    fn projection_vlrs(&self) -> Vec<(u16, Vec<u8>)> {
+        self.parser.projection_vlrs()
+    }
+
+    fn standard_gps_time(&self) -> bool {
+        self.parser.standard_gps_time()
+    }
+
+    fn total_points(&self) -> u64 {
+        self.parser.total_points()
+    }
+
+    fn bounds(&self) -> Option<BoundingBox3D> {
+        self.parser.bounds()
+    }
+
+    fn scale(&self) -> [f64; 3] {
+        self.parser.scale()
+    }
+
+    fn attribute_mask(&self) -> u32 {
+        self.parser.attribute_mask()
+    }
+
+    fn stream_points(
+        &self,
+        batch_size: u64,
+        callback: &mut dyn FnMut(&PointBuffer, u64) -> bool,
+    ) -> Result<(), String> {
+        self.parser.stream_points(batch_size, callback)
+    }
+
+    fn stream_issues(&self) -> Vec<HeaderIssue> {
+        self.parser.stream_issues()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointcloud::copc_writer::{write_copc, CopcPoints};
+    use crate::pointcloud::disk_octree::BuildLimits;
+    use crate::pointcloud::types::{PointColumns, PointRecord};
+    use crate::pointcloud::writer::LasLayout;
+
+    /// A hierarchy entry: key, then offset, byte size and point count
+    fn entry(key: VoxelKey, offset: u64, byte_size: i32, point_count: i32) -> Vec<u8> {
+        let mut e = Vec::with_capacity(HIERARCHY_ENTRY_SIZE);
+        for v in [key.0, key.1, key.2, key.3] {
+            e.extend_from_slice(&v.to_le_bytes());
+        }
+        e.extend_from_slice(&offset.to_le_bytes());
+        e.extend_from_slice(&byte_size.to_le_bytes());
+        e.extend_from_slice(&point_count.to_le_bytes());
+        e
+    }
+
+    #[test]
+    fn child_pages_are_followed() {
+        // Root page at 0 with a node and a child page at 64
+        let mut data = entry((0, 0, 0, 0), 1000, 10, 5);
+        data.extend(entry((1, 1, 0, 0), 64, 32, -1));
+        data.extend(entry((1, 1, 0, 0), 2000, 20, 7));
+
+        let mut entries = HashMap::new();
+        read_hierarchy(&data, 0, 64, &mut entries).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[&(0, 0, 0, 0)], (5, (1000, 10)));
+        assert_eq!(entries[&(1, 1, 0, 0)], (7, (2000, 20)));
+    }
+
+    #[test]
+    fn pages_referencing_each_other_are_read_once() {
+        let mut data = entry((0, 0, 0, 0), 32, 32, -1);
+        data.extend(entry((1, 0, 0, 0), 0, 32, -1));
+
+        let mut entries = HashMap::new();
+        read_hierarchy(&data, 0, 32, &mut entries).unwrap();
+        assert!(entries.is_empty());
+    }
+
+    #[test]
+    fn chunks_claiming_more_points_than_the_file_fail() {
+        let path = std::env::temp_dir().join(format!("ops-copc-{}-count.copc.laz", std::process::id()));
+        let layout = LasLayout {
+            version_minor: 4,
+            point_format: 6,
+            compress: true,
+            scale: [0.001; 3],
+            offset: [0.0; 3],
+            extra_attributes: Vec::new(),
+            projection_vlrs: Vec::new(),
+            leading_vlrs: Vec::new(),
+            variable_chunks: true,
+            waveform: None,
+            standard_gps_time: true,
+        };
+        let limits = BuildLimits { memory_budget: 1 << 30, work_dir: std::env::temp_dir() };
+        let mut points = CopcPoints::new(PointColumns::default(), limits);
+        let records = (0..100).map(|i| PointRecord { x: i as f64, ..PointRecord::default() }).collect::<Vec<_>>();
+        points.push(&PointBuffer::from(records)).unwrap();
+        write_copc(&path, layout, points).unwrap();
+
+        let reader = CopcReader::open(&path, &OpenOptions::default());
+        let _ = std::fs::remove_file(&path);
+        let reader = reader.unwrap();
+        let (offset, byte_size) = reader.nodes.find("r").unwrap().data;
+        assert_eq!(reader.parser.decompress_chunk(offset, byte_size, 100).unwrap().len(), 100);
+        assert!(reader.parser.decompress_chunk(offset, byte_size, i32::MAX as u64).is_err());
+        assert!(reader.parser.decompress_chunk(offset, byte_size, u64::MAX).is_err());
+    }
+
+    #[test]
+    fn pages_outside_the_file_fail() {
+        let data = entry((0, 0, 0, 0), u64::MAX - 8, 32, -1);
+        let mut entries = HashMap::new();
+        assert!(read_hierarchy(&data, 0, 32, &mut entries).is_err());
+        assert!(read_hierarchy(&data, 16, 32, &mut entries).is_err());
+    }
+}
@@ -0,0 +1,258 @@
+use std::path::Path;
+
+use super::copc::HIERARCHY_ENTRY_SIZE;
+use super::disk_octree::{point_memory, BuildLimits, DiskOctree, PointSpill};
+use super::hierarchy::VoxelKey;
+use super::octree::{BuiltOctree, NodeKey, OctreeBuilder, LOD_GRID};
+use super::types::{BoundingBox3D, PointBuffer, PointColumns};
+use super::writer::{LasLayout, LasWriter, Vlr};
+
+/// Size of the copc info VLR payload
+const INFO_SIZE: usize = 160;
+
+/// Size of an EVLR header, which precedes the hierarchy page
+const EVLR_HEADER_SIZE: u64 = 60;
+
+/// Points to write as COPC, gathered as they are read. They are held in
+/// memory while they fit the budget and moved to a spill file beyond it.
+pub struct CopcPoints {
+    builder: OctreeBuilder,
+    spill: Option<PointSpill>,
+    columns: PointColumns,
+    limits: BuildLimits,
+    /// Points held in memory before they are spilled
+    memory_limit: u64,
+    count: u64,
+    bounds: BoundingBox3D,
+    gps_range: (f64, f64),
+}
+
+impl CopcPoints {
+    pub fn new(columns: PointColumns, limits: BuildLimits) -> Self {
+        Self {
+            builder: OctreeBuilder::default(),
+            spill: None,
+            columns,
+            memory_limit: limits.memory_budget / (2 * point_memory(columns)),
+            limits,
+            count: 0,
+            bounds: BoundingBox3D::new(),
+            gps_range: (f64::MAX, f64::MIN),
+        }
+    }
+
+    pub fn push(&mut self, batch: &PointBuffer) -> Result<(), String> {
+        for p in &batch.points {
+            self.bounds.expand(p.x, p.y, p.z);
+            self.gps_range = (self.gps_range.0.min(p.gps_time), self.gps_range.1.max(p.gps_time));
+        }
+        self.count += batch.len() as u64;
+        if self.spill.is_none() && self.count > self.memory_limit {
+            let mut spill = PointSpill::create(&self.limits.work_dir, self.columns)?;
+            let held = std::mem::take(&mut self.builder);
+            for batch in held.batches() {
+                spill.write(batch)?;
+            }
+            self.spill = Some(spill);
+        }
+        match self.spill.as_mut() {
+            Some(spill) => spill.write(batch),
+            None => {
+                self.builder.push(batch);
+                Ok(())
+            }
+        }
+    }
+
+    /// Index the points as an additive octree over the cube around them
+    fn build(self) -> Result<(BuiltOctree, BoundingBox3D, (f64, f64)), String> {
+        let CopcPoints { builder, spill, limits, count, mut bounds, mut gps_range, .. } = self;
+        if count == 0 {
+            bounds.expand(0.0, 0.0, 0.0);
+            gps_range = (0.0, 0.0);
+        }
+        let center = bounds.center();
+        let halfsize = match bounds.max_extent() * 0.5 {
+            h if h > 0.0 => h,
+            _ => 1.0,
+        };
+        let cube = BoundingBox3D {
+            min_x: center[0] - halfsize,
+            min_y: center[1] - halfsize,
+            min_z: center[2] - halfsize,
+            max_x: center[0] + halfsize,
+            max_y: center[1] + halfsize,
+            max_z: center[2] + halfsize,
+        };
+        let octree = match spill {
+            Some(spill) => {
+                let budget = limits.memory_budget;
+                BuiltOctree::Disk(DiskOctree::build(spill, cube.clone(), 0, true, budget, &mut |_, _| {})?)
+            }
+            None => BuiltOctree::Memory(builder.build(cube.clone(), 0, true, &|_| {})),
+        };
+        Ok((octree, cube, gps_range))
+    }
+}
+
+/// Write the points as a Cloud Optimized Point Cloud: LAZ 1.4 whose point data
+/// is one chunk per octree node, located by the copc info VLR and a hierarchy
+/// EVLR. The points are indexed as an additive octree over the cube around
+/// them, so every point is stored once, and each node's chunk is written as the
+/// tree is walked. Returns the number of points written.
+pub fn write_copc<P: AsRef<Path>>(path: P, mut layout: LasLayout, points: CopcPoints) -> Result<u64, String> {
+    if layout.version_minor < 4 || !matches!(layout.point_format, 6..=8) {
+        return Err("COPC requires LAS 1.4 with point format 6, 7 or 8".into());
+    }
+    layout.compress = true;
+    layout.variable_chunks = true;
+    // Filled in once the hierarchy is written; it must be the first VLR
+    layout.leading_vlrs.insert(0, Vlr::new("copc", 1, "COPC info", vec![0; INFO_SIZE]));
+
+    let (octree, cube, gps_range) = points.build()?;
+    let center = cube.center();
+    let halfsize = cube.max_extent() * 0.5;
+
+    // Nodes come parents first; each node's points are freed once written
+    let mut writer = LasWriter::create(path, layout)?;
+    let mut keys = Vec::new();
+    let mut write_node = |key: NodeKey, points: &PointBuffer| -> Result<(), String> {
+        if !points.is_empty() {
+            writer.write_chunk(points)?;
+            keys.push(voxel_key(key));
+        }
+        Ok(())
+    };
+    match octree {
+        BuiltOctree::Memory(octree) => {
+            for node in octree.nodes {
+                write_node(node.key, &node.data)?;
+            }
+        }
+        BuiltOctree::Disk(octree) => octree.for_each_node(&mut |key, points| write_node(key, &points))?,
+    }
+
+    // One hierarchy page holds every node, in the order the chunks were written
+    let points_end = writer.end_points()?;
+    let mut page = Vec::with_capacity(keys.len() * HIERARCHY_ENTRY_SIZE);
+    for (key, chunk) in keys.iter().zip(writer.chunks()) {
+        for v in [key.0, key.1, key.2, key.3] {
+            page.extend_from_slice(&v.to_le_bytes());
+        }
+        let too_large = |_| "COPC node is too large".to_string();
+        let byte_size = i32::try_from(chunk.byte_size).map_err(too_large)?;
+        let point_count = i32::try_from(chunk.point_count).map_err(too_large)?;
+        page.extend_from_slice(&chunk.offset.to_le_bytes());
+        page.extend_from_slice(&byte_size.to_le_bytes());
+        page.extend_from_slice(&point_count.to_le_bytes());
+    }
+
+    let mut info = Vec::with_capacity(INFO_SIZE);
+    for v in [center[0], center[1], center[2], halfsize, root_spacing(halfsize)] {
+        info.extend_from_slice(&v.to_le_bytes());
+    }
+    info.extend_from_slice(&(points_end + EVLR_HEADER_SIZE).to_le_bytes());
+    info.extend_from_slice(&(page.len() as u64).to_le_bytes());
+    info.extend_from_slice(&gps_range.0.to_le_bytes());
+    info.extend_from_slice(&gps_range.1.to_le_bytes());
+    info.resize(INFO_SIZE, 0);
+    writer.set_leading_vlr(0, info)?;
+
+    writer.finish_with_evlrs(&[Vlr::new("copc", 1000, "EPT hierarchy", page)])
+}
+
+/// Distance between the points the root keeps
+fn root_spacing(halfsize: f64) -> f64 {
+    2.0 * halfsize / LOD_GRID
+}
+
+/// COPC key of a node: its level, then its cell index per axis
+fn voxel_key(key: NodeKey) -> VoxelKey {
+    let [x, y, z] = key.cell();
+    (key.level() as i32, x as i32, y as i32, z as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointcloud::copc::CopcReader;
+    use crate::pointcloud::octree::NodeSource;
+    use crate::pointcloud::types::{OpenOptions, PointRecord};
+
+    fn layout() -> LasLayout {
+        LasLayout {
+            version_minor: 4,
+            point_format: 6,
+            compress: true,
+            scale: [0.001; 3],
+            offset: [0.0; 3],
+            extra_attributes: Vec::new(),
+            projection_vlrs: Vec::new(),
+            leading_vlrs: Vec::new(),
+            variable_chunks: true,
+            waveform: None,
+            standard_gps_time: true,
+        }
+    }
+
+    /// Write a plane of points as COPC within a memory budget, read the
+    /// hierarchy back and check every node's chunk
+    fn hierarchy_round_trip(name: &str, memory_budget: u64) {
+        let path = std::env::temp_dir().join(format!("ops-copc-{}-{}.copc.laz", std::process::id(), name));
+        let count = 200_000;
+        // A plane of points several to a root grid cell, too many for a single node
+        let points: Vec<PointRecord> = (0..count)
+            .map(|i| PointRecord {
+                x: (i % 1000) as f64 * 0.01,
+                y: (i / 1000) as f64 * 0.05,
+                z: 0.0,
+                gps_time: i as f64,
+                ..PointRecord::default()
+            })
+            .collect();
+        let limits = BuildLimits { memory_budget, work_dir: std::env::temp_dir() };
+        let mut copc_points = CopcPoints::new(PointColumns::default(), limits);
+        for batch in points.chunks(30_000) {
+            copc_points.push(&PointBuffer::from(batch.to_vec())).unwrap();
+        }
+        assert_eq!(write_copc(&path, layout(), copc_points).unwrap(), count as u64);
+
+        let reader = CopcReader::open(&path, &OpenOptions::default()).unwrap();
+        let nodes = reader.nodes();
+        assert!(nodes.len() > 1);
+        assert_eq!(nodes[0].node_id, "r");
+        assert_eq!(nodes.iter().map(|n| n.point_count as usize).sum::<usize>(), count);
+        // Every node below the root hangs off a parent in the hierarchy
+        for node in &nodes[1..] {
+            let parent = &node.node_id[..node.node_id.len() - 1];
+            assert!(nodes.iter().any(|n| n.node_id == parent && n.has_children));
+        }
+
+        let ids: Vec<String> = nodes.iter().map(|n| n.node_id.clone()).collect();
+        let chunks = reader.node_chunks(&ids).unwrap();
+        let _ = std::fs::remove_file(&path);
+        for chunk in &chunks {
+            let node = nodes.iter().find(|n| n.node_id == chunk.node_id).unwrap();
+            assert_eq!(chunk.point_count, node.point_count);
+        }
+        assert_eq!(chunks.iter().map(|c| c.point_count as usize).sum::<usize>(), count);
+    }
+
+    #[test]
+    fn hierarchy_round_trip_in_memory() {
+        hierarchy_round_trip("memory", 1 << 30);
+    }
+
+    #[test]
+    fn hierarchy_round_trip_on_disk() {
+        // Room for a fraction of the points: the rest spill to disk
+        hierarchy_round_trip("disk", 2 * 50_000 * point_memory(PointColumns::default()));
+    }
+
+    #[test]
+    fn voxel_keys_follow_the_octants() {
+        let key = NodeKey::ROOT.child(1).child(6);
+        assert_eq!(voxel_key(NodeKey::ROOT), (0, 0, 0, 0));
+        assert_eq!(voxel_key(key), (2, 2, 1, 1));
+    }
+}
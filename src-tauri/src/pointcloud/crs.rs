@@ -0,0 +1,476 @@
+use super::types::CrsInfo;
+use super::vlr::VlrRecord;
+
+/// GeoTIFF keys we care about (GeoKeyDirectoryTag, VLR record 34735)
+const GT_MODEL_TYPE_KEY: u16 = 1024;
+const GEOGRAPHIC_TYPE_KEY: u16 = 2048;
+const PROJECTED_CS_TYPE_KEY: u16 = 3072;
+const VERTICAL_CS_TYPE_KEY: u16 = 4096;
+
+/// GeoTIFF "user-defined" code: the CRS is not an EPSG entry
+const GT_USER_DEFINED: u16 = 32767;
+
+/// GTModelTypeGeoKey values
+const MODEL_PROJECTED: u16 = 1;
+const MODEL_GEOGRAPHIC: u16 = 2;
+const MODEL_GEOCENTRIC: u16 = 3;
+
+/// Extract the coordinate reference system from the projection VLRs.
+/// OGC WKT (record 2112) wins over GeoTIFF keys when both are present,
+/// matching how LAS 1.4 writers treat the WKT global encoding bit.
+pub fn crs_from_vlrs<'a, I>(vlrs: I, file: &[u8]) -> Option<CrsInfo>
+where
+    I: IntoIterator<Item = &'a VlrRecord>,
+{
+    let mut wkt = None;
+    let mut geo_keys = None;
+    let mut geo_ascii = None;
+
+    for vlr in vlrs {
+        if vlr.user_id != "LASF_Projection" {
+            continue;
+        }
+        match vlr.record_id {
+            2112 => wkt = Some(vlr.data(file)),
+            34735 => geo_keys = Some(vlr.data(file)),
+            34737 => geo_ascii = Some(vlr.data(file)),
+            _ => {}
+        }
+    }
+
+    if let Some(crs) = wkt.and_then(crs_from_wkt_bytes) {
+        return Some(crs);
+    }
+    geo_keys.and_then(|keys| crs_from_geo_keys(keys, geo_ascii))
+}
+
+fn crs_from_wkt_bytes(bytes: &[u8]) -> Option<CrsInfo> {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    let text = String::from_utf8_lossy(&bytes[..end]).trim().to_string();
+    if text.is_empty() {
+        return None;
+    }
+    Some(crs_from_wkt(&text))
+}
+
+/// Describe a CRS from its WKT (WKT1 or WKT2)
+pub fn crs_from_wkt(wkt: &str) -> CrsInfo {
+    let root = WktNode::parse(wkt);
+
+    let (epsg, name, horizontal_epsg, vertical_epsg, model_type) = match &root {
+        Some(root) => {
+            let epsg = root.epsg();
+            let horizontal_node = root.find_child(&[
+                "PROJCS", "PROJCRS", "PROJECTEDCRS", "GEOGCS", "GEOGCRS", "GEOGRAPHICCRS", "GEODCRS", "GEODETICCRS",
+                "GEOCCS",
+            ]);
+            let vertical = root
+                .find_child(&["VERT_CS", "VERTCRS", "VERTICALCRS"])
+                .and_then(|n| n.epsg());
+
+            let is_compound = matches!(root.keyword.as_str(), "COMPD_CS" | "COMPOUNDCRS");
+            let is_vertical = matches!(root.keyword.as_str(), "VERT_CS" | "VERTCRS" | "VERTICALCRS");
+            let horizontal_node = if is_compound { horizontal_node } else if is_vertical { None } else { Some(root) };
+            let horizontal = horizontal_node.and_then(|n| n.epsg());
+            let vertical = if is_vertical { epsg } else { vertical };
+
+            (epsg, root.name().map(str::to_string), horizontal, vertical, horizontal_node.and_then(WktNode::model_type))
+        }
+        None => (None, None, None, None, None),
+    };
+
+    CrsInfo {
+        epsg: epsg.or(horizontal_epsg),
+        horizontal_epsg,
+        vertical_epsg,
+        model_type,
+        name,
+        wkt: Some(wkt.to_string()),
+        source: "wkt".into(),
+    }
+}
+
+/// Describe a CRS from a GeoTIFF GeoKeyDirectory (u16 array) and optional ASCII params
+fn crs_from_geo_keys(keys: &[u8], ascii: Option<&[u8]>) -> Option<CrsInfo> {
+    let shorts: Vec<u16> = keys
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+    if shorts.len() < 4 {
+        return None;
+    }
+
+    // Header: KeyDirectoryVersion, KeyRevision, MinorRevision, NumberOfKeys
+    let number_of_keys = shorts[3] as usize;
+
+    let mut model_type = None;
+    let mut geographic = None;
+    let mut projected = None;
+    let mut vertical = None;
+
+    for entry in shorts[4..].chunks_exact(4).take(number_of_keys) {
+        let (key_id, location, value) = (entry[0], entry[1], entry[3]);
+        // Only inline SHORT values (TIFFTagLocation 0) hold EPSG codes
+        if location != 0 {
+            continue;
+        }
+        match key_id {
+            GT_MODEL_TYPE_KEY => model_type = Some(value),
+            GEOGRAPHIC_TYPE_KEY => geographic = Some(value),
+            PROJECTED_CS_TYPE_KEY => projected = Some(value),
+            VERTICAL_CS_TYPE_KEY => vertical = Some(value),
+            _ => {}
+        }
+    }
+
+    let as_epsg = |code: Option<u16>| code.filter(|&c| c != 0 && c != GT_USER_DEFINED).map(u32::from);
+
+    // Geographic and geocentric models keep their code in the geographic key;
+    // otherwise prefer the projected code
+    let horizontal_epsg = if matches!(model_type, Some(MODEL_GEOGRAPHIC | MODEL_GEOCENTRIC)) {
+        as_epsg(geographic)
+    } else {
+        as_epsg(projected).or(as_epsg(geographic))
+    };
+    // Files without a model type key tell it by the key holding the code
+    let model_type = model_type.or_else(|| {
+        horizontal_epsg.map(|_| if as_epsg(projected).is_some() { MODEL_PROJECTED } else { MODEL_GEOGRAPHIC })
+    });
+    let vertical_epsg = as_epsg(vertical);
+
+    let name = ascii
+        .map(|a| {
+            let end = a.iter().position(|&b| b == 0).unwrap_or(a.len());
+            String::from_utf8_lossy(&a[..end]).trim_end_matches('|').trim().to_string()
+        })
+        .filter(|s| !s.is_empty());
+
+    if horizontal_epsg.is_none() && vertical_epsg.is_none() && name.is_none() {
+        return None;
+    }
+
+    Some(CrsInfo {
+        epsg: horizontal_epsg,
+        horizontal_epsg,
+        vertical_epsg,
+        model_type,
+        name,
+        wkt: None,
+        source: "geotiff".into(),
+    })
+}
+
+/// Encode a CRS as LASF_Projection record payloads by record ID: the WKT when
+/// known, otherwise GeoTIFF keys holding its EPSG codes. A horizontal code
+/// whose model type is unknown cannot be placed in a key and is left out.
+pub fn projection_vlrs(crs: &CrsInfo) -> Vec<(u16, Vec<u8>)> {
+    if let Some(wkt) = &crs.wkt {
+        let mut data = wkt.as_bytes().to_vec();
+        data.push(0);
+        return vec![(2112, data)];
+    }
+
+    // GeoKey codes are 16-bit
+    let code = |epsg: Option<u32>| epsg.and_then(|c| u16::try_from(c).ok());
+    let mut keys = Vec::new();
+    if let (Some(horizontal), Some(model_type)) = (code(crs.horizontal_epsg), crs.model_type) {
+        keys.push((GT_MODEL_TYPE_KEY, model_type));
+        let key = if model_type == MODEL_PROJECTED { PROJECTED_CS_TYPE_KEY } else { GEOGRAPHIC_TYPE_KEY };
+        keys.push((key, horizontal));
+    }
+    if let Some(vertical) = code(crs.vertical_epsg) {
+        keys.push((VERTICAL_CS_TYPE_KEY, vertical));
+    }
+    if keys.is_empty() {
+        return Vec::new();
+    }
+
+    // Header: KeyDirectoryVersion, KeyRevision, MinorRevision, NumberOfKeys,
+    // then per key: id, TIFFTagLocation (0 = inline), count, value
+    let mut shorts = vec![1, 1, 0, keys.len() as u16];
+    for (key_id, value) in keys {
+        shorts.extend([key_id, 0, 1, value]);
+    }
+    vec![(34735, shorts.iter().flat_map(|s| s.to_le_bytes()).collect())]
+}
+
+/// Minimal WKT syntax tree: KEYWORD[ "quoted", number, CHILD[...], ... ]
+#[derive(Debug)]
+struct WktNode {
+    keyword: String,
+    values: Vec<String>,
+    children: Vec<WktNode>,
+}
+
+impl WktNode {
+    fn parse(text: &str) -> Option<WktNode> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut pos = 0;
+        Self::parse_node(&chars, &mut pos)
+    }
+
+    fn parse_node(chars: &[char], pos: &mut usize) -> Option<WktNode> {
+        let keyword = read_identifier(chars, pos);
+        skip_whitespace(chars, pos);
+        if keyword.is_empty() || *pos >= chars.len() || !matches!(chars[*pos], '[' | '(') {
+            return None;
+        }
+        *pos += 1;
+        Some(Self::parse_body(keyword, chars, pos))
+    }
+
+    fn parse_body(keyword: String, chars: &[char], pos: &mut usize) -> WktNode {
+        let mut node = WktNode { keyword, values: Vec::new(), children: Vec::new() };
+        loop {
+            skip_whitespace(chars, pos);
+            if *pos >= chars.len() {
+                return node;
+            }
+            match chars[*pos] {
+                ']' | ')' => {
+                    *pos += 1;
+                    return node;
+                }
+                ',' => *pos += 1,
+                '"' => node.values.push(parse_quoted(chars, pos)),
+                c if c.is_ascii_alphabetic() => {
+                    let identifier = read_identifier(chars, pos);
+                    skip_whitespace(chars, pos);
+                    if *pos < chars.len() && matches!(chars[*pos], '[' | '(') {
+                        *pos += 1;
+                        node.children.push(Self::parse_body(identifier, chars, pos));
+                    } else {
+                        // Bare enumeration value such as an axis direction (NORTH)
+                        node.values.push(identifier);
+                    }
+                }
+                _ => {
+                    let start = *pos;
+                    while *pos < chars.len() && !matches!(chars[*pos], ',' | ']' | ')') {
+                        *pos += 1;
+                    }
+                    node.values.push(chars[start..*pos].iter().collect::<String>().trim().to_string());
+                }
+            }
+        }
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.values.first().map(String::as_str)
+    }
+
+    /// EPSG code from this node's own AUTHORITY["EPSG","x"] (WKT1) or ID["EPSG",x] (WKT2)
+    fn epsg(&self) -> Option<u32> {
+        self.children
+            .iter()
+            .filter(|c| c.keyword == "AUTHORITY" || c.keyword == "ID")
+            .find(|c| c.values.first().is_some_and(|a| a.eq_ignore_ascii_case("EPSG")))
+            .and_then(|c| c.values.get(1))
+            .and_then(|code| code.trim().parse().ok())
+    }
+
+    fn find_child(&self, keywords: &[&str]) -> Option<&WktNode> {
+        self.children.iter().find(|c| keywords.contains(&c.keyword.as_str()))
+    }
+
+    /// GeoTIFF model type of a horizontal CRS node. A WKT2 geodetic CRS is
+    /// geographic with an ellipsoidal coordinate system, geocentric otherwise.
+    fn model_type(&self) -> Option<u16> {
+        match self.keyword.as_str() {
+            "PROJCS" | "PROJCRS" | "PROJECTEDCRS" => Some(MODEL_PROJECTED),
+            "GEOGCS" | "GEOGCRS" | "GEOGRAPHICCRS" => Some(MODEL_GEOGRAPHIC),
+            "GEOCCS" => Some(MODEL_GEOCENTRIC),
+            "GEODCRS" | "GEODETICCRS" => {
+                let cs = self.find_child(&["CS"])?.values.first()?;
+                Some(if cs.eq_ignore_ascii_case("ellipsoidal") { MODEL_GEOGRAPHIC } else { MODEL_GEOCENTRIC })
+            }
+            _ => None,
+        }
+    }
+}
+
+fn read_identifier(chars: &[char], pos: &mut usize) -> String {
+    skip_whitespace(chars, pos);
+    let start = *pos;
+    while *pos < chars.len() && (chars[*pos].is_ascii_alphanumeric() || chars[*pos] == '_') {
+        *pos += 1;
+    }
+    chars[start..*pos].iter().collect::<String>().to_uppercase()
+}
+
+fn skip_whitespace(chars: &[char], pos: &mut usize) {
+    while *pos < chars.len() && chars[*pos].is_whitespace() {
+        *pos += 1;
+    }
+}
+
+/// Parse a double-quoted WKT string; a doubled quote is an escaped quote
+fn parse_quoted(chars: &[char], pos: &mut usize) -> String {
+    let mut out = String::new();
+    *pos += 1;
+    while *pos < chars.len() {
+        let c = chars[*pos];
+        *pos += 1;
+        if c == '"' {
+            if *pos < chars.len() && chars[*pos] == '"' {
+                out.push('"');
+                *pos += 1;
+            } else {
+                break;
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn geo_keys(keys: &[[u16; 4]]) -> Vec<u8> {
+        let mut shorts = vec![1, 1, 0, keys.len() as u16];
+        for key in keys {
+            shorts.extend(key);
+        }
+        shorts.iter().flat_map(|s| s.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn wkt1_projected_authority() {
+        let crs = crs_from_wkt(concat!(
+            r#"PROJCS["Amersfoort / RD New",GEOGCS["Amersfoort",DATUM["Amersfoort","#,
+            r#"SPHEROID["Bessel 1841",6377397.155,299.1528128,AUTHORITY["EPSG","7004"]],"#,
+            r#"AUTHORITY["EPSG","6289"]],AUTHORITY["EPSG","4289"]],PROJECTION["Oblique_Stereographic"],"#,
+            r#"UNIT["metre",1,AUTHORITY["EPSG","9001"]],AXIS["Easting",EAST],AXIS["Northing",NORTH],"#,
+            r#"AUTHORITY["EPSG","28992"]]"#,
+        ));
+        assert_eq!(crs.epsg, Some(28992));
+        assert_eq!(crs.horizontal_epsg, Some(28992));
+        assert_eq!(crs.vertical_epsg, None);
+        assert_eq!(crs.model_type, Some(MODEL_PROJECTED));
+        assert_eq!(crs.name.as_deref(), Some("Amersfoort / RD New"));
+        assert_eq!(crs.source, "wkt");
+    }
+
+    #[test]
+    fn wkt2_geographic_id() {
+        let crs = crs_from_wkt(concat!(
+            r#"GEOGCRS["WGS 84",DATUM["World Geodetic System 1984",ELLIPSOID["WGS 84",6378137,"#,
+            r#"298.257223563]],CS[ellipsoidal,2],AXIS["latitude",north],AXIS["longitude",east],"#,
+            r#"ANGLEUNIT["degree",0.0174532925199433],ID["EPSG",4326]]"#,
+        ));
+        assert_eq!(crs.horizontal_epsg, Some(4326));
+        assert_eq!(crs.model_type, Some(MODEL_GEOGRAPHIC));
+
+        let geocentric = crs_from_wkt(concat!(
+            r#"GEODCRS["WGS 84",DATUM["World Geodetic System 1984",ELLIPSOID["WGS 84",6378137,"#,
+            r#"298.257223563]],CS[Cartesian,3],ID["EPSG",4978]]"#,
+        ));
+        assert_eq!(geocentric.horizontal_epsg, Some(4978));
+        assert_eq!(geocentric.model_type, Some(MODEL_GEOCENTRIC));
+    }
+
+    #[test]
+    fn wkt1_compound() {
+        let crs = crs_from_wkt(concat!(
+            r#"COMPD_CS["Amersfoort / RD New + NAP height",PROJCS["Amersfoort / RD New","#,
+            r#"GEOGCS["Amersfoort",AUTHORITY["EPSG","4289"]],AUTHORITY["EPSG","28992"]],"#,
+            r#"VERT_CS["NAP height",VERT_DATUM["Normaal Amsterdams Peil",2005],AUTHORITY["EPSG","#,
+            r#""5709"]],AUTHORITY["EPSG","7415"]]"#,
+        ));
+        assert_eq!(crs.epsg, Some(7415));
+        assert_eq!(crs.horizontal_epsg, Some(28992));
+        assert_eq!(crs.vertical_epsg, Some(5709));
+        assert_eq!(crs.model_type, Some(MODEL_PROJECTED));
+    }
+
+    #[test]
+    fn wkt2_compound_without_own_id() {
+        let crs = crs_from_wkt(concat!(
+            r#"COMPOUNDCRS["WGS 84 + EGM96 height",GEOGCRS["WGS 84",CS[ellipsoidal,2],ID["EPSG",4326]],"#,
+            r#"VERTCRS["EGM96 height",VDATUM["EGM96 geoid"],CS[vertical,1],ID["EPSG",5773]]]"#,
+        ));
+        assert_eq!(crs.epsg, Some(4326));
+        assert_eq!(crs.horizontal_epsg, Some(4326));
+        assert_eq!(crs.vertical_epsg, Some(5773));
+        assert_eq!(crs.model_type, Some(MODEL_GEOGRAPHIC));
+    }
+
+    #[test]
+    fn wkt_vertical_only() {
+        let crs = crs_from_wkt(r#"VERTCRS["NAP height",VDATUM["Normaal Amsterdams Peil"],ID["EPSG",5709]]"#);
+        assert_eq!(crs.horizontal_epsg, None);
+        assert_eq!(crs.vertical_epsg, Some(5709));
+        assert_eq!(crs.model_type, None);
+    }
+
+    #[test]
+    fn geo_keys_skip_values_stored_elsewhere() {
+        // The projected code sits in GeoDoubleParams (34736) and the citation
+        // in GeoAsciiParams (34737); only the inline keys are codes
+        let keys = geo_keys(&[
+            [GT_MODEL_TYPE_KEY, 0, 1, MODEL_GEOGRAPHIC],
+            [1026, 34737, 7, 0],
+            [GEOGRAPHIC_TYPE_KEY, 0, 1, 4326],
+            [PROJECTED_CS_TYPE_KEY, 34736, 1, 0],
+            [VERTICAL_CS_TYPE_KEY, 0, 1, 5773],
+        ]);
+        let crs = crs_from_geo_keys(&keys, Some(b"WGS 84|\0")).unwrap();
+        assert_eq!(crs.horizontal_epsg, Some(4326));
+        assert_eq!(crs.vertical_epsg, Some(5773));
+        assert_eq!(crs.model_type, Some(MODEL_GEOGRAPHIC));
+        assert_eq!(crs.name.as_deref(), Some("WGS 84"));
+        assert_eq!(crs.source, "geotiff");
+
+        let stored_elsewhere = geo_keys(&[[PROJECTED_CS_TYPE_KEY, 34736, 1, 0]]);
+        assert!(crs_from_geo_keys(&stored_elsewhere, None).is_none());
+    }
+
+    #[test]
+    fn geo_keys_without_model_type() {
+        let projected = crs_from_geo_keys(&geo_keys(&[[PROJECTED_CS_TYPE_KEY, 0, 1, 28992]]), None).unwrap();
+        assert_eq!(projected.model_type, Some(MODEL_PROJECTED));
+        let geographic = crs_from_geo_keys(&geo_keys(&[[GEOGRAPHIC_TYPE_KEY, 0, 1, 4326]]), None).unwrap();
+        assert_eq!(geographic.model_type, Some(MODEL_GEOGRAPHIC));
+    }
+
+    #[test]
+    fn geo_keys_round_trip() {
+        for (model_type, epsg) in [(MODEL_PROJECTED, 28992), (MODEL_GEOGRAPHIC, 4258), (MODEL_GEOCENTRIC, 4978)] {
+            let crs = CrsInfo {
+                epsg: Some(epsg),
+                horizontal_epsg: Some(epsg),
+                vertical_epsg: Some(5709),
+                model_type: Some(model_type),
+                name: None,
+                wkt: None,
+                source: "geotiff".into(),
+            };
+            let vlrs = projection_vlrs(&crs);
+            assert_eq!(vlrs.len(), 1);
+            assert_eq!(vlrs[0].0, 34735);
+            let read = crs_from_geo_keys(&vlrs[0].1, None).unwrap();
+            assert_eq!(read.horizontal_epsg, Some(epsg));
+            assert_eq!(read.vertical_epsg, Some(5709));
+            assert_eq!(read.model_type, Some(model_type));
+        }
+    }
+
+    #[test]
+    fn unknown_model_type_keeps_only_the_vertical_key() {
+        let crs = CrsInfo {
+            epsg: Some(4978),
+            horizontal_epsg: Some(4978),
+            vertical_epsg: Some(5709),
+            model_type: None,
+            name: None,
+            wkt: None,
+            source: "ept".into(),
+        };
+        let read = crs_from_geo_keys(&projection_vlrs(&crs)[0].1, None).unwrap();
+        assert_eq!(read.horizontal_epsg, None);
+        assert_eq!(read.vertical_epsg, Some(5709));
+    }
+}
@@ -0,0 +1,701 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+
+use rayon::prelude::*;
+
+use super::cache::{decode_points, encode_points, record_size};
+use super::hierarchy::HierarchyNode;
+use super::octree::{
+    cells_at, count_pyramid, find_buckets, grid_sample, is_bucket, morton_code, node_info, pack_chunk,
+    select_visible, Cell, LodNode, NodeKey, NodeSource, NodeTable, Octree, OctreeNode, TableNode, COUNT_LEVEL,
+    MAX_DEPTH, MAX_POINTS_PER_LEAF, MORTON_BITS,
+};
+use super::types::{
+    BoundingBox3D, CameraState, OctreeNodeInfo, PointBuffer, PointChunk, PointColumns, PointRecord, WavePacket,
+};
+
+/// Records per read when streaming a temporary file
+const READ_BATCH: usize = 64 * 1024;
+
+/// Records between progress updates while streaming
+const PROGRESS_INTERVAL: u64 = 1 << 20;
+
+/// Smallest share of the budget buffered before buckets are written out
+const MIN_FLUSH_BYTES: usize = 16 << 20;
+
+/// Phases of a build, each with the part of the build's progress it covers
+const COUNTING: Phase = Phase { name: "Counting points", start: 0.0, end: 0.15 };
+const DISTRIBUTING: Phase = Phase { name: "Distributing points", start: 0.15, end: 0.5 };
+const BUILDING: Phase = Phase { name: "Building octree", start: 0.5, end: 1.0 };
+
+static NEXT_WORK_DIR: AtomicU32 = AtomicU32::new(0);
+
+/// Memory a build may hold points in, and the folder it spills them to beyond that
+#[derive(Debug, Clone)]
+pub struct BuildLimits {
+    /// Bytes of points a build may hold
+    pub memory_budget: u64,
+    pub work_dir: PathBuf,
+}
+
+/// Memory a point takes while an octree is built in memory
+pub fn point_memory(columns: PointColumns) -> u64 {
+    let wave_size = if columns.wave_packets { std::mem::size_of::<WavePacket>() } else { 0 };
+    (std::mem::size_of::<PointRecord>() + wave_size + columns.extra_count * 8) as u64
+}
+
+struct Phase {
+    name: &'static str,
+    start: f64,
+    end: f64,
+}
+
+impl Phase {
+    /// Report `fraction` of this phase done as progress of the whole build
+    fn report(&self, progress: &mut dyn FnMut(&str, f64), fraction: f64) {
+        progress(self.name, self.start + (self.end - self.start) * fraction.min(1.0));
+    }
+}
+
+/// Temporary folder of a build, removed with its files when dropped
+struct WorkDir(PathBuf);
+
+impl WorkDir {
+    fn create(parent: &Path) -> Result<Self, String> {
+        let name = format!("{}-{}", std::process::id(), NEXT_WORK_DIR.fetch_add(1, Ordering::Relaxed));
+        let path = parent.join(name);
+        fs::create_dir_all(&path).map_err(|e| format!("Failed to create work folder: {}", e))?;
+        Ok(Self(path))
+    }
+
+    fn file(&self, name: &str) -> PathBuf {
+        self.0.join(name)
+    }
+}
+
+impl Drop for WorkDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Remove the work folders below `parent` that builds of other processes left
+/// behind, as after a crash. Returns the bytes freed.
+pub fn remove_stale_work_dirs(parent: &Path) -> u64 {
+    let Ok(dir) = fs::read_dir(parent) else {
+        return 0;
+    };
+    let own_prefix = format!("{}-", std::process::id());
+    dir.flatten()
+        .filter(|e| {
+            let name = e.file_name().to_string_lossy().into_owned();
+            let is_work_dir = name.split_once('-').is_some_and(|(pid, n)| {
+                !pid.is_empty() && pid.bytes().all(|b| b.is_ascii_digit()) && n.parse::<u32>().is_ok()
+            });
+            is_work_dir && !name.starts_with(&own_prefix)
+        })
+        .map(|e| {
+            let size = fs::read_dir(e.path())
+                .map(|files| files.flatten().filter_map(|f| f.metadata().ok()).map(|m| m.len()).sum())
+                .unwrap_or(0);
+            if fs::remove_dir_all(e.path()).is_ok() { size } else { 0 }
+        })
+        .sum()
+}
+
+/// Points of a cloud too large for memory, written to a temporary file as
+/// they are read, in the cache's record layout
+pub struct PointSpill {
+    dir: WorkDir,
+    out: BufWriter<File>,
+    columns: PointColumns,
+    count: u64,
+    buffer: Vec<u8>,
+}
+
+impl PointSpill {
+    /// Start a spill file in a new folder below `parent`
+    pub fn create(parent: &Path, columns: PointColumns) -> Result<Self, String> {
+        let dir = WorkDir::create(parent)?;
+        let file = File::create(dir.file("points.bin")).map_err(|e| format!("Failed to create spill file: {}", e))?;
+        Ok(Self {
+            dir,
+            out: BufWriter::new(file),
+            columns,
+            count: 0,
+            buffer: Vec::new(),
+        })
+    }
+
+    pub fn write(&mut self, points: &PointBuffer) -> Result<(), String> {
+        self.buffer.clear();
+        encode_points(points, self.columns, &mut self.buffer);
+        self.out.write_all(&self.buffer).map_err(|e| format!("Failed to write spill file: {}", e))?;
+        self.count += points.len() as u64;
+        Ok(())
+    }
+
+    /// Points written so far
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+/// An octree built out of core. Only the hierarchy is held in memory; node
+/// points live in a temporary file and are read when requested.
+pub struct DiskOctree {
+    /// Node data is the byte offset of the node's points in the node file
+    root: HierarchyNode<u64>,
+    nodes: NodeTable<u64>,
+    file: Mutex<File>,
+    payload_size: u64,
+    attribute_mask: u32,
+    additive: bool,
+    columns: PointColumns,
+    dir: WorkDir,
+}
+
+impl DiskOctree {
+    /// Build the octree of the spilled points within a memory budget in bytes.
+    /// The points are counted on a grid, distributed to one file per bucket
+    /// (the coarsest cells under the budget), and each bucket is built in
+    /// memory in turn; the nodes above the buckets then subsample their
+    /// children from disk. The tree matches the one `Octree::build` makes,
+    /// additive or not.
+    /// `progress` gets the phase and the fraction of the whole build done.
+    pub fn build(
+        spill: PointSpill,
+        bounds: BoundingBox3D,
+        attribute_mask: u32,
+        additive: bool,
+        budget: u64,
+        progress: &mut dyn FnMut(&str, f64),
+    ) -> Result<Self, String> {
+        let PointSpill { dir, out, columns, count, .. } = spill;
+        out.into_inner().map_err(|e| format!("Failed to write spill file: {}", e.error()))?;
+        let record_size = record_size(columns);
+        let points_path = dir.file("points.bin");
+        let grid = Grid { bounds: bounds.clone() };
+
+        COUNTING.report(progress, 0.0);
+        let mut counts = vec![0u64; cells_at(COUNT_LEVEL)];
+        let mut seen = 0u64;
+        for_each_record(&points_path, record_size, &mut |rec| {
+            counts[grid.cell_of(rec)] += 1;
+            seen += 1;
+            if seen & (PROGRESS_INTERVAL - 1) == 0 {
+                COUNTING.report(progress, seen as f64 / count.max(1) as f64);
+            }
+            Ok(())
+        })?;
+        let pyramid = count_pyramid(counts);
+
+        // A bucket is a cell whose points fit the budget, or a finest cell
+        let max_points = (budget / (2 * point_memory(columns))).max(MAX_POINTS_PER_LEAF as u64);
+        let mut buckets = Vec::new();
+        find_buckets(&pyramid, (0, 0), max_points, &mut buckets);
+        let mut bucket_of = vec![u32::MAX; cells_at(COUNT_LEVEL)];
+        for (index, &(level, cell)) in buckets.iter().enumerate() {
+            // A cell's finest cells are a contiguous run in Morton order
+            let shift = 3 * (COUNT_LEVEL - level) as usize;
+            bucket_of[cell << shift..(cell + 1) << shift].fill(index as u32);
+        }
+
+        DISTRIBUTING.report(progress, 0.0);
+        let flush_at = ((budget / 2) as usize).max(MIN_FLUSH_BYTES);
+        let mut buffers = vec![Vec::new(); buckets.len()];
+        let mut buffered = 0usize;
+        let mut seen = 0u64;
+        for_each_record(&points_path, record_size, &mut |rec| {
+            let bucket = bucket_of[grid.cell_of(rec)] as usize;
+            buffers[bucket].extend_from_slice(rec);
+            buffered += rec.len();
+            if buffered >= flush_at {
+                flush_buckets(&dir, &mut buffers)?;
+                buffered = 0;
+            }
+            seen += 1;
+            if seen & (PROGRESS_INTERVAL - 1) == 0 {
+                DISTRIBUTING.report(progress, seen as f64 / count.max(1) as f64);
+            }
+            Ok(())
+        })?;
+        flush_buckets(&dir, &mut buffers)?;
+        drop(buffers);
+        drop(bucket_of);
+        let _ = fs::remove_file(&points_path);
+
+        BUILDING.report(progress, 0.0);
+        let nodes_path = dir.file("nodes.bin");
+        let nodes = File::create(&nodes_path).map_err(|e| format!("Failed to create node file: {}", e))?;
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&nodes_path)
+            .map_err(|e| format!("Failed to open node file: {}", e))?;
+        let mut builder = Builder {
+            dir: &dir,
+            grid: &grid,
+            out: BufWriter::new(nodes),
+            file,
+            written: 0,
+            record_size,
+            columns,
+            additive,
+            max_points,
+            done: 0,
+            total: count.max(1),
+            progress,
+        };
+        let mut next_bucket = 0;
+        let root = builder
+            .build_cell(&pyramid, (0, 0), "r".to_string(), bounds.clone(), &mut next_bucket)?
+            .unwrap_or_else(|| empty_node(bounds));
+        let Builder { mut out, file, written: payload_size, .. } = builder;
+        out.flush().map_err(|e| format!("Failed to write node file: {}", e))?;
+        drop(out);
+
+        Ok(Self {
+            nodes: NodeTable::new(&root, additive, |n| n.data),
+            root,
+            file: Mutex::new(file),
+            payload_size,
+            attribute_mask,
+            additive,
+            columns,
+            dir,
+        })
+    }
+
+    pub fn root(&self) -> &HierarchyNode<u64> {
+        &self.root
+    }
+
+    pub fn attribute_mask(&self) -> u32 {
+        self.attribute_mask
+    }
+
+    pub fn additive(&self) -> bool {
+        self.additive
+    }
+
+    /// File holding the points of every node at the offsets in the hierarchy
+    pub fn payload_path(&self) -> PathBuf {
+        self.dir.file("nodes.bin")
+    }
+
+    pub fn payload_size(&self) -> u64 {
+        self.payload_size
+    }
+
+    /// Call `f` with the key and points of every node, parents first,
+    /// reading one node at a time
+    pub fn for_each_node(&self, f: &mut dyn FnMut(NodeKey, PointBuffer) -> Result<(), String>) -> Result<(), String> {
+        for node in self.nodes.iter() {
+            f(node.key, self.read_points(node)?)?;
+        }
+        Ok(())
+    }
+
+    fn read_points(&self, node: &TableNode<u64>) -> Result<PointBuffer, String> {
+        let mut bytes = vec![0u8; node.info.point_count as usize * record_size(self.columns)];
+        {
+            let mut file = self.file.lock().unwrap();
+            file.seek(SeekFrom::Start(node.data))
+                .and_then(|_| file.read_exact(&mut bytes))
+                .map_err(|e| format!("Failed to read node {}: {}", node.info.node_id, e))?;
+        }
+        Ok(decode_points(&bytes, self.columns))
+    }
+
+    fn node_chunk(&self, node_id: &str) -> Result<Option<PointChunk>, String> {
+        let Some(node) = self.nodes.find(node_id).filter(|n| n.info.point_count > 0) else {
+            return Ok(None);
+        };
+        let info = &node.info;
+        let points = self.read_points(node)?;
+        Ok(Some(pack_chunk(node_id, &info.bounds, info.level, node.spacing, &points, self.attribute_mask)))
+    }
+}
+
+impl NodeSource for DiskOctree {
+    fn visible_nodes(&self, camera: &CameraState, point_budget: u32) -> Vec<OctreeNodeInfo> {
+        select_visible(&self.root, camera, point_budget, self.additive)
+            .into_iter()
+            .map(|n| node_info(n, self.additive))
+            .collect()
+    }
+
+    /// Read the requested nodes one at a time and decode them in parallel
+    fn node_chunks(&self, node_ids: &[String]) -> Result<Vec<PointChunk>, String> {
+        let chunks = node_ids.par_iter().map(|id| self.node_chunk(id)).collect::<Result<Vec<_>, String>>()?;
+        Ok(chunks.into_iter().flatten().collect())
+    }
+
+    fn nodes(&self) -> Vec<OctreeNodeInfo> {
+        self.nodes.infos()
+    }
+
+    fn is_additive(&self) -> bool {
+        self.additive
+    }
+}
+
+/// Writes node points to the node file and assembles the hierarchy
+struct Builder<'a> {
+    dir: &'a WorkDir,
+    grid: &'a Grid,
+    out: BufWriter<File>,
+    /// Second handle on the node file, to read back and rewrite nodes
+    file: File,
+    /// Bytes written to the node file
+    written: u64,
+    record_size: usize,
+    columns: PointColumns,
+    additive: bool,
+    /// Points a bucket may hold to be built in memory
+    max_points: u64,
+    done: u64,
+    total: u64,
+    progress: &'a mut dyn FnMut(&str, f64),
+}
+
+impl Builder<'_> {
+    /// Build the node of a counting grid cell, replaying the choice of
+    /// buckets so `next_bucket` walks them in the order they were found
+    fn build_cell(
+        &mut self,
+        pyramid: &[Vec<u64>],
+        cell: Cell,
+        node_id: String,
+        bounds: BoundingBox3D,
+        next_bucket: &mut usize,
+    ) -> Result<Option<HierarchyNode<u64>>, String> {
+        let (level, index) = cell;
+        let count = pyramid[level as usize][index];
+        if count == 0 {
+            return Ok(None);
+        }
+        if is_bucket(count, level, self.max_points) {
+            let path = self.dir.file(&format!("bucket-{}.bin", next_bucket));
+            *next_bucket += 1;
+            return self.build_bucket(&path, count, node_id, bounds, level).map(Some);
+        }
+
+        let mut children: [Option<Box<HierarchyNode<u64>>>; 8] = Default::default();
+        for (octant, child) in children.iter_mut().enumerate() {
+            let child_cell = (level + 1, index * 8 + octant);
+            let child_id = format!("{}{}", node_id, octant);
+            *child = self
+                .build_cell(pyramid, child_cell, child_id, bounds.octant(octant as u8), next_bucket)?
+                .map(Box::new);
+        }
+        self.subsample(node_id, bounds, level, children).map(Some)
+    }
+
+    /// Build a bucket in memory, or split it on disk first when even the
+    /// finest counting cell holds more points than the budget allows
+    fn build_bucket(
+        &mut self,
+        path: &Path,
+        count: u64,
+        node_id: String,
+        bounds: BoundingBox3D,
+        level: u8,
+    ) -> Result<HierarchyNode<u64>, String> {
+        if count > self.max_points && level < MAX_DEPTH {
+            let parts = self.split(path, level, &node_id)?;
+            let mut children: [Option<Box<HierarchyNode<u64>>>; 8] = Default::default();
+            for (octant, (child, (part, part_count))) in children.iter_mut().zip(parts).enumerate() {
+                if part_count > 0 {
+                    let child_id = format!("{}{}", node_id, octant);
+                    let child_bounds = bounds.octant(octant as u8);
+                    *child = Some(Box::new(self.build_bucket(&part, part_count, child_id, child_bounds, level + 1)?));
+                }
+            }
+            return self.subsample(node_id, bounds, level, children);
+        }
+
+        let mut bytes = Vec::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_end(&mut bytes))
+            .map_err(|e| format!("Failed to read bucket: {}", e))?;
+        let _ = fs::remove_file(path);
+        let points = decode_points(&bytes, self.columns);
+        drop(bytes);
+
+        let node = Octree::build_node(points, node_id, bounds, level, self.additive);
+        let node = self.write_subtree(node)?;
+        self.done += count;
+        BUILDING.report(self.progress, self.done as f64 / self.total as f64);
+        Ok(node)
+    }
+
+    /// Move a bucket's points into one file per child octant
+    fn split(&mut self, path: &Path, level: u8, node_id: &str) -> Result<Vec<(PathBuf, u64)>, String> {
+        let mut parts = Vec::with_capacity(8);
+        let mut writers = Vec::with_capacity(8);
+        for octant in 0..8 {
+            let part = self.dir.file(&format!("split-{}{}.bin", node_id, octant));
+            let file = File::create(&part).map_err(|e| format!("Failed to create bucket: {}", e))?;
+            writers.push(BufWriter::new(file));
+            parts.push((part, 0u64));
+        }
+        for_each_record(path, self.record_size, &mut |rec| {
+            let octant = self.grid.octant(rec, level);
+            parts[octant].1 += 1;
+            writers[octant].write_all(rec).map_err(|e| format!("Failed to write bucket: {}", e))
+        })?;
+        for mut writer in writers {
+            writer.flush().map_err(|e| format!("Failed to write bucket: {}", e))?;
+        }
+        let _ = fs::remove_file(path);
+        Ok(parts)
+    }
+
+    /// Write the points of a subtree built in memory, parents first
+    fn write_subtree(&mut self, node: OctreeNode) -> Result<HierarchyNode<u64>, String> {
+        let OctreeNode { node_id, bounds, level, points, children } = node;
+        let mut bytes = Vec::new();
+        encode_points(&points, self.columns, &mut bytes);
+        let offset = self.append(&bytes)?;
+        let point_count = points.len() as u32;
+        drop(points);
+
+        let mut written: [Option<Box<HierarchyNode<u64>>>; 8] = Default::default();
+        for (slot, child) in written.iter_mut().zip(children) {
+            if let Some(child) = child {
+                *slot = Some(Box::new(self.write_subtree(*child)?));
+            }
+        }
+        Ok(HierarchyNode { node_id, bounds, level, point_count, data: offset, children: written })
+    }
+
+    /// An internal node above the buckets: a grid sample of its children's
+    /// points, as `Octree::build` takes. In an additive tree the children
+    /// are rewritten in place without the sample, and dropped if emptied.
+    fn subsample(
+        &mut self,
+        node_id: String,
+        bounds: BoundingBox3D,
+        level: u8,
+        mut children: [Option<Box<HierarchyNode<u64>>>; 8],
+    ) -> Result<HierarchyNode<u64>, String> {
+        let mut candidates = Vec::new();
+        for child in children.iter().flatten() {
+            candidates.extend_from_slice(&self.read(child.data, child.point_count)?);
+        }
+        let records: Vec<&[u8]> = candidates.chunks_exact(self.record_size).collect();
+        let picked = grid_sample(records.iter().map(|rec| position(rec)), &bounds);
+        let mut bytes = Vec::new();
+        for &i in &picked {
+            bytes.extend_from_slice(records[i]);
+        }
+        if self.additive {
+            let mut picked = picked.into_iter().peekable();
+            let mut offset = 0;
+            for slot in &mut children {
+                let Some(child) = slot else { continue };
+                let len = child.point_count as usize;
+                let mut kept = Vec::with_capacity(len * self.record_size);
+                for (i, rec) in records[offset..offset + len].iter().enumerate() {
+                    if picked.next_if_eq(&(offset + i)).is_none() {
+                        kept.extend_from_slice(rec);
+                    }
+                }
+                offset += len;
+                child.point_count = (kept.len() / self.record_size) as u32;
+                if child.point_count == 0 && !child.has_children() {
+                    *slot = None;
+                } else {
+                    self.rewrite(child.data, &kept)?;
+                }
+            }
+        }
+        drop(records);
+        let point_count = (bytes.len() / self.record_size) as u32;
+        let offset = self.append(&bytes)?;
+        Ok(HierarchyNode { node_id, bounds, level, point_count, data: offset, children })
+    }
+
+    fn append(&mut self, bytes: &[u8]) -> Result<u64, String> {
+        let offset = self.written;
+        self.out.write_all(bytes).map_err(|e| format!("Failed to write node file: {}", e))?;
+        self.written += bytes.len() as u64;
+        Ok(offset)
+    }
+
+    fn read(&mut self, offset: u64, point_count: u32) -> Result<Vec<u8>, String> {
+        let mut bytes = vec![0u8; point_count as usize * self.record_size];
+        self.out
+            .flush()
+            .and_then(|_| self.file.seek(SeekFrom::Start(offset)))
+            .and_then(|_| self.file.read_exact(&mut bytes))
+            .map_err(|e| format!("Failed to read node file: {}", e))?;
+        Ok(bytes)
+    }
+
+    /// Overwrite the start of a node's points, once they are read back
+    fn rewrite(&mut self, offset: u64, bytes: &[u8]) -> Result<(), String> {
+        self.file
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| self.file.write_all(bytes))
+            .map_err(|e| format!("Failed to write node file: {}", e))
+    }
+}
+
+/// Morton cells over the cloud's bounds, as `Octree::build` partitions it
+struct Grid {
+    bounds: BoundingBox3D,
+}
+
+impl Grid {
+    fn code(&self, rec: &[u8]) -> u64 {
+        let [x, y, z] = position(rec);
+        morton_code(x, y, z, &self.bounds, MORTON_BITS)
+    }
+
+    /// Finest counting cell of an encoded point
+    fn cell_of(&self, rec: &[u8]) -> usize {
+        (self.code(rec) >> (3 * (MORTON_BITS - COUNT_LEVEL as u32))) as usize
+    }
+
+    /// Child octant of the node at `level` an encoded point falls in
+    fn octant(&self, rec: &[u8], level: u8) -> usize {
+        ((self.code(rec) >> (3 * (MORTON_BITS - 1 - level as u32))) & 7) as usize
+    }
+}
+
+/// Append the buffered records to their bucket files and empty the buffers
+fn flush_buckets(dir: &WorkDir, buffers: &mut [Vec<u8>]) -> Result<(), String> {
+    for (index, buffer) in buffers.iter_mut().enumerate().filter(|(_, b)| !b.is_empty()) {
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.file(&format!("bucket-{}.bin", index)))
+            .and_then(|mut f| f.write_all(buffer))
+            .map_err(|e| format!("Failed to write bucket: {}", e))?;
+        *buffer = Vec::new();
+    }
+    Ok(())
+}
+
+/// Call `f` with every record of a temporary file
+fn for_each_record(
+    path: &Path,
+    record_size: usize,
+    f: &mut dyn FnMut(&[u8]) -> Result<(), String>,
+) -> Result<(), String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut input = BufReader::new(file);
+    let mut batch = vec![0u8; READ_BATCH * record_size];
+    loop {
+        let len = read_full(&mut input, &mut batch).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        for rec in batch[..len].chunks_exact(record_size) {
+            f(rec)?;
+        }
+        if len < batch.len() {
+            return Ok(());
+        }
+    }
+}
+
+/// Fill `buf` as far as the input allows. Returns the bytes read.
+fn read_full(input: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match input.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(len)
+}
+
+/// Position of an encoded point, without decoding the rest
+fn position(rec: &[u8]) -> [f64; 3] {
+    let f64_at = |o: usize| f64::from_le_bytes(rec[o..o + 8].try_into().unwrap());
+    [f64_at(0), f64_at(8), f64_at(16)]
+}
+
+fn empty_node(bounds: BoundingBox3D) -> HierarchyNode<u64> {
+    HierarchyNode {
+        node_id: "r".to_string(),
+        bounds,
+        level: 0,
+        point_count: 0,
+        data: 0,
+        children: Default::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointcloud::octree::NodeSource;
+
+    fn node_counts(source: &dyn NodeSource) -> Vec<(String, u32)> {
+        let mut counts: Vec<_> = source.nodes().into_iter().map(|n| (n.node_id, n.point_count)).collect();
+        counts.sort();
+        counts
+    }
+
+    #[test]
+    fn disk_build_matches_memory_build() {
+        let bounds = BoundingBox3D { min_x: 0.0, min_y: 0.0, min_z: 0.0, max_x: 1.0, max_y: 1.0, max_z: 1.0 };
+        // Points spread over the cube, and a cluster inside one finest counting
+        // cell holding more than a bucket may, so that bucket is split on disk
+        let spread = (0..200_000).map(|i| {
+            let i = i as f64;
+            [(i * 0.618_034).fract(), (i * 0.414_214).fract(), (i * 0.732_051).fract()]
+        });
+        let cluster = (0..100_000).map(|i| {
+            let i = i as f64;
+            let at = |f: f64| 0.298 + 0.005 * (i * f).fract();
+            [at(0.618_034), at(0.414_214), at(0.732_051)]
+        });
+        let points: Vec<PointRecord> = spread
+            .chain(cluster)
+            .map(|[x, y, z]| PointRecord { x, y, z, ..PointRecord::default() })
+            .collect();
+        let points = PointBuffer::from(points);
+
+        for additive in [true, false] {
+            let memory = Octree::build(points.clone(), bounds.clone(), 0, additive, &|_| {});
+
+            let mut spill = PointSpill::create(&std::env::temp_dir(), points.columns()).unwrap();
+            // Spill in batches, as the readers do
+            for start in (0..points.len()).step_by(30_000) {
+                let mut batch = PointBuffer::with_capacity(points.columns(), 30_000);
+                for i in start..(start + 30_000).min(points.len()) {
+                    batch.push_from(&points, i);
+                }
+                spill.write(&batch).unwrap();
+            }
+            // The smallest budget: buckets of MAX_POINTS_PER_LEAF points
+            let disk = DiskOctree::build(spill, bounds.clone(), 0, additive, 0, &mut |_, _| {}).unwrap();
+
+            let counts = node_counts(&disk);
+            // The cluster's nodes lie below the counting grid
+            assert!(counts.iter().any(|(id, _)| id.len() > COUNT_LEVEL as usize + 1), "additive {}", additive);
+            assert_eq!(counts, node_counts(&memory), "additive {}", additive);
+            // Each node holds the same points, so subsampled children were rewritten
+            let positions = |points: &PointBuffer| {
+                let mut positions: Vec<_> = points.points.iter().map(|p| [p.x, p.y, p.z].map(f64::to_bits)).collect();
+                positions.sort();
+                positions
+            };
+            disk.for_each_node(&mut |key, points| {
+                let node = memory.nodes.find(&key.to_string()).unwrap();
+                assert_eq!(positions(&points), positions(&node.data), "node {}", key);
+                Ok(())
+            })
+            .unwrap();
+        }
+    }
+}
@@ -0,0 +1,811 @@
+use std::fs::File;
+use std::path::Path;
+use std::sync::Mutex;
+
+use memmap2::Mmap;
+use roxmltree::{Document, Node};
+
+use super::crs::crs_from_wkt;
+use super::format::PointcloudFormat;
+use super::reader::{base_metadata, PointReader};
+use super::types::{
+    point_attributes, BoundingBox3D, HeaderIssue, PointBuffer, PointColumns, PointRecord, PointcloudMetadata,
+};
+
+/// Size of the E57 file header at the start of the file
+const FILE_HEADER_SIZE: usize = 48;
+
+/// Size of a CompressedVector binary section header
+const SECTION_HEADER_SIZE: usize = 32;
+
+/// Every physical page ends in a CRC that is not part of the logical data
+const PAGE_CRC_SIZE: u64 = 4;
+
+/// Packet type of a CompressedVector data packet (0 = index, 2 = empty)
+const DATA_PACKET: u8 = 1;
+
+/// How one prototype field is stored in its bytestream (bitPack codec)
+#[derive(Debug, Clone, Copy)]
+enum FieldEncoding {
+    Float32,
+    Float64,
+    /// Bit-packed `value - minimum`
+    Integer { minimum: i64, bits: u32 },
+    /// Bit-packed `raw - minimum`, value = raw * scale + offset
+    ScaledInteger { minimum: i64, bits: u32, scale: f64, offset: f64 },
+}
+
+impl FieldEncoding {
+    fn bits(self) -> u32 {
+        match self {
+            Self::Float32 => 32,
+            Self::Float64 => 64,
+            Self::Integer { bits, .. } | Self::ScaledInteger { bits, .. } => bits,
+        }
+    }
+
+    fn decode(self, stream: &mut BitStream) -> f64 {
+        match self {
+            Self::Float32 => f32::from_bits(stream.read(32) as u32) as f64,
+            Self::Float64 => f64::from_bits(stream.read(64)),
+            Self::Integer { minimum, bits } => (stream.read(bits) as i64).wrapping_add(minimum) as f64,
+            Self::ScaledInteger { minimum, bits, scale, offset } => {
+                (stream.read(bits) as i64).wrapping_add(minimum) as f64 * scale + offset
+            }
+        }
+    }
+}
+
+/// Bits needed to store any value in `minimum..=maximum`
+fn bits_for_range(minimum: i64, maximum: i64) -> u32 {
+    let range = (maximum as i128 - minimum as i128).max(0) as u128;
+    128 - range.leading_zeros()
+}
+
+/// One field of a scan's point prototype
+#[derive(Debug, Clone)]
+struct E57Field {
+    name: String,
+    encoding: FieldEncoding,
+    /// Declared value range, used to normalize colors and intensity
+    range: Option<(f64, f64)>,
+}
+
+/// Rigid transform from a scan's local coordinates to the file coordinates
+#[derive(Debug, Clone, Copy)]
+struct Pose {
+    /// Unit quaternion w, x, y, z
+    rotation: [f64; 4],
+    translation: [f64; 3],
+}
+
+impl Pose {
+    const IDENTITY: Pose = Pose { rotation: [1.0, 0.0, 0.0, 0.0], translation: [0.0; 3] };
+
+    fn apply(&self, p: [f64; 3]) -> [f64; 3] {
+        let [w, qx, qy, qz] = self.rotation;
+        // v' = v + w*t + q x t, with t = 2 * (q x v)
+        let tx = 2.0 * (qy * p[2] - qz * p[1]);
+        let ty = 2.0 * (qz * p[0] - qx * p[2]);
+        let tz = 2.0 * (qx * p[1] - qy * p[0]);
+        [
+            p[0] + w * tx + (qy * tz - qz * ty) + self.translation[0],
+            p[1] + w * ty + (qz * tx - qx * tz) + self.translation[1],
+            p[2] + w * tz + (qx * ty - qy * tx) + self.translation[2],
+        ]
+    }
+}
+
+/// A data3D entry: one scan with its own prototype and pose
+#[derive(Debug, Clone)]
+struct E57Scan {
+    name: String,
+    record_count: u64,
+    /// Physical offset of the CompressedVector section
+    section_offset: u64,
+    fields: Vec<E57Field>,
+    pose: Pose,
+    /// cartesianBounds in scan-local coordinates
+    local_bounds: Option<BoundingBox3D>,
+    color_limits: Option<(f64, f64)>,
+    intensity_limits: Option<(f64, f64)>,
+}
+
+impl E57Scan {
+    fn field(&self, name: &str) -> Option<usize> {
+        self.fields.iter().position(|f| f.name == name)
+    }
+
+    fn has_fields(&self, names: &[&str]) -> bool {
+        names.iter().all(|n| self.field(n).is_some())
+    }
+
+    /// Scan bounds in file coordinates: the transformed corners of the local bounds
+    fn bounds(&self) -> Option<BoundingBox3D> {
+        let local = self.local_bounds.as_ref()?;
+        let mut bounds = BoundingBox3D::new();
+        for corner in 0..8u8 {
+            let x = if corner & 1 == 0 { local.min_x } else { local.max_x };
+            let y = if corner & 2 == 0 { local.min_y } else { local.max_y };
+            let z = if corner & 4 == 0 { local.min_z } else { local.max_z };
+            let [x, y, z] = self.pose.apply([x, y, z]);
+            bounds.expand(x, y, z);
+        }
+        Some(bounds)
+    }
+}
+
+enum Coordinates {
+    Cartesian([usize; 3]),
+    /// range, azimuth, elevation
+    Spherical([usize; 3]),
+}
+
+/// Prototype field indices resolved once per scan
+struct ScanLayout {
+    coordinates: Coordinates,
+    invalid_state: Option<usize>,
+    color: Option<([usize; 3], (f64, f64))>,
+    intensity: Option<(usize, (f64, f64))>,
+    return_index: Option<usize>,
+    return_count: Option<usize>,
+    time_stamp: Option<usize>,
+}
+
+impl ScanLayout {
+    fn resolve(scan: &E57Scan) -> Option<Self> {
+        let (coordinates, invalid_state) =
+            if let (Some(x), Some(y), Some(z)) = (scan.field("cartesianX"), scan.field("cartesianY"), scan.field("cartesianZ")) {
+                (Coordinates::Cartesian([x, y, z]), scan.field("cartesianInvalidState"))
+            } else if let (Some(r), Some(a), Some(e)) =
+                (scan.field("sphericalRange"), scan.field("sphericalAzimuth"), scan.field("sphericalElevation"))
+            {
+                (Coordinates::Spherical([r, a, e]), scan.field("sphericalInvalidState"))
+            } else {
+                return None;
+            };
+
+        // Normalize by the scan's declared limits, else by the field's own range
+        let value_range = |index: usize, limits: Option<(f64, f64)>| {
+            limits.or(scan.fields[index].range).unwrap_or(match scan.fields[index].encoding {
+                FieldEncoding::Float32 | FieldEncoding::Float64 => (0.0, 1.0),
+                _ => (0.0, 255.0),
+            })
+        };
+
+        let color = match (scan.field("colorRed"), scan.field("colorGreen"), scan.field("colorBlue")) {
+            (Some(r), Some(g), Some(b)) => Some(([r, g, b], value_range(r, scan.color_limits))),
+            _ => None,
+        };
+        let intensity = scan.field("intensity").map(|i| (i, value_range(i, scan.intensity_limits)));
+
+        Some(Self {
+            coordinates,
+            invalid_state,
+            color,
+            intensity,
+            return_index: scan.field("returnIndex"),
+            return_count: scan.field("returnCount"),
+            time_stamp: scan.field("timeStamp"),
+        })
+    }
+
+    /// Build a point from one decoded record. Records without a valid position yield None.
+    fn point(&self, values: &[f64], pose: &Pose) -> Option<PointRecord> {
+        if let Some(i) = self.invalid_state {
+            if values[i] != 0.0 {
+                return None;
+            }
+        }
+
+        let local = match self.coordinates {
+            Coordinates::Cartesian([x, y, z]) => [values[x], values[y], values[z]],
+            Coordinates::Spherical([r, a, e]) => {
+                let (range, azimuth, elevation) = (values[r], values[a], values[e]);
+                [
+                    range * elevation.cos() * azimuth.cos(),
+                    range * elevation.cos() * azimuth.sin(),
+                    range * elevation.sin(),
+                ]
+            }
+        };
+        let [x, y, z] = pose.apply(local);
+        let mut point = PointRecord { x, y, z, ..Default::default() };
+
+        if let Some(([r, g, b], (lo, hi))) = self.color {
+            point.r = (normalize(values[r], lo, hi) * 65535.0).round() as u16;
+            point.g = (normalize(values[g], lo, hi) * 65535.0).round() as u16;
+            point.b = (normalize(values[b], lo, hi) * 65535.0).round() as u16;
+        }
+        if let Some((i, (lo, hi))) = self.intensity {
+            point.intensity = (normalize(values[i], lo, hi) * 65535.0).round() as u16;
+        }
+        if let Some(i) = self.return_index {
+            // E57 return indices are zero-based
+            point.return_number = (values[i] as u8).saturating_add(1);
+        }
+        if let Some(i) = self.return_count {
+            point.number_of_returns = values[i] as u8;
+        }
+        if let Some(i) = self.time_stamp {
+            point.gps_time = values[i];
+        }
+
+        Some(point)
+    }
+}
+
+/// Map `value` from `lo..=hi` to 0..=1
+fn normalize(value: f64, lo: f64, hi: f64) -> f64 {
+    if hi > lo {
+        ((value - lo) / (hi - lo)).clamp(0.0, 1.0)
+    } else {
+        0.0
+    }
+}
+
+/// The values of one field, accumulated across data packets.
+/// Bit-packed values may straddle packet boundaries.
+#[derive(Default)]
+struct BitStream {
+    bytes: Vec<u8>,
+    bit_pos: usize,
+}
+
+impl BitStream {
+    fn push(&mut self, data: &[u8]) {
+        let consumed = self.bit_pos / 8;
+        if consumed > 0 {
+            self.bytes.drain(..consumed);
+            self.bit_pos -= consumed * 8;
+        }
+        self.bytes.extend_from_slice(data);
+    }
+
+    /// Number of whole values of `bits` bits that can be read
+    fn available(&self, bits: u32) -> u64 {
+        if bits == 0 {
+            return u64::MAX;
+        }
+        ((self.bytes.len() * 8 - self.bit_pos) / bits as usize) as u64
+    }
+
+    /// Read `bits` bits, least significant bit first
+    fn read(&mut self, bits: u32) -> u64 {
+        let mut value = 0u64;
+        let mut read = 0;
+        while read < bits {
+            let byte = self.bytes[self.bit_pos / 8];
+            let shift = (self.bit_pos % 8) as u32;
+            let take = (8 - shift).min(bits - read);
+            let chunk = (byte >> shift) as u64 & ((1u64 << take) - 1);
+            value |= chunk << read;
+            read += take;
+            self.bit_pos += take as usize;
+        }
+        value
+    }
+}
+
+/// Memory-mapped reader for ASTM E57 files: the XML section describes the
+/// scans, whose points are decoded from their CompressedVector sections.
+pub struct E57Reader {
+    mmap: Mmap,
+    page_size: u64,
+    version: (u32, u32),
+    scans: Vec<E57Scan>,
+    coordinate_metadata: Option<String>,
+    /// Problems found while streaming the points, see `PointReader::stream_issues`
+    stream_issues: Mutex<Vec<HeaderIssue>>,
+}
+
+impl E57Reader {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let file = File::open(path.as_ref()).map_err(|e| format!("Failed to open file: {}", e))?;
+        let mmap = unsafe { Mmap::map(&file) }.map_err(|e| format!("Failed to mmap file: {}", e))?;
+
+        if mmap.len() < FILE_HEADER_SIZE || &mmap[0..8] != b"ASTM-E57" {
+            return Err("Not a valid E57 file".into());
+        }
+        let u32_at = |o: usize| u32::from_le_bytes([mmap[o], mmap[o + 1], mmap[o + 2], mmap[o + 3]]);
+        let version = (u32_at(8), u32_at(12));
+        let xml_offset = u64_at(&mmap, 24);
+        let xml_length = u64_at(&mmap, 32);
+        let page_size = u64_at(&mmap, 40);
+        if page_size <= PAGE_CRC_SIZE {
+            return Err(format!("Invalid E57 page size: {}", page_size));
+        }
+
+        let mut reader = Self {
+            mmap,
+            page_size,
+            version,
+            scans: Vec::new(),
+            coordinate_metadata: None,
+            stream_issues: Mutex::new(Vec::new()),
+        };
+
+        let xml_length = usize::try_from(xml_length).map_err(|_| "E57 XML section is too large")?;
+        let xml_bytes = reader.read_logical(reader.logical(xml_offset), xml_length)?;
+        let xml = String::from_utf8_lossy(&xml_bytes);
+        let doc = Document::parse(xml.trim_end_matches('\0'))
+            .map_err(|e| format!("Failed to parse E57 XML section: {}", e))?;
+
+        let root = doc.root_element();
+        reader.coordinate_metadata = child(root, "coordinateMetadata")
+            .and_then(|n| n.text())
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty());
+
+        if let Some(data3d) = child(root, "data3D") {
+            for (i, node) in data3d.children().filter(|c| c.has_tag_name("vectorChild")).enumerate() {
+                if let Some(scan) = parse_scan(node, i)? {
+                    reader.scans.push(scan);
+                }
+            }
+        }
+        if reader.scans.is_empty() {
+            return Err("No point cloud data found in E57 file".into());
+        }
+
+        Ok(reader)
+    }
+
+    /// Logical (CRC-free) offset of a physical file offset
+    fn logical(&self, physical: u64) -> u64 {
+        let page_data = self.page_size - PAGE_CRC_SIZE;
+        physical / self.page_size * page_data + physical % self.page_size
+    }
+
+    /// Read `len` logical bytes, skipping the CRC at the end of every page.
+    /// A length beyond the file is rejected before anything is reserved.
+    fn read_logical(&self, start: u64, len: usize) -> Result<Vec<u8>, String> {
+        const PAST_END: &str = "E57 data runs past the end of the file";
+        if len > self.mmap.len() {
+            return Err(PAST_END.into());
+        }
+        let page_data = self.page_size - PAGE_CRC_SIZE;
+        let mut out = Vec::with_capacity(len);
+        let mut pos = start;
+        while out.len() < len {
+            let in_page = pos % page_data;
+            let physical = (pos / page_data)
+                .checked_mul(self.page_size)
+                .and_then(|page| page.checked_add(in_page))
+                .and_then(|physical| usize::try_from(physical).ok())
+                .ok_or(PAST_END)?;
+            let take = ((page_data - in_page) as usize).min(len - out.len());
+            let bytes = physical
+                .checked_add(take)
+                .and_then(|end| self.mmap.get(physical..end))
+                .ok_or(PAST_END)?;
+            out.extend_from_slice(bytes);
+            pos += take as u64;
+        }
+        Ok(out)
+    }
+
+    /// Bounds from the scans' cartesianBounds, when every scan declares them
+    fn declared_bounds(&self) -> Option<BoundingBox3D> {
+        let mut bounds = BoundingBox3D::new();
+        for scan in &self.scans {
+            let b = scan.bounds()?;
+            bounds.expand(b.min_x, b.min_y, b.min_z);
+            bounds.expand(b.max_x, b.max_y, b.max_z);
+        }
+        Some(bounds)
+    }
+
+    fn any_scan(&self, names: &[&str]) -> bool {
+        self.scans.iter().any(|s| s.has_fields(names))
+    }
+
+    /// Decode one scan's CompressedVector section into the batch, flushing full batches.
+    /// Returns false when the callback asked to stop.
+    fn stream_scan(
+        &self,
+        scan: &E57Scan,
+        batch_size: usize,
+        batch: &mut PointBuffer,
+        streamed: &mut u64,
+        callback: &mut dyn FnMut(&PointBuffer, u64) -> bool,
+    ) -> Result<bool, String> {
+        // Scans without coordinates are reported in the metadata
+        let layout = match ScanLayout::resolve(scan) {
+            Some(layout) => layout,
+            None => return Ok(true),
+        };
+
+        let section_start = self.logical(scan.section_offset);
+        let section = self.read_logical(section_start, SECTION_HEADER_SIZE)?;
+        if section[0] != 1 {
+            return Err(format!("Invalid CompressedVector section in E57 scan {}", scan.name));
+        }
+        let section_end = section_start + u64_at(&section, 8);
+        let mut pos = self.logical(u64_at(&section, 16));
+
+        let mut streams: Vec<BitStream> = scan.fields.iter().map(|_| BitStream::default()).collect();
+        let mut values = vec![0.0; scan.fields.len()];
+        let mut remaining = scan.record_count;
+
+        while remaining > 0 && pos < section_end {
+            let packet_header = self.read_logical(pos, 4)?;
+            let packet_len = u16::from_le_bytes([packet_header[2], packet_header[3]]) as u64 + 1;
+
+            if packet_header[0] == DATA_PACKET {
+                let packet = self.read_logical(pos, packet_len as usize)?;
+                let u16_at = |o: usize| {
+                    packet
+                        .get(o..o + 2)
+                        .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+                        .ok_or_else(|| "E57 data packet too short".to_string())
+                };
+                let stream_count = u16_at(4)?;
+                let mut data_offset = 6 + stream_count * 2;
+                for i in 0..stream_count {
+                    let len = u16_at(6 + i * 2)?;
+                    let bytes = packet
+                        .get(data_offset..data_offset + len)
+                        .ok_or_else(|| format!("Corrupt data packet in E57 scan {}", scan.name))?;
+                    if let Some(stream) = streams.get_mut(i) {
+                        stream.push(bytes);
+                    }
+                    data_offset += len;
+                }
+
+                // Decode every record whose fields have all arrived
+                let ready = scan.fields.iter()
+                    .zip(&streams)
+                    .map(|(f, s)| s.available(f.encoding.bits()))
+                    .min()
+                    .unwrap_or(0)
+                    .min(remaining);
+
+                for _ in 0..ready {
+                    for (i, (field, stream)) in scan.fields.iter().zip(streams.iter_mut()).enumerate() {
+                        values[i] = field.encoding.decode(stream);
+                    }
+                    remaining -= 1;
+
+                    if let Some(point) = layout.point(&values, &scan.pose) {
+                        batch.points.push(point);
+                        if batch.len() >= batch_size {
+                            if !callback(batch, *streamed) {
+                                return Ok(false);
+                            }
+                            *streamed += batch.len() as u64;
+                            batch.clear();
+                        }
+                    }
+                }
+            }
+
+            pos += packet_len;
+        }
+
+        if remaining > 0 {
+            self.stream_issues.lock().unwrap().push(HeaderIssue::warning(
+                "recordCount",
+                scan.record_count,
+                scan.record_count - remaining,
+                format!("E57 scan {} ended {} records early", scan.name, remaining),
+            ));
+        }
+        Ok(true)
+    }
+}
+
+impl PointReader for E57Reader {
+    fn metadata(&self, id: &str, file_path: &str) -> PointcloudMetadata {
+        PointcloudMetadata {
+            has_color: self.any_scan(&["colorRed", "colorGreen", "colorBlue"]),
+            has_intensity: self.any_scan(&["intensity"]),
+            has_returns: self.any_scan(&["returnIndex"]),
+            has_gps_time: self.any_scan(&["timeStamp"]),
+            las_version: format!("E57 {}.{}", self.version.0, self.version.1),
+            crs: self.coordinate_metadata
+                .as_deref()
+                .filter(|m| m.contains('['))
+                .map(crs_from_wkt),
+            validation: self.scans.iter()
+                .filter(|scan| ScanLayout::resolve(scan).is_none())
+                .map(|scan| HeaderIssue::warning(
+                    "prototype",
+                    "cartesian or spherical coordinates",
+                    "none",
+                    format!("E57 scan {} has no coordinates, skipping it", scan.name),
+                ))
+                .collect(),
+            // Refined from the points once indexing finishes
+            ..base_metadata(id, file_path, PointcloudFormat::E57, self.total_points(), self.declared_bounds())
+        }
+    }
+
+    fn total_points(&self) -> u64 {
+        self.scans.iter().map(|s| s.record_count).sum()
+    }
+
+    /// cartesianBounds are optional and often loose, so bounds come from the points
+    fn bounds(&self) -> Option<BoundingBox3D> {
+        None
+    }
+
+    fn attribute_mask(&self) -> u32 {
+        let mut mask = 0;
+        if self.any_scan(&["returnIndex"]) {
+            mask |= point_attributes::RETURNS;
+        }
+        if self.any_scan(&["timeStamp"]) {
+            mask |= point_attributes::GPS_TIME;
+        }
+        mask
+    }
+
+    fn stream_points(
+        &self,
+        batch_size: u64,
+        callback: &mut dyn FnMut(&PointBuffer, u64) -> bool,
+    ) -> Result<(), String> {
+        let batch_size = batch_size.max(1) as usize;
+        let mut batch = PointBuffer::with_capacity(PointColumns::default(), batch_size);
+        let mut streamed = 0u64;
+
+        for scan in &self.scans {
+            if !self.stream_scan(scan, batch_size, &mut batch, &mut streamed, callback)? {
+                return Ok(());
+            }
+        }
+        if !batch.is_empty() {
+            callback(&batch, streamed);
+        }
+        Ok(())
+    }
+
+    fn stream_issues(&self) -> Vec<HeaderIssue> {
+        self.stream_issues.lock().unwrap().clone()
+    }
+}
+
+fn u64_at(data: &[u8], o: usize) -> u64 {
+    u64::from_le_bytes([
+        data[o], data[o + 1], data[o + 2], data[o + 3],
+        data[o + 4], data[o + 5], data[o + 6], data[o + 7],
+    ])
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|c| c.has_tag_name(name))
+}
+
+fn child_f64(node: Node, name: &str) -> Option<f64> {
+    child(node, name)?.text()?.trim().parse().ok()
+}
+
+fn attr_i64(node: Node, name: &str) -> Option<i64> {
+    let value = node.attribute(name)?.trim();
+    value.parse().ok().or_else(|| value.parse::<f64>().ok().map(|v| v as i64))
+}
+
+fn attr_f64(node: Node, name: &str) -> Option<f64> {
+    node.attribute(name)?.trim().parse().ok()
+}
+
+/// Parse a data3D vectorChild. Scans without points yield None.
+fn parse_scan(node: Node, index: usize) -> Result<Option<E57Scan>, String> {
+    let name = child(node, "name")
+        .and_then(|n| n.text())
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("scan_{}", index));
+
+    let points = match child(node, "points").filter(|p| p.attribute("type") == Some("CompressedVector")) {
+        Some(p) => p,
+        None => return Ok(None),
+    };
+    let record_count = attr_i64(points, "recordCount").unwrap_or(0).max(0) as u64;
+    let section_offset = attr_i64(points, "fileOffset").unwrap_or(0).max(0) as u64;
+    if record_count == 0 {
+        return Ok(None);
+    }
+
+    if child(points, "codecs").is_some_and(|c| c.children().any(|n| n.is_element())) {
+        return Err(format!("E57 scan {} uses a codec other than bit packing, which is not supported", name));
+    }
+
+    let prototype = child(points, "prototype")
+        .ok_or_else(|| format!("E57 scan {} has no point prototype", name))?;
+    let mut fields = Vec::new();
+    for field in prototype.children().filter(|c| c.is_element()) {
+        let field_name = field.tag_name().name().to_string();
+        let (encoding, range) = match field.attribute("type") {
+            Some("Float") => {
+                let encoding = if field.attribute("precision") == Some("single") {
+                    FieldEncoding::Float32
+                } else {
+                    FieldEncoding::Float64
+                };
+                let range = attr_f64(field, "minimum").zip(attr_f64(field, "maximum"));
+                (encoding, range)
+            }
+            Some(kind @ ("Integer" | "ScaledInteger")) => {
+                let minimum = attr_i64(field, "minimum").unwrap_or(i64::MIN);
+                let maximum = attr_i64(field, "maximum").unwrap_or(i64::MAX);
+                let bits = bits_for_range(minimum, maximum);
+                if kind == "Integer" {
+                    (FieldEncoding::Integer { minimum, bits }, Some((minimum as f64, maximum as f64)))
+                } else {
+                    let scale = attr_f64(field, "scale").unwrap_or(1.0);
+                    let offset = attr_f64(field, "offset").unwrap_or(0.0);
+                    let range = (minimum as f64 * scale + offset, maximum as f64 * scale + offset);
+                    (FieldEncoding::ScaledInteger { minimum, bits, scale, offset }, Some(range))
+                }
+            }
+            other => {
+                return Err(format!(
+                    "E57 scan {}: unsupported field type {:?} for {}",
+                    name, other.unwrap_or("none"), field_name
+                ))
+            }
+        };
+        fields.push(E57Field { name: field_name, encoding, range });
+    }
+
+    let pose = child(node, "pose").map_or(Pose::IDENTITY, |pose| {
+        let mut result = Pose::IDENTITY;
+        if let Some(r) = child(pose, "rotation") {
+            let q = [
+                child_f64(r, "w").unwrap_or(1.0),
+                child_f64(r, "x").unwrap_or(0.0),
+                child_f64(r, "y").unwrap_or(0.0),
+                child_f64(r, "z").unwrap_or(0.0),
+            ];
+            let norm = q.iter().map(|v| v * v).sum::<f64>().sqrt();
+            if norm > 0.0 {
+                result.rotation = q.map(|v| v / norm);
+            }
+        }
+        if let Some(t) = child(pose, "translation") {
+            result.translation = [
+                child_f64(t, "x").unwrap_or(0.0),
+                child_f64(t, "y").unwrap_or(0.0),
+                child_f64(t, "z").unwrap_or(0.0),
+            ];
+        }
+        result
+    });
+
+    let local_bounds = child(node, "cartesianBounds").and_then(|b| {
+        Some(BoundingBox3D {
+            min_x: child_f64(b, "xMinimum")?,
+            min_y: child_f64(b, "yMinimum")?,
+            min_z: child_f64(b, "zMinimum")?,
+            max_x: child_f64(b, "xMaximum")?,
+            max_y: child_f64(b, "yMaximum")?,
+            max_z: child_f64(b, "zMaximum")?,
+        })
+    });
+    let color_limits = child(node, "colorLimits")
+        .and_then(|c| child_f64(c, "colorRedMinimum").zip(child_f64(c, "colorRedMaximum")));
+    let intensity_limits = child(node, "intensityLimits")
+        .and_then(|c| child_f64(c, "intensityMinimum").zip(child_f64(c, "intensityMaximum")));
+
+    Ok(Some(E57Scan {
+        name,
+        record_count,
+        section_offset,
+        fields,
+        pose,
+        local_bounds,
+        color_limits,
+        intensity_limits,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("ops-e57-{}-{}", std::process::id(), name))
+    }
+
+    /// A reader over `data` with pages of `page_size` bytes and no scans
+    fn reader(name: &str, data: &[u8], page_size: u64) -> E57Reader {
+        let path = temp_path(name);
+        std::fs::write(&path, data).unwrap();
+        let mmap = unsafe { Mmap::map(&File::open(&path).unwrap()) }.unwrap();
+        let _ = std::fs::remove_file(&path);
+        E57Reader {
+            mmap,
+            page_size,
+            version: (1, 0),
+            scans: Vec::new(),
+            coordinate_metadata: None,
+            stream_issues: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn assert_near(a: [f64; 3], b: [f64; 3]) {
+        assert!(a.iter().zip(&b).all(|(a, b)| (a - b).abs() < 1e-12), "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn bit_stream_reads_across_bytes_and_packets() {
+        let mut stream = BitStream::default();
+        stream.push(&[0b1011_0110]);
+        assert_eq!(stream.available(3), 2);
+        assert_eq!(stream.read(3), 0b110);
+        stream.push(&[0xff]);
+        // The last five bits of the first byte, then two of the second
+        assert_eq!(stream.read(7), 0b11_10110);
+        assert_eq!(stream.available(6), 1);
+        assert_eq!(stream.available(7), 0);
+    }
+
+    #[test]
+    fn fields_decode_zero_and_full_width_values() {
+        let mut stream = BitStream::default();
+        stream.push(&1.5f64.to_bits().to_le_bytes());
+        stream.push(&u64::MAX.to_le_bytes());
+        stream.push(&[0x2a]);
+
+        // A field whose range holds a single value takes no bits
+        let constant = FieldEncoding::Integer { minimum: 7, bits: bits_for_range(7, 7) };
+        assert_eq!(constant.bits(), 0);
+        assert_eq!(stream.available(0), u64::MAX);
+        assert_eq!(constant.decode(&mut stream), 7.0);
+
+        assert_eq!(FieldEncoding::Float64.decode(&mut stream), 1.5);
+        let full = FieldEncoding::Integer { minimum: i64::MIN, bits: bits_for_range(i64::MIN, i64::MAX) };
+        assert_eq!(full.bits(), 64);
+        assert_eq!(full.decode(&mut stream), i64::MAX as f64);
+        let scaled = FieldEncoding::ScaledInteger { minimum: -2, bits: 8, scale: 0.5, offset: 10.0 };
+        assert_eq!(scaled.decode(&mut stream), 10.0 + 40.0 * 0.5);
+        assert_eq!(stream.available(1), 0);
+    }
+
+    #[test]
+    fn logical_reads_skip_page_checksums() {
+        // Pages of 8 bytes: 4 of data, then a 4-byte CRC
+        let mut data = Vec::new();
+        for page in 0..3u8 {
+            data.extend((0..4).map(|i| page * 4 + i));
+            data.extend([0xcc; 4]);
+        }
+        let reader = reader("pages", &data, 8);
+        assert_eq!(reader.logical(8), 4);
+        assert_eq!(reader.logical(18), 10);
+        assert_eq!(reader.read_logical(2, 6).unwrap(), [2, 3, 4, 5, 6, 7]);
+        assert_eq!(reader.read_logical(4, 8).unwrap(), [4, 5, 6, 7, 8, 9, 10, 11]);
+        assert!(reader.read_logical(6, 10).is_err());
+        assert!(reader.read_logical(0, usize::MAX).is_err());
+        assert!(reader.read_logical(u64::MAX - 1, 4).is_err());
+    }
+
+    #[test]
+    fn xml_sections_longer_than_the_file_fail() {
+        let mut header = vec![0u8; FILE_HEADER_SIZE];
+        header[..8].copy_from_slice(b"ASTM-E57");
+        header[8..12].copy_from_slice(&1u32.to_le_bytes());
+        header[24..32].copy_from_slice(&(FILE_HEADER_SIZE as u64).to_le_bytes());
+        header[32..40].copy_from_slice(&(1u64 << 60).to_le_bytes());
+        header[40..48].copy_from_slice(&1024u64.to_le_bytes());
+        let path = temp_path("xml.e57");
+        std::fs::write(&path, &header).unwrap();
+        let result = E57Reader::open(&path);
+        let _ = std::fs::remove_file(&path);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn poses_rotate_then_translate() {
+        assert_near(Pose::IDENTITY.apply([1.0, 2.0, 3.0]), [1.0, 2.0, 3.0]);
+        // A quarter turn about z, then a shift
+        let half = std::f64::consts::FRAC_1_SQRT_2;
+        let pose = Pose { rotation: [half, 0.0, 0.0, half], translation: [10.0, 20.0, 30.0] };
+        assert_near(pose.apply([1.0, 0.0, 0.0]), [10.0, 21.0, 30.0]);
+        assert_near(pose.apply([0.0, 1.0, 5.0]), [9.0, 20.0, 35.0]);
+        // A half turn about x flips y and z
+        let pose = Pose { rotation: [0.0, 1.0, 0.0, 0.0], translation: [0.0; 3] };
+        assert_near(pose.apply([1.0, 2.0, 3.0]), [1.0, -2.0, -3.0]);
+    }
+}
@@ -0,0 +1,451 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+use serde::Deserialize;
+
+use super::crs::crs_from_wkt;
+use super::format::PointcloudFormat;
+use super::hierarchy::{build_hierarchy, parse_voxel_key, HierarchyNode, VoxelKey};
+use super::octree::{
+    estimate_spacing, node_info, pack_chunk, select_visible, NodeSource, NodeTable,
+};
+use super::parser::PointcloudParser;
+use super::reader::{base_metadata, PointReader, ValueScale};
+use super::types::{
+    point_attributes, BoundingBox3D, CameraState, ColorDepth, CrsInfo, OctreeNodeInfo, OpenOptions,
+    PointBuffer, PointChunk, PointRecord, PointcloudMetadata,
+};
+
+/// Points decoded at a time when reading a LAZ tile
+const TILE_BATCH_SIZE: u64 = 100_000;
+
+/// The ept.json dataset description
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EptInfo {
+    /// Cubic bounds of the octree root
+    bounds: [f64; 6],
+    /// Tight bounds of the points
+    #[serde(default)]
+    bounds_conformance: Option<[f64; 6]>,
+    data_type: String,
+    points: u64,
+    schema: Vec<EptDimension>,
+    #[serde(default)]
+    srs: Option<EptSrs>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EptDimension {
+    name: String,
+    /// "signed", "unsigned" or "float"
+    #[serde(rename = "type")]
+    kind: String,
+    size: usize,
+    #[serde(default)]
+    scale: Option<f64>,
+    #[serde(default)]
+    offset: Option<f64>,
+}
+
+impl EptDimension {
+    fn read(&self, b: &[u8]) -> f64 {
+        macro_rules! read {
+            ($t:ty, $n:literal) => {{
+                let mut raw = [0u8; $n];
+                raw.copy_from_slice(&b[..$n]);
+                <$t>::from_le_bytes(raw) as f64
+            }};
+        }
+        let value = match (self.kind.as_str(), self.size) {
+            ("float", 4) => read!(f32, 4),
+            ("float", 8) => read!(f64, 8),
+            ("unsigned", 1) => b[0] as f64,
+            ("unsigned", 2) => read!(u16, 2),
+            ("unsigned", 4) => read!(u32, 4),
+            ("unsigned", 8) => read!(u64, 8),
+            ("signed", 1) => b[0] as i8 as f64,
+            ("signed", 2) => read!(i16, 2),
+            ("signed", 4) => read!(i32, 4),
+            ("signed", 8) => read!(i64, 8),
+            _ => 0.0,
+        };
+        value * self.scale.unwrap_or(1.0) + self.offset.unwrap_or(0.0)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct EptSrs {
+    #[serde(default)]
+    authority: Option<String>,
+    #[serde(default)]
+    horizontal: Option<String>,
+    #[serde(default)]
+    vertical: Option<String>,
+    #[serde(default)]
+    wkt: Option<String>,
+}
+
+/// Reader for Entwine Point Tile datasets in a local directory. The EPT
+/// hierarchy is the LOD tree; tiles are loaded when their node is requested.
+pub struct EptReader {
+    root_dir: PathBuf,
+    info: EptInfo,
+    /// Node data is the EPT key, which names the tile file
+    root: HierarchyNode<VoxelKey>,
+    nodes: NodeTable<VoxelKey>,
+    /// Byte offset of each schema dimension in a binary tile record
+    offsets: Vec<usize>,
+    record_size: usize,
+    color_depth: ColorDepth,
+}
+
+impl EptReader {
+    /// Open a dataset from its directory or its ept.json
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let (root_dir, info_path) = if path.is_dir() {
+            (path.to_path_buf(), path.join("ept.json"))
+        } else {
+            (path.parent().unwrap_or(Path::new(".")).to_path_buf(), path.to_path_buf())
+        };
+
+        let text = fs::read_to_string(&info_path).map_err(|e| format!("Failed to read ept.json: {}", e))?;
+        let info: EptInfo = serde_json::from_str(&text).map_err(|e| format!("Failed to parse ept.json: {}", e))?;
+        if !matches!(info.data_type.as_str(), "laszip" | "binary") {
+            return Err(format!("Unsupported EPT data type: {}", info.data_type));
+        }
+
+        let mut entries = HashMap::new();
+        read_hierarchy_file(&root_dir, "0-0-0-0", &mut entries)?;
+
+        let [min_x, min_y, min_z, max_x, max_y, max_z] = info.bounds;
+        let cube = BoundingBox3D { min_x, min_y, min_z, max_x, max_y, max_z };
+        let root = build_hierarchy((0, 0, 0, 0), "r".into(), cube, &entries)
+            .ok_or("EPT hierarchy has no root node")?;
+
+        let mut offsets = Vec::with_capacity(info.schema.len());
+        let mut record_size = 0;
+        for dim in &info.schema {
+            offsets.push(record_size);
+            record_size += dim.size;
+        }
+
+        let mut reader = Self {
+            root_dir,
+            info,
+            nodes: NodeTable::new(&*root, true, |n| n.data),
+            root: *root,
+            offsets,
+            record_size,
+            color_depth: ColorDepth::Bit16,
+        };
+        if reader.has_dimension("Red") {
+            reader.color_depth = reader.detect_color_depth().unwrap_or_else(|e| {
+                eprintln!("EPT color depth detection failed, assuming 16-bit: {}", e);
+                ColorDepth::Bit16
+            });
+        }
+        Ok(reader)
+    }
+
+    fn dimension(&self, name: &str) -> Option<usize> {
+        self.info.schema.iter().position(|d| d.name == name)
+    }
+
+    fn has_dimension(&self, name: &str) -> bool {
+        self.dimension(name).is_some()
+    }
+
+    /// Colors are 16-bit by the LAS convention, but many sources store 8-bit
+    /// values. Decide once from the root tile, the same test the LAS reader uses.
+    fn detect_color_depth(&self) -> Result<ColorDepth, String> {
+        if self.info.data_type == "laszip" {
+            let parser = PointcloudParser::open(self.tile_path(self.root.data, "laz"), &OpenOptions::default())?;
+            return Ok(parser.metadata("", "").color_depth.unwrap_or(ColorDepth::Bit16));
+        }
+
+        let data = self.read_binary_tile(self.root.data)?;
+        let channels: Vec<usize> = ["Red", "Green", "Blue"].iter().filter_map(|n| self.dimension(n)).collect();
+        let max = data
+            .chunks_exact(self.record_size.max(1))
+            .flat_map(|rec| channels.iter().map(move |&i| self.info.schema[i].read(&rec[self.offsets[i]..])))
+            .fold(0.0, f64::max);
+        Ok(if max > 0.0 && max <= 255.0 { ColorDepth::Bit8 } else { ColorDepth::Bit16 })
+    }
+
+    fn read_binary_tile(&self, key: VoxelKey) -> Result<Vec<u8>, String> {
+        let path = self.tile_path(key, "bin");
+        fs::read(&path).map_err(|e| format!("Failed to read EPT tile {}: {}", path.display(), e))
+    }
+
+    fn tile_path(&self, key: VoxelKey, extension: &str) -> PathBuf {
+        let (d, x, y, z) = key;
+        self.root_dir.join("ept-data").join(format!("{}-{}-{}-{}.{}", d, x, y, z, extension))
+    }
+
+    /// Read the points of one tile
+    fn load_tile(&self, key: VoxelKey) -> Result<Vec<PointRecord>, String> {
+        if self.info.data_type == "laszip" {
+            let options = OpenOptions { color_depth: Some(self.color_depth), ..Default::default() };
+            let parser = PointcloudParser::open(self.tile_path(key, "laz"), &options)?;
+            // Extra dimensions are not listed in the metadata, so only the records are kept
+            let mut points = Vec::with_capacity(parser.total_points() as usize);
+            parser.stream_points(TILE_BATCH_SIZE, &mut |batch, _| {
+                points.extend_from_slice(&batch.points);
+                true
+            })?;
+            return Ok(points);
+        }
+
+        let data = self.read_binary_tile(key)?;
+        Ok(data.par_chunks_exact(self.record_size.max(1)).map(|rec| self.decode_binary(rec)).collect())
+    }
+
+    fn decode_binary(&self, rec: &[u8]) -> PointRecord {
+        let mut point = PointRecord::default();
+        let color_scale = match self.color_depth {
+            ColorDepth::Bit8 => ValueScale::Byte,
+            ColorDepth::Bit16 => ValueScale::Word,
+        };
+
+        for (dim, &offset) in self.info.schema.iter().zip(&self.offsets) {
+            let value = dim.read(&rec[offset..]);
+            match dim.name.as_str() {
+                "X" => point.x = value,
+                "Y" => point.y = value,
+                "Z" => point.z = value,
+                "Intensity" => point.intensity = value as u16,
+                "ReturnNumber" => point.return_number = value as u8,
+                "NumberOfReturns" => point.number_of_returns = value as u8,
+                "Classification" => point.classification = value as u8,
+                "ScanAngleRank" | "ScanAngle" => point.scan_angle = value as f32,
+                "UserData" => point.user_data = value as u8,
+                "PointSourceId" => point.point_source_id = value as u16,
+                "GpsTime" => point.gps_time = value,
+                "Red" => point.r = color_scale.to_u16(value),
+                "Green" => point.g = color_scale.to_u16(value),
+                "Blue" => point.b = color_scale.to_u16(value),
+                "Infrared" => point.nir = value as u16,
+                _ => {}
+            }
+        }
+        point
+    }
+
+    fn node_chunk(&self, node_id: &str) -> Result<Option<PointChunk>, String> {
+        let (node, key) = match self.nodes.find(node_id) {
+            Some(n) if n.info.point_count > 0 => (&n.info, n.data),
+            _ => return Ok(None),
+        };
+        let points = self.load_tile(key)?;
+        let spacing = estimate_spacing(&node.bounds, points.len());
+        let points = PointBuffer::from(points);
+        Ok(Some(pack_chunk(node_id, &node.bounds, node.level, spacing, &points, self.attribute_mask())))
+    }
+
+    fn crs(&self) -> Option<CrsInfo> {
+        let srs = self.info.srs.as_ref()?;
+        if let Some(wkt) = srs.wkt.as_deref().filter(|w| !w.is_empty()) {
+            return Some(crs_from_wkt(wkt));
+        }
+        let horizontal = srs.horizontal.as_deref()?.parse().ok();
+        (srs.authority.as_deref() == Some("EPSG")).then(|| CrsInfo {
+            epsg: horizontal,
+            horizontal_epsg: horizontal,
+            vertical_epsg: srs.vertical.as_deref().and_then(|v| v.parse().ok()),
+            model_type: None,
+            name: None,
+            wkt: None,
+            source: "ept".into(),
+        })
+    }
+
+    /// All keys with point data, for streaming every tile
+    fn tile_keys(&self) -> Vec<VoxelKey> {
+        let mut keys = Vec::new();
+        let mut stack = vec![&self.root];
+        while let Some(node) = stack.pop() {
+            if node.point_count > 0 {
+                keys.push(node.data);
+            }
+            stack.extend(node.children.iter().flatten().map(|c| &**c));
+        }
+        keys
+    }
+}
+
+/// Read a hierarchy file, following the subtrees it delegates to other files
+/// (marked with a point count of -1). Files are read from a work queue, each
+/// at most once, so files that delegate back cannot loop.
+fn read_hierarchy_file(
+    root_dir: &Path,
+    name: &str,
+    entries: &mut HashMap<VoxelKey, (u32, VoxelKey)>,
+) -> Result<(), String> {
+    let mut files = vec![name.to_string()];
+    let mut visited = HashSet::new();
+
+    while let Some(name) = files.pop() {
+        if !visited.insert(name.clone()) {
+            continue;
+        }
+        let path = root_dir.join("ept-hierarchy").join(format!("{}.json", name));
+        let text = fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read EPT hierarchy {}: {}", path.display(), e))?;
+        let counts: HashMap<String, i64> = serde_json::from_str(&text)
+            .map_err(|e| format!("Failed to parse EPT hierarchy {}: {}", path.display(), e))?;
+
+        for (text_key, count) in counts {
+            let key = parse_voxel_key(&text_key).ok_or_else(|| format!("Invalid EPT key: {}", text_key))?;
+            if count < 0 {
+                files.push(text_key);
+            } else {
+                entries.insert(key, (count as u32, key));
+            }
+        }
+    }
+    Ok(())
+}
+
+impl NodeSource for EptReader {
+    fn visible_nodes(&self, camera: &CameraState, point_budget: u32) -> Vec<OctreeNodeInfo> {
+        select_visible(&self.root, camera, point_budget, true).into_iter().map(|n| node_info(n, true)).collect()
+    }
+
+    /// Load the requested tiles in parallel
+    fn node_chunks(&self, node_ids: &[String]) -> Result<Vec<PointChunk>, String> {
+        let chunks = node_ids
+            .par_iter()
+            .map(|id| self.node_chunk(id))
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(chunks.into_iter().flatten().collect())
+    }
+
+    fn nodes(&self) -> Vec<OctreeNodeInfo> {
+        self.nodes.infos()
+    }
+
+    fn is_additive(&self) -> bool {
+        true
+    }
+}
+
+impl PointReader for EptReader {
+    fn metadata(&self, id: &str, file_path: &str) -> PointcloudMetadata {
+        let [min_x, min_y, min_z, max_x, max_y, max_z] = self.info.bounds_conformance.unwrap_or(self.info.bounds);
+        let bounds = BoundingBox3D { min_x, min_y, min_z, max_x, max_y, max_z };
+        let has_color = self.has_dimension("Red");
+
+        PointcloudMetadata {
+            has_color,
+            has_intensity: self.has_dimension("Intensity"),
+            has_classification: self.has_dimension("Classification"),
+            has_returns: self.has_dimension("ReturnNumber"),
+            has_gps_time: self.has_dimension("GpsTime"),
+            has_nir: self.has_dimension("Infrared"),
+            crs: self.crs(),
+            color_depth: has_color.then_some(self.color_depth),
+            ..base_metadata(id, file_path, PointcloudFormat::Ept, self.info.points, Some(bounds))
+        }
+    }
+
+    fn total_points(&self) -> u64 {
+        self.info.points
+    }
+
+    fn bounds(&self) -> Option<BoundingBox3D> {
+        let [min_x, min_y, min_z, max_x, max_y, max_z] = self.info.bounds_conformance?;
+        Some(BoundingBox3D { min_x, min_y, min_z, max_x, max_y, max_z })
+    }
+
+    fn attribute_mask(&self) -> u32 {
+        let mut mask = 0;
+        for (name, bit) in [
+            ("ReturnNumber", point_attributes::RETURNS),
+            ("ScanAngleRank", point_attributes::SCAN_ANGLE),
+            ("ScanAngle", point_attributes::SCAN_ANGLE),
+            ("UserData", point_attributes::USER_DATA),
+            ("PointSourceId", point_attributes::POINT_SOURCE_ID),
+            ("GpsTime", point_attributes::GPS_TIME),
+            ("Infrared", point_attributes::NIR),
+        ] {
+            if self.has_dimension(name) {
+                mask |= bit;
+            }
+        }
+        mask
+    }
+
+    /// Stream every tile in turn, split into batches
+    fn stream_points(
+        &self,
+        batch_size: u64,
+        callback: &mut dyn FnMut(&PointBuffer, u64) -> bool,
+    ) -> Result<(), String> {
+        let batch_size = batch_size.max(1) as usize;
+        let mut streamed = 0;
+        for key in self.tile_keys() {
+            let tile = PointBuffer::from(self.load_tile(key)?);
+            for start in (0..tile.len()).step_by(batch_size) {
+                let batch = tile.slice(start..tile.len().min(start + batch_size));
+                if !callback(&batch, streamed) {
+                    return Ok(());
+                }
+                streamed += batch.len() as u64;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A dataset folder holding these hierarchy files, by name
+    fn hierarchy_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ops-ept-{}-{}", std::process::id(), name));
+        fs::create_dir_all(dir.join("ept-hierarchy")).unwrap();
+        for (file, text) in files {
+            fs::write(dir.join("ept-hierarchy").join(format!("{}.json", file)), text).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn delegated_subtrees_are_followed() {
+        let dir = hierarchy_dir(
+            "subtree",
+            &[("0-0-0-0", r#"{"0-0-0-0": 10, "1-1-0-0": -1}"#), ("1-1-0-0", r#"{"1-1-0-0": 5, "2-2-1-0": 3}"#)],
+        );
+        let mut entries = HashMap::new();
+        let result = read_hierarchy_file(&dir, "0-0-0-0", &mut entries);
+        let _ = fs::remove_dir_all(&dir);
+        result.unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[&(1, 1, 0, 0)], (5, (1, 1, 0, 0)));
+        assert_eq!(entries[&(2, 2, 1, 0)], (3, (2, 2, 1, 0)));
+    }
+
+    #[test]
+    fn files_delegating_to_themselves_are_read_once() {
+        let dir = hierarchy_dir("cycle", &[("0-0-0-0", r#"{"0-0-0-0": -1}"#)]);
+        let mut entries = HashMap::new();
+        let result = read_hierarchy_file(&dir, "0-0-0-0", &mut entries);
+        let _ = fs::remove_dir_all(&dir);
+        result.unwrap();
+        assert!(entries.is_empty());
+    }
+
+    #[test]
+    fn missing_and_malformed_files_fail() {
+        let dir = hierarchy_dir("missing", &[("0-0-0-0", r#"{"1-0-0-0": -1}"#), ("1-0-0-1", "[1, 2")]);
+        let mut entries = HashMap::new();
+        assert!(read_hierarchy_file(&dir, "0-0-0-0", &mut entries).is_err());
+        assert!(read_hierarchy_file(&dir, "1-0-0-1", &mut entries).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,345 @@
+use std::fs;
+
+use super::copc_writer::{write_copc, CopcPoints};
+use super::crs::projection_vlrs;
+use super::disk_octree::BuildLimits;
+use super::parser::PointcloudParser;
+use super::reader::PointReader;
+use super::types::{
+    BoundingBox3D, ExportFormat, ExportOptions, HeaderIssue, PointBuffer, PointColumns, PointcloudMetadata,
+};
+use super::writer::{LasLayout, LasWriter};
+
+/// Points read from the source per batch
+const EXPORT_BATCH_SIZE: u64 = 100_000;
+
+/// Coordinate resolution for sources that do not declare one
+const DEFAULT_SCALE: f64 = 0.001;
+
+/// Write the points of `reader` that pass the options' filter to a LAS, LAZ
+/// or COPC file. COPC points beyond `limits` are indexed on disk. `progress`
+/// gets the number of source points read so far and `warnings` what the output
+/// could not keep. Returns the number of points written; a failed export
+/// removes the partial file.
+pub fn export_las(
+    reader: &dyn PointReader,
+    metadata: &PointcloudMetadata,
+    output_path: &str,
+    options: &ExportOptions,
+    limits: &BuildLimits,
+    warnings: &mut Vec<HeaderIssue>,
+    progress: &mut dyn FnMut(u64),
+) -> Result<u64, String> {
+    let result = write_las(reader, metadata, output_path, options, limits, warnings, progress);
+    if result.is_err() {
+        let _ = fs::remove_file(output_path);
+    }
+    result
+}
+
+fn write_las(
+    reader: &dyn PointReader,
+    metadata: &PointcloudMetadata,
+    output_path: &str,
+    options: &ExportOptions,
+    limits: &BuildLimits,
+    warnings: &mut Vec<HeaderIssue>,
+    progress: &mut dyn FnMut(u64),
+) -> Result<u64, String> {
+    // Reading the points to measure their bounds is the first half of the progress
+    let (bounds, measured_points) = if needs_measuring(reader, metadata) {
+        let (bounds, read) = measure_bounds(reader, &mut |read| progress(read / 2))?;
+        (bounds, Some(read))
+    } else {
+        (metadata.bounds.clone(), None)
+    };
+    let mut progress = |read: u64| progress(measured_points.map_or(read, |before| (before + read) / 2));
+
+    let layout = las_layout(reader, metadata, &bounds, options, warnings)?;
+    if options.format == ExportFormat::Copc {
+        return write_copc_from(reader, layout, output_path, options, limits, &mut progress);
+    }
+    let mut writer = LasWriter::create(output_path, layout)?;
+    let filter = options.filter.clone().unwrap_or_default();
+
+    let mut error = None;
+    reader.stream_points(EXPORT_BATCH_SIZE, &mut |batch, offset| {
+        let accepted = (0..batch.len()).filter(|&i| filter.accepts(&batch.points[i]));
+        if let Err(e) = writer.write_points(batch, accepted) {
+            error = Some(e);
+            return false;
+        }
+        progress(offset + batch.len() as u64);
+        true
+    })?;
+    if let Some(e) = error {
+        return Err(e);
+    }
+
+    writer.finish()
+}
+
+/// COPC nodes are laid out over the whole cloud, so the points that pass the
+/// filter are collected batch by batch, on disk beyond the memory budget, and
+/// indexed before anything is written
+fn write_copc_from(
+    reader: &dyn PointReader,
+    layout: LasLayout,
+    output_path: &str,
+    options: &ExportOptions,
+    limits: &BuildLimits,
+    progress: &mut dyn FnMut(u64),
+) -> Result<u64, String> {
+    let filter = options.filter.clone().unwrap_or_default();
+    let columns = PointColumns { wave_packets: false, extra_count: layout.extra_attributes.len() };
+    let mut points = CopcPoints::new(columns, limits.clone());
+    let mut error = None;
+    reader.stream_points(EXPORT_BATCH_SIZE, &mut |batch, offset| {
+        let mut accepted = PointBuffer::with_capacity(batch.columns(), batch.len());
+        for i in (0..batch.len()).filter(|&i| filter.accepts(&batch.points[i])) {
+            accepted.push_from(batch, i);
+        }
+        if let Err(e) = points.push(&accepted) {
+            error = Some(e);
+            return false;
+        }
+        progress(offset + batch.len() as u64);
+        true
+    })?;
+    if let Some(e) = error {
+        return Err(e);
+    }
+    write_copc(output_path, layout, points)
+}
+
+/// Whether the bounds must be measured from the points: the source declares none
+/// and the cloud is not indexed yet, or its points fall outside the declared ones
+fn needs_measuring(reader: &dyn PointReader, metadata: &PointcloudMetadata) -> bool {
+    let b = &metadata.bounds;
+    let unknown = reader.bounds().is_none()
+        && [b.min_x, b.min_y, b.min_z, b.max_x, b.max_y, b.max_z].iter().all(|v| *v == 0.0);
+    unknown || metadata.validation.iter().any(|issue| issue.field == "bounds")
+}
+
+/// Read every point once for the bounds. Returns them with the number of points read.
+fn measure_bounds(reader: &dyn PointReader, progress: &mut dyn FnMut(u64)) -> Result<(BoundingBox3D, u64), String> {
+    let mut bounds = BoundingBox3D::new();
+    let mut read = 0;
+    reader.stream_points(EXPORT_BATCH_SIZE, &mut |batch, offset| {
+        for p in &batch.points {
+            bounds.expand(p.x, p.y, p.z);
+        }
+        read = offset + batch.len() as u64;
+        progress(read);
+        true
+    })?;
+    Ok((bounds, read))
+}
+
+/// Pick version, point format, scale and offset for the cloud. Unless asked
+/// otherwise the output is LAS 1.2 with the smallest point format holding the
+/// cloud's attributes, or LAS 1.4 when the source needs it.
+fn las_layout(
+    reader: &dyn PointReader,
+    metadata: &PointcloudMetadata,
+    bounds: &BoundingBox3D,
+    options: &ExportOptions,
+    warnings: &mut Vec<HeaderIssue>,
+) -> Result<LasLayout, String> {
+    // Waveform data is kept by LAS and LAZ, in the LAS 1.4 formats with wave packets
+    let waveform = match options.format {
+        ExportFormat::Copc => None,
+        _ => reader.waveform(&metadata.file_path),
+    };
+    let needs_14 = options.format == ExportFormat::Copc
+        || waveform.is_some()
+        || metadata.has_nir
+        || (metadata.las_version == "1.4" && metadata.point_record_format >= 6)
+        || metadata.total_points > u32::MAX as u64
+        || options.point_format.is_some_and(|format| format >= 4);
+    let version_minor = match options.las_version.as_deref() {
+        Some("1.2") if options.format == ExportFormat::Copc => {
+            return Err("COPC requires LAS 1.4".into());
+        }
+        Some("1.2") => 2,
+        Some("1.4") => 4,
+        Some(other) => return Err(format!("Unsupported LAS version for export: {}", other)),
+        None if needs_14 => 4,
+        None => 2,
+    };
+
+    let point_format = match options.point_format {
+        Some(format) => format,
+        None if version_minor >= 4 && waveform.is_some() => {
+            if metadata.has_color || metadata.has_nir {
+                10
+            } else {
+                9
+            }
+        }
+        None if version_minor >= 4 => {
+            if metadata.has_nir {
+                8
+            } else if metadata.has_color {
+                7
+            } else {
+                6
+            }
+        }
+        None => match (metadata.has_gps_time, metadata.has_color) {
+            (false, false) => 0,
+            (true, false) => 1,
+            (false, true) => 2,
+            (true, true) => 3,
+        },
+    };
+
+    let (scale, offset) = scale_and_offset(bounds, options.scale.unwrap_or(reader.scale()))?;
+
+    // Sources without LAS projection records get them from the detected CRS
+    let mut projection = reader.projection_vlrs();
+    if point_format >= 6 {
+        // Point formats 6-10 describe the CRS as OGC WKT only
+        projection.retain(|(id, _)| matches!(id, 2111 | 2112));
+        if !projection.iter().any(|(id, _)| *id == 2112) {
+            projection = metadata.crs.as_ref().map(projection_vlrs).unwrap_or_default();
+        }
+        // Without a CRS database there is no WKT for an EPSG code, so a CRS
+        // known by its GeoTIFF keys only is left out
+        if projection.iter().any(|(id, _)| *id == 34735) {
+            projection.clear();
+            let crs = match metadata.crs.as_ref().and_then(|crs| crs.epsg) {
+                Some(epsg) => format!("EPSG:{} as GeoTIFF keys", epsg),
+                None => "GeoTIFF keys".to_string(),
+            };
+            warnings.push(HeaderIssue::warning(
+                "crs",
+                "OGC WKT",
+                crs,
+                format!("Point format {} holds the CRS as OGC WKT only; the exported file has no CRS", point_format),
+            ));
+        }
+    } else if projection.is_empty() {
+        projection = metadata.crs.as_ref().map(projection_vlrs).unwrap_or_default();
+        // An EPSG code alone does not say which GeoTIFF key it belongs in
+        if let Some(crs) = metadata.crs.as_ref().filter(|crs| crs.wkt.is_none() && crs.model_type.is_none()) {
+            if let Some(epsg) = crs.horizontal_epsg {
+                warnings.push(HeaderIssue::warning(
+                    "crs",
+                    "GeoTIFF keys",
+                    format!("EPSG:{} of unknown type", epsg),
+                    "The horizontal CRS is not known to be projected or geographic; the exported file leaves it out",
+                ));
+            }
+        }
+    }
+
+    Ok(LasLayout {
+        version_minor,
+        point_format,
+        compress: options.format != ExportFormat::Las,
+        scale,
+        offset,
+        extra_attributes: metadata.extra_attributes.clone(),
+        projection_vlrs: projection,
+        leading_vlrs: Vec::new(),
+        variable_chunks: false,
+        waveform: waveform.filter(|_| PointcloudParser::record_layout(point_format).wave_packet.is_some()),
+        standard_gps_time: reader.standard_gps_time(),
+    })
+}
+
+/// Offset at the center of the bounds, rounded to whole units, and a scale
+/// coarsened by powers of ten until the bounds fit the 32-bit coordinates
+fn scale_and_offset(bounds: &BoundingBox3D, requested: [f64; 3]) -> Result<([f64; 3], [f64; 3]), String> {
+    let valid = bounds.min_x <= bounds.max_x && bounds.min_y <= bounds.max_y && bounds.min_z <= bounds.max_z;
+    let center = if valid { bounds.center() } else { [0.0; 3] };
+    let half = if valid { bounds.size().map(|s| s * 0.5) } else { [0.0; 3] };
+
+    let mut scale = [0.0; 3];
+    let mut offset = [0.0; 3];
+    for axis in 0..3 {
+        offset[axis] = center[axis].round();
+        let reach = half[axis] + (center[axis] - offset[axis]).abs();
+        let mut s = if requested[axis] > 0.0 { requested[axis] } else { DEFAULT_SCALE };
+        while reach / s > i32::MAX as f64 {
+            s *= 10.0;
+            if !s.is_finite() {
+                return Err("Point coordinates are out of range".into());
+            }
+        }
+        scale[axis] = s;
+    }
+    Ok((scale, offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointcloud::copc::CopcReader;
+    use crate::pointcloud::types::{CrsInfo, OpenOptions, PointRecord};
+
+    #[test]
+    fn geo_key_crs_is_left_out_of_copc() {
+        let source = std::env::temp_dir().join(format!("ops-export-{}-geokeys.las", std::process::id()));
+        let output = std::env::temp_dir().join(format!("ops-export-{}-geokeys.copc.laz", std::process::id()));
+        let crs = CrsInfo {
+            epsg: Some(28992),
+            horizontal_epsg: Some(28992),
+            vertical_epsg: None,
+            model_type: Some(1),
+            name: None,
+            wkt: None,
+            source: "geotiff".into(),
+        };
+        let layout = LasLayout {
+            version_minor: 2,
+            point_format: 1,
+            compress: false,
+            scale: [0.001; 3],
+            offset: [150_000.0, 450_000.0, 0.0],
+            extra_attributes: Vec::new(),
+            projection_vlrs: projection_vlrs(&crs),
+            leading_vlrs: Vec::new(),
+            variable_chunks: false,
+            waveform: None,
+            standard_gps_time: false,
+        };
+        let points: Vec<PointRecord> = (0..1000)
+            .map(|i| PointRecord {
+                x: 150_000.0 + i as f64 * 0.1,
+                y: 450_000.0 + (i % 10) as f64,
+                z: 1.0,
+                ..PointRecord::default()
+            })
+            .collect();
+        let points = PointBuffer::from(points);
+        let mut writer = LasWriter::create(&source, layout).unwrap();
+        writer.write_points(&points, 0..points.len()).unwrap();
+        writer.finish().unwrap();
+
+        let reader = PointcloudParser::open(&source, &OpenOptions::default()).unwrap();
+        let metadata = reader.metadata("pc_1", &source.to_string_lossy());
+        assert_eq!(metadata.crs.as_ref().and_then(|crs| crs.epsg), Some(28992));
+        let options = ExportOptions {
+            format: ExportFormat::Copc,
+            las_version: None,
+            point_format: None,
+            scale: None,
+            filter: None,
+        };
+        let limits = BuildLimits { memory_budget: 1 << 30, work_dir: std::env::temp_dir() };
+        let mut warnings = Vec::new();
+        let output_path = output.to_string_lossy();
+        let written = export_las(&reader, &metadata, &output_path, &options, &limits, &mut warnings, &mut |_| {});
+        let copc = CopcReader::open(&output, &OpenOptions::default());
+        let _ = fs::remove_file(&source);
+        let _ = fs::remove_file(&output);
+
+        assert_eq!(written.unwrap(), 1000);
+        assert!(warnings.iter().any(|w| w.field == "crs" && w.actual.contains("28992")));
+        let copc = copc.unwrap();
+        assert_eq!(copc.total_points(), 1000);
+        assert!(copc.metadata("pc_2", "").crs.is_none());
+    }
+}
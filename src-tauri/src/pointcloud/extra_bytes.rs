@@ -0,0 +1,350 @@
+use super::types::ExtraAttributeInfo;
+use super::vlr::{read_fixed_string, write_fixed_string};
+
+/// Size of one EXTRA_BYTES descriptor in the VLR payload
+const DESCRIPTOR_SIZE: usize = 192;
+
+/// Scalar type of an extra bytes value (LAS 1.4 data types 1-10)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExtraBytesType {
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    U64,
+    I64,
+    F32,
+    F64,
+}
+
+impl ExtraBytesType {
+    fn from_code(code: u8) -> Option<Self> {
+        Some(match code {
+            1 => Self::U8,
+            2 => Self::I8,
+            3 => Self::U16,
+            4 => Self::I16,
+            5 => Self::U32,
+            6 => Self::I32,
+            7 => Self::U64,
+            8 => Self::I64,
+            9 => Self::F32,
+            10 => Self::F64,
+            _ => return None,
+        })
+    }
+
+    /// Inverse of `name`
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "u8" => Self::U8,
+            "i8" => Self::I8,
+            "u16" => Self::U16,
+            "i16" => Self::I16,
+            "u32" => Self::U32,
+            "i32" => Self::I32,
+            "u64" => Self::U64,
+            "i64" => Self::I64,
+            "f32" => Self::F32,
+            "f64" => Self::F64,
+            _ => return None,
+        })
+    }
+
+    fn code(self) -> u8 {
+        match self {
+            Self::U8 => 1,
+            Self::I8 => 2,
+            Self::U16 => 3,
+            Self::I16 => 4,
+            Self::U32 => 5,
+            Self::I32 => 6,
+            Self::U64 => 7,
+            Self::I64 => 8,
+            Self::F32 => 9,
+            Self::F64 => 10,
+        }
+    }
+
+    pub fn size(self) -> usize {
+        match self {
+            Self::U8 | Self::I8 => 1,
+            Self::U16 | Self::I16 => 2,
+            Self::U32 | Self::I32 | Self::F32 => 4,
+            Self::U64 | Self::I64 | Self::F64 => 8,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::U8 => "u8",
+            Self::I8 => "i8",
+            Self::U16 => "u16",
+            Self::I16 => "i16",
+            Self::U32 => "u32",
+            Self::I32 => "i32",
+            Self::U64 => "u64",
+            Self::I64 => "i64",
+            Self::F32 => "f32",
+            Self::F64 => "f64",
+        }
+    }
+
+    fn is_float(self) -> bool {
+        matches!(self, Self::F32 | Self::F64)
+    }
+
+    fn is_signed(self) -> bool {
+        matches!(self, Self::I8 | Self::I16 | Self::I32 | Self::I64)
+    }
+
+    /// Read one raw (unscaled) value
+    fn read(self, b: &[u8]) -> f64 {
+        match self {
+            Self::U8 => b[0] as f64,
+            Self::I8 => b[0] as i8 as f64,
+            Self::U16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+            Self::I16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+            Self::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Self::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Self::U64 => u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f64,
+            Self::I64 => i64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f64,
+            Self::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Self::F64 => f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]),
+        }
+    }
+
+    /// Write one raw value, rounding and clamping to the integer types' range
+    fn write(self, value: f64, b: &mut [u8]) {
+        let v = value.round();
+        match self {
+            Self::U8 => b[0] = v as u8,
+            Self::I8 => b[0] = v as i8 as u8,
+            Self::U16 => b[..2].copy_from_slice(&(v as u16).to_le_bytes()),
+            Self::I16 => b[..2].copy_from_slice(&(v as i16).to_le_bytes()),
+            Self::U32 => b[..4].copy_from_slice(&(v as u32).to_le_bytes()),
+            Self::I32 => b[..4].copy_from_slice(&(v as i32).to_le_bytes()),
+            Self::U64 => b[..8].copy_from_slice(&(v as u64).to_le_bytes()),
+            Self::I64 => b[..8].copy_from_slice(&(v as i64).to_le_bytes()),
+            Self::F32 => b[..4].copy_from_slice(&(value as f32).to_le_bytes()),
+            Self::F64 => b[..8].copy_from_slice(&value.to_le_bytes()),
+        }
+    }
+
+    /// Fill a no_data/min/max slot, the inverse of `read_any`
+    fn write_any(self, value: f64, b: &mut [u8]) {
+        let raw = if self.is_float() {
+            value.to_le_bytes()
+        } else if self.is_signed() {
+            (value.round() as i64).to_le_bytes()
+        } else {
+            (value.round() as u64).to_le_bytes()
+        };
+        b[..8].copy_from_slice(&raw);
+    }
+
+    /// Read a no_data/min/max slot: 8 bytes stored as u64, i64 or f64 depending on the type
+    fn read_any(self, b: &[u8]) -> f64 {
+        let raw = [b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]];
+        if self.is_float() {
+            f64::from_le_bytes(raw)
+        } else if self.is_signed() {
+            i64::from_le_bytes(raw) as f64
+        } else {
+            u64::from_le_bytes(raw) as f64
+        }
+    }
+}
+
+/// One per-point value declared in the Extra Bytes VLR. Array descriptors
+/// (deprecated types 11-30) expand into one attribute per element.
+#[derive(Debug, Clone)]
+struct ExtraAttribute {
+    info: ExtraAttributeInfo,
+    data_type: ExtraBytesType,
+    /// Byte offset within the extra bytes section of a point record
+    byte_offset: usize,
+    /// Raw no_data value, compared before scale/offset are applied
+    raw_no_data: Option<f64>,
+}
+
+/// Decoded EXTRA_BYTES descriptors (VLR user id "LASF_Spec", record id 4)
+#[derive(Debug, Clone, Default)]
+pub struct ExtraBytesLayout {
+    attributes: Vec<ExtraAttribute>,
+    /// Bytes per point covered by the descriptors, including undocumented ones
+    size: usize,
+}
+
+impl ExtraBytesLayout {
+    /// Parse the descriptor list from the VLR payload
+    pub fn parse(payload: &[u8]) -> Self {
+        let mut layout = Self::default();
+
+        for d in payload.chunks_exact(DESCRIPTOR_SIZE) {
+            let code = d[2];
+            let options = d[3];
+            let name = read_fixed_string(&d[4..36]);
+            let description = read_fixed_string(&d[160..192]);
+
+            // Type 0: undocumented extra bytes, the options field holds the byte count
+            if code == 0 {
+                layout.size += options as usize;
+                continue;
+            }
+
+            let (base, elements) = match code {
+                1..=10 => (code, 1),
+                11..=20 => (code - 10, 2),
+                21..=30 => (code - 20, 3),
+                _ => break, // unknown type: we can no longer tell where later fields start
+            };
+            let data_type = match ExtraBytesType::from_code(base) {
+                Some(t) => t,
+                None => break,
+            };
+
+            let f64_at = |o: usize| {
+                f64::from_le_bytes([d[o], d[o + 1], d[o + 2], d[o + 3], d[o + 4], d[o + 5], d[o + 6], d[o + 7]])
+            };
+
+            for i in 0..elements {
+                let slot = i * 8;
+                let no_data = (options & 0x01 != 0).then(|| data_type.read_any(&d[40 + slot..48 + slot]));
+                let min = (options & 0x02 != 0).then(|| data_type.read_any(&d[64 + slot..72 + slot]));
+                let max = (options & 0x04 != 0).then(|| data_type.read_any(&d[88 + slot..96 + slot]));
+                let scale = if options & 0x08 != 0 { f64_at(112 + slot) } else { 1.0 };
+                let offset = if options & 0x10 != 0 { f64_at(136 + slot) } else { 0.0 };
+
+                let element_name = if elements > 1 {
+                    format!("{}[{}]", name, i)
+                } else {
+                    name.clone()
+                };
+
+                layout.attributes.push(ExtraAttribute {
+                    info: ExtraAttributeInfo {
+                        name: element_name,
+                        description: description.clone(),
+                        data_type: data_type.name().to_string(),
+                        scale,
+                        offset,
+                        no_data,
+                        min,
+                        max,
+                    },
+                    data_type,
+                    byte_offset: layout.size,
+                    raw_no_data: no_data,
+                });
+                layout.size += data_type.size();
+            }
+        }
+
+        layout
+    }
+
+    /// Layout for writing the given attributes, one scalar descriptor each.
+    /// Types without an Extra Bytes equivalent are stored as f64. Like `parse`,
+    /// no_data, min and max are raw values, before scale and offset.
+    pub fn from_infos(infos: &[ExtraAttributeInfo]) -> Self {
+        let mut layout = Self::default();
+        for info in infos {
+            let data_type = ExtraBytesType::from_name(&info.data_type).unwrap_or(ExtraBytesType::F64);
+            layout.attributes.push(ExtraAttribute {
+                info: info.clone(),
+                data_type,
+                byte_offset: layout.size,
+                raw_no_data: info.no_data,
+            });
+            layout.size += data_type.size();
+        }
+        layout
+    }
+
+    /// Extra Bytes VLR payload describing this layout
+    pub fn to_vlr(&self) -> Vec<u8> {
+        let mut payload = vec![0u8; self.attributes.len() * DESCRIPTOR_SIZE];
+        for (a, d) in self.attributes.iter().zip(payload.chunks_exact_mut(DESCRIPTOR_SIZE)) {
+            let info = &a.info;
+            let mut options = 0u8;
+            if let Some(no_data) = a.raw_no_data {
+                options |= 0x01;
+                a.data_type.write_any(no_data, &mut d[40..48]);
+            }
+            if let Some(min) = info.min {
+                options |= 0x02;
+                a.data_type.write_any(min, &mut d[64..72]);
+            }
+            if let Some(max) = info.max {
+                options |= 0x04;
+                a.data_type.write_any(max, &mut d[88..96]);
+            }
+            if info.scale != 1.0 {
+                options |= 0x08;
+                d[112..120].copy_from_slice(&info.scale.to_le_bytes());
+            }
+            if info.offset != 0.0 {
+                options |= 0x10;
+                d[136..144].copy_from_slice(&info.offset.to_le_bytes());
+            }
+            d[2] = a.data_type.code();
+            d[3] = options;
+            write_fixed_string(&info.name, &mut d[4..36]);
+            write_fixed_string(&info.description, &mut d[160..192]);
+        }
+        payload
+    }
+
+    /// Encode attribute values into the extra bytes section of a point record;
+    /// `value` gives the value of the attribute at an index in `infos` order.
+    /// NaN is written as no_data; without one, integer types get 0.
+    pub fn encode(&self, value: impl Fn(usize) -> f64, extra: &mut [u8]) {
+        for (i, a) in self.attributes.iter().enumerate() {
+            let value = value(i);
+            let raw = if value.is_nan() {
+                a.raw_no_data.unwrap_or(if a.data_type.is_float() { f64::NAN } else { 0.0 })
+            } else {
+                (value - a.info.offset) / a.info.scale
+            };
+            a.data_type.write(raw, &mut extra[a.byte_offset..a.byte_offset + a.data_type.size()]);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.attributes.is_empty()
+    }
+
+    /// Number of attributes
+    pub fn len(&self) -> usize {
+        self.attributes.len()
+    }
+
+    /// Bytes per point covered by the descriptors
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Attribute descriptions in the order values are stored per point
+    pub fn infos(&self) -> Vec<ExtraAttributeInfo> {
+        self.attributes.iter().map(|a| a.info.clone()).collect()
+    }
+
+    /// Decode attribute `index` from the extra bytes section of one point record.
+    /// The value has scale/offset applied; no_data (or missing bytes) decode as NaN.
+    pub fn decode(&self, index: usize, extra: &[u8]) -> f64 {
+        let a = &self.attributes[index];
+        let end = a.byte_offset + a.data_type.size();
+        if end > extra.len() {
+            return f64::NAN;
+        }
+        let raw = a.data_type.read(&extra[a.byte_offset..end]);
+        if a.raw_no_data == Some(raw) {
+            f64::NAN
+        } else {
+            raw * a.info.scale + a.info.offset
+        }
+    }
+}
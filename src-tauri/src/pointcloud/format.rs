@@ -0,0 +1,158 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use super::vlr::read_vlrs;
+
+/// How many leading bytes are read to identify a file
+const SNIFF_LEN: usize = 64 * 1024;
+
+/// Pointcloud file formats the backend can read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointcloudFormat {
+    Las,
+    Laz,
+    /// Cloud Optimized Point Cloud: LAZ 1.4 with an octree hierarchy
+    Copc,
+    E57,
+    Ply,
+    Pts,
+    Ptx,
+    Xyz,
+    Csv,
+    Pcd,
+    /// Entwine Point Tile dataset: a directory with ept.json
+    Ept,
+    /// PotreeConverter 2 output: metadata.json, hierarchy.bin and octree.bin
+    Potree,
+}
+
+impl PointcloudFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Las => "LAS",
+            Self::Laz => "LAZ",
+            Self::Copc => "COPC",
+            Self::E57 => "E57",
+            Self::Ply => "PLY",
+            Self::Pts => "PTS",
+            Self::Ptx => "PTX",
+            Self::Xyz => "XYZ",
+            Self::Csv => "CSV",
+            Self::Pcd => "PCD",
+            Self::Ept => "EPT",
+            Self::Potree => "Potree",
+        }
+    }
+}
+
+/// Identify a pointcloud file from its content. ASCII formats without a
+/// signature fall back to the file extension.
+pub fn detect_format<P: AsRef<Path>>(path: P) -> Result<PointcloudFormat, String> {
+    let path = path.as_ref();
+    if is_ept(path) {
+        return Ok(PointcloudFormat::Ept);
+    }
+    if is_potree(path) {
+        return Ok(PointcloudFormat::Potree);
+    }
+    let mut file = File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
+
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    file.by_ref()
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut head)
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+
+    sniff_format(&head).or_else(|| format_from_extension(path)).ok_or_else(|| {
+        format!(
+            "Unrecognized pointcloud format: {}",
+            path.file_name().and_then(|n| n.to_str()).unwrap_or("unknown")
+        )
+    })
+}
+
+/// An EPT dataset is opened by its directory or its ept.json
+fn is_ept(path: &Path) -> bool {
+    if path.is_dir() {
+        return path.join("ept.json").is_file();
+    }
+    path.file_name().is_some_and(|n| n == "ept.json")
+}
+
+/// A Potree 2.0 folder is opened by its directory or its metadata.json
+fn is_potree(path: &Path) -> bool {
+    let dir = if path.is_dir() {
+        path
+    } else if path.file_name().is_some_and(|n| n == "metadata.json") {
+        path.parent().unwrap_or(Path::new("."))
+    } else {
+        return false;
+    };
+    dir.join("hierarchy.bin").is_file() && dir.join("octree.bin").is_file()
+}
+
+/// Identify a format from the leading bytes of a file
+pub fn sniff_format(head: &[u8]) -> Option<PointcloudFormat> {
+    if head.starts_with(b"LASF") {
+        return Some(if las_is_copc(head) {
+            PointcloudFormat::Copc
+        } else if las_is_compressed(head) {
+            PointcloudFormat::Laz
+        } else {
+            PointcloudFormat::Las
+        });
+    }
+    if head.starts_with(b"ASTM-E57") {
+        return Some(PointcloudFormat::E57);
+    }
+    if head.starts_with(b"ply\n") || head.starts_with(b"ply\r\n") {
+        return Some(PointcloudFormat::Ply);
+    }
+    if head.starts_with(b"# .PCD") || head.starts_with(b"VERSION") {
+        return Some(PointcloudFormat::Pcd);
+    }
+    None
+}
+
+/// Formats that have no signature, identified by extension
+fn format_from_extension(path: &Path) -> Option<PointcloudFormat> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    Some(match ext.as_str() {
+        "pts" => PointcloudFormat::Pts,
+        "ptx" => PointcloudFormat::Ptx,
+        "xyz" | "txt" | "asc" => PointcloudFormat::Xyz,
+        "csv" => PointcloudFormat::Csv,
+        "pcd" => PointcloudFormat::Pcd,
+        _ => return None,
+    })
+}
+
+/// Whether LAS data (at least the header and VLRs) holds LASzip-compressed points:
+/// either compression bit of the point format byte is set, or a LASzip VLR is present.
+pub fn las_is_compressed(data: &[u8]) -> bool {
+    if data.len() < 227 {
+        return false;
+    }
+    if data[104] & 0xC0 != 0 {
+        return true;
+    }
+
+    let offset_to_points = u32::from_le_bytes([data[96], data[97], data[98], data[99]]) as usize;
+    read_vlrs(data, offset_to_points)
+        .iter()
+        .any(|v| v.is("laszip encoded", 22204))
+}
+
+/// Whether LAS data starts with the COPC info VLR, which the COPC spec
+/// requires to be the first VLR of the file
+pub fn las_is_copc(data: &[u8]) -> bool {
+    if data.len() < 227 {
+        return false;
+    }
+
+    let offset_to_points = u32::from_le_bytes([data[96], data[97], data[98], data[99]]) as usize;
+    read_vlrs(data, offset_to_points)
+        .first()
+        .is_some_and(|v| v.is("copc", 1))
+}
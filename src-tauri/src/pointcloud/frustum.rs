@@ -0,0 +1,232 @@
+use super::types::{BoundingBox3D, CameraProjection, CameraState};
+
+/// Nodes closer than this to a perspective camera fill the screen
+const MIN_DISTANCE: f64 = 0.001;
+
+/// Up direction when the camera sends none
+const DEFAULT_UP: [f64; 3] = [0.0, 0.0, 1.0];
+
+/// The volume a camera sees, bounded by planes facing inwards
+pub struct Frustum {
+    /// Normal and offset of each plane: `p` is inside when `n·p + d >= 0`
+    planes: Vec<([f64; 3], f64)>,
+    position: [f64; 3],
+    projection: CameraProjection,
+    /// Pixels per world unit: at unit distance for a perspective camera,
+    /// anywhere in view for an orthographic one
+    pixels_per_unit: f64,
+}
+
+impl Frustum {
+    /// The frustum of a camera looking from its position at its target.
+    /// A camera without a view direction culls nothing.
+    pub fn new(camera: &CameraState) -> Self {
+        let position = camera.position;
+        let half_fov = (camera.fov.to_radians() / 2.0).tan();
+        let to_target = sub(camera.target, position);
+        let distance = length(to_target);
+
+        let pixels_per_unit = match camera.projection {
+            CameraProjection::Perspective => camera.screen_height / (2.0 * half_fov),
+            CameraProjection::Orthographic => {
+                let height = camera.ortho_height.unwrap_or(2.0 * distance * half_fov);
+                camera.screen_height / height
+            }
+        };
+        let mut frustum = Self { planes: Vec::new(), position, projection: camera.projection, pixels_per_unit };
+        if distance <= 0.0 {
+            return frustum;
+        }
+
+        // View axes: right and up complete the view direction, whatever up
+        // the camera sends
+        let forward = scale(to_target, 1.0 / distance);
+        let mut right = cross(forward, camera.up.unwrap_or(DEFAULT_UP));
+        if length(right) < 1e-9 {
+            let fallback = if forward[2].abs() < 0.9 { [0.0, 0.0, 1.0] } else { [0.0, 1.0, 0.0] };
+            right = cross(forward, fallback);
+        }
+        let right = scale(right, 1.0 / length(right));
+        let up = cross(right, forward);
+
+        // Sides as normals and offsets relative to the camera position
+        let sides = match camera.projection {
+            CameraProjection::Perspective => {
+                let (half_h, half_w) = (half_fov, half_fov * camera.aspect);
+                [
+                    (add(scale(forward, half_w), scale(right, -1.0)), 0.0),
+                    (add(scale(forward, half_w), right), 0.0),
+                    (add(scale(forward, half_h), scale(up, -1.0)), 0.0),
+                    (add(scale(forward, half_h), up), 0.0),
+                ]
+            }
+            CameraProjection::Orthographic => {
+                let half_h = 0.5 * camera.screen_height / pixels_per_unit;
+                let half_w = half_h * camera.aspect;
+                [(scale(right, -1.0), half_w), (right, half_w), (scale(up, -1.0), half_h), (up, half_h)]
+            }
+        };
+        let mut planes = sides.to_vec();
+        if let Some(near) = camera.near {
+            planes.push((forward, -near));
+        }
+        if let Some(far) = camera.far {
+            planes.push((scale(forward, -1.0), far));
+        }
+        frustum.planes = planes.into_iter().map(|(n, offset)| (n, offset - dot(n, position))).collect();
+        frustum
+    }
+
+    /// Whether any part of the box may be in view: no plane has the box's
+    /// corner furthest along its normal outside
+    pub fn intersects(&self, bounds: &BoundingBox3D) -> bool {
+        self.planes.iter().all(|&(n, d)| {
+            let corner = [
+                if n[0] >= 0.0 { bounds.max_x } else { bounds.min_x },
+                if n[1] >= 0.0 { bounds.max_y } else { bounds.min_y },
+                if n[2] >= 0.0 { bounds.max_z } else { bounds.min_z },
+            ];
+            dot(n, corner) + d >= 0.0
+        })
+    }
+
+    /// Pixels the box's largest extent covers on screen
+    pub fn screen_size(&self, bounds: &BoundingBox3D) -> f64 {
+        let extent = bounds.max_extent();
+        match self.projection {
+            CameraProjection::Perspective => {
+                let distance = length(sub(bounds.center(), self.position));
+                if distance > MIN_DISTANCE {
+                    extent / distance * self.pixels_per_unit
+                } else {
+                    f64::MAX
+                }
+            }
+            CameraProjection::Orthographic => extent * self.pixels_per_unit,
+        }
+    }
+}
+
+fn add(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn scale(a: [f64; 3], s: f64) -> [f64; 3] {
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn length(a: [f64; 3]) -> f64 {
+    dot(a, a).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A camera at the origin looking along x with a 90° view
+    fn camera(projection: CameraProjection) -> CameraState {
+        CameraState {
+            position: [0.0, 0.0, 0.0],
+            target: [10.0, 0.0, 0.0],
+            up: None,
+            fov: 90.0,
+            aspect: 1.0,
+            near: None,
+            far: None,
+            projection,
+            ortho_height: None,
+            screen_height: 1000.0,
+        }
+    }
+
+    /// A cube of side `size` around `center`
+    fn cube(center: [f64; 3], size: f64) -> BoundingBox3D {
+        let h = size / 2.0;
+        BoundingBox3D {
+            min_x: center[0] - h,
+            min_y: center[1] - h,
+            min_z: center[2] - h,
+            max_x: center[0] + h,
+            max_y: center[1] + h,
+            max_z: center[2] + h,
+        }
+    }
+
+    #[test]
+    fn perspective_culls_boxes_out_of_view() {
+        let frustum = Frustum::new(&camera(CameraProjection::Perspective));
+        assert!(frustum.intersects(&cube([5.0, 0.0, 0.0], 1.0)));
+        // Straddling a side plane still counts
+        assert!(frustum.intersects(&cube([5.0, 5.0, 0.0], 1.0)));
+        assert!(!frustum.intersects(&cube([-5.0, 0.0, 0.0], 1.0)));
+        assert!(!frustum.intersects(&cube([5.0, 8.0, 0.0], 1.0)));
+        assert!(!frustum.intersects(&cube([5.0, 0.0, -8.0], 1.0)));
+        // The box around the camera is always in view
+        assert!(frustum.intersects(&cube([0.0, 0.0, 0.0], 1.0)));
+    }
+
+    #[test]
+    fn near_and_far_planes_cull() {
+        let mut camera = camera(CameraProjection::Perspective);
+        camera.near = Some(2.0);
+        camera.far = Some(20.0);
+        let frustum = Frustum::new(&camera);
+        assert!(frustum.intersects(&cube([10.0, 0.0, 0.0], 1.0)));
+        assert!(!frustum.intersects(&cube([1.0, 0.0, 0.0], 1.0)));
+        assert!(!frustum.intersects(&cube([30.0, 0.0, 0.0], 1.0)));
+    }
+
+    #[test]
+    fn orthographic_culls_outside_the_view_height() {
+        let mut camera = camera(CameraProjection::Orthographic);
+        camera.ortho_height = Some(10.0);
+        let frustum = Frustum::new(&camera);
+        assert!(frustum.intersects(&cube([50.0, 4.0, 0.0], 1.0)));
+        assert!(frustum.intersects(&cube([-50.0, 0.0, 0.0], 1.0)));
+        assert!(!frustum.intersects(&cube([50.0, 7.0, 0.0], 1.0)));
+        assert!(!frustum.intersects(&cube([50.0, 0.0, -7.0], 1.0)));
+        // Size on screen does not change with distance
+        assert_eq!(frustum.screen_size(&cube([5.0, 0.0, 0.0], 1.0)), 100.0);
+        assert_eq!(frustum.screen_size(&cube([50.0, 0.0, 0.0], 1.0)), 100.0);
+    }
+
+    #[test]
+    fn perspective_screen_size_shrinks_with_distance() {
+        let frustum = Frustum::new(&camera(CameraProjection::Perspective));
+        let near = frustum.screen_size(&cube([5.0, 0.0, 0.0], 1.0));
+        let far = frustum.screen_size(&cube([10.0, 0.0, 0.0], 1.0));
+        assert!((near - 100.0).abs() < 1e-9);
+        assert!((far - 50.0).abs() < 1e-9);
+        assert_eq!(frustum.screen_size(&cube([0.0, 0.0, 0.0], 1.0)), f64::MAX);
+    }
+
+    #[test]
+    fn camera_without_a_view_direction_culls_nothing() {
+        let mut camera = camera(CameraProjection::Perspective);
+        camera.target = camera.position;
+        let frustum = Frustum::new(&camera);
+        assert!(frustum.intersects(&cube([-50.0, 30.0, 0.0], 1.0)));
+    }
+
+    #[test]
+    fn up_along_the_view_direction_still_culls() {
+        let mut camera = camera(CameraProjection::Perspective);
+        camera.up = Some([1.0, 0.0, 0.0]);
+        let frustum = Frustum::new(&camera);
+        assert!(frustum.intersects(&cube([5.0, 0.0, 0.0], 1.0)));
+        assert!(!frustum.intersects(&cube([-5.0, 0.0, 0.0], 1.0)));
+        assert!(!frustum.intersects(&cube([5.0, 8.0, 0.0], 1.0)));
+    }
+}
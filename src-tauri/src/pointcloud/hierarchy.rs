@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+
+use super::octree::LodNode;
+use super::types::BoundingBox3D;
+
+/// Octree cell as stored by COPC and EPT: depth, then x/y/z cell index at that depth
+pub type VoxelKey = (i32, i32, i32, i32);
+
+/// Parse an EPT style "D-X-Y-Z" key
+pub fn parse_voxel_key(text: &str) -> Option<VoxelKey> {
+    let mut parts = text.split('-').map(|p| p.parse::<i32>().ok());
+    let key = (parts.next()??, parts.next()??, parts.next()??, parts.next()??);
+    parts.next().is_none().then_some(key)
+}
+
+/// A node of a hierarchy read from the file. The points stay on disk;
+/// `data` locates them.
+pub struct HierarchyNode<T> {
+    pub node_id: String,
+    pub bounds: BoundingBox3D,
+    pub level: u8,
+    pub point_count: u32,
+    pub data: T,
+    pub children: [Option<Box<HierarchyNode<T>>>; 8],
+}
+
+impl<T> LodNode for HierarchyNode<T> {
+    fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    fn bounds(&self) -> &BoundingBox3D {
+        &self.bounds
+    }
+
+    fn level(&self) -> u8 {
+        self.level
+    }
+
+    fn point_count(&self) -> u32 {
+        self.point_count
+    }
+
+    fn children(&self) -> &[Option<Box<Self>>; 8] {
+        &self.children
+    }
+}
+
+/// Build the node tree below `key` from the hierarchy entries (point count and
+/// location per key). Node IDs follow the in-memory octree, one octant digit per
+/// level with bit 0 the upper x half, bit 1 upper y and bit 2 upper z, so the
+/// frontend addresses these nodes the same way.
+pub fn build_hierarchy<T: Copy>(
+    key: VoxelKey,
+    node_id: String,
+    bounds: BoundingBox3D,
+    entries: &HashMap<VoxelKey, (u32, T)>,
+) -> Option<Box<HierarchyNode<T>>> {
+    let &(point_count, data) = entries.get(&key)?;
+    let (d, x, y, z) = key;
+
+    let mut children: [Option<Box<HierarchyNode<T>>>; 8] = Default::default();
+    for (octant, child) in children.iter_mut().enumerate() {
+        let o = octant as i32;
+        let child_key = (d + 1, 2 * x + (o & 1), 2 * y + ((o >> 1) & 1), 2 * z + ((o >> 2) & 1));
+        *child = build_hierarchy(child_key, format!("{}{}", node_id, octant), bounds.octant(octant as u8), entries);
+    }
+
+    Some(Box::new(HierarchyNode {
+        node_id,
+        bounds,
+        level: d as u8,
+        point_count,
+        data,
+        children,
+    }))
+}
@@ -1,36 +1,70 @@
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex, RwLock};
 
+use super::ascii::AsciiReader;
+use super::cache::{record_columns, OctreeCache};
+use super::copc::CopcReader;
+use super::disk_octree::{point_memory, remove_stale_work_dirs, BuildLimits, DiskOctree, PointSpill};
+use super::e57::E57Reader;
+use super::ept::EptReader;
+use super::export::export_las;
+use super::tiles::export_tiles;
+use super::format::{detect_format, PointcloudFormat};
 use super::parser::PointcloudParser;
-use super::octree::Octree;
+use super::pcd::PcdReader;
+use super::ply::PlyReader;
+use super::potree::PotreeReader;
+use super::reader::PointReader;
+use super::octree::{BuiltOctree, NodeSource, OctreeBuilder};
 use super::types::{
-    CameraState, IndexProgress, OctreeNodeInfo, PointChunk, PointcloudMetadata,
+    BoundingBox3D, CacheInfo, CameraState, ExportOptions, HeaderIssue, IndexProgress, OctreeNodeInfo, OpenOptions,
+    PointChunk, PointcloudMetadata, TilesExportOptions, VlrInfo,
 };
 
+/// Memory an octree build may use until a budget is set
+const DEFAULT_MEMORY_BUDGET: u64 = 8 << 30;
+
 /// Lifecycle state for a single loaded pointcloud
 struct PointcloudEntry {
     metadata: PointcloudMetadata,
-    octree: Option<Octree>,
+    vlrs: Vec<VlrInfo>,
+    /// Options the file was opened with, to read it again for export
+    options: OpenOptions,
+    /// Node index: built in the background, or read from the file (COPC, EPT, Potree)
+    octree: Option<Arc<dyn NodeSource>>,
     progress: IndexProgress,
+    /// Progress of the running or last export, if any
+    export_progress: Option<IndexProgress>,
 }
 
 /// Manages all loaded pointclouds — shared via Tauri state
 pub struct PointcloudManager {
     entries: RwLock<HashMap<String, PointcloudEntry>>,
     next_id: Mutex<u32>,
+    /// Built octrees kept on disk; None when no cache folder is known
+    cache: Option<OctreeCache>,
+    /// Bytes of points an octree build may hold; larger clouds are built on disk
+    memory_budget: AtomicU64,
 }
 
 impl PointcloudManager {
     pub fn new() -> Self {
-        Self {
+        let manager = Self {
             entries: RwLock::new(HashMap::new()),
             next_id: Mutex::new(1),
-        }
+            cache: OctreeCache::default_dir().map(OctreeCache::new),
+            memory_budget: AtomicU64::new(DEFAULT_MEMORY_BUDGET),
+        };
+        // Builds of a run that crashed or was killed leave their work folders
+        remove_stale_work_dirs(&manager.work_dir());
+        manager
     }
 
     /// Open a pointcloud file, parse header, and start async octree construction.
     /// Returns metadata immediately; octree builds in the background.
-    pub fn open(self: &Arc<Self>, file_path: &str) -> Result<PointcloudMetadata, String> {
+    pub fn open(self: &Arc<Self>, file_path: &str, options: &OpenOptions) -> Result<PointcloudMetadata, String> {
         let id = {
             let mut counter = self.next_id.lock().unwrap();
             let id = format!("pc_{}", *counter);
@@ -38,21 +72,51 @@ impl PointcloudManager {
             id
         };
 
-        let parser = PointcloudParser::open(file_path)?;
-        let metadata = parser.metadata(&id, file_path);
-        let total_points = parser.total_points();
+        let format = detect_format(file_path)?;
+        match format {
+            PointcloudFormat::Copc => {
+                let copc = CopcReader::open(file_path, options)?;
+                return Ok(self.insert_indexed(id, file_path, options, copc));
+            }
+            PointcloudFormat::Ept => {
+                let ept = EptReader::open(file_path)?;
+                return Ok(self.insert_indexed(id, file_path, options, ept));
+            }
+            PointcloudFormat::Potree => {
+                let potree = PotreeReader::open(file_path)?;
+                return Ok(self.insert_indexed(id, file_path, options, potree));
+            }
+            _ => {}
+        }
+
+        // A cached octree makes reading and indexing the file unnecessary
+        if let Some((octree, mut metadata, vlrs)) = self.cache.as_ref().and_then(|c| c.load(file_path, options)) {
+            metadata.id = id.clone();
+            metadata.file_path = file_path.to_string();
+            self.insert_complete(id, metadata.clone(), vlrs, options, Arc::new(octree));
+            return Ok(metadata);
+        }
+
+        let reader = open_reader(file_path, format, options)?;
+        let metadata = reader.metadata(&id, file_path);
+        let vlrs = reader.vlr_infos();
+        let total_points = reader.total_points();
 
         let progress = IndexProgress {
             progress: 0.0,
             phase: "Reading points".into(),
             points_processed: 0,
             total_points,
+            warnings: Vec::new(),
         };
 
         let entry = PointcloudEntry {
             metadata: metadata.clone(),
+            vlrs,
+            options: options.clone(),
             octree: None,
             progress: progress.clone(),
+            export_progress: None,
         };
 
         self.entries.write().unwrap().insert(id.clone(), entry);
@@ -61,7 +125,7 @@ impl PointcloudManager {
         let manager = Arc::clone(self);
         let id_clone = id.clone();
         std::thread::spawn(move || {
-            if let Err(e) = manager.build_octree(&id_clone, parser) {
+            if let Err(e) = manager.build_octree(&id_clone, reader) {
                 eprintln!("Octree build failed for {}: {}", id_clone, e);
                 if let Ok(mut entries) = manager.entries.write() {
                     if let Some(entry) = entries.get_mut(&id_clone) {
@@ -74,11 +138,79 @@ impl PointcloudManager {
         Ok(metadata)
     }
 
-    /// Build octree from parser (runs on background thread)
-    fn build_octree(&self, id: &str, parser: PointcloudParser) -> Result<(), String> {
-        let bounds = parser.bounds();
-        let total = parser.total_points();
-        let mut all_points = Vec::new();
+    /// Register a file that carries its own octree. Nodes are served from it
+    /// directly, so the entry is complete without a build step.
+    fn insert_indexed<S>(&self, id: String, file_path: &str, options: &OpenOptions, source: S) -> PointcloudMetadata
+    where
+        S: PointReader + NodeSource + 'static,
+    {
+        let metadata = source.metadata(&id, file_path);
+        let vlrs = source.vlr_infos();
+        self.insert_complete(id, metadata.clone(), vlrs, options, Arc::new(source));
+        metadata
+    }
+
+    /// Register a pointcloud whose octree is ready
+    fn insert_complete(
+        &self,
+        id: String,
+        metadata: PointcloudMetadata,
+        vlrs: Vec<VlrInfo>,
+        options: &OpenOptions,
+        octree: Arc<dyn NodeSource>,
+    ) {
+        let entry = PointcloudEntry {
+            progress: IndexProgress {
+                progress: 1.0,
+                phase: "Complete".into(),
+                points_processed: metadata.total_points,
+                total_points: metadata.total_points,
+                warnings: Vec::new(),
+            },
+            metadata,
+            vlrs,
+            options: options.clone(),
+            octree: Some(octree),
+            export_progress: None,
+        };
+
+        self.entries.write().unwrap().insert(id, entry);
+    }
+
+    /// Validate a file header without loading it
+    pub fn validate(file_path: &str) -> Result<Vec<HeaderIssue>, String> {
+        match detect_format(file_path)? {
+            PointcloudFormat::Las | PointcloudFormat::Laz | PointcloudFormat::Copc => {
+                Ok(PointcloudParser::inspect(file_path)?.issues().to_vec())
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Build octree from a reader (runs on background thread)
+    fn build_octree(&self, id: &str, reader: Box<dyn PointReader>) -> Result<(), String> {
+        let declared_bounds = reader.bounds();
+        let total = reader.total_points();
+        let attribute_mask = reader.attribute_mask();
+        let (extra_count, additive) = self
+            .entries
+            .read()
+            .unwrap()
+            .get(id)
+            .map_or((0, false), |e| (e.metadata.extra_attributes.len(), e.options.additive));
+
+        // Points are held in memory while they fit the budget, with room for
+        // building the octree from them; beyond it they go to a spill file.
+        let budget = self.memory_budget.load(Ordering::Relaxed);
+        let columns = record_columns(attribute_mask, extra_count);
+        let memory_limit = budget / (2 * point_memory(columns));
+        let mut builder = OctreeBuilder::default();
+        let mut spill = None;
+        if total > memory_limit {
+            spill = Some(PointSpill::create(&self.work_dir(), columns)?);
+        }
+        let mut spill_error = None;
+        let mut actual_bounds = BoundingBox3D::new();
 
         // Update progress
         {
@@ -92,44 +224,295 @@ impl PointcloudManager {
         let batch_size = 100_000u64;
         let id_owned = id.to_string();
         let entries_ref = &self.entries;
+        let work_dir = self.work_dir();
 
-        parser.stream_points(batch_size, |batch, offset| {
-            all_points.extend_from_slice(batch);
+        reader.stream_points(batch_size, &mut |batch, offset| {
+            if spill.is_none() && (builder.len() + batch.len()) as u64 > memory_limit {
+                // More points than the file declared: move what was read to disk
+                let moved = PointSpill::create(&work_dir, columns).and_then(|mut s| {
+                    builder.batches().iter().try_for_each(|b| s.write(b)).map(|_| s)
+                });
+                match moved {
+                    Ok(s) => spill = Some(s),
+                    Err(e) => {
+                        spill_error = Some(e);
+                        return false;
+                    }
+                }
+                builder = OctreeBuilder::default();
+            }
+            match spill.as_mut() {
+                Some(spill) => {
+                    if let Err(e) = spill.write(batch) {
+                        spill_error = Some(e);
+                        return false;
+                    }
+                }
+                None => builder.push(batch),
+            }
+            for p in &batch.points {
+                actual_bounds.expand(p.x, p.y, p.z);
+            }
 
             // Update progress
             if let Ok(mut entries) = entries_ref.write() {
                 if let Some(entry) = entries.get_mut(&id_owned) {
                     entry.progress.points_processed = offset + batch.len() as u64;
-                    entry.progress.progress = (offset + batch.len() as u64) as f64 / total as f64 * 0.5;
+                    entry.progress.progress = (offset + batch.len() as u64) as f64 / total.max(1) as f64 * 0.5;
                 }
             }
             true
         })?;
+        if let Some(e) = spill_error {
+            return Err(e);
+        }
+
+        // Compare the declared bounds against what the points actually cover
+        let read = spill.as_ref().map_or(builder.len() as u64, PointSpill::count);
+        let scale = reader.scale();
+        let outside = declared_bounds.as_ref().filter(|_| read > 0).is_some_and(|declared| {
+            actual_bounds.min_x < declared.min_x - scale[0]
+                || actual_bounds.min_y < declared.min_y - scale[1]
+                || actual_bounds.min_z < declared.min_z - scale[2]
+                || actual_bounds.max_x > declared.max_x + scale[0]
+                || actual_bounds.max_y > declared.max_y + scale[1]
+                || actual_bounds.max_z > declared.max_z + scale[2]
+        });
+        // Formats without trustworthy bounds get the ones computed from the points.
+        // Points outside the declared bounds widen them, so every node's box
+        // holds its points.
+        let bounds = match &declared_bounds {
+            Some(declared) if outside => {
+                let mut bounds = declared.clone();
+                bounds.expand(actual_bounds.min_x, actual_bounds.min_y, actual_bounds.min_z);
+                bounds.expand(actual_bounds.max_x, actual_bounds.max_y, actual_bounds.max_z);
+                bounds
+            }
+            Some(declared) => declared.clone(),
+            None => actual_bounds.clone(),
+        };
 
-        // Build octree
         {
             let mut entries = self.entries.write().unwrap();
             if let Some(entry) = entries.get_mut(id) {
+                entry.metadata.validation.extend(reader.stream_issues());
+                if let Some(declared) = declared_bounds.as_ref().filter(|_| outside) {
+                    entry.metadata.validation.push(HeaderIssue::warning(
+                        "bounds",
+                        format_bounds(&actual_bounds),
+                        format_bounds(declared),
+                        "Points fall outside the header bounding box",
+                    ));
+                }
+                if (declared_bounds.is_none() || outside) && read > 0 {
+                    entry.metadata.bounds = bounds.clone();
+                }
+                entry.metadata.total_points = read;
+                entry.progress.total_points = read;
                 entry.progress.phase = "Building octree".into();
                 entry.progress.progress = 0.5;
             }
         }
 
-        let octree = Octree::build(all_points, bounds);
+        let octree = match spill {
+            Some(spill) => {
+                let mut progress = |phase: &str, fraction: f64| {
+                    if let Some(entry) = self.entries.write().unwrap().get_mut(id) {
+                        entry.progress.phase = phase.into();
+                        entry.progress.progress = 0.5 + 0.5 * fraction.min(0.99);
+                    }
+                };
+                BuiltOctree::Disk(DiskOctree::build(spill, bounds, attribute_mask, additive, budget, &mut progress)?)
+            }
+            None => {
+                let progress = |fraction: f64| {
+                    if let Some(entry) = self.entries.write().unwrap().get_mut(id) {
+                        entry.progress.progress = 0.5 + 0.5 * fraction.min(0.99);
+                    }
+                };
+                BuiltOctree::Memory(builder.build(bounds, attribute_mask, additive, &progress))
+            }
+        };
+        let octree = Arc::new(octree);
 
         // Store octree
-        {
+        let cached = {
             let mut entries = self.entries.write().unwrap();
-            if let Some(entry) = entries.get_mut(id) {
-                entry.octree = Some(octree);
+            entries.get_mut(id).map(|entry| {
+                entry.octree = Some(octree.clone());
                 entry.progress.phase = "Complete".into();
                 entry.progress.progress = 1.0;
+                (entry.metadata.clone(), entry.vlrs.clone(), entry.options.clone())
+            })
+        };
+
+        // Keep it for the next time the file is opened
+        if let (Some(cache), Some((metadata, vlrs, options))) = (&self.cache, cached) {
+            if let Err(e) = cache.store(&metadata.file_path, &options, &octree, &metadata, &vlrs) {
+                eprintln!("Failed to cache octree for {}: {}", id, e);
             }
         }
 
         Ok(())
     }
 
+    /// Export a loaded pointcloud, or the part of it that passes the options'
+    /// filter, to a LAS, LAZ or COPC file. The source file is read again and written
+    /// in the background; poll `get_export_progress` for the outcome.
+    pub fn export(self: &Arc<Self>, id: &str, output_path: &str, options: ExportOptions) -> Result<(), String> {
+        let (metadata, open_options) = {
+            let entries = self.entries.read().unwrap();
+            let entry = entries.get(id).ok_or("Pointcloud not found")?;
+            (entry.metadata.clone(), entry.options.clone())
+        };
+        self.start_export(id, "Writing points")?;
+
+        let manager = Arc::clone(self);
+        let id = id.to_string();
+        let output_path = output_path.to_string();
+        std::thread::spawn(move || {
+            let result = detect_format(&metadata.file_path)
+                .and_then(|format| open_reader(&metadata.file_path, format, &open_options))
+                .and_then(|reader| {
+                    let total = metadata.total_points.max(1);
+                    let limits = BuildLimits { memory_budget: manager.memory_budget(), work_dir: manager.work_dir() };
+                    let mut warnings = Vec::new();
+                    let mut progress = |processed| {
+                        manager.update_export(&id, |p| {
+                            p.points_processed = processed;
+                            p.progress = (processed as f64 / total as f64).min(0.99);
+                        });
+                    };
+                    let written = export_las(
+                        reader.as_ref(),
+                        &metadata,
+                        &output_path,
+                        &options,
+                        &limits,
+                        &mut warnings,
+                        &mut progress,
+                    );
+                    manager.update_export(&id, |p| p.warnings = warnings);
+                    written
+                });
+            manager.finish_export(&id, result);
+        });
+
+        Ok(())
+    }
+
+    /// Export the octree of a loaded pointcloud as a 3D Tiles tileset in
+    /// `output_dir`, in the background; poll `get_export_progress` for the outcome.
+    pub fn export_tiles(
+        self: &Arc<Self>,
+        id: &str,
+        output_dir: &str,
+        options: TilesExportOptions,
+    ) -> Result<(), String> {
+        let (metadata, source) = {
+            let entries = self.entries.read().unwrap();
+            let entry = entries.get(id).ok_or("Pointcloud not found")?;
+            let source = entry.octree.clone().ok_or("Octree not yet built")?;
+            (entry.metadata.clone(), source)
+        };
+        self.start_export(id, "Writing tiles")?;
+
+        let manager = Arc::clone(self);
+        let id = id.to_string();
+        let output_dir = output_dir.to_string();
+        std::thread::spawn(move || {
+            let result = export_tiles(source.as_ref(), &metadata, &output_dir, &options, &mut |written, total| {
+                manager.update_export(&id, |p| {
+                    p.points_processed = written;
+                    p.total_points = total;
+                    p.progress = (written as f64 / total.max(1) as f64).min(0.99);
+                });
+            });
+            manager.finish_export(&id, result);
+        });
+
+        Ok(())
+    }
+
+    /// Reset the export progress, unless an export is already running
+    fn start_export(&self, id: &str, phase: &str) -> Result<(), String> {
+        let mut entries = self.entries.write().unwrap();
+        let entry = entries.get_mut(id).ok_or("Pointcloud not found")?;
+        if entry.export_progress.as_ref().is_some_and(|p| p.progress < 1.0 && !p.phase.starts_with("Error")) {
+            return Err("An export of this pointcloud is already running".into());
+        }
+        entry.export_progress = Some(IndexProgress {
+            progress: 0.0,
+            phase: phase.into(),
+            points_processed: 0,
+            total_points: entry.metadata.total_points,
+            warnings: Vec::new(),
+        });
+        Ok(())
+    }
+
+    fn finish_export(&self, id: &str, result: Result<u64, String>) {
+        self.update_export(id, |p| match &result {
+            Ok(written) => {
+                p.phase = "Complete".into();
+                p.progress = 1.0;
+                p.points_processed = *written;
+            }
+            Err(e) => {
+                eprintln!("Export failed for {}: {}", id, e);
+                p.phase = format!("Error: {}", e);
+            }
+        });
+    }
+
+    fn update_export(&self, id: &str, update: impl FnOnce(&mut IndexProgress)) {
+        if let Ok(mut entries) = self.entries.write() {
+            if let Some(progress) = entries.get_mut(id).and_then(|e| e.export_progress.as_mut()) {
+                update(progress);
+            }
+        }
+    }
+
+    /// Get the progress of the running or last export. On completion
+    /// `points_processed` is the number of points written.
+    pub fn get_export_progress(&self, id: &str) -> Option<IndexProgress> {
+        self.entries.read().unwrap().get(id).and_then(|e| e.export_progress.clone())
+    }
+
+    /// Location, size and limit of the octree cache
+    pub fn cache_info(&self) -> Option<CacheInfo> {
+        self.cache.as_ref().map(OctreeCache::info)
+    }
+
+    /// Change the octree cache size limit
+    pub fn set_cache_limit(&self, bytes: u64) -> Result<(), String> {
+        self.cache.as_ref().ok_or("No octree cache folder")?.set_limit(bytes);
+        Ok(())
+    }
+
+    /// Remove every cached octree. Returns the bytes freed.
+    pub fn clear_cache(&self) -> u64 {
+        self.cache.as_ref().map_or(0, OctreeCache::clear)
+    }
+
+    /// Bytes of points an octree build may hold in memory
+    pub fn memory_budget(&self) -> u64 {
+        self.memory_budget.load(Ordering::Relaxed)
+    }
+
+    /// Change the memory budget of octree builds started from now on
+    pub fn set_memory_budget(&self, bytes: u64) {
+        self.memory_budget.store(bytes, Ordering::Relaxed);
+    }
+
+    /// Folder for the temporary files of builds that exceed the memory budget
+    fn work_dir(&self) -> PathBuf {
+        match &self.cache {
+            Some(cache) => cache.work_dir(),
+            None => std::env::temp_dir().join("open-pointcloud-studio"),
+        }
+    }
+
     /// Get indexing progress
     pub fn get_progress(&self, id: &str) -> Option<IndexProgress> {
         self.entries.read().unwrap().get(id).map(|e| e.progress.clone())
@@ -140,19 +523,18 @@ impl PointcloudManager {
         self.entries.read().unwrap().get(id).map(|e| e.metadata.clone())
     }
 
+    /// Get the VLR/EVLR records of a pointcloud's file header
+    pub fn get_vlrs(&self, id: &str) -> Option<Vec<VlrInfo>> {
+        self.entries.read().unwrap().get(id).map(|e| e.vlrs.clone())
+    }
+
     /// Load point data for specific nodes
     pub fn get_nodes(&self, id: &str, node_ids: &[String]) -> Result<Vec<PointChunk>, String> {
         let entries = self.entries.read().unwrap();
         let entry = entries.get(id).ok_or("Pointcloud not found")?;
         let octree = entry.octree.as_ref().ok_or("Octree not yet built")?;
 
-        let mut chunks = Vec::new();
-        for node_id in node_ids {
-            if let Some(chunk) = octree.get_node_chunk(node_id) {
-                chunks.push(chunk);
-            }
-        }
-        Ok(chunks)
+        octree.node_chunks(node_ids)
     }
 
     /// Get visible nodes for LOD rendering
@@ -166,14 +548,7 @@ impl PointcloudManager {
         let entry = entries.get(id).ok_or("Pointcloud not found")?;
         let octree = entry.octree.as_ref().ok_or("Octree not yet built")?;
 
-        let node_ids = octree.get_visible_nodes(camera, point_budget);
-        let mut infos = Vec::new();
-        for node_id in &node_ids {
-            if let Some(info) = octree.get_node_info(node_id) {
-                infos.push(info);
-            }
-        }
-        Ok(infos)
+        Ok(octree.visible_nodes(camera, point_budget))
     }
 
     /// Close and remove a pointcloud
@@ -186,3 +561,26 @@ impl PointcloudManager {
         self.entries.read().unwrap().values().map(|e| e.metadata.clone()).collect()
     }
 }
+
+/// Open a file for streaming its points
+fn open_reader(file_path: &str, format: PointcloudFormat, options: &OpenOptions) -> Result<Box<dyn PointReader>, String> {
+    Ok(match format {
+        PointcloudFormat::Las | PointcloudFormat::Laz => Box::new(PointcloudParser::open(file_path, options)?),
+        PointcloudFormat::Copc => Box::new(CopcReader::open(file_path, options)?),
+        PointcloudFormat::Ept => Box::new(EptReader::open(file_path)?),
+        PointcloudFormat::Potree => Box::new(PotreeReader::open(file_path)?),
+        PointcloudFormat::E57 => Box::new(E57Reader::open(file_path)?),
+        PointcloudFormat::Ply => Box::new(PlyReader::open(file_path)?),
+        PointcloudFormat::Pcd => Box::new(PcdReader::open(file_path)?),
+        PointcloudFormat::Pts | PointcloudFormat::Ptx | PointcloudFormat::Xyz | PointcloudFormat::Csv => {
+            Box::new(AsciiReader::open(file_path, format, options.columns.as_ref())?)
+        }
+    })
+}
+
+fn format_bounds(b: &BoundingBox3D) -> String {
+    format!(
+        "[{}, {}, {}] - [{}, {}, {}]",
+        b.min_x, b.min_y, b.min_z, b.max_x, b.max_y, b.max_z
+    )
+}
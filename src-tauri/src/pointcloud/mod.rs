@@ -1,5 +1,25 @@
 pub mod types;
+pub mod format;
+pub mod reader;
 pub mod parser;
+pub mod hierarchy;
+pub mod copc;
+pub mod ept;
+pub mod potree;
+pub mod e57;
+pub mod ascii;
+pub mod ply;
+pub mod pcd;
+pub mod vlr;
+pub mod extra_bytes;
+pub mod crs;
+pub mod frustum;
 pub mod octree;
+pub mod cache;
+pub mod disk_octree;
+pub mod writer;
+pub mod copc_writer;
+pub mod export;
+pub mod tiles;
 pub mod manager;
 pub mod commands;
@@ -1,15 +1,31 @@
-use super::types::{BoundingBox3D, CameraState, OctreeNodeInfo, PointChunk, PointRecord};
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 
-const MAX_POINTS_PER_LEAF: usize = 65_536;
-const MAX_DEPTH: u8 = 12;
-const SUBSAMPLE_RATIO: usize = 8; // Keep every Nth point for parent LOD
+use rayon::prelude::*;
+
+use super::disk_octree::DiskOctree;
+use super::frustum::Frustum;
+use super::hierarchy::HierarchyNode;
+use super::types::{
+    point_attributes, BoundingBox3D, CameraState, OctreeNodeInfo, PointBuffer, PointChunk, PointRecord,
+};
+
+pub const MAX_POINTS_PER_LEAF: usize = 65_536;
+pub const MAX_DEPTH: u8 = 12;
+/// Cells along the largest extent of the grid an internal node is sampled on
+pub const LOD_GRID: f64 = 128.0;
+/// Pixels a node covers on screen beyond which its children are selected
+const REFINE_SCREEN_SIZE: f64 = 200.0;
+/// Pixels a node must cover on screen to be selected at all
+const MIN_SCREEN_SIZE: f64 = 1.0;
 
 /// Internal octree node storing point data
 pub struct OctreeNode {
     pub node_id: String,
     pub bounds: BoundingBox3D,
     pub level: u8,
-    pub points: Vec<PointRecord>,
+    pub points: PointBuffer,
     pub children: [Option<Box<OctreeNode>>; 8],
 }
 
@@ -19,302 +35,1134 @@ impl OctreeNode {
             node_id,
             bounds,
             level,
-            points: Vec::new(),
+            points: PointBuffer::default(),
             children: [None, None, None, None, None, None, None, None],
         }
     }
+}
+
+/// A node of an octree the LOD selection walks: the one built in memory,
+/// or a hierarchy stored in the file itself
+pub trait LodNode: Sized {
+    fn node_id(&self) -> &str;
+    fn bounds(&self) -> &BoundingBox3D;
+    fn level(&self) -> u8;
+    /// Points stored in this node
+    fn point_count(&self) -> u32;
+    fn children(&self) -> &[Option<Box<Self>>; 8];
 
     fn is_leaf(&self) -> bool {
-        self.children.iter().all(|c| c.is_none())
+        self.children().iter().all(|c| c.is_none())
+    }
+
+    fn has_children(&self) -> bool {
+        self.children().iter().any(|c| c.is_some())
+    }
+}
+
+impl LodNode for OctreeNode {
+    fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    fn bounds(&self) -> &BoundingBox3D {
+        &self.bounds
+    }
+
+    fn level(&self) -> u8 {
+        self.level
     }
 
     fn point_count(&self) -> u32 {
         self.points.len() as u32
     }
 
-    fn has_children(&self) -> bool {
-        self.children.iter().any(|c| c.is_some())
+    fn children(&self) -> &[Option<Box<Self>>; 8] {
+        &self.children
+    }
+}
+
+/// An octree the manager serves nodes from
+pub trait NodeSource: Send + Sync {
+    /// Nodes to render for a camera, in priority order, within the point budget
+    fn visible_nodes(&self, camera: &CameraState, point_budget: u32) -> Vec<OctreeNodeInfo>;
+
+    /// Point data of the given nodes; unknown or empty nodes are skipped
+    fn node_chunks(&self, node_ids: &[String]) -> Result<Vec<PointChunk>, String>;
+
+    /// Every node of the tree, parents before their children
+    fn nodes(&self) -> Vec<OctreeNodeInfo>;
+
+    /// Whether each point is stored in one node only, so a node's children
+    /// add detail to it rather than replace it
+    fn is_additive(&self) -> bool;
+}
+
+/// Bits per axis of the Morton codes points are sorted by
+pub const MORTON_BITS: u32 = 21;
+
+/// Share of a build spent computing and sorting Morton codes, for progress
+const SORT_SHARE: f64 = 0.3;
+
+/// Cell of `v` on an axis from `min` to `min + size` split into 2^`bits` cells
+pub fn quantize(v: f64, min: f64, size: f64, bits: u32) -> u64 {
+    let cells = 1u64 << bits;
+    if size > 0.0 {
+        (((v - min) / size * cells as f64).max(0.0) as u64).min(cells - 1)
+    } else {
+        0
+    }
+}
+
+/// Morton code of a position in `bounds`, `bits` per axis. Bit 0 of each
+/// triplet is x, bit 1 y and bit 2 z, so from the top every three bits are
+/// an octant digit of the node IDs.
+pub fn morton_code(x: f64, y: f64, z: f64, bounds: &BoundingBox3D, bits: u32) -> u64 {
+    let size = bounds.size();
+    let q = [
+        quantize(x, bounds.min_x, size[0], bits),
+        quantize(y, bounds.min_y, size[1], bits),
+        quantize(z, bounds.min_z, size[2], bits),
+    ];
+    spread_bits(q[0]) | (spread_bits(q[1]) << 1) | (spread_bits(q[2]) << 2)
+}
+
+/// Move the low 21 bits of `v` three bits apart
+fn spread_bits(v: u64) -> u64 {
+    let mut v = v & 0x1f_ffff;
+    v = (v | v << 32) & 0x001f_0000_0000_ffff;
+    v = (v | v << 16) & 0x001f_0000_ff00_00ff;
+    v = (v | v << 8) & 0x100f_00f0_0f00_f00f;
+    v = (v | v << 4) & 0x10c3_0c30_c30c_30c3;
+    v = (v | v << 2) & 0x1249_2492_4924_9249;
+    v
+}
+
+/// Depth of the counting grid: 128 cells per axis, each a node at that level
+pub const COUNT_LEVEL: u8 = 7;
+
+/// A counting grid cell: level, then Morton index at that level
+pub type Cell = (u8, usize);
+
+/// Cells of the counting grid at a level
+pub fn cells_at(level: u8) -> usize {
+    1 << (3 * level as usize)
+}
+
+/// Whether a counting grid cell is built as one bucket: it holds at most
+/// `max_points`, or is a finest cell
+pub fn is_bucket(count: u64, level: u8, max_points: u64) -> bool {
+    count <= max_points || level == COUNT_LEVEL
+}
+
+/// Point counts per cell at every level of the grid, coarsest first
+pub fn count_pyramid(counts: Vec<u64>) -> Vec<Vec<u64>> {
+    let mut pyramid = vec![counts];
+    for level in (0..COUNT_LEVEL).rev() {
+        let finer = pyramid.last().unwrap();
+        let mut coarse = vec![0u64; cells_at(level)];
+        for (index, count) in finer.iter().enumerate() {
+            coarse[index >> 3] += count;
+        }
+        pyramid.push(coarse);
+    }
+    pyramid.reverse();
+    pyramid
+}
+
+/// Collect the bucket cells below `cell`, depth first in octant order
+pub fn find_buckets(pyramid: &[Vec<u64>], cell: Cell, max_points: u64, buckets: &mut Vec<Cell>) {
+    let (level, index) = cell;
+    let count = pyramid[level as usize][index];
+    if count == 0 {
+        return;
+    }
+    if is_bucket(count, level, max_points) {
+        buckets.push(cell);
+        return;
+    }
+    for octant in 0..8 {
+        find_buckets(pyramid, (level + 1, index * 8 + octant), max_points, buckets);
+    }
+}
+
+
+/// Builds the nodes of a subtree from points sorted by Morton code
+struct SubtreeBuilder<'a> {
+    bits: u32,
+    base_level: u8,
+    /// Move sampled points out of the children instead of copying them
+    additive: bool,
+    total: u64,
+    /// Points placed in leaves so far
+    done: AtomicU64,
+    node_count: AtomicU32,
+    progress: &'a (dyn Fn(f64) + Sync),
+}
+
+impl SubtreeBuilder<'_> {
+    /// A node is a leaf while it holds at most a leaf's worth of points, as
+    /// if they had been inserted one by one. Internal nodes get a grid
+    /// sample of their children's points for their LOD; in an additive tree
+    /// the sample leaves the children, and leaves left empty are dropped.
+    fn node(
+        &self,
+        node_id: String,
+        bounds: BoundingBox3D,
+        level: u8,
+        codes: &[u64],
+        points: &PointBuffer,
+        start: usize,
+    ) -> OctreeNode {
+        self.node_count.fetch_add(1, Ordering::Relaxed);
+        let mut node = OctreeNode::new(node_id, bounds, level);
+        if codes.len() <= MAX_POINTS_PER_LEAF || level >= MAX_DEPTH {
+            node.points = points.slice(start..start + codes.len());
+            let done = self.done.fetch_add(codes.len() as u64, Ordering::Relaxed) + codes.len() as u64;
+            (self.progress)(SORT_SHARE + (1.0 - SORT_SHARE) * done as f64 / self.total as f64);
+            return node;
+        }
+
+        // The octant is the code's next three bits below this node's
+        let shift = 3 * (self.bits - 1 - (level - self.base_level) as u32);
+        let mut parts = Vec::with_capacity(8);
+        let (mut codes, mut start) = (codes, start);
+        for octant in 0..8u8 {
+            let end = codes.partition_point(|c| (c >> shift) & 7 <= octant as u64);
+            let (part, rest) = codes.split_at(end);
+            if !part.is_empty() {
+                parts.push((octant, part, start));
+            }
+            codes = rest;
+            start += end;
+        }
+
+        let children: Vec<(u8, OctreeNode)> = parts
+            .into_par_iter()
+            .map(|(octant, codes, start)| {
+                let child_id = format!("{}{}", node.node_id, octant);
+                let child_bounds = node.bounds.octant(octant);
+                (octant, self.node(child_id, child_bounds, level + 1, codes, points, start))
+            })
+            .collect();
+        let dropped = sample_children(&mut node, children, self.additive);
+        self.node_count.fetch_sub(dropped, Ordering::Relaxed);
+        node
+    }
+}
+
+/// Give an internal node a grid sample of its children's points for its LOD
+/// and attach the children. In an additive tree the sample leaves the
+/// children, and leaves left empty are dropped; returns how many were.
+pub fn sample_children(node: &mut OctreeNode, mut children: Vec<(u8, OctreeNode)>, additive: bool) -> u32 {
+    let positions = children.iter().flat_map(|(_, c)| c.points.points.iter().map(|p| [p.x, p.y, p.z]));
+    let mut picked = grid_sample(positions, &node.bounds).into_iter().peekable();
+    let columns = children.first().map(|(_, c)| c.points.columns()).unwrap_or_default();
+    node.points = PointBuffer::new(columns);
+
+    let mut offset = 0;
+    let mut taken = Vec::new();
+    for (_, child) in &mut children {
+        let len = child.points.len();
+        taken.clear();
+        while let Some(i) = picked.next_if(|&i| i < offset + len) {
+            taken.push(i - offset);
+        }
+        for &i in &taken {
+            node.points.push_from(&child.points, i);
+        }
+        if additive {
+            child.points.remove_sorted(&taken);
+        }
+        offset += len;
+    }
+
+    let before = children.len();
+    if additive {
+        children.retain(|(_, c)| !c.points.is_empty() || c.has_children());
+    }
+    let dropped = (before - children.len()) as u32;
+    for (octant, child) in children {
+        node.children[octant as usize] = Some(Box::new(child));
     }
+    dropped
 }
 
 /// The octree spatial index
 pub struct Octree {
-    pub root: OctreeNode,
+    /// Nodes the LOD selection walks; a node's data is its index in `nodes`
+    pub root: HierarchyNode<usize>,
+    /// Every node with its points, by key
+    pub nodes: NodeTable<PointBuffer>,
     pub total_points: u64,
-    node_count: u32,
+    /// Optional attributes carried by the points, see `point_attributes`
+    pub attribute_mask: u32,
+    /// Whether each point is stored in one node only
+    pub additive: bool,
 }
 
 impl Octree {
-    /// Build an octree from a set of points
-    pub fn build(points: Vec<PointRecord>, bounds: BoundingBox3D) -> Self {
+    /// Build an octree from a set of points in parallel, additive or with
+    /// parents holding copies of their children's points. `progress` gets
+    /// the fraction of the build done.
+    pub fn build(
+        points: PointBuffer,
+        bounds: BoundingBox3D,
+        attribute_mask: u32,
+        additive: bool,
+        progress: &(dyn Fn(f64) + Sync),
+    ) -> Self {
         let total_points = points.len() as u64;
-        let mut tree = Self {
-            root: OctreeNode::new("r".to_string(), bounds, 0),
+        let (root, node_count) = Self::grow(points, "r".to_string(), bounds, 0, additive, progress);
+        Self::from_tree(root, node_count, total_points, attribute_mask, additive)
+    }
+
+    /// Index a built tree, moving each node's points into the node table
+    fn from_tree(root: OctreeNode, node_count: u32, total_points: u64, attribute_mask: u32, additive: bool) -> Self {
+        let mut nodes = NodeTable::with_capacity(node_count as usize);
+        let root = nodes.take_points(root, NodeKey::ROOT, additive);
+        Self {
+            root,
+            nodes,
             total_points,
-            node_count: 1,
-        };
+            attribute_mask,
+            additive,
+        }
+    }
+
+    /// Build the subtree below a node from the points that fall in it. Built
+    /// from the root of a part of the cloud, it matches that part of the
+    /// octree `build` makes of the whole cloud.
+    pub fn build_node(
+        points: PointBuffer,
+        node_id: String,
+        bounds: BoundingBox3D,
+        level: u8,
+        additive: bool,
+    ) -> OctreeNode {
+        Self::grow(points, node_id, bounds, level, additive, &|_| {}).0
+    }
 
-        for point in points {
-            tree.insert_point(point);
+    /// Sort the points by Morton code, so every node's points are a
+    /// contiguous run, then build the subtrees below `level` concurrently.
+    /// Returns the root and the number of nodes.
+    fn grow(
+        mut points: PointBuffer,
+        node_id: String,
+        bounds: BoundingBox3D,
+        level: u8,
+        additive: bool,
+        progress: &(dyn Fn(f64) + Sync),
+    ) -> (OctreeNode, u32) {
+        // Codes relative to this node have the resolution codes over the root have here
+        let bits = MORTON_BITS - level as u32;
+        let mut keys: Vec<(u64, usize)> = points
+            .points
+            .par_iter()
+            .enumerate()
+            .map(|(i, p)| (morton_code(p.x, p.y, p.z, &bounds, bits), i))
+            .collect();
+        // The index breaks ties, so points in one cell keep the read order
+        keys.par_sort_unstable();
+        let codes: Vec<u64> = keys.iter().map(|k| k.0).collect();
+        let mut order: Vec<usize> = keys.into_iter().map(|k| k.1).collect();
+
+        // Put the points in that order in place, one cycle of the permutation
+        // at a time, rather than through a second buffer as large
+        for start in 0..order.len() {
+            let mut j = start;
+            loop {
+                let k = order[j];
+                order[j] = j;
+                if k == start || k == j {
+                    break;
+                }
+                points.swap(j, k);
+                j = k;
+            }
         }
+        drop(order);
+        progress(SORT_SHARE);
 
-        // Build LOD subsamples for internal nodes
-        Self::build_lod(&mut tree.root as *mut OctreeNode);
+        let builder = SubtreeBuilder {
+            bits,
+            base_level: level,
+            additive,
+            total: points.len().max(1) as u64,
+            done: AtomicU64::new(0),
+            node_count: AtomicU32::new(0),
+            progress,
+        };
+        let root = builder.node(node_id, bounds, level, &codes, &points, 0);
+        (root, builder.node_count.into_inner())
+    }
 
-        tree
+    /// Get info about a node by ID
+    pub fn get_node_info(&self, node_id: &str) -> Option<OctreeNodeInfo> {
+        self.nodes.find(node_id).map(|n| n.info.clone())
     }
 
-    /// Insert a single point into the octree
-    fn insert_point(&mut self, point: PointRecord) {
-        Self::insert_into_node(&mut self.root, point, &mut self.node_count);
+    /// Get point data for a node, packed for GPU transfer
+    pub fn get_node_chunk(&self, node_id: &str) -> Option<PointChunk> {
+        let node = self.nodes.find(node_id).filter(|n| !n.data.is_empty())?;
+        let info = &node.info;
+        Some(pack_chunk(node_id, &info.bounds, info.level, node.spacing, &node.data, self.attribute_mask))
     }
 
-    fn insert_into_node(node: &mut OctreeNode, point: PointRecord, node_count: &mut u32) {
-        // If leaf and under capacity, just add
-        if node.is_leaf() && node.points.len() < MAX_POINTS_PER_LEAF {
-            node.points.push(point);
-            return;
-        }
+    /// Select the nodes in the camera's view within the point budget.
+    /// Returns node IDs sorted by priority (largest on screen first).
+    pub fn get_visible_nodes(&self, camera: &CameraState, point_budget: u32) -> Vec<String> {
+        select_visible(&self.root, camera, point_budget, self.additive)
+            .into_iter()
+            .map(|n| n.node_id.clone())
+            .collect()
+    }
+
+    /// Collect all node infos for debugging/listing
+    pub fn all_node_infos(&self) -> Vec<OctreeNodeInfo> {
+        self.nodes.infos()
+    }
+}
+
+/// Points the in-memory build sorts at once; larger clouds are split into
+/// buckets of the counting grid first
+const MEMORY_BUCKET_POINTS: u64 = 1 << 22;
+
+/// Share of an in-memory build spent moving points to buckets, for progress
+const DISTRIBUTE_SHARE: f64 = 0.1;
+
+/// Collects the points of a cloud in the batches they are read in, rather
+/// than in one buffer for the whole cloud, and builds the octree once the
+/// bounds are known
+#[derive(Default)]
+pub struct OctreeBuilder {
+    batches: Vec<PointBuffer>,
+    len: usize,
+}
+
+impl OctreeBuilder {
+    pub fn push(&mut self, batch: &PointBuffer) {
+        self.len += batch.len();
+        self.batches.push(batch.clone());
+    }
 
-        // If leaf at max depth, just add (can't split further)
-        if node.level >= MAX_DEPTH {
-            node.points.push(point);
-            return;
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Batches pushed so far, in read order
+    pub fn batches(&self) -> &[PointBuffer] {
+        &self.batches
+    }
+
+    /// Build the octree. The points are counted on a grid and moved batch by
+    /// batch into buckets, the coarsest cells small enough to sort at once;
+    /// each bucket is built in turn and the nodes above sample their
+    /// children. The tree matches the one `Octree::build` makes.
+    pub fn build(
+        self,
+        bounds: BoundingBox3D,
+        attribute_mask: u32,
+        additive: bool,
+        progress: &(dyn Fn(f64) + Sync),
+    ) -> Octree {
+        let OctreeBuilder { batches, len } = self;
+        let total_points = len as u64;
+        let columns = batches.first().map(PointBuffer::columns).unwrap_or_default();
+        let shift = 3 * (MORTON_BITS - COUNT_LEVEL as u32);
+        let cell_of = |p: &PointRecord| (morton_code(p.x, p.y, p.z, &bounds, MORTON_BITS) >> shift) as usize;
+
+        // Small clouds are a single bucket
+        let mut pyramid = Vec::new();
+        let mut bucket_of = Vec::new();
+        let mut buckets = vec![PointBuffer::with_capacity(columns, len)];
+        if total_points > MEMORY_BUCKET_POINTS {
+            let mut counts = vec![0u64; cells_at(COUNT_LEVEL)];
+            for batch in &batches {
+                for p in &batch.points {
+                    counts[cell_of(p)] += 1;
+                }
+            }
+            pyramid = count_pyramid(counts);
+            let mut cells = Vec::new();
+            find_buckets(&pyramid, (0, 0), MEMORY_BUCKET_POINTS, &mut cells);
+            bucket_of = vec![0u32; cells_at(COUNT_LEVEL)];
+            buckets.clear();
+            for (index, &(level, cell)) in cells.iter().enumerate() {
+                // A cell's finest cells are a contiguous run in Morton order
+                let shift = 3 * (COUNT_LEVEL - level) as usize;
+                bucket_of[cell << shift..(cell + 1) << shift].fill(index as u32);
+                buckets.push(PointBuffer::with_capacity(columns, pyramid[level as usize][cell] as usize));
+            }
         }
 
-        // Need to split: if this is a leaf with points, redistribute
-        if node.is_leaf() && !node.points.is_empty() {
-            let existing_points: Vec<PointRecord> = node.points.drain(..).collect();
-            for p in existing_points {
-                let octant = Self::get_octant(&node.bounds, p.x, p.y, p.z);
-                let child = Self::ensure_child(node, octant, node_count);
-                Self::insert_into_node(child, p, node_count);
+        // Each batch is freed once its points are in their buckets
+        let mut moved = 0;
+        for batch in batches {
+            for (i, p) in batch.points.iter().enumerate() {
+                let bucket = if bucket_of.is_empty() { 0 } else { bucket_of[cell_of(p)] as usize };
+                buckets[bucket].push_from(&batch, i);
             }
+            moved += batch.len();
+            progress(DISTRIBUTE_SHARE * moved as f64 / len.max(1) as f64);
+        }
+
+        if pyramid.is_empty() {
+            let points = buckets.pop().unwrap_or_default();
+            let progress = |f: f64| progress(DISTRIBUTE_SHARE + (1.0 - DISTRIBUTE_SHARE) * f);
+            return Octree::build(points, bounds, attribute_mask, additive, &progress);
+        }
+
+        let mut assembly = BucketAssembly {
+            pyramid: &pyramid,
+            buckets: buckets.into_iter(),
+            additive,
+            done: 0,
+            total: len as u64,
+            node_count: 0,
+            progress,
+        };
+        let root = assembly.cell((0, 0), "r".to_string(), bounds.clone());
+        let root = root.unwrap_or_else(|| OctreeNode::new("r".to_string(), bounds, 0));
+        Octree::from_tree(root, assembly.node_count.max(1), total_points, attribute_mask, additive)
+    }
+}
+
+/// Builds the buckets of an `OctreeBuilder` and the nodes above them
+struct BucketAssembly<'a> {
+    pyramid: &'a [Vec<u64>],
+    /// Buckets in the order `find_buckets` found them
+    buckets: std::vec::IntoIter<PointBuffer>,
+    additive: bool,
+    done: u64,
+    total: u64,
+    node_count: u32,
+    progress: &'a (dyn Fn(f64) + Sync),
+}
+
+impl BucketAssembly<'_> {
+    /// Build the node of a counting grid cell, walking the cells in the
+    /// order their buckets were found
+    fn cell(&mut self, cell: Cell, node_id: String, bounds: BoundingBox3D) -> Option<OctreeNode> {
+        let (level, index) = cell;
+        let count = self.pyramid[level as usize][index];
+        if count == 0 {
+            return None;
+        }
+        if is_bucket(count, level, MEMORY_BUCKET_POINTS) {
+            return self.bucket(node_id, bounds, level);
         }
 
-        // Insert new point into appropriate child
-        let octant = Self::get_octant(&node.bounds, point.x, point.y, point.z);
-        let child = Self::ensure_child(node, octant, node_count);
-        Self::insert_into_node(child, point, node_count);
+        let mut children = Vec::with_capacity(8);
+        for octant in 0..8u8 {
+            let child_id = format!("{}{}", node_id, octant);
+            let child = self.cell((level + 1, index * 8 + octant as usize), child_id, bounds.octant(octant));
+            children.extend(child.map(|c| (octant, c)));
+        }
+        let mut node = OctreeNode::new(node_id, bounds, level);
+        let dropped = sample_children(&mut node, children, self.additive);
+        self.node_count = self.node_count + 1 - dropped;
+        Some(node)
+    }
+
+    /// Build the subtree of the next bucket
+    fn bucket(&mut self, node_id: String, bounds: BoundingBox3D, level: u8) -> Option<OctreeNode> {
+        let points = self.buckets.next()?;
+        let count = points.len() as u64;
+        let base = DISTRIBUTE_SHARE + (1.0 - DISTRIBUTE_SHARE) * self.done as f64 / self.total as f64;
+        let share = (1.0 - DISTRIBUTE_SHARE) * count as f64 / self.total as f64;
+        let progress = self.progress;
+        let (node, node_count) =
+            Octree::grow(points, node_id, bounds, level, self.additive, &|f| progress(base + share * f));
+        self.done += count;
+        self.node_count += node_count;
+        Some(node)
+    }
+}
+
+impl NodeSource for Octree {
+    fn visible_nodes(&self, camera: &CameraState, point_budget: u32) -> Vec<OctreeNodeInfo> {
+        select_visible(&self.root, camera, point_budget, self.additive)
+            .into_iter()
+            .map(|n| node_info(n, self.additive))
+            .collect()
+    }
+
+    fn node_chunks(&self, node_ids: &[String]) -> Result<Vec<PointChunk>, String> {
+        Ok(node_ids.iter().filter_map(|id| self.get_node_chunk(id)).collect())
     }
 
-    fn get_octant(bounds: &BoundingBox3D, x: f64, y: f64, z: f64) -> u8 {
-        let c = bounds.center();
-        let mut octant = 0u8;
-        if x >= c[0] { octant |= 1; }
-        if y >= c[1] { octant |= 2; }
-        if z >= c[2] { octant |= 4; }
-        octant
+    fn nodes(&self) -> Vec<OctreeNodeInfo> {
+        self.all_node_infos()
     }
 
-    fn ensure_child<'a>(node: &'a mut OctreeNode, octant: u8, node_count: &mut u32) -> &'a mut OctreeNode {
-        if node.children[octant as usize].is_none() {
-            let child_bounds = node.bounds.octant(octant);
-            let child_id = format!("{}{}", node.node_id, octant);
-            *node_count += 1;
-            node.children[octant as usize] = Some(Box::new(OctreeNode::new(
-                child_id,
-                child_bounds,
-                node.level + 1,
-            )));
+    fn is_additive(&self) -> bool {
+        self.additive
+    }
+}
+
+/// An octree the manager built: in memory, or on disk for clouds beyond
+/// the memory budget
+pub enum BuiltOctree {
+    Memory(Octree),
+    Disk(DiskOctree),
+}
+
+impl NodeSource for BuiltOctree {
+    fn visible_nodes(&self, camera: &CameraState, point_budget: u32) -> Vec<OctreeNodeInfo> {
+        match self {
+            Self::Memory(octree) => octree.visible_nodes(camera, point_budget),
+            Self::Disk(octree) => octree.visible_nodes(camera, point_budget),
         }
-        node.children[octant as usize].as_mut().unwrap()
     }
 
-    /// Build LOD: each parent gets a subsample of its children's points
-    fn build_lod(node_ptr: *mut OctreeNode) {
-        let node = unsafe { &mut *node_ptr };
+    fn node_chunks(&self, node_ids: &[String]) -> Result<Vec<PointChunk>, String> {
+        match self {
+            Self::Memory(octree) => octree.node_chunks(node_ids),
+            Self::Disk(octree) => octree.node_chunks(node_ids),
+        }
+    }
 
-        // Recurse into children first
-        for child in &mut node.children {
-            if let Some(ref mut c) = child {
-                Self::build_lod(&mut **c as *mut OctreeNode);
-            }
+    fn nodes(&self) -> Vec<OctreeNodeInfo> {
+        match self {
+            Self::Memory(octree) => octree.nodes(),
+            Self::Disk(octree) => octree.nodes(),
         }
+    }
 
-        // For internal nodes, subsample from children
-        if node.has_children() && node.points.is_empty() {
-            let mut subsample = Vec::new();
-            for child in &node.children {
-                if let Some(ref c) = child {
-                    for (i, p) in c.points.iter().enumerate() {
-                        if i % SUBSAMPLE_RATIO == 0 {
-                            subsample.push(p.clone());
-                        }
-                    }
-                }
-            }
-            node.points = subsample;
+    fn is_additive(&self) -> bool {
+        match self {
+            Self::Memory(octree) => octree.is_additive(),
+            Self::Disk(octree) => octree.is_additive(),
         }
     }
+}
 
-    /// Get info about a node by ID
-    pub fn get_node_info(&self, node_id: &str) -> Option<OctreeNodeInfo> {
-        self.find_node(&self.root, node_id).map(|n| OctreeNodeInfo {
-            node_id: n.node_id.clone(),
-            bounds: n.bounds.clone(),
-            level: n.level,
-            point_count: n.point_count(),
-            has_children: n.has_children(),
-        })
+/// Info of a node of a tree that is additive or not
+pub fn node_info<N: LodNode>(node: &N, additive: bool) -> OctreeNodeInfo {
+    OctreeNodeInfo {
+        node_id: node.node_id().to_string(),
+        bounds: node.bounds().clone(),
+        level: node.level(),
+        point_count: node.point_count(),
+        has_children: node.has_children(),
+        additive,
     }
+}
+
+/// Compact node identifier: the node's octant digits as a Morton index,
+/// behind a leading 1 bit that marks the level. Node IDs are the root "r"
+/// followed by the same digits, for up to `MORTON_BITS` levels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeKey(u64);
 
-    fn find_node<'a>(&self, node: &'a OctreeNode, node_id: &str) -> Option<&'a OctreeNode> {
-        if node.node_id == node_id {
-            return Some(node);
+impl NodeKey {
+    pub const ROOT: NodeKey = NodeKey(1);
+
+    /// Key of a node ID, if it is well formed
+    pub fn parse(node_id: &str) -> Option<Self> {
+        let digits = node_id.strip_prefix('r')?;
+        if digits.len() > MORTON_BITS as usize {
+            return None;
         }
-        for child in &node.children {
-            if let Some(ref c) = child {
-                if node_id.starts_with(&c.node_id) {
-                    if let Some(found) = self.find_node(c, node_id) {
-                        return Some(found);
-                    }
-                }
+        digits.bytes().try_fold(Self::ROOT, |key, d| match d {
+            b'0'..=b'7' => Some(key.child(d - b'0')),
+            _ => None,
+        })
+    }
+
+    pub fn level(self) -> u8 {
+        ((63 - self.0.leading_zeros()) / 3) as u8
+    }
+
+    pub fn child(self, octant: u8) -> Self {
+        Self(self.0 << 3 | octant as u64)
+    }
+
+    /// Octant of the ancestor at `depth + 1` within the one at `depth`
+    pub fn octant_at(self, depth: u8) -> usize {
+        ((self.0 >> (3 * (self.level() - 1 - depth) as u32)) & 7) as usize
+    }
+
+    /// X, y and z index of the node's cell among the 2^level cells per axis
+    /// at its level
+    pub fn cell(self) -> [u32; 3] {
+        let mut cell = [0u32; 3];
+        for depth in 0..self.level() {
+            let octant = self.octant_at(depth);
+            for (axis, c) in cell.iter_mut().enumerate() {
+                *c = *c << 1 | (octant >> axis & 1) as u32;
             }
         }
-        None
+        cell
     }
+}
 
-    /// Get point data for a node, packed for GPU transfer
-    pub fn get_node_chunk(&self, node_id: &str) -> Option<PointChunk> {
-        let node = self.find_node(&self.root, node_id)?;
-        if node.points.is_empty() {
-            return None;
+impl std::fmt::Display for NodeKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("r")?;
+        for depth in 0..self.level() {
+            write!(f, "{}", self.octant_at(depth))?;
         }
+        Ok(())
+    }
+}
 
-        let center = node.bounds.center();
-        let count = node.points.len();
-
-        let mut positions = Vec::with_capacity(count * 3);
-        let mut colors = Vec::with_capacity(count * 3);
-        let mut intensities = Vec::with_capacity(count);
-        let mut classifications = Vec::with_capacity(count);
-
-        for p in &node.points {
-            // Store positions relative to chunk center for double-precision workaround
-            positions.push((p.x - center[0]) as f32);
-            positions.push((p.y - center[1]) as f32);
-            positions.push((p.z - center[2]) as f32);
-            colors.push(p.r);
-            colors.push(p.g);
-            colors.push(p.b);
-            intensities.push(p.intensity);
-            classifications.push(p.classification);
-        }
-
-        // Compute per-node spacing from the 2D surface footprint.
-        // LiDAR points lie on surfaces, so use the two largest bbox dimensions
-        // to estimate the area, then derive spacing as sqrt(area / pointCount).
-        let s = node.bounds.size();
-        let mut dims = [s[0], s[1], s[2]];
-        dims.sort_by(|a, b| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
-        let surface_area = dims[0] * dims[1]; // two largest dimensions
-        let spacing = (surface_area / count as f64).sqrt() as f32;
-
-        Some(PointChunk {
-            node_id: node_id.to_string(),
-            center,
-            level: node.level,
-            spacing,
-            positions,
-            colors,
-            intensities,
-            classifications,
-            point_count: count as u32,
-        })
+/// A node of a `NodeTable`
+pub struct TableNode<T> {
+    pub key: NodeKey,
+    pub info: OctreeNodeInfo,
+    /// Distance between the node's points, see `node_spacing`
+    pub spacing: f32,
+    /// Locates the node's points, or holds them
+    pub data: T,
+}
+
+/// Every node of a tree, parents before their children, looked up by key
+/// rather than by walking from the root. Built once the tree is.
+pub struct NodeTable<T> {
+    slots: HashMap<NodeKey, usize>,
+    nodes: Vec<TableNode<T>>,
+}
+
+impl<T> NodeTable<T> {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            slots: HashMap::with_capacity(capacity),
+            nodes: Vec::with_capacity(capacity),
+        }
     }
 
-    /// Select visible nodes based on camera state and point budget.
-    /// Returns node IDs sorted by priority (closest/largest screen-space first).
-    pub fn get_visible_nodes(&self, camera: &CameraState, point_budget: u32) -> Vec<String> {
-        let mut candidates: Vec<(String, f64, u32)> = Vec::new(); // (id, priority, point_count)
-        self.collect_visible(&self.root, camera, &mut candidates);
-
-        // Sort by priority (lower = more important = should render first)
-        candidates.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
-
-        // Accumulate until budget is reached
-        let mut result = Vec::new();
-        let mut total = 0u32;
-        for (id, _priority, count) in candidates {
-            if total + count > point_budget && !result.is_empty() {
-                break;
+    /// Table of the tree below `root`, with the data `data` gives each node
+    pub fn new<N: LodNode>(root: &N, additive: bool, data: impl Fn(&N) -> T) -> Self {
+        let nodes = collect_nodes(root);
+        let mut table = Self::with_capacity(nodes.len());
+        // Keys follow the nodes in the order they were collected, parents first
+        let mut keys = vec![NodeKey::ROOT];
+        for (i, node) in nodes.into_iter().enumerate() {
+            let key = keys[i];
+            for (octant, _) in node.children().iter().enumerate().filter(|(_, c)| c.is_some()) {
+                keys.push(key.child(octant as u8));
             }
-            total += count;
-            result.push(id);
+            let info = node_info(node, additive);
+            table.push(key, info, data(node));
         }
+        table
+    }
 
-        result
+    fn push(&mut self, key: NodeKey, info: OctreeNodeInfo, data: T) {
+        self.slots.insert(key, self.nodes.len());
+        let spacing = node_spacing(&info);
+        self.nodes.push(TableNode { key, info, spacing, data });
     }
 
-    fn collect_visible(
-        &self,
-        node: &OctreeNode,
-        camera: &CameraState,
-        candidates: &mut Vec<(String, f64, u32)>,
-    ) {
-        if node.points.is_empty() && !node.has_children() {
-            return;
-        }
-
-        let node_center = node.bounds.center();
-        let dx = node_center[0] - camera.position[0];
-        let dy = node_center[1] - camera.position[1];
-        let dz = node_center[2] - camera.position[2];
-        let distance = (dx * dx + dy * dy + dz * dz).sqrt();
-
-        let node_size = node.bounds.max_extent();
-
-        // Screen-space error: how many pixels would this node's extent cover?
-        let screen_size = if distance > 0.001 {
-            (node_size / distance) * camera.screen_height / (2.0 * (camera.fov.to_radians() / 2.0).tan())
-        } else {
-            f64::MAX
+    pub fn get(&self, key: NodeKey) -> Option<&TableNode<T>> {
+        self.slots.get(&key).map(|&i| &self.nodes[i])
+    }
+
+    /// Find a node by ID. IDs are the root "r" followed by one octant digit per level.
+    pub fn find(&self, node_id: &str) -> Option<&TableNode<T>> {
+        self.get(NodeKey::parse(node_id)?)
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, TableNode<T>> {
+        self.nodes.iter()
+    }
+
+    /// Info of every node, parents first
+    pub fn infos(&self) -> Vec<OctreeNodeInfo> {
+        self.nodes.iter().map(|n| n.info.clone()).collect()
+    }
+}
+
+impl NodeTable<PointBuffer> {
+    /// Add the nodes of a built tree below `key`, taking their points.
+    /// Returns the tree's hierarchy, whose node data is the table index.
+    fn take_points(&mut self, node: OctreeNode, key: NodeKey, additive: bool) -> HierarchyNode<usize> {
+        let OctreeNode { node_id, bounds, level, points, children } = node;
+        let index = self.nodes.len();
+        let point_count = points.len() as u32;
+        let info = OctreeNodeInfo {
+            node_id: node_id.clone(),
+            bounds: bounds.clone(),
+            level,
+            point_count,
+            has_children: children.iter().any(Option::is_some),
+            additive,
         };
+        self.push(key, info, points);
+
+        let mut hierarchy_children: [Option<Box<HierarchyNode<usize>>>; 8] = Default::default();
+        for (octant, child) in children.into_iter().enumerate() {
+            if let Some(child) = child {
+                let child = self.take_points(*child, key.child(octant as u8), additive);
+                hierarchy_children[octant] = Some(Box::new(child));
+            }
+        }
+        HierarchyNode { node_id, bounds, level, point_count, data: index, children: hierarchy_children }
+    }
+}
+
+impl<T> IntoIterator for NodeTable<T> {
+    type Item = TableNode<T>;
+    type IntoIter = std::vec::IntoIter<TableNode<T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.nodes.into_iter()
+    }
+}
+
+/// Every node below and including `root`, parents before their children
+pub fn collect_nodes<N: LodNode>(root: &N) -> Vec<&N> {
+    let mut nodes = vec![root];
+    let mut next = 0;
+    while next < nodes.len() {
+        let node = nodes[next];
+        nodes.extend(node.children().iter().flatten().map(|c| &**c));
+        next += 1;
+    }
+    nodes
+}
+
+/// Select the nodes in the camera's view to render within the point budget,
+/// sorted by priority (largest on screen first). Nodes are refined largest
+/// first while they cover more than `REFINE_SCREEN_SIZE` pixels. In an
+/// additive tree a refined node stays selected with its children; otherwise
+/// its children replace it, so no point is selected twice either way.
+pub fn select_visible<'a, N: LodNode>(
+    root: &'a N,
+    camera: &CameraState,
+    point_budget: u32,
+    additive: bool,
+) -> Vec<&'a N> {
+    let frustum = Frustum::new(camera);
+    let root_size = frustum.screen_size(root.bounds());
+    if root_size < MIN_SCREEN_SIZE || !frustum.intersects(root.bounds()) {
+        return Vec::new();
+    }
+    if additive {
+        select_additive(root, root_size, &frustum, point_budget)
+    } else {
+        select_replacing(root, root_size, &frustum, point_budget)
+    }
+}
 
-        // If the node is too small on screen, skip it and its children
-        if screen_size < 1.0 {
-            return;
+/// Select nodes in order of screen size, each after its parent, until the
+/// next one would exceed the budget
+fn select_additive<'a, N: LodNode>(root: &'a N, root_size: f64, frustum: &Frustum, point_budget: u32) -> Vec<&'a N> {
+    let mut queue = BinaryHeap::from([Queued { screen_size: root_size, item: root }]);
+    let mut result = Vec::new();
+    let mut total = 0u32;
+    while let Some(Queued { screen_size, item: node }) = queue.pop() {
+        let count = node.point_count();
+        if total.saturating_add(count) > point_budget && !result.is_empty() {
+            break;
+        }
+        total += count;
+        if count > 0 {
+            result.push(node);
         }
+        if screen_size > REFINE_SCREEN_SIZE {
+            let children = visible_children(node, frustum);
+            queue.extend(children.into_iter().map(|(child, size)| Queued { screen_size: size, item: child }));
+        }
+    }
+    result
+}
 
-        // If leaf or screen-space error is small enough, use this node
-        let should_use_node = node.is_leaf() || screen_size < 200.0;
+/// Start from the root and replace the largest selected node by its
+/// children while they fit the budget
+fn select_replacing<'a, N: LodNode>(root: &'a N, root_size: f64, frustum: &Frustum, point_budget: u32) -> Vec<&'a N> {
+    // The cut so far; refined nodes are taken out
+    let mut cut = vec![Some((root, root_size))];
+    let mut queue = BinaryHeap::from([Queued { screen_size: root_size, item: 0 }]);
+    let mut total = root.point_count();
+    while let Some(Queued { screen_size, item: index }) = queue.pop() {
+        let (node, _) = cut[index].unwrap();
+        if screen_size <= REFINE_SCREEN_SIZE || !node.has_children() {
+            continue;
+        }
+        let children = visible_children(node, frustum);
+        let added: u32 = children.iter().map(|(c, _)| c.point_count()).fold(0, u32::saturating_add);
+        let replaced = total - node.point_count();
+        if replaced.saturating_add(added) > point_budget {
+            break;
+        }
+        total = replaced + added;
+        cut[index] = None;
+        for (child, size) in children {
+            queue.push(Queued { screen_size: size, item: cut.len() });
+            cut.push(Some((child, size)));
+        }
+    }
+
+    let mut result: Vec<(&N, f64)> = cut.into_iter().flatten().filter(|(n, _)| n.point_count() > 0).collect();
+    result.sort_by(|a, b| b.1.total_cmp(&a.1));
+    result.into_iter().map(|(n, _)| n).collect()
+}
+
+/// Children of a node in view and large enough on screen to select, with
+/// their size
+fn visible_children<'a, N: LodNode>(node: &'a N, frustum: &Frustum) -> Vec<(&'a N, f64)> {
+    node.children()
+        .iter()
+        .flatten()
+        .filter(|c| (c.point_count() > 0 || c.has_children()) && frustum.intersects(c.bounds()))
+        .map(|c| (&**c, frustum.screen_size(c.bounds())))
+        .filter(|(_, size)| *size >= MIN_SCREEN_SIZE)
+        .collect()
+}
+
+/// An entry of a selection queue, largest on screen first
+struct Queued<T> {
+    screen_size: f64,
+    item: T,
+}
 
-        if should_use_node && !node.points.is_empty() {
-            // Priority: distance / node_size (smaller = more important)
-            let priority = distance / node_size.max(0.001);
-            candidates.push((node.node_id.clone(), priority, node.point_count()));
+impl<T> PartialEq for Queued<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == CmpOrdering::Equal
+    }
+}
+
+impl<T> Eq for Queued<T> {}
+
+impl<T> PartialOrd for Queued<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Queued<T> {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        self.screen_size.total_cmp(&other.screen_size)
+    }
+}
+
+/// Pack the points of a node for GPU transfer
+pub fn pack_chunk(
+    node_id: &str,
+    bounds: &BoundingBox3D,
+    level: u8,
+    spacing: f32,
+    points: &PointBuffer,
+    mask: u32,
+) -> PointChunk {
+    // Wave packets are sent when the mask asks for them and the points have them
+    let wave_packets = points.wave_packets.as_deref().filter(|_| mask & point_attributes::WAVE_PACKETS != 0);
+    let mask = if wave_packets.is_some() { mask } else { mask & !point_attributes::WAVE_PACKETS };
+    let wave_packets = wave_packets.unwrap_or_default();
+    let extra_attributes = points.extra_attributes.iter().map(|v| v.iter().map(|&x| x as f32).collect()).collect();
+    let points = &points.points;
+    let center = bounds.center();
+    let count = points.len();
+
+    let has = |bit: u32| mask & bit != 0;
+    let optional_capacity = |bit: u32| if has(bit) { count } else { 0 };
+
+    let mut positions = Vec::with_capacity(count * 3);
+    let mut colors = Vec::with_capacity(count * 3);
+    let mut intensities = Vec::with_capacity(count);
+    let mut classifications = Vec::with_capacity(count);
+    let mut return_numbers = Vec::with_capacity(optional_capacity(point_attributes::RETURNS));
+    let mut number_of_returns = Vec::with_capacity(optional_capacity(point_attributes::RETURNS));
+    let mut flags = Vec::with_capacity(optional_capacity(point_attributes::FLAGS));
+    let mut scanner_channels = Vec::with_capacity(optional_capacity(point_attributes::SCANNER_CHANNEL));
+    let mut scan_angles = Vec::with_capacity(optional_capacity(point_attributes::SCAN_ANGLE));
+    let mut user_data = Vec::with_capacity(optional_capacity(point_attributes::USER_DATA));
+    let mut point_source_ids = Vec::with_capacity(optional_capacity(point_attributes::POINT_SOURCE_ID));
+    let mut gps_times = Vec::with_capacity(optional_capacity(point_attributes::GPS_TIME));
+    let mut nir = Vec::with_capacity(optional_capacity(point_attributes::NIR));
+
+    for p in points {
+        // Store positions relative to chunk center for double-precision workaround
+        positions.push((p.x - center[0]) as f32);
+        positions.push((p.y - center[1]) as f32);
+        positions.push((p.z - center[2]) as f32);
+        // Chunks carry 8-bit colors for display
+        colors.push((p.r >> 8) as u8);
+        colors.push((p.g >> 8) as u8);
+        colors.push((p.b >> 8) as u8);
+        intensities.push(p.intensity);
+        classifications.push(p.classification);
+
+        if has(point_attributes::RETURNS) {
+            return_numbers.push(p.return_number);
+            number_of_returns.push(p.number_of_returns);
+        }
+        if has(point_attributes::FLAGS) {
+            flags.push(p.flags);
+        }
+        if has(point_attributes::SCANNER_CHANNEL) {
+            scanner_channels.push(p.scanner_channel);
+        }
+        if has(point_attributes::SCAN_ANGLE) {
+            scan_angles.push(p.scan_angle);
         }
+        if has(point_attributes::USER_DATA) {
+            user_data.push(p.user_data);
+        }
+        if has(point_attributes::POINT_SOURCE_ID) {
+            point_source_ids.push(p.point_source_id);
+        }
+        if has(point_attributes::GPS_TIME) {
+            gps_times.push(p.gps_time);
+        }
+        if has(point_attributes::NIR) {
+            nir.push(p.nir);
+        }
+    }
+    let wave_directions = wave_packets.iter().flat_map(|w| [w.dx, w.dy, w.dz]).collect();
+
+    PointChunk {
+        node_id: node_id.to_string(),
+        center,
+        level,
+        spacing,
+        positions,
+        colors,
+        intensities,
+        classifications,
+        attribute_mask: mask,
+        return_numbers,
+        number_of_returns,
+        flags,
+        scanner_channels,
+        scan_angles,
+        user_data,
+        point_source_ids,
+        gps_times,
+        nir,
+        wave_descriptor_indices: wave_packets.iter().map(|w| w.descriptor_index).collect(),
+        wave_byte_offsets: wave_packets.iter().map(|w| w.byte_offset).collect(),
+        wave_sizes: wave_packets.iter().map(|w| w.size).collect(),
+        wave_return_locations: wave_packets.iter().map(|w| w.return_point_location).collect(),
+        wave_directions,
+        extra_attributes,
+        point_count: count as u32,
+    }
+}
 
-        // Recurse into children for higher detail
-        if !should_use_node || !node.is_leaf() {
-            for child in &node.children {
-                if let Some(ref c) = child {
-                    self.collect_visible(c, camera, candidates);
+/// Estimate the spacing of points spread over a node from its 2D surface
+/// footprint. LiDAR points lie on surfaces, so use the two largest bbox
+/// dimensions to estimate the area, then derive spacing as sqrt(area / pointCount).
+pub fn estimate_spacing(bounds: &BoundingBox3D, count: usize) -> f32 {
+    let s = bounds.size();
+    let mut dims = [s[0], s[1], s[2]];
+    dims.sort_by(|a, b| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+    let surface_area = dims[0] * dims[1]; // two largest dimensions
+    (surface_area / count as f64).sqrt() as f32
+}
+
+/// Distance between the points of a node of a built octree: the grid cell
+/// size for internal nodes, an estimate for leaves, which keep every point
+pub fn node_spacing(node: &OctreeNodeInfo) -> f32 {
+    if node.has_children {
+        (node.bounds.max_extent() / LOD_GRID) as f32
+    } else {
+        estimate_spacing(&node.bounds, node.point_count as usize)
+    }
+}
+
+/// Pick the point nearest the center of each occupied cell of a grid over
+/// `bounds` with `LOD_GRID` cells along its largest extent. Returns the
+/// indices of the picked positions in ascending order.
+pub fn grid_sample(positions: impl Iterator<Item = [f64; 3]>, bounds: &BoundingBox3D) -> Vec<usize> {
+    let cell_size = bounds.max_extent() / LOD_GRID;
+    if cell_size <= 0.0 {
+        // Every point is at the same spot; one stands for all
+        return positions.take(1).map(|_| 0).collect();
+    }
+    let min = [bounds.min_x, bounds.min_y, bounds.min_z];
+    let size = bounds.size();
+    let cells = size.map(|s| ((s / cell_size).ceil() as u64).max(1));
+
+    // Cell -> (squared distance to its center, index)
+    let mut picked: HashMap<u64, (f64, usize)> = HashMap::new();
+    for (i, p) in positions.enumerate() {
+        let mut key = 0u64;
+        let mut distance = 0.0;
+        for axis in 0..3 {
+            let v = (p[axis] - min[axis]) / cell_size;
+            let cell = (v.max(0.0) as u64).min(cells[axis] - 1);
+            let offset = v - (cell as f64 + 0.5);
+            distance += offset * offset;
+            key = key * cells[axis] + cell;
+        }
+        picked
+            .entry(key)
+            .and_modify(|best| {
+                if distance < best.0 {
+                    *best = (distance, i);
                 }
-            }
+            })
+            .or_insert((distance, i));
+    }
+
+    let mut indices: Vec<usize> = picked.into_values().map(|(_, i)| i).collect();
+    indices.sort_unstable();
+    indices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_cube() -> BoundingBox3D {
+        BoundingBox3D { min_x: 0.0, min_y: 0.0, min_z: 0.0, max_x: 1.0, max_y: 1.0, max_z: 1.0 }
+    }
+
+    #[test]
+    fn node_keys_round_trip_through_ids() {
+        for id in ["r", "r0", "r7", "r53", "r0123456701234567", &format!("r{}", "7".repeat(MORTON_BITS as usize))] {
+            let key = NodeKey::parse(id).unwrap();
+            assert_eq!(key.to_string(), id);
+            assert_eq!(key.level() as usize, id.len() - 1);
         }
+        assert_eq!(NodeKey::parse("r5").unwrap().child(3), NodeKey::parse("r53").unwrap());
+        assert_eq!(NodeKey::parse("r53").unwrap().octant_at(0), 5);
+        assert_eq!(NodeKey::parse("r53").unwrap().octant_at(1), 3);
     }
 
-    /// Collect all node infos for debugging/listing
-    pub fn all_node_infos(&self) -> Vec<OctreeNodeInfo> {
-        let mut infos = Vec::new();
-        self.collect_infos(&self.root, &mut infos);
-        infos
-    }
-
-    fn collect_infos(&self, node: &OctreeNode, infos: &mut Vec<OctreeNodeInfo>) {
-        infos.push(OctreeNodeInfo {
-            node_id: node.node_id.clone(),
-            bounds: node.bounds.clone(),
-            level: node.level,
-            point_count: node.point_count(),
-            has_children: node.has_children(),
-        });
-        for child in &node.children {
-            if let Some(ref c) = child {
-                self.collect_infos(c, infos);
-            }
+    #[test]
+    fn malformed_node_ids_are_rejected() {
+        let too_deep = format!("r{}", "0".repeat(MORTON_BITS as usize + 1));
+        for id in ["", "0", "x1", "r8", "r1a", "R1", too_deep.as_str()] {
+            assert_eq!(NodeKey::parse(id), None, "{} was accepted", id);
+        }
+    }
+
+    #[test]
+    fn morton_digits_name_the_node_and_cell() {
+        // Two bits per axis: x 3, y 1 and z 2, so octant 5 then octant 3
+        let code = morton_code(0.8, 0.3, 0.6, &unit_cube(), 2);
+        assert_eq!(code, 0o53);
+        let key = NodeKey::parse(&format!("r{:02o}", code)).unwrap();
+        assert_eq!(key.cell(), [3, 1, 2]);
+        // Points past the bounds clamp to the edge cells
+        assert_eq!(morton_code(-1.0, 2.0, 0.5, &unit_cube(), 2), 0o62);
+    }
+
+    #[test]
+    fn node_table_finds_every_node() {
+        let count = 200_000;
+        let points: Vec<PointRecord> = (0..count)
+            .map(|i| PointRecord {
+                x: (i % 1000) as f64 * 0.001,
+                y: (i / 1000) as f64 * 0.005,
+                z: (i % 7) as f64 * 0.1,
+                ..PointRecord::default()
+            })
+            .collect();
+        let octree = Octree::build(PointBuffer::from(points), unit_cube(), 0, true, &|_| {});
+        assert!(octree.nodes.iter().count() > 1);
+        for node in octree.nodes.iter() {
+            let found = octree.nodes.find(&node.info.node_id).unwrap();
+            assert_eq!(found.key, node.key);
+            assert_eq!(found.key.to_string(), node.info.node_id);
+            assert_eq!(found.data.len() as u32, node.info.point_count);
         }
+        assert_eq!(octree.nodes.iter().map(|n| n.data.len()).sum::<usize>(), count);
+        assert!(octree.nodes.find("r8").is_none());
     }
 }
@@ -1,25 +1,93 @@
 use std::fs::File;
-use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::io::{Cursor, Seek, SeekFrom};
 use std::path::Path;
+use std::sync::Mutex;
 use memmap2::Mmap;
-
-use super::types::{BoundingBox3D, PointRecord, PointcloudMetadata};
+use rayon::prelude::*;
+
+use super::crs::crs_from_vlrs;
+use super::extra_bytes::ExtraBytesLayout;
+use super::format::{las_is_compressed, PointcloudFormat};
+use super::types::{
+    point_attributes, point_flags, BoundingBox3D, ColorDepth, HeaderIssue, IssueSeverity, OpenOptions, PointBuffer,
+    PointRecord, PointcloudMetadata, VlrInfo, WavePacket,
+};
+use super::reader::PointReader;
+use super::vlr::{read_evlrs, read_vlrs, VlrRecord, EVLR_HEADER_SIZE};
+use super::writer::{
+    Waveform, WaveformData, GLOBAL_ENCODING_GPS_STANDARD_TIME, GLOBAL_ENCODING_WAVEFORM_EXTERNAL,
+};
 
 /// LAS file header (simplified for 1.2-1.4)
 #[derive(Debug)]
 struct LasHeader {
     version_major: u8,
     version_minor: u8,
+    /// Bit field of file-wide flags: GPS time type, waveform location, WKT
+    global_encoding: u16,
+    header_size: u16,
     point_data_format: u8,
     point_data_record_length: u16,
     number_of_points: u64,
+    /// 32-bit point count (the only count before LAS 1.4)
+    legacy_number_of_points: u32,
     offset_to_points: u32,
+    number_of_vlrs: u32,
+    /// Start of the waveform data packet record (LAS 1.3+, 0 if none)
+    start_of_waveform_data: u64,
+    /// Start and count of the extended VLRs (LAS 1.4)
+    start_of_first_evlr: u64,
+    number_of_evlrs: u32,
     scale: [f64; 3],
     offset: [f64; 3],
     min: [f64; 3],
     max: [f64; 3],
     has_color: bool,
     has_gps_time: bool,
+    has_nir: bool,
+}
+
+/// Byte offsets of the optional, format-dependent fields in a point record
+pub struct RecordLayout {
+    pub color: Option<usize>,
+    pub nir: Option<usize>,
+    pub wave_packet: Option<usize>,
+}
+
+/// Size in bytes of the standard fields of a point data record format.
+/// Anything beyond this in a record is Extra Bytes.
+pub fn standard_record_length(format: u8) -> usize {
+    match format {
+        0 => 20,
+        1 => 28,
+        2 => 26,
+        3 => 34,
+        4 => 57,
+        5 => 63,
+        6 => 30,
+        7 => 36,
+        8 => 38,
+        9 => 59,
+        10 => 67,
+        _ => 0,
+    }
+}
+
+/// Size of the wave packet fields of point formats 4, 5, 9 and 10
+pub const WAVE_PACKET_SIZE: usize = 29;
+
+/// Decode the wave packet fields of a point record
+fn decode_wave_packet(bytes: &[u8]) -> WavePacket {
+    let f32_at = |o: usize| f32::from_le_bytes(bytes[o..o + 4].try_into().unwrap());
+    WavePacket {
+        descriptor_index: bytes[0],
+        byte_offset: u64::from_le_bytes(bytes[1..9].try_into().unwrap()),
+        size: u32::from_le_bytes(bytes[9..13].try_into().unwrap()),
+        return_point_location: f32_at(13),
+        dx: f32_at(17),
+        dy: f32_at(21),
+        dz: f32_at(25),
+    }
 }
 
 /// Parse a LAS file header from memory-mapped data
@@ -40,26 +108,44 @@ fn parse_las_header(data: &[u8]) -> Result<LasHeader, String> {
         return Err(format!("Unsupported LAS version {}.{}", version_major, version_minor));
     }
 
+    let global_encoding = u16::from_le_bytes([data[6], data[7]]);
+    let header_size = u16::from_le_bytes([data[94], data[95]]);
     let offset_to_points = u32::from_le_bytes([data[96], data[97], data[98], data[99]]);
-    let point_data_format = data[104];
+    let number_of_vlrs = u32::from_le_bytes([data[100], data[101], data[102], data[103]]);
+    // Bits 6 and 7 of the format byte are the LASzip compression flags
+    let point_data_format = data[104] & 0x3F;
     let point_data_record_length = u16::from_le_bytes([data[105], data[106]]);
 
     // Point count: LAS 1.4 uses 64-bit at offset 247, older uses 32-bit at offset 107
+    let legacy_number_of_points = u32::from_le_bytes([data[107], data[108], data[109], data[110]]);
     let number_of_points = if version_minor >= 4 && data.len() >= 255 {
         u64::from_le_bytes([
             data[247], data[248], data[249], data[250],
             data[251], data[252], data[253], data[254],
         ])
     } else {
-        u32::from_le_bytes([data[107], data[108], data[109], data[110]]) as u64
+        legacy_number_of_points as u64
     };
 
-    let read_f64 = |off: usize| -> f64 {
-        f64::from_le_bytes([
+    let read_u64 = |off: usize| -> u64 {
+        u64::from_le_bytes([
             data[off], data[off + 1], data[off + 2], data[off + 3],
             data[off + 4], data[off + 5], data[off + 6], data[off + 7],
         ])
     };
+    let read_f64 = |off: usize| -> f64 { f64::from_bits(read_u64(off)) };
+
+    // LAS 1.3 adds the waveform data start at 227, LAS 1.4 the EVLR start/count at 235/243
+    let start_of_waveform_data = if version_minor >= 3 && data.len() >= 235 {
+        read_u64(227)
+    } else {
+        0
+    };
+    let (start_of_first_evlr, number_of_evlrs) = if version_minor >= 4 && data.len() >= 247 {
+        (read_u64(235), u32::from_le_bytes([data[243], data[244], data[245], data[246]]))
+    } else {
+        (0, 0)
+    };
 
     let scale = [read_f64(131), read_f64(139), read_f64(147)];
     let offset = [read_f64(155), read_f64(163), read_f64(171)];
@@ -73,84 +159,312 @@ fn parse_las_header(data: &[u8]) -> Result<LasHeader, String> {
     // Point formats with RGB: 2, 3, 5, 7, 8, 10
     let has_color = matches!(point_data_format, 2 | 3 | 5 | 7 | 8 | 10);
     let has_gps_time = matches!(point_data_format, 1 | 3 | 4 | 5 | 6 | 7 | 8 | 9 | 10);
+    let has_nir = matches!(point_data_format, 8 | 10);
 
     Ok(LasHeader {
         version_major,
         version_minor,
+        global_encoding,
+        header_size,
         point_data_format,
         point_data_record_length,
         number_of_points,
+        legacy_number_of_points,
         offset_to_points,
+        number_of_vlrs,
+        start_of_waveform_data,
+        start_of_first_evlr,
+        number_of_evlrs,
         scale,
         offset,
         min: [min_x, min_y, min_z],
         max: [max_x, max_y, max_z],
         has_color,
         has_gps_time,
+        has_nir,
     })
 }
 
+/// Check header fields against each other and against the file size.
+/// `vlrs` are the regular VLRs found between the header and the point data.
+fn validate_las_header(
+    data: &[u8],
+    header: &LasHeader,
+    vlrs: &[VlrRecord],
+    is_laz: bool,
+) -> Vec<HeaderIssue> {
+    let mut issues = Vec::new();
+    let file_len = data.len() as u64;
+    let offset_to_points = header.offset_to_points as u64;
+
+    if header.point_data_format > 10 {
+        issues.push(HeaderIssue::error(
+            "point_data_format",
+            "0-10",
+            header.point_data_format,
+            "Unknown point data record format",
+            false,
+        ));
+    }
+
+    let min_header_size = match header.version_minor {
+        0..=2 => 227,
+        3 => 235,
+        _ => 375,
+    };
+    if (header.header_size as u64) < min_header_size {
+        issues.push(HeaderIssue::error(
+            "header_size",
+            format!(">= {}", min_header_size),
+            header.header_size,
+            format!("Header is too small for LAS 1.{}", header.version_minor),
+            false,
+        ));
+    }
+
+    if offset_to_points < header.header_size as u64 || offset_to_points > file_len {
+        issues.push(HeaderIssue::error(
+            "offset_to_point_data",
+            format!("{}..={}", header.header_size, file_len),
+            offset_to_points,
+            "Offset to point data points outside the file or into the header",
+            false,
+        ));
+        return issues;
+    }
+
+    if (vlrs.len() as u64) < header.number_of_vlrs as u64 {
+        issues.push(HeaderIssue::warning(
+            "number_of_vlrs",
+            header.number_of_vlrs,
+            vlrs.len(),
+            "VLRs overlap the point data; the remaining records were skipped",
+        ));
+    }
+
+    let standard_len = standard_record_length(header.point_data_format);
+    if (header.point_data_record_length as usize) < standard_len {
+        issues.push(HeaderIssue::error(
+            "point_data_record_length",
+            format!(">= {}", standard_len),
+            header.point_data_record_length,
+            format!("Records are too short for point format {}", header.point_data_format),
+            false,
+        ));
+    }
+
+    if header.scale.iter().any(|s| !s.is_finite() || *s <= 0.0) {
+        issues.push(HeaderIssue::error(
+            "scale",
+            "finite values > 0",
+            format!("{:?}", header.scale),
+            "Invalid coordinate scale factors",
+            false,
+        ));
+    }
+
+    let bounds_valid = (0..3).all(|i| {
+        header.min[i].is_finite() && header.max[i].is_finite() && header.min[i] <= header.max[i]
+    });
+    if !bounds_valid {
+        issues.push(HeaderIssue::warning(
+            "bounds",
+            "finite min <= max",
+            format!("min {:?}, max {:?}", header.min, header.max),
+            "Header bounding box is invalid; open with trust_data to recompute it from the points",
+        ));
+    }
+
+    if header.version_minor >= 4
+        && header.legacy_number_of_points != 0
+        && header.legacy_number_of_points as u64 != header.number_of_points
+    {
+        issues.push(HeaderIssue::warning(
+            "legacy_number_of_point_records",
+            header.number_of_points,
+            header.legacy_number_of_points,
+            "Legacy and 64-bit point counts disagree; using the 64-bit count",
+        ));
+    }
+
+    if header.number_of_evlrs > 0 && header.start_of_first_evlr > file_len {
+        issues.push(HeaderIssue::warning(
+            "start_of_first_evlr",
+            format!("<= {}", file_len),
+            header.start_of_first_evlr,
+            "EVLRs start past the end of the file and were skipped",
+        ));
+    }
+
+    // Compressed record counts can only be checked by decoding
+    if !is_laz && standard_len > 0 && header.point_data_record_length as usize >= standard_len {
+        let available = records_available(data, header);
+        if header.number_of_points > available {
+            issues.push(HeaderIssue::error(
+                "number_of_point_records",
+                format!("<= {}", available),
+                header.number_of_points,
+                "File is shorter than the point count requires (truncated file?)",
+                true,
+            ));
+        } else if header.number_of_points < available {
+            issues.push(HeaderIssue::warning(
+                "number_of_point_records",
+                available,
+                header.number_of_points,
+                "File holds more point records than the header declares; extra records are ignored",
+            ));
+        }
+    }
+
+    issues
+}
+
+/// Number of whole uncompressed point records between the point data offset
+/// and the end of the point data (the first EVLR or waveform record, or EOF)
+fn records_available(data: &[u8], header: &LasHeader) -> u64 {
+    let start = header.offset_to_points as u64;
+    let mut end = data.len() as u64;
+    for candidate in [header.start_of_first_evlr, header.start_of_waveform_data] {
+        if candidate > start && candidate < end {
+            end = candidate;
+        }
+    }
+    let record_len = header.point_data_record_length.max(1) as u64;
+    end.saturating_sub(start) / record_len
+}
+
+/// Summarize header errors into one message for a failed open
+fn describe_issues(issues: &[&HeaderIssue]) -> String {
+    issues
+        .iter()
+        .map(|i| format!("{}: {} (expected {}, found {})", i.field, i.message, i.expected, i.actual))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Number of points inspected to tell 8-bit from 16-bit color
+const COLOR_SAMPLE_POINTS: u64 = 20_000;
+
 /// Memory-mapped LAS/LAZ parser
 pub struct PointcloudParser {
     mmap: Mmap,
     header: LasHeader,
     is_laz: bool,
+    vlrs: Vec<VlrRecord>,
+    extra_bytes: ExtraBytesLayout,
+    issues: Vec<HeaderIssue>,
+    /// Problems found while streaming the points, see `PointReader::stream_issues`
+    stream_issues: Mutex<Vec<HeaderIssue>>,
+    /// Recover from recoverable header errors using the point records themselves
+    trust_data: bool,
+    color_depth: ColorDepth,
 }
 
 impl PointcloudParser {
-    /// Open a LAS or LAZ file using memory-mapped I/O
-    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, String> {
-        let path = path.as_ref();
-        let ext = path.extension()
-            .and_then(|e| e.to_str())
-            .map(|e| e.to_lowercase())
-            .unwrap_or_default();
+    /// Open a LAS or LAZ file using memory-mapped I/O.
+    /// Compression is detected from the header, not the file extension.
+    ///
+    /// Header errors fail the open unless they are recoverable and
+    /// `options.trust_data` is set, in which case the point count is taken
+    /// from the records. Warnings are reported in the metadata.
+    pub fn open<P: AsRef<Path>>(path: P, options: &OpenOptions) -> Result<Self, String> {
+        let mut parser = Self::inspect(path)?;
+
+        let errors: Vec<&HeaderIssue> = parser.issues.iter()
+            .filter(|i| i.severity == IssueSeverity::Error)
+            .collect();
+        let fatal: Vec<&HeaderIssue> = errors.iter().copied().filter(|i| !i.recoverable).collect();
+        if !fatal.is_empty() {
+            return Err(format!("Invalid LAS header: {}", describe_issues(&fatal)));
+        }
+        if !errors.is_empty() && !options.trust_data {
+            return Err(format!(
+                "Invalid LAS header: {}. Open with trust_data to recover from the point records.",
+                describe_issues(&errors)
+            ));
+        }
+
+        parser.trust_data = options.trust_data;
+        if options.trust_data && !parser.is_laz {
+            parser.header.number_of_points = records_available(&parser.mmap, &parser.header);
+        }
 
-        let is_laz = ext == "laz";
+        if parser.header.has_color {
+            parser.color_depth = match options.color_depth {
+                Some(depth) => depth,
+                None => parser.detect_color_depth().unwrap_or_else(|e| {
+                    parser.issues.push(HeaderIssue::warning(
+                        "color_depth",
+                        "detected",
+                        "16bit",
+                        format!("Color depth detection failed, assuming 16-bit: {}", e),
+                    ));
+                    ColorDepth::Bit16
+                }),
+            };
+        }
 
-        let file = File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
+        Ok(parser)
+    }
+
+    /// Map the file, parse its header and VLRs and run the header validation,
+    /// without rejecting files that fail it.
+    pub fn inspect<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let file = File::open(path.as_ref()).map_err(|e| format!("Failed to open file: {}", e))?;
         let mmap = unsafe { Mmap::map(&file) }.map_err(|e| format!("Failed to mmap file: {}", e))?;
 
         let header = parse_las_header(&mmap)?;
+        let is_laz = las_is_compressed(&mmap);
+        let mut vlrs = read_vlrs(&mmap, header.offset_to_points as usize);
+        let mut issues = validate_las_header(&mmap, &header, &vlrs, is_laz);
+
+        // Extended VLRs live after the point data. LAS 1.3 has exactly one, the
+        // waveform data packet record; LAS 1.4 lists them from the EVLR start.
+        if header.number_of_evlrs > 0 && header.start_of_first_evlr > 0 {
+            vlrs.extend(read_evlrs(&mmap, header.start_of_first_evlr, header.number_of_evlrs));
+        } else if header.version_minor == 3 && header.start_of_waveform_data > 0 {
+            vlrs.extend(read_evlrs(&mmap, header.start_of_waveform_data, 1));
+        }
 
-        Ok(Self { mmap, header, is_laz })
-    }
-
-    /// Get metadata from the parsed header
-    pub fn metadata(&self, id: &str, file_path: &str) -> PointcloudMetadata {
-        let file_name = Path::new(file_path)
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("unknown")
-            .to_string();
+        // Extra Bytes VLR: user_id "LASF_Spec", record_id = 4
+        let mut extra_bytes = vlrs
+            .iter()
+            .find(|v| v.is("LASF_Spec", 4))
+            .map(|v| ExtraBytesLayout::parse(v.data(&mmap)))
+            .unwrap_or_default();
 
-        PointcloudMetadata {
-            id: id.to_string(),
-            file_path: file_path.to_string(),
-            file_name,
-            format: if self.is_laz { "LAZ".into() } else { "LAS".into() },
-            total_points: self.header.number_of_points,
-            bounds: BoundingBox3D {
-                min_x: self.header.min[0],
-                min_y: self.header.min[1],
-                min_z: self.header.min[2],
-                max_x: self.header.max[0],
-                max_y: self.header.max[1],
-                max_z: self.header.max[2],
-            },
-            has_color: self.header.has_color,
-            has_intensity: true,
-            has_classification: true,
-            point_record_format: self.header.point_data_format,
-            las_version: format!("{}.{}", self.header.version_major, self.header.version_minor),
+        // Ignore descriptors that claim more bytes than the records carry
+        let standard_len = standard_record_length(header.point_data_format);
+        let record_len = header.point_data_record_length as usize;
+        if standard_len + extra_bytes.size() > record_len {
+            issues.push(HeaderIssue::warning(
+                "extra_bytes",
+                format!("at most {} bytes", record_len.saturating_sub(standard_len)),
+                format!("{} bytes", extra_bytes.size()),
+                "Extra Bytes VLR does not fit the point record length, ignoring it",
+            ));
+            extra_bytes = ExtraBytesLayout::default();
         }
+
+        Ok(Self {
+            mmap,
+            header,
+            is_laz,
+            vlrs,
+            extra_bytes,
+            issues,
+            stream_issues: Mutex::new(Vec::new()),
+            trust_data: false,
+            color_depth: ColorDepth::Bit16,
+        })
     }
 
-    pub fn total_points(&self) -> u64 {
-        self.header.number_of_points
+    pub fn format(&self) -> PointcloudFormat {
+        if self.is_laz { PointcloudFormat::Laz } else { PointcloudFormat::Las }
     }
 
-    pub fn bounds(&self) -> BoundingBox3D {
+    fn header_bounds(&self) -> BoundingBox3D {
         BoundingBox3D {
             min_x: self.header.min[0],
             min_y: self.header.min[1],
@@ -161,216 +475,515 @@ impl PointcloudParser {
         }
     }
 
+    /// Header validation findings
+    pub fn issues(&self) -> &[HeaderIssue] {
+        &self.issues
+    }
+
+    /// Payload of the first VLR or EVLR with this user ID and record ID
+    pub fn vlr_data(&self, user_id: &str, record_id: u16) -> Option<&[u8]> {
+        self.vlrs.iter().find(|v| v.is(user_id, record_id)).map(|v| v.data(&self.mmap))
+    }
+
+    /// The mapped file, for records addressed by absolute offset (COPC hierarchy pages)
+    pub fn file_data(&self) -> &[u8] {
+        &self.mmap
+    }
+
+    /// Decompress the single LAZ chunk of `byte_count` bytes starting at byte
+    /// `offset` of the file. COPC stores each octree node as one such chunk.
+    /// A chunk cannot hold more points than the whole file.
+    pub fn decompress_chunk(&self, offset: u64, byte_count: u64, point_count: u64) -> Result<PointBuffer, String> {
+        let compressed = offset
+            .checked_add(byte_count)
+            .and_then(|end| self.mmap.get(offset as usize..end as usize))
+            .ok_or("LAZ chunk lies outside the file")?;
+        let record_len = self.header.point_data_record_length as usize;
+        let raw_len = usize::try_from(point_count)
+            .ok()
+            .filter(|_| point_count <= self.header.number_of_points)
+            .and_then(|count| count.checked_mul(record_len))
+            .ok_or_else(|| {
+                let total = self.header.number_of_points;
+                format!("LAZ chunk claims {} points, more than the file's {}", point_count, total)
+            })?;
+        let mut raw = vec![0u8; raw_len];
+        let chunk = laz::laszip::ChunkTableEntry { point_count, byte_count };
+        laz::par_decompress(compressed, &mut raw, &self.laszip_vlr()?, &[chunk])
+            .map_err(|e| format!("LAZ decompression error: {}", e))?;
+
+        Ok(self.decode_records(&raw))
+    }
+
     /// Read a range of points from an uncompressed LAS file.
-    pub fn read_points(&self, start_index: u64, count: u64) -> Result<Vec<PointRecord>, String> {
+    pub fn read_points(&self, start_index: u64, count: u64) -> Result<PointBuffer, String> {
         let record_len = self.header.point_data_record_length as u64;
         let data_start = self.header.offset_to_points as u64;
         let total = self.header.number_of_points;
 
+        // Records cut off by the end of the file are left out
         let actual_count = count.min(total.saturating_sub(start_index));
-        let mut points = Vec::with_capacity(actual_count as usize);
+        let start = data_start + start_index * record_len;
+        let available = (self.mmap.len() as u64).saturating_sub(start) / record_len;
+        let end = start + actual_count.min(available) * record_len;
+
+        Ok(self.decode_records(self.mmap.get(start as usize..end as usize).unwrap_or_default()))
+    }
 
+    /// Decode a single raw point record (LAS layout) into a `PointRecord`.
+    fn decode_record(&self, rec: &[u8]) -> PointRecord {
         let scale = &self.header.scale;
         let offset = &self.header.offset;
         let format = self.header.point_data_format;
-        let has_color = self.header.has_color;
 
-        // Color byte offset depends on point format
-        let color_offset = Self::color_byte_offset(format);
+        let u16_at = |o: usize| u16::from_le_bytes([rec[o], rec[o + 1]]);
+        let i32_at = |o: usize| i32::from_le_bytes([rec[o], rec[o + 1], rec[o + 2], rec[o + 3]]);
+        let f64_at = |o: usize| {
+            f64::from_le_bytes([
+                rec[o], rec[o + 1], rec[o + 2], rec[o + 3],
+                rec[o + 4], rec[o + 5], rec[o + 6], rec[o + 7],
+            ])
+        };
+
+        // X, Y, Z as i32 scaled
+        let mut point = PointRecord {
+            x: i32_at(0) as f64 * scale[0] + offset[0],
+            y: i32_at(4) as f64 * scale[1] + offset[1],
+            z: i32_at(8) as f64 * scale[2] + offset[2],
+            intensity: u16_at(12),
+            ..PointRecord::default()
+        };
+
+        if format >= 6 {
+            // Point Data Record Format 6+: 4-bit return fields, separate flags byte
+            point.return_number = rec[14] & 0x0F;
+            point.number_of_returns = rec[14] >> 4;
+            let flags = rec[15];
+            point.flags = flags & 0x0F; // synthetic, key-point, withheld, overlap
+            point.scanner_channel = (flags >> 4) & 0x03;
+            if flags & 0x40 != 0 {
+                point.flags |= point_flags::SCAN_DIRECTION;
+            }
+            if flags & 0x80 != 0 {
+                point.flags |= point_flags::EDGE_OF_FLIGHT_LINE;
+            }
+            point.classification = rec[16];
+            point.user_data = rec[17];
+            // Scan angle in increments of 0.006 degrees
+            point.scan_angle = i16::from_le_bytes([rec[18], rec[19]]) as f32 * 0.006;
+            point.point_source_id = u16_at(20);
+            point.gps_time = f64_at(22);
+        } else {
+            // Point Data Record Format 0-5: 3-bit return fields, flags packed with the class
+            let returns = rec[14];
+            point.return_number = returns & 0x07;
+            point.number_of_returns = (returns >> 3) & 0x07;
+            if returns & 0x40 != 0 {
+                point.flags |= point_flags::SCAN_DIRECTION;
+            }
+            if returns & 0x80 != 0 {
+                point.flags |= point_flags::EDGE_OF_FLIGHT_LINE;
+            }
+            point.classification = rec[15] & 0x1F;
+            point.flags |= rec[15] >> 5; // synthetic, key-point, withheld
+            point.scan_angle = rec[16] as i8 as f32;
+            point.user_data = rec[17];
+            point.point_source_id = u16_at(18);
+            if self.header.has_gps_time {
+                point.gps_time = f64_at(20);
+            }
+        }
 
-        for i in 0..actual_count {
-            let byte_offset = data_start + (start_index + i) * record_len;
-            let end = byte_offset + record_len;
+        let layout = Self::record_layout(format);
+
+        if let Some(co) = layout.color {
+            if co + 5 < rec.len() {
+                let to_u16 = |v: u16| match self.color_depth {
+                    ColorDepth::Bit8 => v.min(255) * 257,
+                    ColorDepth::Bit16 => v,
+                };
+                point.r = to_u16(u16_at(co));
+                point.g = to_u16(u16_at(co + 2));
+                point.b = to_u16(u16_at(co + 4));
+            }
+        }
 
-            if end as usize > self.mmap.len() {
-                break;
+        if let Some(no) = layout.nir {
+            if no + 1 < rec.len() {
+                point.nir = u16_at(no);
             }
+        }
 
-            let rec = &self.mmap[byte_offset as usize..end as usize];
+        point
+    }
 
-            // X, Y, Z as i32 scaled
-            let xi = i32::from_le_bytes([rec[0], rec[1], rec[2], rec[3]]);
-            let yi = i32::from_le_bytes([rec[4], rec[5], rec[6], rec[7]]);
-            let zi = i32::from_le_bytes([rec[8], rec[9], rec[10], rec[11]]);
+    /// Decode raw point records (LAS layout) in parallel, with the wave
+    /// packets of point formats that carry them and the Extra Bytes columns
+    fn decode_records(&self, raw: &[u8]) -> PointBuffer {
+        let record_len = self.header.point_data_record_length as usize;
+        let wave_offset = Self::record_layout(self.header.point_data_format).wave_packet;
+        let standard_len = standard_record_length(self.header.point_data_format);
+        PointBuffer {
+            points: raw.par_chunks_exact(record_len).map(|rec| self.decode_record(rec)).collect(),
+            wave_packets: wave_offset.map(|wo| {
+                raw.par_chunks_exact(record_len)
+                    .map(|rec| rec.get(wo..wo + WAVE_PACKET_SIZE).map(decode_wave_packet).unwrap_or_default())
+                    .collect()
+            }),
+            extra_attributes: (0..self.extra_bytes.len())
+                .map(|i| {
+                    raw.par_chunks_exact(record_len)
+                        .map(|rec| rec.get(standard_len..).map_or(f64::NAN, |extra| self.extra_bytes.decode(i, extra)))
+                        .collect()
+                })
+                .collect(),
+        }
+    }
 
-            let x = xi as f64 * scale[0] + offset[0];
-            let y = yi as f64 * scale[1] + offset[1];
-            let z = zi as f64 * scale[2] + offset[2];
+    /// Byte offsets of the format-dependent fields within a point record
+    pub fn record_layout(format: u8) -> RecordLayout {
+        let (color, nir, wave_packet) = match format {
+            2 => (Some(20), None, None),          // Format 2: XYZ(12) + Intensity(2) + Flags(2) + Angle/UserData(2) + SourceId(2) + RGB
+            3 => (Some(28), None, None),          // Format 3: like 2 but with GPS time (8 bytes) before RGB
+            4 => (None, None, Some(28)),          // Format 4: format 1 + wave packet
+            5 => (Some(28), None, Some(34)),      // Format 5: format 3 + wave packet
+            7 => (Some(30), None, None),          // Format 7: format 6 (30 bytes incl. GPS time) + RGB
+            8 => (Some(30), Some(36), None),      // Format 8: like 7 + NIR
+            9 => (None, None, Some(30)),          // Format 9: format 6 + wave packet
+            10 => (Some(30), Some(36), Some(38)), // Format 10: like 8 + wave packet
+            _ => (None, None, None),              // No color
+        };
+        RecordLayout { color, nir, wave_packet }
+    }
 
-            let intensity = u16::from_le_bytes([rec[12], rec[13]]);
+    /// Guess whether the color fields hold 8-bit or 16-bit values by sampling
+    /// records: if no channel ever exceeds 255 the file was written 8-bit.
+    /// Uncompressed files are sampled across the whole file, LAZ files from the start.
+    fn detect_color_depth(&self) -> Result<ColorDepth, String> {
+        let color_offset = match Self::record_layout(self.header.point_data_format).color {
+            Some(o) => o,
+            None => return Ok(ColorDepth::Bit16),
+        };
+        let record_len = self.header.point_data_record_length as usize;
+        if record_len < color_offset + 6 {
+            return Ok(ColorDepth::Bit16);
+        }
 
-            // Classification depends on format
-            let classification = if format >= 6 {
-                rec[16] // Point Data Record Format 6+
-            } else {
-                rec[15] // Point Data Record Format 0-5
-            };
+        let sample_count = self.header.number_of_points.min(COLOR_SAMPLE_POINTS) as usize;
+        let mut max_value = 0u16;
+        let mut track = |rec: &[u8]| {
+            for c in rec[color_offset..color_offset + 6].chunks_exact(2) {
+                max_value = max_value.max(u16::from_le_bytes([c[0], c[1]]));
+            }
+        };
 
-            let (r, g, b) = if has_color && color_offset > 0 {
-                let co = color_offset;
-                if co + 5 < rec.len() {
-                    let r16 = u16::from_le_bytes([rec[co], rec[co + 1]]);
-                    let g16 = u16::from_le_bytes([rec[co + 2], rec[co + 3]]);
-                    let b16 = u16::from_le_bytes([rec[co + 4], rec[co + 5]]);
-                    // LAS stores 16-bit color, scale to 8-bit
-                    ((r16 >> 8) as u8, (g16 >> 8) as u8, (b16 >> 8) as u8)
-                } else {
-                    (128, 128, 128)
+        if self.is_laz {
+            let mut decompressor = laz::LasZipDecompressor::new(self.point_data_cursor()?, self.laszip_vlr()?)
+                .map_err(|e| format!("Failed to create LAZ decompressor: {}", e))?;
+            let mut raw = vec![0u8; sample_count * record_len];
+            decompressor.decompress_many(&mut raw)
+                .map_err(|e| format!("LAZ decompression error: {}", e))?;
+            raw.chunks_exact(record_len).for_each(&mut track);
+        } else {
+            let stride = (self.header.number_of_points / sample_count.max(1) as u64).max(1);
+            for i in 0..sample_count as u64 {
+                let start = self.header.offset_to_points as usize + (i * stride) as usize * record_len;
+                match self.mmap.get(start..start + record_len) {
+                    Some(rec) => track(rec),
+                    None => break,
                 }
-            } else {
-                (128, 128, 128)
-            };
-
-            points.push(PointRecord {
-                x, y, z, r, g, b, intensity, classification,
-            });
+            }
         }
 
-        Ok(points)
+        // All-zero colors carry no information; keep the spec's 16-bit reading
+        Ok(if max_value > 0 && max_value <= 255 { ColorDepth::Bit8 } else { ColorDepth::Bit16 })
+    }
+
+    /// Find the LASzip VLR in the file header and return its data
+    fn find_laszip_vlr(&self) -> Result<&[u8], String> {
+        // LASzip VLR: user_id "laszip encoded", record_id = 22204
+        self.vlrs
+            .iter()
+            .find(|v| v.is("laszip encoded", 22204))
+            .map(|v| v.data(&self.mmap))
+            .ok_or_else(|| "LASzip VLR not found in LAZ file".into())
+    }
+
+    /// Parse the LASzip VLR describing the compressed point layout.
+    fn laszip_vlr(&self) -> Result<laz::LazVlr, String> {
+        let vlr_data = self.find_laszip_vlr()?;
+        laz::LazVlr::from_buffer(vlr_data)
+            .map_err(|e| format!("Failed to parse LASzip VLR: {}", e))
     }
 
-    /// Get byte offset to RGB color within a point record
-    fn color_byte_offset(format: u8) -> usize {
-        match format {
-            2 => 20,     // Format 2: XYZ(12) + Intensity(2) + Flags(4) + Classification(1) + ...
-            3 => 28,     // Format 3: like 2 but with GPS time (8 bytes)
-            5 => 28,     // Format 5: like 3
-            7 => 30,     // Format 7: XYZ(12) + Intensity(2) + ReturnInfo(2) + Flags(2) + Classification(1) + ... + GPSTime(8)
-            8 => 30,     // Format 8: like 7 + NIR
-            10 => 30,    // Format 10: like 8 + waveform
-            _ => 0,      // No color
+    /// Cursor over the mapped file, positioned at the start of the point data.
+    fn point_data_cursor(&self) -> Result<Cursor<&[u8]>, String> {
+        let mut cursor = Cursor::new(&self.mmap[..]);
+        cursor.seek(SeekFrom::Start(self.header.offset_to_points as u64))
+            .map_err(|e| format!("Failed to seek to point data: {}", e))?;
+        Ok(cursor)
+    }
+
+    /// Whether the points are compressed in chunks, which the LASzip VLR's
+    /// first field tells: 2 point-wise chunked, 3 layered chunked
+    fn is_chunked_laz(&self) -> bool {
+        self.find_laszip_vlr()
+            .ok()
+            .and_then(|data| data.get(..2))
+            .is_some_and(|compressor| matches!(u16::from_le_bytes([compressor[0], compressor[1]]), 2 | 3))
+    }
+
+    /// Read the LASzip chunk table. Returns None when the points are not
+    /// chunked, or the writer did not store one (e.g. streamed output that
+    /// was never finalized).
+    fn read_laz_chunk_table(&self, vlr: &laz::LazVlr) -> Option<laz::laszip::ChunkTable> {
+        if !self.is_chunked_laz() {
+            return None;
         }
+        let mut cursor = self.point_data_cursor().ok()?;
+        laz::laszip::ChunkTable::read_from(&mut cursor, vlr)
+            .ok()
+            .filter(|table| !table.is_empty())
     }
 
-    /// Find the LASzip VLR in the file header and return its data
-    fn find_laszip_vlr(&self) -> Result<Vec<u8>, String> {
-        let data = &self.mmap[..];
-        let header_size = self.header.offset_to_points as usize;
-
-        // VLRs start after the fixed header (94 bytes for LAS 1.2, varies by version)
-        let vlr_start = if self.header.version_minor >= 3 { 235usize } else { 227usize };
-        let num_vlrs = u32::from_le_bytes([data[100], data[101], data[102], data[103]]) as usize;
-
-        let mut offset = vlr_start;
-        for _ in 0..num_vlrs {
-            if offset + 54 > header_size { break; }
-
-            // VLR header: reserved(2) + user_id(16) + record_id(2) + record_length(2) + description(32)
-            let user_id = &data[offset + 2..offset + 18];
-            let record_id = u16::from_le_bytes([data[offset + 18], data[offset + 19]]);
-            let record_length = u16::from_le_bytes([data[offset + 20], data[offset + 21]]) as usize;
-
-            let vlr_data_start = offset + 54;
-            let vlr_data_end = vlr_data_start + record_length;
-
-            // LASzip VLR: user_id starts with "laszip encoded", record_id = 22204
-            if record_id == 22204 && user_id.starts_with(b"laszip encoded") {
-                if vlr_data_end <= data.len() {
-                    return Ok(data[vlr_data_start..vlr_data_end].to_vec());
-                }
+    /// Decompress LAZ points batch by batch, handing each batch to the callback
+    /// as soon as it is decoded. Only one batch is resident at a time.
+    fn stream_laz_points<F>(&self, batch_size: u64, callback: &mut F) -> Result<(), String>
+    where
+        F: FnMut(&PointBuffer, u64) -> bool + ?Sized,
+    {
+        let vlr = self.laszip_vlr()?;
+        let batch_size = batch_size.max(1);
+
+        // Files the parallel decompressor cannot take are decoded sequentially
+        let parallel = self.read_laz_chunk_table(&vlr).and_then(|table| {
+            let decompressor = laz::ParLasZipDecompressor::new(self.point_data_cursor().ok()?, vlr.clone()).ok()?;
+            Some((table, decompressor))
+        });
+        match parallel {
+            Some((table, decompressor)) => {
+                self.stream_laz_points_parallel(&vlr, &table, decompressor, batch_size, callback)
             }
-
-            offset = vlr_data_end;
+            None => self.stream_laz_points_sequential(vlr, batch_size, callback),
         }
+    }
 
-        Err("LASzip VLR not found in LAZ file".into())
+    /// A decode failure ends the stream. With `trust_data` the points decoded so far
+    /// are kept, since a header count larger than the compressed data fails here.
+    fn laz_decode_error<E: std::fmt::Display>(&self, error: E, decoded: u64) -> Result<(), String> {
+        if self.trust_data && decoded > 0 {
+            self.stream_issues.lock().unwrap().push(HeaderIssue::warning(
+                "number_of_points",
+                self.header.number_of_points,
+                decoded,
+                format!("LAZ data ends after {} points: {}", decoded, error),
+            ));
+            Ok(())
+        } else {
+            Err(format!("LAZ decompression error: {}", error))
+        }
     }
 
-    /// Decompress and read all points from a LAZ file using the laz crate.
-    fn read_laz_all_points(&self) -> Result<Vec<PointRecord>, String> {
-        let vlr_data = self.find_laszip_vlr()?;
-        let vlr = laz::LazVlr::from_buffer(&vlr_data)
-            .map_err(|e| format!("Failed to parse LASzip VLR: {}", e))?;
+    /// Single-threaded LAZ decoding, used when the file has no chunk table.
+    fn stream_laz_points_sequential<F>(
+        &self,
+        vlr: laz::LazVlr,
+        batch_size: u64,
+        callback: &mut F,
+    ) -> Result<(), String>
+    where
+        F: FnMut(&PointBuffer, u64) -> bool + ?Sized,
+    {
+        let mut decompressor = laz::LasZipDecompressor::new(self.point_data_cursor()?, vlr)
+            .map_err(|e| format!("Failed to create LAZ decompressor: {}", e))?;
 
-        let compressed_data = &self.mmap[..];
-        let mut cursor = Cursor::new(compressed_data);
-        cursor.seek(SeekFrom::Start(self.header.offset_to_points as u64))
-            .map_err(|e| format!("Failed to seek to point data: {}", e))?;
+        let total = self.header.number_of_points;
+        let record_len = self.header.point_data_record_length as usize;
 
-        let mut decompressor = laz::LasZipDecompressor::new(cursor, vlr)
-            .map_err(|e| format!("Failed to create LAZ decompressor: {}", e))?;
+        let mut raw = vec![0u8; batch_size as usize * record_len];
+        let mut offset = 0u64;
+
+        while offset < total {
+            let count = batch_size.min(total - offset) as usize;
+            let raw_batch = &mut raw[..count * record_len];
+            if let Err(e) = decompressor.decompress_many(raw_batch) {
+                return self.laz_decode_error(e, offset);
+            }
 
-        self.decompress_laz_points(&mut decompressor)
+            let points = self.decode_records(raw_batch);
+            if !callback(&points, offset) {
+                break;
+            }
+            offset += count as u64;
+        }
+
+        Ok(())
     }
 
-    /// Decompress LAZ points using the laz crate decompressor
-    fn decompress_laz_points<R: Read + Seek + Send>(
+    /// Chunk-parallel LAZ decoding. Each wave spans enough chunks to keep every
+    /// rayon worker busy; the decoded wave is then handed out in `batch_size` slices.
+    fn stream_laz_points_parallel<F>(
         &self,
-        decompressor: &mut laz::LasZipDecompressor<'_, R>,
-    ) -> Result<Vec<PointRecord>, String> {
-        let total = self.header.number_of_points as usize;
+        vlr: &laz::LazVlr,
+        chunk_table: &laz::laszip::ChunkTable,
+        mut decompressor: laz::ParLasZipDecompressor<Cursor<&[u8]>>,
+        batch_size: u64,
+        callback: &mut F,
+    ) -> Result<(), String>
+    where
+        F: FnMut(&PointBuffer, u64) -> bool + ?Sized,
+    {
+        let largest_chunk = if vlr.uses_variable_size_chunks() {
+            chunk_table.as_ref().iter().map(|c| c.point_count).max().unwrap_or(0)
+        } else {
+            vlr.chunk_size() as u64
+        };
+        let threads = rayon::current_num_threads().max(1) as u64;
+        let wave_size = (largest_chunk * threads).max(batch_size);
+
+        let total = self.header.number_of_points;
         let record_len = self.header.point_data_record_length as usize;
-        let scale = &self.header.scale;
-        let offset = &self.header.offset;
-        let format = self.header.point_data_format;
-        let has_color = self.header.has_color;
-        let color_offset = Self::color_byte_offset(format);
 
-        let mut points = Vec::with_capacity(total);
-        let mut record_buf = vec![0u8; record_len];
+        let mut raw = vec![0u8; wave_size.min(total) as usize * record_len];
+        let mut offset = 0u64;
 
-        for _ in 0..total {
-            decompressor.decompress_one(&mut record_buf)
-                .map_err(|e| format!("LAZ decompression error: {}", e))?;
+        while offset < total {
+            let count = wave_size.min(total - offset) as usize;
+            let raw_wave = &mut raw[..count * record_len];
+            if let Err(e) = decompressor.decompress_many(raw_wave) {
+                return self.laz_decode_error(e, offset);
+            }
 
-            let rec = &record_buf;
+            for (i, raw_batch) in raw_wave.chunks(batch_size as usize * record_len).enumerate() {
+                if !callback(&self.decode_records(raw_batch), offset + i as u64 * batch_size) {
+                    return Ok(());
+                }
+            }
+            offset += count as u64;
+        }
 
-            let xi = i32::from_le_bytes([rec[0], rec[1], rec[2], rec[3]]);
-            let yi = i32::from_le_bytes([rec[4], rec[5], rec[6], rec[7]]);
-            let zi = i32::from_le_bytes([rec[8], rec[9], rec[10], rec[11]]);
+        Ok(())
+    }
+}
 
-            let x = xi as f64 * scale[0] + offset[0];
-            let y = yi as f64 * scale[1] + offset[1];
-            let z = zi as f64 * scale[2] + offset[2];
+impl PointReader for PointcloudParser {
+    /// Get metadata from the parsed header
+    fn metadata(&self, id: &str, file_path: &str) -> PointcloudMetadata {
+        let file_name = Path::new(file_path)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("unknown")
+            .to_string();
 
-            let intensity = u16::from_le_bytes([rec[12], rec[13]]);
+        PointcloudMetadata {
+            id: id.to_string(),
+            file_path: file_path.to_string(),
+            file_name,
+            format: self.format().name().into(),
+            total_points: self.header.number_of_points,
+            bounds: self.header_bounds(),
+            has_color: self.header.has_color,
+            has_intensity: true,
+            has_classification: true,
+            has_returns: true,
+            has_gps_time: self.header.has_gps_time,
+            has_nir: self.header.has_nir,
+            point_record_format: self.header.point_data_format,
+            las_version: format!("{}.{}", self.header.version_major, self.header.version_minor),
+            extra_attributes: self.extra_bytes.infos(),
+            crs: crs_from_vlrs(&self.vlrs, &self.mmap),
+            color_depth: self.header.has_color.then_some(self.color_depth),
+            validation: self.issues.clone(),
+        }
+    }
 
-            let classification = if format >= 6 {
-                rec[16]
-            } else {
-                rec[15]
-            };
+    /// Describe every VLR and EVLR in the file
+    fn vlr_infos(&self) -> Vec<VlrInfo> {
+        self.vlrs.iter().map(|v| v.info(&self.mmap)).collect()
+    }
 
-            let (r, g, b) = if has_color && color_offset > 0 && color_offset + 5 < rec.len() {
-                let co = color_offset;
-                let r16 = u16::from_le_bytes([rec[co], rec[co + 1]]);
-                let g16 = u16::from_le_bytes([rec[co + 2], rec[co + 3]]);
-                let b16 = u16::from_le_bytes([rec[co + 4], rec[co + 5]]);
-                ((r16 >> 8) as u8, (g16 >> 8) as u8, (b16 >> 8) as u8)
-            } else {
-                (128, 128, 128)
-            };
+    fn standard_gps_time(&self) -> bool {
+        self.header.global_encoding & GLOBAL_ENCODING_GPS_STANDARD_TIME != 0
+    }
 
-            points.push(PointRecord {
-                x, y, z, r, g, b, intensity, classification,
-            });
-        }
+    fn projection_vlrs(&self) -> Vec<(u16, Vec<u8>)> {
+        self.vlrs
+            .iter()
+            .filter(|v| v.user_id == "LASF_Projection")
+            .map(|v| (v.record_id, v.data(&self.mmap).to_vec()))
+            .collect()
+    }
+
+    /// Waveform descriptors, and the data packet record or the .wdp file beside `file_path`
+    fn waveform(&self, file_path: &str) -> Option<Waveform> {
+        Self::record_layout(self.header.point_data_format).wave_packet?;
+        let descriptors = self
+            .vlrs
+            .iter()
+            .filter(|v| v.user_id == "LASF_Spec" && (100..=354).contains(&v.record_id) && !v.is_extended)
+            .map(|v| (v.record_id, v.data(&self.mmap).to_vec()))
+            .collect();
+        let data = if self.header.global_encoding & GLOBAL_ENCODING_WAVEFORM_EXTERNAL != 0 {
+            let wdp = Path::new(file_path).with_extension("wdp");
+            if !wdp.is_file() {
+                return None;
+            }
+            WaveformData::External(wdp)
+        } else {
+            let record = self.vlrs.iter().find(|v| v.is("LASF_Spec", 65535) && v.is_extended)?;
+            WaveformData::Internal {
+                path: file_path.into(),
+                offset: (record.data_offset - EVLR_HEADER_SIZE) as u64,
+                len: (record.data_len + EVLR_HEADER_SIZE) as u64,
+            }
+        };
+        Some(Waveform { descriptors, data })
+    }
+
+    fn total_points(&self) -> u64 {
+        self.header.number_of_points
+    }
 
-        Ok(points)
+    /// Header bounds, unless the header is not trusted
+    fn bounds(&self) -> Option<BoundingBox3D> {
+        (!self.trust_data).then(|| self.header_bounds())
+    }
+
+    fn scale(&self) -> [f64; 3] {
+        self.header.scale
+    }
+
+    /// Optional attributes carried by this file's point format
+    fn attribute_mask(&self) -> u32 {
+        let mut mask = point_attributes::RETURNS
+            | point_attributes::FLAGS
+            | point_attributes::SCAN_ANGLE
+            | point_attributes::USER_DATA
+            | point_attributes::POINT_SOURCE_ID;
+        if self.header.point_data_format >= 6 {
+            mask |= point_attributes::SCANNER_CHANNEL;
+        }
+        if self.header.has_gps_time {
+            mask |= point_attributes::GPS_TIME;
+        }
+        if self.header.has_nir {
+            mask |= point_attributes::NIR;
+        }
+        if Self::record_layout(self.header.point_data_format).wave_packet.is_some() {
+            mask |= point_attributes::WAVE_PACKETS;
+        }
+        mask
     }
 
     /// Streaming iterator over all points - works for both LAS and LAZ.
     /// Calls the callback for each batch of points.
-    pub fn stream_points<F>(&self, batch_size: u64, mut callback: F) -> Result<(), String>
-    where
-        F: FnMut(&[PointRecord], u64) -> bool, // return false to stop
-    {
+    fn stream_points(
+        &self,
+        batch_size: u64,
+        callback: &mut dyn FnMut(&PointBuffer, u64) -> bool,
+    ) -> Result<(), String> {
         if self.is_laz {
-            // For LAZ: decompress all points, then deliver in batches
-            let all_points = self.read_laz_all_points()?;
-            let total = all_points.len() as u64;
-            let mut offset = 0u64;
-
-            while offset < total {
-                let end = (offset + batch_size).min(total) as usize;
-                let batch = &all_points[offset as usize..end];
-                if !callback(batch, offset) {
-                    break;
-                }
-                offset = end as u64;
-            }
-
-            return Ok(());
+            return self.stream_laz_points(batch_size, callback);
         }
 
         // For uncompressed LAS: read in batches from memory-mapped file
@@ -394,4 +1007,8 @@ impl PointcloudParser {
 
         Ok(())
     }
+
+    fn stream_issues(&self) -> Vec<HeaderIssue> {
+        self.stream_issues.lock().unwrap().clone()
+    }
 }
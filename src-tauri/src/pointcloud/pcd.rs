@@ -0,0 +1,637 @@
+use std::fs::File;
+use std::path::Path;
+
+use memmap2::Mmap;
+use rayon::prelude::*;
+
+use super::ascii::next_line;
+use super::format::PointcloudFormat;
+use super::reader::{base_metadata, PointReader, ValueScale};
+use super::types::{BoundingBox3D, ExtraAttributeInfo, PointBuffer, PointColumns, PointRecord, PointcloudMetadata};
+
+/// Points sampled to detect the range of float intensities
+const SAMPLE_POINTS: u64 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PcdEncoding {
+    Ascii,
+    /// Row-major records
+    Binary,
+    /// LZF-compressed, column-major (all values of one field, then the next)
+    BinaryCompressed,
+}
+
+#[derive(Debug, Clone)]
+struct PcdField {
+    name: String,
+    size: usize,
+    /// 'F' float, 'I' signed or 'U' unsigned
+    ty: u8,
+    count: usize,
+    /// Byte offset within a row-major record
+    offset: usize,
+    /// Index of the first element in a decoded value row
+    index: usize,
+}
+
+impl PcdField {
+    fn read(&self, b: &[u8]) -> f64 {
+        macro_rules! read {
+            ($t:ty, $n:literal) => {{
+                let mut raw = [0u8; $n];
+                raw.copy_from_slice(&b[..$n]);
+                <$t>::from_le_bytes(raw) as f64
+            }};
+        }
+        match (self.ty, self.size) {
+            (b'F', 4) => read!(f32, 4),
+            (b'F', 8) => read!(f64, 8),
+            (b'U', 1) => b[0] as f64,
+            (b'U', 2) => read!(u16, 2),
+            (b'U', 4) => read!(u32, 4),
+            (b'U', 8) => read!(u64, 8),
+            (b'I', 1) => b[0] as i8 as f64,
+            (b'I', 2) => read!(i16, 2),
+            (b'I', 4) => read!(i32, 4),
+            (b'I', 8) => read!(i64, 8),
+            _ => f64::NAN,
+        }
+    }
+
+    /// Same naming as the Extra Bytes data types
+    fn type_name(&self) -> String {
+        let prefix = match self.ty {
+            b'F' => 'f',
+            b'I' => 'i',
+            _ => 'u',
+        };
+        format!("{}{}", prefix, self.size * 8)
+    }
+
+    /// The packed 0x00RRGGBB color word. Float fields hold the bits of the integer.
+    fn packed_color(&self, value: f64) -> u32 {
+        if self.ty == b'F' {
+            (value as f32).to_bits()
+        } else {
+            value as u32
+        }
+    }
+}
+
+/// Decoded-value indices of the fields we read
+#[derive(Debug, Clone)]
+struct PcdLayout {
+    x: usize,
+    y: usize,
+    z: usize,
+    /// Packed rgb/rgba field
+    rgb: Option<usize>,
+    /// Separate r/g/b fields
+    channels: Option<[usize; 3]>,
+    intensity: Option<(usize, ValueScale)>,
+    classification: Option<usize>,
+    /// Remaining single-valued fields, carried as extra attributes
+    extra: Vec<usize>,
+}
+
+/// Reader for Point Cloud Library files: ascii, binary and binary_compressed
+pub struct PcdReader {
+    mmap: Mmap,
+    encoding: PcdEncoding,
+    fields: Vec<PcdField>,
+    points: u64,
+    /// Byte offset of the point data
+    data_start: usize,
+    record_size: usize,
+    /// Number of values in a decoded row (fields times their counts)
+    row_len: usize,
+    /// Sensor pose: translation then rotation quaternion (w, x, y, z)
+    viewpoint: Option<[f64; 7]>,
+    layout: PcdLayout,
+}
+
+impl PcdReader {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let file = File::open(path.as_ref()).map_err(|e| format!("Failed to open file: {}", e))?;
+        let mmap = unsafe { Mmap::map(&file) }.map_err(|e| format!("Failed to mmap file: {}", e))?;
+
+        let mut pos = 0;
+        let mut names = Vec::new();
+        let mut sizes = Vec::new();
+        let mut types = Vec::new();
+        let mut counts = Vec::new();
+        let mut width = 0u64;
+        let mut height = 1u64;
+        let mut points = None;
+        let mut viewpoint = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0];
+
+        let encoding = loop {
+            let line = next_line(&mmap, &mut pos).ok_or("PCD header has no DATA line")?;
+            let line = std::str::from_utf8(line).map_err(|_| "PCD header is not valid text")?;
+            let mut parts = line.split_whitespace();
+            let Some(key) = parts.next() else { continue };
+            let values: Vec<&str> = parts.collect();
+            let number = |v: &str| v.parse::<u64>().map_err(|_| format!("Invalid PCD header line: {}", line));
+            match key.to_ascii_uppercase().as_str() {
+                "FIELDS" | "COLUMNS" => names = values.iter().map(|v| v.to_string()).collect(),
+                "SIZE" => sizes = values.iter().map(|v| number(v).map(|n| n as usize)).collect::<Result<_, _>>()?,
+                "TYPE" => types = values.iter().map(|v| v.as_bytes()[0].to_ascii_uppercase()).collect(),
+                "COUNT" => counts = values.iter().map(|v| number(v).map(|n| n as usize)).collect::<Result<_, _>>()?,
+                "WIDTH" => width = number(values.first().copied().unwrap_or(""))?,
+                "HEIGHT" => height = number(values.first().copied().unwrap_or(""))?,
+                "POINTS" => points = Some(number(values.first().copied().unwrap_or(""))?),
+                "VIEWPOINT" => {
+                    for (v, s) in viewpoint.iter_mut().zip(&values) {
+                        *v = s.parse().map_err(|_| format!("Invalid PCD header line: {}", line))?;
+                    }
+                }
+                "DATA" => {
+                    break match values.first().copied() {
+                        Some("ascii") => PcdEncoding::Ascii,
+                        Some("binary") => PcdEncoding::Binary,
+                        Some("binary_compressed") => PcdEncoding::BinaryCompressed,
+                        other => return Err(format!("Unsupported PCD data type: {}", other.unwrap_or(""))),
+                    };
+                }
+                _ => {} // comments and VERSION
+            }
+        };
+
+        if names.is_empty() {
+            return Err("PCD header has no FIELDS".into());
+        }
+        if counts.is_empty() {
+            counts = vec![1; names.len()];
+        }
+        if encoding == PcdEncoding::Ascii {
+            // Sizes and types only matter for the binary encodings
+            sizes.resize(names.len(), 4);
+            types.resize(names.len(), b'F');
+        }
+        if sizes.len() != names.len() || types.len() != names.len() || counts.len() != names.len() {
+            return Err("PCD header FIELDS, SIZE, TYPE and COUNT differ in length".into());
+        }
+
+        let mut fields = Vec::with_capacity(names.len());
+        let mut offset = 0;
+        let mut index = 0;
+        for (((name, size), ty), count) in names.into_iter().zip(sizes).zip(types).zip(counts) {
+            if !matches!((ty, size), (b'F', 4 | 8) | (b'I' | b'U', 1 | 2 | 4 | 8)) {
+                return Err(format!("Unsupported PCD field type {}{} for {}", ty as char, size, name));
+            }
+            if count == 0 {
+                return Err(format!("PCD field {} has COUNT 0", name));
+            }
+            let field_size = size.checked_mul(count).ok_or_else(|| format!("PCD field {} is too large", name))?;
+            fields.push(PcdField { name, size, ty, count, offset, index });
+            offset = offset.checked_add(field_size).ok_or("PCD record size overflows")?;
+            index += count;
+        }
+        if offset == 0 {
+            return Err("PCD records have no size".into());
+        }
+        if let Some(field) = fields.iter().find(|f| f.offset + f.size * f.count > offset) {
+            return Err(format!("PCD field {} runs past the end of the record", field.name));
+        }
+
+        let identity = viewpoint == [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0];
+        let mut reader = Self {
+            mmap,
+            encoding,
+            fields,
+            points: points.unwrap_or(width * height),
+            data_start: pos,
+            record_size: offset,
+            row_len: index,
+            viewpoint: (!identity).then_some(viewpoint),
+            layout: PcdLayout {
+                x: 0, y: 0, z: 0,
+                rgb: None,
+                channels: None,
+                intensity: None,
+                classification: None,
+                extra: Vec::new(),
+            },
+        };
+        if reader.encoding != PcdEncoding::Ascii && reader.data_len().is_none() {
+            return Err(format!("PCD point count {} is too large", reader.points));
+        }
+        reader.layout = reader.resolve_layout()?;
+        Ok(reader)
+    }
+
+    /// Bytes of binary point data the header declares; None when it overflows
+    fn data_len(&self) -> Option<usize> {
+        usize::try_from(self.points).ok()?.checked_mul(self.record_size)
+    }
+
+    fn field(&self, names: &[&str]) -> Option<&PcdField> {
+        self.fields.iter().find(|f| names.contains(&f.name.as_str()))
+    }
+
+    fn resolve_layout(&self) -> Result<PcdLayout, String> {
+        let (x, y, z) = match (self.field(&["x"]), self.field(&["y"]), self.field(&["z"])) {
+            (Some(x), Some(y), Some(z)) => (x.index, y.index, z.index),
+            _ => return Err("PCD file missing x/y/z fields".into()),
+        };
+
+        let rgb = self.field(&["rgb", "rgba"]).map(|f| f.index);
+        let channels = match (self.field(&["r"]), self.field(&["g"]), self.field(&["b"])) {
+            (Some(r), Some(g), Some(b)) if rgb.is_none() => Some([r.index, g.index, b.index]),
+            _ => None,
+        };
+        let classification = self.field(&["label", "classification"]).map(|f| f.index);
+
+        // Integer intensities have a fixed range; float ones are sampled
+        let intensity = self.field(&["intensity"]).map(|f| {
+            let scale = match (f.ty, f.size) {
+                (b'U' | b'I', 1) => ValueScale::Byte,
+                (b'U' | b'I', _) => ValueScale::Word,
+                _ => {
+                    let values: Vec<f64> = self
+                        .decode_rows(0, self.points.min(SAMPLE_POINTS))
+                        .unwrap_or_default()
+                        .chunks_exact(self.row_len)
+                        .map(|row| row[f.index])
+                        .filter(|v| v.is_finite())
+                        .collect();
+                    let min = values.iter().copied().fold(f64::MAX, f64::min);
+                    let max = values.iter().copied().fold(f64::MIN, f64::max);
+                    let fractional = values.iter().any(|v| v.fract() != 0.0);
+                    if min <= max { ValueScale::detect(min, max, fractional) } else { ValueScale::Unit }
+                }
+            };
+            (f.index, scale)
+        });
+
+        let mapped = [Some(x), Some(y), Some(z), rgb, intensity.map(|i| i.0), classification];
+        let extra = self.fields
+            .iter()
+            .filter(|f| f.count == 1 && f.name != "_")
+            .map(|f| f.index)
+            .filter(|i| !mapped.contains(&Some(*i)) && !channels.is_some_and(|c| c.contains(i)))
+            .collect();
+
+        Ok(PcdLayout { x, y, z, rgb, channels, intensity, classification, extra })
+    }
+
+    /// Decode points `start..start + count` into rows of field values, `row_len`
+    /// values per point. ASCII files are scanned from the start of the data.
+    fn decode_rows(&self, start: u64, count: u64) -> Result<Vec<f64>, String> {
+        match self.encoding {
+            PcdEncoding::Ascii => {
+                let mut pos = self.data_start;
+                for _ in 0..start {
+                    next_line(&self.mmap, &mut pos);
+                }
+                let lines: Vec<&[u8]> = (0..count).map_while(|_| next_line(&self.mmap, &mut pos)).collect();
+                Ok(self.decode_lines(&lines))
+            }
+            PcdEncoding::Binary => {
+                let bytes = |n: u64| {
+                    usize::try_from(n)
+                        .ok()
+                        .and_then(|n| n.checked_mul(self.record_size))
+                        .ok_or_else(|| format!("PCD point range {}..{} is too large", start, start + count))
+                };
+                let begin = self.data_start.saturating_add(bytes(start)?).min(self.mmap.len());
+                let end = begin.saturating_add(bytes(count)?).min(self.mmap.len());
+                Ok(self.decode_records(&self.mmap[begin..end]))
+            }
+            PcdEncoding::BinaryCompressed => {
+                let columns = self.decompress()?;
+                Ok(self.decode_columns(&columns, start, count))
+            }
+        }
+    }
+
+    /// Rows of the lines that hold a value for every field
+    fn decode_lines(&self, lines: &[&[u8]]) -> Vec<f64> {
+        lines
+            .par_iter()
+            .filter_map(|line| {
+                let row: Vec<f64> = std::str::from_utf8(line)
+                    .ok()?
+                    .split_ascii_whitespace()
+                    .take(self.row_len)
+                    .map(|v| v.parse().unwrap_or(f64::NAN))
+                    .collect();
+                (row.len() == self.row_len).then_some(row)
+            })
+            .flatten_iter()
+            .collect()
+    }
+
+    fn decode_records(&self, data: &[u8]) -> Vec<f64> {
+        data.par_chunks_exact(self.record_size)
+            .flat_map_iter(|rec| {
+                self.fields.iter().flat_map(move |field| {
+                    (0..field.count).map(move |k| field.read(&rec[field.offset + k * field.size..]))
+                })
+            })
+            .collect()
+    }
+
+    fn decode_columns(&self, columns: &[u8], start: u64, count: u64) -> Vec<f64> {
+        let end = (start + count).min(self.points);
+        (start..end)
+            .into_par_iter()
+            .flat_map_iter(|i| {
+                self.fields.iter().flat_map(move |field| {
+                    // A field's column starts after all points of the preceding fields
+                    let base = self.points as usize * field.offset + i as usize * field.size * field.count;
+                    (0..field.count).map(move |k| field.read(&columns[base + k * field.size..]))
+                })
+            })
+            .collect()
+    }
+
+    /// Inflate the binary_compressed block: compressed and uncompressed sizes, then LZF data
+    fn decompress(&self) -> Result<Vec<u8>, String> {
+        let data = &self.mmap[self.data_start.min(self.mmap.len())..];
+        if data.len() < 8 {
+            return Err("PCD compressed data is truncated".into());
+        }
+        let compressed = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
+        let uncompressed = u32::from_le_bytes([data[4], data[5], data[6], data[7]]) as usize;
+        let input = data.get(8..8 + compressed).ok_or("PCD compressed data is truncated")?;
+
+        let expected = self.data_len().ok_or("PCD point data size overflows")?;
+        let columns = lzf_decompress(input, uncompressed)?;
+        if columns.len() < expected {
+            return Err(format!("PCD compressed data holds {} bytes, expected {}", columns.len(), expected));
+        }
+        Ok(columns)
+    }
+
+    fn to_point(&self, row: &[f64]) -> Option<PointRecord> {
+        let l = &self.layout;
+        let (mut x, mut y, mut z) = (row[l.x], row[l.y], row[l.z]);
+        // Organized clouds mark missing measurements with NaN
+        if !(x.is_finite() && y.is_finite() && z.is_finite()) {
+            return None;
+        }
+        if let Some(vp) = &self.viewpoint {
+            (x, y, z) = apply_viewpoint(vp, x, y, z);
+        }
+
+        let mut point = PointRecord { x, y, z, ..Default::default() };
+        if let Some(i) = l.rgb {
+            let field = self.fields.iter().find(|f| f.index == i)?;
+            let packed = field.packed_color(row[i]);
+            point.r = ((packed >> 16) & 0xFF) as u16 * 257;
+            point.g = ((packed >> 8) & 0xFF) as u16 * 257;
+            point.b = (packed & 0xFF) as u16 * 257;
+        } else if let Some([r, g, b]) = l.channels {
+            point.r = ValueScale::Byte.to_u16(row[r]);
+            point.g = ValueScale::Byte.to_u16(row[g]);
+            point.b = ValueScale::Byte.to_u16(row[b]);
+        }
+        if let Some((i, scale)) = l.intensity {
+            if row[i].is_finite() {
+                point.intensity = scale.to_u16(row[i]);
+            }
+        }
+        if let Some(i) = l.classification {
+            point.classification = row[i] as u8;
+        }
+        Some(point)
+    }
+
+    /// Points of the rows with finite coordinates, with their extra attributes
+    fn to_points(&self, rows: &[f64]) -> PointBuffer {
+        let rows = rows.chunks_exact(self.row_len);
+        let columns = PointColumns { extra_count: self.layout.extra.len(), ..Default::default() };
+        let mut points = PointBuffer::with_capacity(columns, rows.len());
+        for row in rows {
+            if let Some(point) = self.to_point(row) {
+                points.points.push(point);
+                for (column, &i) in points.extra_attributes.iter_mut().zip(&self.layout.extra) {
+                    column.push(row[i]);
+                }
+            }
+        }
+        points
+    }
+}
+
+impl PointReader for PcdReader {
+    fn metadata(&self, id: &str, file_path: &str) -> PointcloudMetadata {
+        PointcloudMetadata {
+            has_color: self.layout.rgb.is_some() || self.layout.channels.is_some(),
+            has_intensity: self.layout.intensity.is_some(),
+            has_classification: self.layout.classification.is_some(),
+            extra_attributes: self.layout.extra
+                .iter()
+                .filter_map(|&i| self.fields.iter().find(|f| f.index == i))
+                .map(|f| ExtraAttributeInfo {
+                    name: f.name.clone(),
+                    description: String::new(),
+                    data_type: f.type_name(),
+                    scale: 1.0,
+                    offset: 0.0,
+                    no_data: None,
+                    min: None,
+                    max: None,
+                })
+                .collect(),
+            ..base_metadata(id, file_path, PointcloudFormat::Pcd, self.points, None)
+        }
+    }
+
+    fn total_points(&self) -> u64 {
+        self.points
+    }
+
+    fn bounds(&self) -> Option<BoundingBox3D> {
+        None
+    }
+
+    fn attribute_mask(&self) -> u32 {
+        0
+    }
+
+    fn stream_points(
+        &self,
+        batch_size: u64,
+        callback: &mut dyn FnMut(&PointBuffer, u64) -> bool,
+    ) -> Result<(), String> {
+        let batch_size = batch_size.max(1);
+        // Compressed data is inflated once; ASCII lines are walked with a cursor
+        let columns = match self.encoding {
+            PcdEncoding::BinaryCompressed => Some(self.decompress()?),
+            _ => None,
+        };
+        let mut line_pos = self.data_start;
+        let mut offset = 0;
+        let mut streamed = 0;
+
+        while offset < self.points {
+            let count = batch_size.min(self.points - offset);
+            let rows = match (self.encoding, &columns) {
+                (PcdEncoding::Ascii, _) => {
+                    let lines: Vec<&[u8]> =
+                        (0..count).map_while(|_| next_line(&self.mmap, &mut line_pos)).collect();
+                    if lines.is_empty() {
+                        break;
+                    }
+                    self.decode_lines(&lines)
+                }
+                (PcdEncoding::BinaryCompressed, Some(columns)) => self.decode_columns(columns, offset, count),
+                _ => {
+                    // Records cut off by the end of the file end the points
+                    let rows = self.decode_rows(offset, count)?;
+                    if rows.is_empty() {
+                        break;
+                    }
+                    rows
+                }
+            };
+
+            let points = self.to_points(&rows);
+            if !points.is_empty() {
+                if !callback(&points, streamed) {
+                    break;
+                }
+                streamed += points.len() as u64;
+            }
+            offset += count;
+        }
+
+        Ok(())
+    }
+}
+
+/// Rotate by the viewpoint quaternion, then translate
+fn apply_viewpoint(vp: &[f64; 7], x: f64, y: f64, z: f64) -> (f64, f64, f64) {
+    let [tx, ty, tz, qw, qx, qy, qz] = *vp;
+    // v' = v + 2w(q x v) + 2 q x (q x v)
+    let (cx, cy, cz) = (qy * z - qz * y, qz * x - qx * z, qx * y - qy * x);
+    let (cx, cy, cz) = (2.0 * cx, 2.0 * cy, 2.0 * cz);
+    (
+        x + qw * cx + (qy * cz - qz * cy) + tx,
+        y + qw * cy + (qz * cx - qx * cz) + ty,
+        z + qw * cz + (qx * cy - qy * cx) + tz,
+    )
+}
+
+/// Decompress an LZF block (liblzf format, as written by PCL)
+fn lzf_decompress(input: &[u8], out_len: usize) -> Result<Vec<u8>, String> {
+    let err = || "Corrupt LZF data in PCD file".to_string();
+    let mut out = Vec::with_capacity(out_len);
+    let mut i = 0;
+
+    while i < input.len() {
+        let ctrl = input[i] as usize;
+        i += 1;
+
+        if ctrl < 32 {
+            // Literal run of ctrl + 1 bytes
+            let run = input.get(i..i + ctrl + 1).ok_or_else(err)?;
+            out.extend_from_slice(run);
+            i += ctrl + 1;
+        } else {
+            // Back reference: length in the top 3 bits, extended by a byte when 7
+            let mut len = ctrl >> 5;
+            if len == 7 {
+                len += *input.get(i).ok_or_else(err)? as usize;
+                i += 1;
+            }
+            let low = *input.get(i).ok_or_else(err)? as usize;
+            i += 1;
+            let distance = ((ctrl & 0x1f) << 8) + low + 1;
+            let from = out.len().checked_sub(distance).ok_or_else(err)?;
+            // Overlapping copies repeat bytes, so copy one at a time
+            for k in 0..len + 2 {
+                out.push(out[from + k]);
+            }
+        }
+
+        if out.len() > out_len {
+            return Err(err());
+        }
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointcloud::reader::read_temp_file;
+
+    /// Open a PCD file with these contents and read all its points
+    fn read_pcd(name: &str, contents: &[u8]) -> Result<Vec<PointRecord>, String> {
+        read_temp_file(&format!("{}.pcd", name), contents, |path| PcdReader::open(path))
+    }
+
+    fn header(fields: &str, size: &str, ty: &str, count: &str, points: u64, data: &str) -> String {
+        format!(
+            "# .PCD v0.7\nVERSION 0.7\nFIELDS {}\nSIZE {}\nTYPE {}\nCOUNT {}\nWIDTH {}\nHEIGHT 1\nPOINTS {}\nDATA {}\n",
+            fields, size, ty, count, points, points, data
+        )
+    }
+
+    #[test]
+    fn ascii_points_are_read() {
+        let mut file = header("x y z intensity", "4 4 4 2", "F F F U", "1 1 1 1", 2, "ascii");
+        file.push_str("1.5 2.5 3.5 100\n-1 -2 -3 200\n");
+        let points = read_pcd("ascii", file.as_bytes()).unwrap();
+        assert_eq!(points.len(), 2);
+        assert_eq!((points[0].x, points[0].y, points[0].z), (1.5, 2.5, 3.5));
+        assert_eq!((points[1].x, points[1].y, points[1].z), (-1.0, -2.0, -3.0));
+    }
+
+    #[test]
+    fn binary_points_are_read() {
+        let mut file = header("x y z rgb", "4 4 4 4", "F F F U", "1 1 1 1", 2, "binary").into_bytes();
+        for (p, rgb) in [([1.0f32, 2.0, 3.0], 0x00ff_8000u32), ([4.0, 5.0, 6.0], 0x0000_00ff)] {
+            for v in p {
+                file.extend_from_slice(&v.to_le_bytes());
+            }
+            file.extend_from_slice(&rgb.to_le_bytes());
+        }
+        let points = read_pcd("binary", &file).unwrap();
+        assert_eq!(points.len(), 2);
+        assert_eq!((points[1].x, points[1].y, points[1].z), (4.0, 5.0, 6.0));
+        assert_eq!((points[0].r >> 8, points[0].g >> 8, points[0].b >> 8), (0xff, 0x80, 0));
+        assert_eq!((points[1].r >> 8, points[1].g >> 8, points[1].b >> 8), (0, 0, 0xff));
+    }
+
+    #[test]
+    fn malformed_headers_are_rejected() {
+        let cases = [
+            ("no-data", "FIELDS x y z\nSIZE 4 4 4\nTYPE F F F\nPOINTS 1\n".to_string()),
+            ("no-fields", "POINTS 1\nDATA ascii\n".to_string()),
+            ("lengths", header("x y z", "4 4", "F F F", "1 1 1", 1, "binary")),
+            ("count-zero", header("x y z", "4 4 4", "F F F", "1 0 1", 1, "binary")),
+            ("type", header("x y z", "4 4 3", "F F F", "1 1 1", 1, "binary")),
+            ("no-xyz", header("x y intensity", "4 4 4", "F F F", "1 1 1", 1, "binary")),
+            ("encoding", header("x y z", "4 4 4", "F F F", "1 1 1", 1, "packed")),
+            ("points", header("x y z", "4 4 4", "F F F", "1 1 1", u64::MAX, "binary")),
+            ("size", header("x y z", "4 4 4", "F F F", "1 1 4611686018427387904", 1, "binary")),
+        ];
+        for (name, file) in cases {
+            assert!(read_pcd(name, file.as_bytes()).is_err(), "{} was accepted", name);
+        }
+    }
+
+    #[test]
+    fn truncated_binary_data_ends_early() {
+        let mut file = header("x y z", "4 4 4", "F F F", "1 1 1", 10, "binary").into_bytes();
+        file.extend_from_slice(&[0u8; 18]);
+        assert_eq!(read_pcd("truncated", &file).unwrap().len(), 1);
+
+        // A point count far beyond the file stops at its end
+        let mut file = header("x y z", "4 4 4", "F F F", "1 1 1", 10_000_000_000_000, "binary").into_bytes();
+        file.extend_from_slice(&[0u8; 18]);
+        assert_eq!(read_pcd("overstated", &file).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn lzf_back_references_repeat_bytes() {
+        // Literal "ab", then copy 4 bytes from 2 back
+        assert_eq!(lzf_decompress(&[1, b'a', b'b', 2 << 5, 1], 6).unwrap(), b"ababab");
+        assert!(lzf_decompress(&[2 << 5, 5], 4).is_err());
+    }
+}
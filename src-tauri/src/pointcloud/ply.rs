@@ -0,0 +1,515 @@
+use std::fs::File;
+use std::path::Path;
+
+use memmap2::Mmap;
+use rayon::prelude::*;
+
+use super::ascii::next_line;
+use super::format::PointcloudFormat;
+use super::reader::{base_metadata, PointReader, ValueScale};
+use super::types::{BoundingBox3D, ExtraAttributeInfo, PointBuffer, PointColumns, PointRecord, PointcloudMetadata};
+
+/// Vertices sampled to detect the range of float colors and intensities
+const SAMPLE_VERTICES: u64 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PlyEncoding {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+/// Scalar property types (both the PLY 1.0 names and the sized aliases)
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PlyType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl PlyType {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => Self::I8,
+            "uchar" | "uint8" => Self::U8,
+            "short" | "int16" => Self::I16,
+            "ushort" | "uint16" => Self::U16,
+            "int" | "int32" => Self::I32,
+            "uint" | "uint32" => Self::U32,
+            "float" | "float32" => Self::F32,
+            "double" | "float64" => Self::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    /// Same naming as the Extra Bytes data types
+    fn name(self) -> &'static str {
+        match self {
+            Self::I8 => "i8",
+            Self::U8 => "u8",
+            Self::I16 => "i16",
+            Self::U16 => "u16",
+            Self::I32 => "i32",
+            Self::U32 => "u32",
+            Self::F32 => "f32",
+            Self::F64 => "f64",
+        }
+    }
+
+    fn read(self, b: &[u8], big_endian: bool) -> f64 {
+        macro_rules! read {
+            ($t:ty, $n:literal) => {{
+                let mut raw = [0u8; $n];
+                raw.copy_from_slice(&b[..$n]);
+                if big_endian { <$t>::from_be_bytes(raw) as f64 } else { <$t>::from_le_bytes(raw) as f64 }
+            }};
+        }
+        match self {
+            Self::I8 => b[0] as i8 as f64,
+            Self::U8 => b[0] as f64,
+            Self::I16 => read!(i16, 2),
+            Self::U16 => read!(u16, 2),
+            Self::I32 => read!(i32, 4),
+            Self::U32 => read!(u32, 4),
+            Self::F32 => read!(f32, 4),
+            Self::F64 => read!(f64, 8),
+        }
+    }
+
+    /// Value range of integer types; floats have to be sampled
+    fn scale(self) -> Option<ValueScale> {
+        match self {
+            Self::U8 | Self::I8 => Some(ValueScale::Byte),
+            Self::U16 | Self::I16 => Some(ValueScale::Word),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct PlyProperty {
+    name: String,
+    ty: PlyType,
+    /// Count type of a list property
+    list_count: Option<PlyType>,
+}
+
+#[derive(Debug, Clone)]
+struct PlyElement {
+    name: String,
+    count: u64,
+    properties: Vec<PlyProperty>,
+}
+
+impl PlyElement {
+    /// Byte size of one binary record, None when it has list properties
+    fn record_size(&self) -> Option<usize> {
+        self.properties
+            .iter()
+            .map(|p| p.list_count.is_none().then_some(p.ty.size()))
+            .sum()
+    }
+}
+
+/// Vertex property indices of the fields we read
+#[derive(Debug, Clone)]
+struct VertexLayout {
+    x: usize,
+    y: usize,
+    z: usize,
+    color: Option<([usize; 3], ValueScale)>,
+    intensity: Option<(usize, ValueScale)>,
+    classification: Option<usize>,
+    /// Remaining scalar properties, carried as extra attributes
+    extra: Vec<usize>,
+}
+
+/// Reader for ASCII and binary PLY files. Only the vertex element is read.
+pub struct PlyReader {
+    mmap: Mmap,
+    encoding: PlyEncoding,
+    properties: Vec<PlyProperty>,
+    vertex_count: u64,
+    /// Byte offset of the first vertex
+    data_start: usize,
+    /// Byte offset of each property within a binary vertex record
+    offsets: Vec<usize>,
+    record_size: usize,
+    layout: VertexLayout,
+}
+
+impl PlyReader {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let file = File::open(path.as_ref()).map_err(|e| format!("Failed to open file: {}", e))?;
+        let mmap = unsafe { Mmap::map(&file) }.map_err(|e| format!("Failed to mmap file: {}", e))?;
+
+        // Header: ply / format / element / property lines up to end_header
+        let mut pos = 0;
+        let mut encoding = None;
+        let mut elements: Vec<PlyElement> = Vec::new();
+        loop {
+            let line = next_line(&mmap, &mut pos).ok_or("PLY header has no end_header")?;
+            let line = std::str::from_utf8(line).map_err(|_| "PLY header is not valid text")?;
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            match parts.as_slice() {
+                ["end_header"] => break,
+                ["format", "ascii", ..] => encoding = Some(PlyEncoding::Ascii),
+                ["format", "binary_little_endian", ..] => encoding = Some(PlyEncoding::BinaryLittleEndian),
+                ["format", "binary_big_endian", ..] => encoding = Some(PlyEncoding::BinaryBigEndian),
+                ["format", other, ..] => return Err(format!("Unsupported PLY format: {}", other)),
+                ["element", name, count] => elements.push(PlyElement {
+                    name: name.to_string(),
+                    count: count.parse().map_err(|_| format!("Invalid PLY element count: {}", line))?,
+                    properties: Vec::new(),
+                }),
+                ["property", "list", count_ty, ty, name] => {
+                    let element = elements.last_mut().ok_or("PLY property before any element")?;
+                    element.properties.push(PlyProperty {
+                        name: name.to_string(),
+                        ty: PlyType::from_name(ty).ok_or_else(|| format!("Unknown PLY type: {}", ty))?,
+                        list_count: Some(
+                            PlyType::from_name(count_ty).ok_or_else(|| format!("Unknown PLY type: {}", count_ty))?,
+                        ),
+                    });
+                }
+                ["property", ty, name] => {
+                    let element = elements.last_mut().ok_or("PLY property before any element")?;
+                    element.properties.push(PlyProperty {
+                        name: name.to_string(),
+                        ty: PlyType::from_name(ty).ok_or_else(|| format!("Unknown PLY type: {}", ty))?,
+                        list_count: None,
+                    });
+                }
+                _ => {} // ply magic, comment, obj_info
+            }
+        }
+        let encoding = encoding.ok_or("PLY header has no format line")?;
+
+        // Skip the elements stored before the vertices
+        let vertex_index = elements.iter()
+            .position(|e| e.name == "vertex")
+            .ok_or("PLY file has no vertex element")?;
+        for element in &elements[..vertex_index] {
+            if encoding == PlyEncoding::Ascii {
+                for _ in 0..element.count {
+                    next_line(&mmap, &mut pos);
+                }
+            } else {
+                let size = element.record_size()
+                    .ok_or_else(|| format!("PLY element {} with lists precedes the vertices", element.name))?;
+                pos = usize::try_from(element.count)
+                    .ok()
+                    .and_then(|count| size.checked_mul(count))
+                    .and_then(|bytes| pos.checked_add(bytes))
+                    .filter(|&end| end <= mmap.len())
+                    .ok_or_else(|| format!("PLY element {} runs past the end of the file", element.name))?;
+            }
+        }
+
+        let vertex = elements.swap_remove(vertex_index);
+        if vertex.properties.iter().any(|p| p.list_count.is_some()) {
+            return Err("PLY vertex element with list properties is not supported".into());
+        }
+
+        let mut offsets = Vec::with_capacity(vertex.properties.len());
+        let mut record_size = 0;
+        for p in &vertex.properties {
+            offsets.push(record_size);
+            record_size += p.ty.size();
+        }
+
+        let mut reader = Self {
+            mmap,
+            encoding,
+            properties: vertex.properties,
+            vertex_count: vertex.count,
+            data_start: pos,
+            offsets,
+            record_size,
+            layout: VertexLayout {
+                x: 0, y: 0, z: 0,
+                color: None,
+                intensity: None,
+                classification: None,
+                extra: Vec::new(),
+            },
+        };
+        reader.layout = reader.resolve_layout()?;
+        Ok(reader)
+    }
+
+    fn property(&self, names: &[&str]) -> Option<usize> {
+        self.properties.iter().position(|p| p.list_count.is_none() && names.contains(&p.name.as_str()))
+    }
+
+    fn resolve_layout(&self) -> Result<VertexLayout, String> {
+        let (x, y, z) = match (self.property(&["x"]), self.property(&["y"]), self.property(&["z"])) {
+            (Some(x), Some(y), Some(z)) => (x, y, z),
+            _ => return Err("PLY file missing x/y/z vertex properties".into()),
+        };
+
+        let color = match (
+            self.property(&["red", "r", "diffuse_red"]),
+            self.property(&["green", "g", "diffuse_green"]),
+            self.property(&["blue", "b", "diffuse_blue"]),
+        ) {
+            (Some(r), Some(g), Some(b)) => Some([r, g, b]),
+            _ => None,
+        };
+        let intensity = self.property(&["intensity", "scalar_intensity", "scalar_Intensity"]);
+        let classification = self.property(&["classification", "scalar_classification", "scalar_Classification"]);
+
+        // Integer types fix the value range; floats are sampled
+        let sample = |indices: &[usize]| {
+            let mut pos = self.data_start;
+            let count = self.vertex_count.min(SAMPLE_VERTICES);
+            let values: Vec<f64> = self
+                .read_vertices(&mut pos, count)
+                .chunks_exact(self.properties.len())
+                .flat_map(|v| indices.iter().map(|&i| v[i]))
+                .collect();
+            let min = values.iter().copied().fold(f64::MAX, f64::min);
+            let max = values.iter().copied().fold(f64::MIN, f64::max);
+            let fractional = values.iter().any(|v| v.fract() != 0.0);
+            if min <= max { ValueScale::detect(min.max(0.0), max, fractional) } else { ValueScale::Byte }
+        };
+        let color = color.map(|c| (c, self.properties[c[0]].ty.scale().unwrap_or_else(|| sample(&c))));
+        let intensity = intensity.map(|i| {
+            let ty = self.properties[i].ty;
+            (i, ty.scale().unwrap_or_else(|| sample(&[i])))
+        });
+
+        let mapped = [Some(x), Some(y), Some(z), intensity.map(|i| i.0), classification];
+        let extra = (0..self.properties.len())
+            .filter(|i| self.properties[*i].list_count.is_none())
+            .filter(|i| !mapped.contains(&Some(*i)) && !color.is_some_and(|(c, _)| c.contains(i)))
+            .filter(|i| !matches!(self.properties[*i].name.as_str(), "alpha" | "diffuse_alpha" | "a"))
+            .collect();
+
+        Ok(VertexLayout { x, y, z, color, intensity, classification, extra })
+    }
+
+    /// Decode up to `count` vertices at byte offset `pos` into property values,
+    /// one row of `properties.len()` values per vertex. Advances `pos` past the
+    /// vertices read.
+    fn read_vertices(&self, pos: &mut usize, count: u64) -> Vec<f64> {
+        if self.encoding == PlyEncoding::Ascii {
+            let lines: Vec<&[u8]> = (0..count).map_while(|_| next_line(&self.mmap, pos)).collect();
+            return lines
+                .par_iter()
+                .filter_map(|line| {
+                    let values: Vec<f64> = std::str::from_utf8(line)
+                        .ok()?
+                        .split_ascii_whitespace()
+                        .take(self.properties.len())
+                        .map(|v| v.parse().unwrap_or(f64::NAN))
+                        .collect();
+                    (values.len() == self.properties.len()).then_some(values)
+                })
+                .flatten_iter()
+                .collect();
+        }
+
+        let big_endian = self.encoding == PlyEncoding::BinaryBigEndian;
+        let begin = (*pos).min(self.mmap.len());
+        let end = (begin + count as usize * self.record_size).min(self.mmap.len());
+        *pos = end;
+        self.mmap[begin..end]
+            .par_chunks_exact(self.record_size)
+            .flat_map_iter(|rec| {
+                self.properties
+                    .iter()
+                    .zip(&self.offsets)
+                    .map(move |(p, &o)| p.ty.read(&rec[o..], big_endian))
+            })
+            .collect()
+    }
+
+    /// Points of the vertex rows with finite coordinates, with their extra attributes
+    fn to_points(&self, values: &[f64]) -> PointBuffer {
+        let rows = values.chunks_exact(self.properties.len());
+        let columns = PointColumns { extra_count: self.layout.extra.len(), ..Default::default() };
+        let mut points = PointBuffer::with_capacity(columns, rows.len());
+        for v in rows {
+            if let Some(point) = self.to_point(v) {
+                points.points.push(point);
+                for (column, &i) in points.extra_attributes.iter_mut().zip(&self.layout.extra) {
+                    column.push(v[i]);
+                }
+            }
+        }
+        points
+    }
+
+    fn to_point(&self, v: &[f64]) -> Option<PointRecord> {
+        let l = &self.layout;
+        let (x, y, z) = (v[l.x], v[l.y], v[l.z]);
+        if !(x.is_finite() && y.is_finite() && z.is_finite()) {
+            return None;
+        }
+
+        let mut point = PointRecord { x, y, z, ..Default::default() };
+        if let Some(([r, g, b], scale)) = l.color {
+            point.r = scale.to_u16(v[r]);
+            point.g = scale.to_u16(v[g]);
+            point.b = scale.to_u16(v[b]);
+        }
+        if let Some((i, scale)) = l.intensity {
+            point.intensity = scale.to_u16(v[i]);
+        }
+        if let Some(i) = l.classification {
+            point.classification = v[i] as u8;
+        }
+        Some(point)
+    }
+}
+
+impl PointReader for PlyReader {
+    fn metadata(&self, id: &str, file_path: &str) -> PointcloudMetadata {
+        PointcloudMetadata {
+            has_color: self.layout.color.is_some(),
+            has_intensity: self.layout.intensity.is_some(),
+            has_classification: self.layout.classification.is_some(),
+            extra_attributes: self.layout.extra
+                .iter()
+                .map(|&i| ExtraAttributeInfo {
+                    name: self.properties[i].name.clone(),
+                    description: String::new(),
+                    data_type: self.properties[i].ty.name().into(),
+                    scale: 1.0,
+                    offset: 0.0,
+                    no_data: None,
+                    min: None,
+                    max: None,
+                })
+                .collect(),
+            ..base_metadata(id, file_path, PointcloudFormat::Ply, self.vertex_count, None)
+        }
+    }
+
+    fn total_points(&self) -> u64 {
+        self.vertex_count
+    }
+
+    fn bounds(&self) -> Option<BoundingBox3D> {
+        None
+    }
+
+    fn attribute_mask(&self) -> u32 {
+        0
+    }
+
+    fn stream_points(
+        &self,
+        batch_size: u64,
+        callback: &mut dyn FnMut(&PointBuffer, u64) -> bool,
+    ) -> Result<(), String> {
+        let batch_size = batch_size.max(1);
+        let mut pos = self.data_start;
+        let mut offset = 0;
+        let mut streamed = 0;
+
+        while offset < self.vertex_count && pos < self.mmap.len() {
+            let count = batch_size.min(self.vertex_count - offset);
+            let points = self.to_points(&self.read_vertices(&mut pos, count));
+            if !points.is_empty() {
+                if !callback(&points, streamed) {
+                    break;
+                }
+                streamed += points.len() as u64;
+            }
+            offset += count;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointcloud::reader::read_temp_file;
+
+    /// Open a PLY file with these contents and read all its points
+    fn read_ply(name: &str, contents: &[u8]) -> Result<Vec<PointRecord>, String> {
+        read_temp_file(&format!("{}.ply", name), contents, |path| PlyReader::open(path))
+    }
+
+    const VERTEX: &str = "element vertex 2\nproperty float x\nproperty float y\nproperty float z\n\
+                          property uchar red\nproperty uchar green\nproperty uchar blue\n";
+
+    #[test]
+    fn ascii_vertices_are_read() {
+        let file = format!("ply\nformat ascii 1.0\ncomment test\n{}end_header\n1 2 3 255 0 0\n4 5 6 0 0 255\n", VERTEX);
+        let points = read_ply("ascii", file.as_bytes()).unwrap();
+        assert_eq!(points.len(), 2);
+        assert_eq!((points[1].x, points[1].y, points[1].z), (4.0, 5.0, 6.0));
+        assert_eq!((points[0].r >> 8, points[0].b >> 8), (255, 0));
+        assert_eq!((points[1].r >> 8, points[1].b >> 8), (0, 255));
+    }
+
+    #[test]
+    fn binary_vertices_are_read_in_either_byte_order() {
+        for (format, big_endian) in [("binary_little_endian", false), ("binary_big_endian", true)] {
+            // A face element before the vertices is skipped
+            let header = format!(
+                "ply\nformat {} 1.0\nelement material 1\nproperty int id\n{}end_header\n",
+                format, VERTEX
+            );
+            let mut file = header.into_bytes();
+            file.extend_from_slice(&[0; 4]);
+            for (p, color) in [([1.0f32, 2.0, 3.0], [10u8, 20, 30]), ([-1.0, -2.0, -3.0], [40, 50, 60])] {
+                for v in p {
+                    file.extend_from_slice(&if big_endian { v.to_be_bytes() } else { v.to_le_bytes() });
+                }
+                file.extend_from_slice(&color);
+            }
+            let points = read_ply(format, &file).unwrap();
+            assert_eq!(points.len(), 2);
+            assert_eq!((points[0].x, points[0].y, points[0].z), (1.0, 2.0, 3.0));
+            assert_eq!((points[1].x, points[1].y, points[1].z), (-1.0, -2.0, -3.0));
+            assert_eq!(points[1].g >> 8, 50);
+        }
+    }
+
+    #[test]
+    fn malformed_headers_are_rejected() {
+        let xyz = "element vertex 1\nproperty float x\nproperty float y\nproperty float z\n";
+        let cases = [
+            ("no-end", format!("ply\nformat ascii 1.0\n{}", xyz)),
+            ("no-format", format!("ply\n{}end_header\n", xyz)),
+            ("format", format!("ply\nformat binary_middle_endian 1.0\n{}end_header\n", xyz)),
+            ("type", "ply\nformat ascii 1.0\nelement vertex 1\nproperty float128 x\nend_header\n".to_string()),
+            ("orphan", "ply\nformat ascii 1.0\nproperty float x\nend_header\n".to_string()),
+            ("count", "ply\nformat ascii 1.0\nelement vertex many\nend_header\n".to_string()),
+            ("no-vertex", "ply\nformat ascii 1.0\nelement face 1\nproperty int id\nend_header\n".to_string()),
+            ("no-xyz", "ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\nend_header\n".to_string()),
+            ("list", format!("ply\nformat ascii 1.0\n{}property list uchar int ids\nend_header\n", xyz)),
+            (
+                "skipped",
+                format!(
+                    "ply\nformat binary_little_endian 1.0\nelement face {}\nproperty int id\n{}end_header\n",
+                    u64::MAX,
+                    xyz
+                ),
+            ),
+        ];
+        for (name, file) in cases {
+            assert!(read_ply(name, file.as_bytes()).is_err(), "{} was accepted", name);
+        }
+    }
+}
@@ -0,0 +1,614 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use memmap2::Mmap;
+use rayon::prelude::*;
+use serde::Deserialize;
+
+use super::crs::crs_from_wkt;
+use super::format::PointcloudFormat;
+use super::hierarchy::{build_hierarchy, HierarchyNode, VoxelKey};
+use super::octree::{
+    estimate_spacing, node_info, pack_chunk, select_visible, NodeSource, NodeTable,
+};
+use super::reader::{base_metadata, PointReader};
+use super::types::{
+    point_attributes, BoundingBox3D, CameraState, ExtraAttributeInfo, OctreeNodeInfo, PointBuffer, PointChunk,
+    PointRecord, PointcloudMetadata,
+};
+
+/// Size of a hierarchy.bin node: type(1) + child mask(1) + points(4) + byte offset(8) + byte size(8)
+const HIERARCHY_NODE_SIZE: usize = 22;
+
+/// Hierarchy node type whose byte range points at another hierarchy chunk
+const NODE_TYPE_PROXY: u8 = 2;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PotreeMetadata {
+    version: String,
+    points: u64,
+    #[serde(default)]
+    projection: String,
+    hierarchy: PotreeHierarchyInfo,
+    offset: [f64; 3],
+    scale: [f64; 3],
+    bounding_box: PotreeBox,
+    encoding: String,
+    attributes: Vec<PotreeAttribute>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PotreeHierarchyInfo {
+    first_chunk_size: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct PotreeBox {
+    min: [f64; 3],
+    max: [f64; 3],
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PotreeAttribute {
+    name: String,
+    #[serde(default)]
+    description: String,
+    /// Bytes per point
+    size: usize,
+    num_elements: usize,
+    element_size: usize,
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    min: Vec<f64>,
+    #[serde(default)]
+    max: Vec<f64>,
+}
+
+impl PotreeAttribute {
+    /// Read element `k` of this attribute from the bytes of one point
+    fn read(&self, b: &[u8], k: usize) -> f64 {
+        let b = &b[k * self.element_size..];
+        macro_rules! read {
+            ($t:ty, $n:literal) => {{
+                let mut raw = [0u8; $n];
+                raw.copy_from_slice(&b[..$n]);
+                <$t>::from_le_bytes(raw) as f64
+            }};
+        }
+        match self.kind.as_str() {
+            "int8" => b[0] as i8 as f64,
+            "uint8" => b[0] as f64,
+            "int16" => read!(i16, 2),
+            "uint16" => read!(u16, 2),
+            "int32" => read!(i32, 4),
+            "uint32" => read!(u32, 4),
+            "int64" => read!(i64, 8),
+            "uint64" => read!(u64, 8),
+            "float" => read!(f32, 4),
+            "double" => read!(f64, 8),
+            _ => f64::NAN,
+        }
+    }
+
+    /// Bytes of one element of this attribute's type; None for unknown types
+    fn type_size(&self) -> Option<usize> {
+        Some(match self.kind.as_str() {
+            "int8" | "uint8" => 1,
+            "int16" | "uint16" => 2,
+            "int32" | "uint32" | "float" => 4,
+            "int64" | "uint64" | "double" => 8,
+            _ => return None,
+        })
+    }
+
+    /// Same naming as the Extra Bytes data types
+    fn data_type(&self) -> String {
+        match self.kind.as_str() {
+            "float" => "f32".into(),
+            "double" => "f64".into(),
+            kind => kind.replace("uint", "u").replace("int", "i"),
+        }
+    }
+}
+
+/// PointRecord field an attribute decodes into
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Position,
+    Color,
+    Intensity,
+    Classification,
+    ReturnNumber,
+    NumberOfReturns,
+    GpsTime,
+    PointSourceId,
+    UserData,
+    ScanAngle,
+    /// Index into the extra attributes
+    Extra(usize),
+    Skip,
+}
+
+/// Reader for PotreeConverter 2 output folders. The hierarchy.bin nodes are
+/// the LOD tree; node slices of octree.bin are decoded when requested.
+pub struct PotreeReader {
+    metadata: PotreeMetadata,
+    octree: Mmap,
+    /// Node data is the byte range of the node in octree.bin
+    root: HierarchyNode<(u64, u64)>,
+    nodes: NodeTable<(u64, u64)>,
+    fields: Vec<Field>,
+    extra_count: usize,
+    brotli: bool,
+}
+
+impl PotreeReader {
+    /// Open a Potree 2.0 folder from its directory or its metadata.json
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let dir: PathBuf = if path.is_dir() {
+            path.to_path_buf()
+        } else {
+            path.parent().unwrap_or(Path::new(".")).to_path_buf()
+        };
+
+        let text = fs::read_to_string(dir.join("metadata.json"))
+            .map_err(|e| format!("Failed to read metadata.json: {}", e))?;
+        let metadata: PotreeMetadata = serde_json::from_str(&text)
+            .map_err(|e| format!("Failed to parse metadata.json: {}", e))?;
+        if !metadata.version.starts_with("2.") {
+            return Err(format!("Unsupported Potree version: {}", metadata.version));
+        }
+        let brotli = match metadata.encoding.as_str() {
+            "DEFAULT" => false,
+            "BROTLI" => true,
+            other => return Err(format!("Unsupported Potree encoding: {}", other)),
+        };
+
+        let hierarchy = fs::read(dir.join("hierarchy.bin"))
+            .map_err(|e| format!("Failed to read hierarchy.bin: {}", e))?;
+        let mut entries = HashMap::new();
+        read_hierarchy_chunk(&hierarchy, 0, metadata.hierarchy.first_chunk_size, (0, 0, 0, 0), &mut entries)?;
+
+        let PotreeBox { min, max } = &metadata.bounding_box;
+        let cube = BoundingBox3D {
+            min_x: min[0], min_y: min[1], min_z: min[2],
+            max_x: max[0], max_y: max[1], max_z: max[2],
+        };
+        let root = build_hierarchy((0, 0, 0, 0), "r".into(), cube, &entries)
+            .ok_or("Potree hierarchy has no root node")?;
+
+        let file = File::open(dir.join("octree.bin")).map_err(|e| format!("Failed to open octree.bin: {}", e))?;
+        let octree = unsafe { Mmap::map(&file) }.map_err(|e| format!("Failed to mmap octree.bin: {}", e))?;
+
+        let mut extra_count = 0;
+        let fields = metadata.attributes
+            .iter()
+            .map(|a| match a.name.as_str() {
+                "position" => Field::Position,
+                "rgb" | "rgba" => Field::Color,
+                "intensity" => Field::Intensity,
+                "classification" => Field::Classification,
+                "return number" => Field::ReturnNumber,
+                "number of returns" => Field::NumberOfReturns,
+                "gps-time" => Field::GpsTime,
+                "point source id" => Field::PointSourceId,
+                "user data" => Field::UserData,
+                "scan angle rank" | "scan angle" => Field::ScanAngle,
+                _ if a.num_elements == 1 => {
+                    extra_count += 1;
+                    Field::Extra(extra_count - 1)
+                }
+                _ => Field::Skip,
+            })
+            .collect::<Vec<_>>();
+        if !fields.contains(&Field::Position) {
+            return Err("Potree metadata has no position attribute".into());
+        }
+        // The decoded attributes must fit the bytes the metadata gives them
+        for (attr, &field) in metadata.attributes.iter().zip(&fields) {
+            let elements = match field {
+                Field::Skip => continue,
+                Field::Position | Field::Color => 3,
+                _ => 1,
+            };
+            let fits = attr.type_size() == Some(attr.element_size)
+                && attr.num_elements >= elements
+                && attr.num_elements.checked_mul(attr.element_size).is_some_and(|n| n <= attr.size);
+            if !fits {
+                return Err(format!(
+                    "Invalid Potree attribute {}: {} x {} byte {} in {} bytes",
+                    attr.name, attr.num_elements, attr.element_size, attr.kind, attr.size
+                ));
+            }
+        }
+
+        let nodes = NodeTable::new(&*root, true, |n| n.data);
+        Ok(Self { metadata, octree, root: *root, nodes, fields, extra_count, brotli })
+    }
+
+    fn has(&self, field: Field) -> bool {
+        self.fields.contains(&field)
+    }
+
+    /// Decode the points of one node
+    fn decode_node(&self, byte_offset: u64, byte_size: u64, count: usize) -> Result<PointBuffer, String> {
+        let slice = byte_offset
+            .checked_add(byte_size)
+            .and_then(|end| self.octree.get(byte_offset as usize..end as usize))
+            .ok_or("Potree node lies outside octree.bin")?;
+
+        // BROTLI: attributes stored column by column; positions and colors Morton-coded
+        let mut decompressed = Vec::new();
+        let data = if self.brotli {
+            brotli_decompressor::Decompressor::new(slice, 4096)
+                .read_to_end(&mut decompressed)
+                .map_err(|e| format!("Failed to decompress Potree node: {}", e))?;
+            &decompressed[..]
+        } else {
+            slice
+        };
+
+        // Check the point count against the data before allocating for it
+        let strides: Vec<usize> = self.fields
+            .iter()
+            .zip(&self.metadata.attributes)
+            .map(|(&field, attr)| match field {
+                Field::Position if self.brotli => 16,
+                Field::Color if self.brotli => 8,
+                _ => attr.size,
+            })
+            .collect();
+        let record_size: usize = strides.iter().sum();
+        record_size
+            .checked_mul(count)
+            .filter(|&n| n <= data.len())
+            .ok_or("Potree node is shorter than its point count")?;
+
+        let mut points = PointBuffer {
+            points: vec![PointRecord::default(); count],
+            wave_packets: None,
+            extra_attributes: vec![vec![f64::NAN; count]; self.extra_count],
+        };
+
+        if !self.brotli {
+            // DEFAULT: one record per point, attributes interleaved
+            let mut offset = 0;
+            for (attr, &field) in self.metadata.attributes.iter().zip(&self.fields) {
+                for j in 0..count {
+                    self.assign(&mut points, j, attr, field, &data[j * record_size + offset..]);
+                }
+                offset += attr.size;
+            }
+            return Ok(points);
+        }
+
+        let mut offset = 0;
+        for ((attr, &field), &stride) in self.metadata.attributes.iter().zip(&self.fields).zip(&strides) {
+            let column = &data[offset..offset + stride * count];
+            for (j, b) in column.chunks_exact(stride).enumerate() {
+                let p = &mut points.points[j];
+                let word = |o: usize| u32::from_le_bytes([b[o], b[o + 1], b[o + 2], b[o + 3]]);
+                match field {
+                    Field::Position => {
+                        let [x, y, z] = morton_decode(word(8), word(12), word(0), word(4));
+                        let (scale, origin) = (&self.metadata.scale, &self.metadata.offset);
+                        p.x = x as i32 as f64 * scale[0] + origin[0];
+                        p.y = y as i32 as f64 * scale[1] + origin[1];
+                        p.z = z as i32 as f64 * scale[2] + origin[2];
+                    }
+                    Field::Color => {
+                        let [r, g, b] = morton_decode(word(0), word(4), 0, 0);
+                        p.r = color_word(r as f64);
+                        p.g = color_word(g as f64);
+                        p.b = color_word(b as f64);
+                    }
+                    _ => self.assign(&mut points, j, attr, field, b),
+                }
+            }
+            offset += stride * count;
+        }
+        Ok(points)
+    }
+
+    /// Store an uncompressed attribute value of point `j`, `b` starting at the attribute's bytes
+    fn assign(&self, points: &mut PointBuffer, j: usize, attr: &PotreeAttribute, field: Field, b: &[u8]) {
+        let p = &mut points.points[j];
+        match field {
+            Field::Position => {
+                let (scale, origin) = (&self.metadata.scale, &self.metadata.offset);
+                p.x = attr.read(b, 0) * scale[0] + origin[0];
+                p.y = attr.read(b, 1) * scale[1] + origin[1];
+                p.z = attr.read(b, 2) * scale[2] + origin[2];
+            }
+            Field::Color => {
+                p.r = color_word(attr.read(b, 0));
+                p.g = color_word(attr.read(b, 1));
+                p.b = color_word(attr.read(b, 2));
+            }
+            Field::Intensity => p.intensity = attr.read(b, 0) as u16,
+            Field::Classification => p.classification = attr.read(b, 0) as u8,
+            Field::ReturnNumber => p.return_number = attr.read(b, 0) as u8,
+            Field::NumberOfReturns => p.number_of_returns = attr.read(b, 0) as u8,
+            Field::GpsTime => p.gps_time = attr.read(b, 0),
+            Field::PointSourceId => p.point_source_id = attr.read(b, 0) as u16,
+            Field::UserData => p.user_data = attr.read(b, 0) as u8,
+            Field::ScanAngle => p.scan_angle = attr.read(b, 0) as f32,
+            Field::Extra(i) => points.extra_attributes[i][j] = attr.read(b, 0),
+            Field::Skip => {}
+        }
+    }
+
+    fn node_chunk(&self, node_id: &str) -> Result<Option<PointChunk>, String> {
+        let (node, (byte_offset, byte_size)) = match self.nodes.find(node_id) {
+            Some(n) if n.info.point_count > 0 => (&n.info, n.data),
+            _ => return Ok(None),
+        };
+        let points = self.decode_node(byte_offset, byte_size, node.point_count as usize)?;
+        let spacing = estimate_spacing(&node.bounds, points.len());
+        Ok(Some(pack_chunk(node_id, &node.bounds, node.level, spacing, &points, self.attribute_mask())))
+    }
+
+    /// Nodes with point data, for streaming every point
+    fn data_nodes(&self) -> Vec<&HierarchyNode<(u64, u64)>> {
+        let mut nodes = Vec::new();
+        let mut stack = vec![&self.root];
+        while let Some(node) = stack.pop() {
+            if node.point_count > 0 {
+                nodes.push(node);
+            }
+            stack.extend(node.children.iter().flatten().map(|c| &**c));
+        }
+        nodes
+    }
+}
+
+/// Potree stores LAS 16-bit colors as they are and displays values above 255
+/// as 16-bit, the rest as 8-bit
+fn color_word(value: f64) -> u16 {
+    if value > 255.0 { value as u16 } else { value as u16 * 257 }
+}
+
+/// Gather every third bit of a 24-bit Morton code, starting at bit 0
+fn dealign24b(mut x: u32) -> u32 {
+    x = ((x & 0b001000001000001000001000) >> 2) | (x & 0b000001000001000001000001);
+    x = ((x & 0b000011000000000011000000) >> 4) | (x & 0b000000000011000000000011);
+    x = ((x & 0b000000001111000000000000) >> 8) | (x & 0b000000000000000000001111);
+    x & 0xFF
+}
+
+/// Split PotreeConverter's 3-axis Morton code into its components. `low_*` and
+/// `high_*` are the 32-bit words of the low and high 64-bit halves.
+fn morton_decode(low_a: u32, low_b: u32, high_a: u32, high_b: u32) -> [u32; 3] {
+    let low = [low_a & 0x00FF_FFFF, (low_a >> 24) | (low_b << 8)];
+    let high = [high_a & 0x00FF_FFFF, (high_a >> 24) | (high_b << 8)];
+    let mut axes = [0u32; 3];
+    for (axis, value) in axes.iter_mut().enumerate() {
+        *value = dealign24b(low[0] >> axis)
+            | (dealign24b(low[1] >> axis) << 8)
+            | (dealign24b(high[0] >> axis) << 16)
+            | (dealign24b(high[1] >> axis) << 24);
+    }
+    axes
+}
+
+/// Collect the nodes of the hierarchy chunk at `offset`, breadth first from
+/// `root_key`, and of the chunks its proxy nodes point to. Chunks are read
+/// from a work queue, each at most once, so proxies that point back cannot loop.
+fn read_hierarchy_chunk(
+    data: &[u8],
+    offset: u64,
+    size: u64,
+    root_key: VoxelKey,
+    entries: &mut HashMap<VoxelKey, (u32, (u64, u64))>,
+) -> Result<(), String> {
+    let mut chunks = vec![(offset, size, root_key)];
+    let mut visited = HashSet::new();
+
+    while let Some((offset, size, root_key)) = chunks.pop() {
+        if !visited.insert(offset) {
+            continue;
+        }
+        let chunk = offset
+            .checked_add(size)
+            .filter(|&end| end <= data.len() as u64)
+            .and_then(|end| data.get(offset as usize..end as usize))
+            .ok_or("Potree hierarchy chunk lies outside hierarchy.bin")?;
+
+        let mut keys = vec![root_key];
+        for (i, n) in chunk.chunks_exact(HIERARCHY_NODE_SIZE).enumerate() {
+            let Some(&key) = keys.get(i) else { break };
+            let node_type = n[0];
+            let child_mask = n[1];
+            let point_count = u32::from_le_bytes(n[2..6].try_into().unwrap());
+            let byte_offset = u64::from_le_bytes(n[6..14].try_into().unwrap());
+            let byte_size = u64::from_le_bytes(n[14..22].try_into().unwrap());
+
+            if node_type == NODE_TYPE_PROXY {
+                chunks.push((byte_offset, byte_size, key));
+                continue;
+            }
+            entries.insert(key, (point_count, (byte_offset, byte_size)));
+
+            // Potree child indices put z in bit 0 and x in bit 2
+            let (d, x, y, z) = key;
+            for child in 0..8 {
+                if child_mask & (1 << child) != 0 {
+                    let (cx, cy, cz) = ((child >> 2) & 1, (child >> 1) & 1, child & 1);
+                    keys.push((d + 1, 2 * x + cx, 2 * y + cy, 2 * z + cz));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+impl NodeSource for PotreeReader {
+    fn visible_nodes(&self, camera: &CameraState, point_budget: u32) -> Vec<OctreeNodeInfo> {
+        select_visible(&self.root, camera, point_budget, true).into_iter().map(|n| node_info(n, true)).collect()
+    }
+
+    /// Decode the requested nodes in parallel
+    fn node_chunks(&self, node_ids: &[String]) -> Result<Vec<PointChunk>, String> {
+        let chunks = node_ids
+            .par_iter()
+            .map(|id| self.node_chunk(id))
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(chunks.into_iter().flatten().collect())
+    }
+
+    fn nodes(&self) -> Vec<OctreeNodeInfo> {
+        self.nodes.infos()
+    }
+
+    fn is_additive(&self) -> bool {
+        true
+    }
+}
+
+impl PointReader for PotreeReader {
+    fn metadata(&self, id: &str, file_path: &str) -> PointcloudMetadata {
+        let projection = self.metadata.projection.trim();
+        let extra_attributes = self.metadata.attributes
+            .iter()
+            .zip(&self.fields)
+            .filter(|(_, f)| matches!(f, Field::Extra(_)))
+            .map(|(a, _)| ExtraAttributeInfo {
+                name: a.name.clone(),
+                description: a.description.clone(),
+                data_type: a.data_type(),
+                scale: 1.0,
+                offset: 0.0,
+                no_data: None,
+                min: a.min.first().copied(),
+                max: a.max.first().copied(),
+            })
+            .collect();
+
+        PointcloudMetadata {
+            has_color: self.has(Field::Color),
+            has_intensity: self.has(Field::Intensity),
+            has_classification: self.has(Field::Classification),
+            has_returns: self.has(Field::ReturnNumber),
+            has_gps_time: self.has(Field::GpsTime),
+            extra_attributes,
+            // PotreeConverter copies the WKT of the source LAS files
+            crs: projection.contains('[').then(|| crs_from_wkt(projection)),
+            ..base_metadata(id, file_path, PointcloudFormat::Potree, self.metadata.points, self.bounds())
+        }
+    }
+
+    fn total_points(&self) -> u64 {
+        self.metadata.points
+    }
+
+    /// Tight bounds from the position attribute's range
+    fn bounds(&self) -> Option<BoundingBox3D> {
+        let position = self.metadata.attributes.iter().find(|a| a.name == "position")?;
+        match (position.min.as_slice(), position.max.as_slice()) {
+            ([min_x, min_y, min_z], [max_x, max_y, max_z]) => Some(BoundingBox3D {
+                min_x: *min_x, min_y: *min_y, min_z: *min_z,
+                max_x: *max_x, max_y: *max_y, max_z: *max_z,
+            }),
+            _ => None,
+        }
+    }
+
+    fn scale(&self) -> [f64; 3] {
+        self.metadata.scale
+    }
+
+    fn attribute_mask(&self) -> u32 {
+        let mut mask = 0;
+        for (field, bit) in [
+            (Field::ReturnNumber, point_attributes::RETURNS),
+            (Field::ScanAngle, point_attributes::SCAN_ANGLE),
+            (Field::UserData, point_attributes::USER_DATA),
+            (Field::PointSourceId, point_attributes::POINT_SOURCE_ID),
+            (Field::GpsTime, point_attributes::GPS_TIME),
+        ] {
+            if self.has(field) {
+                mask |= bit;
+            }
+        }
+        mask
+    }
+
+    /// Stream every node in turn, split into batches
+    fn stream_points(
+        &self,
+        batch_size: u64,
+        callback: &mut dyn FnMut(&PointBuffer, u64) -> bool,
+    ) -> Result<(), String> {
+        let batch_size = batch_size.max(1) as usize;
+        let mut streamed = 0;
+        for node in self.data_nodes() {
+            let (byte_offset, byte_size) = node.data;
+            let points = self.decode_node(byte_offset, byte_size, node.point_count as usize)?;
+            for start in (0..points.len()).step_by(batch_size) {
+                let batch = points.slice(start..points.len().min(start + batch_size));
+                if !callback(&batch, streamed) {
+                    return Ok(());
+                }
+                streamed += batch.len() as u64;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A hierarchy node: type, child mask, point count, byte offset and byte size
+    fn node(node_type: u8, child_mask: u8, point_count: u32, byte_offset: u64, byte_size: u64) -> Vec<u8> {
+        let mut n = vec![node_type, child_mask];
+        n.extend_from_slice(&point_count.to_le_bytes());
+        n.extend_from_slice(&byte_offset.to_le_bytes());
+        n.extend_from_slice(&byte_size.to_le_bytes());
+        n
+    }
+
+    #[test]
+    fn proxy_nodes_are_followed() {
+        // Root chunk: the root with children 0 and 4 (x), the latter a proxy
+        // for the chunk that follows
+        let proxy_at = 3 * HIERARCHY_NODE_SIZE as u64;
+        let mut data = node(0, 0b0001_0001, 10, 100, 50);
+        data.extend(node(0, 0, 5, 150, 20));
+        data.extend(node(NODE_TYPE_PROXY, 0, 0, proxy_at, HIERARCHY_NODE_SIZE as u64));
+        data.extend(node(0, 0, 7, 170, 30));
+
+        let mut entries = HashMap::new();
+        read_hierarchy_chunk(&data, 0, proxy_at, (0, 0, 0, 0), &mut entries).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[&(0, 0, 0, 0)], (10, (100, 50)));
+        assert_eq!(entries[&(1, 0, 0, 0)], (5, (150, 20)));
+        assert_eq!(entries[&(1, 1, 0, 0)], (7, (170, 30)));
+    }
+
+    #[test]
+    fn proxies_pointing_back_are_read_once() {
+        let size = HIERARCHY_NODE_SIZE as u64;
+        let data = node(NODE_TYPE_PROXY, 0, 0, 0, size);
+        let mut entries = HashMap::new();
+        read_hierarchy_chunk(&data, 0, size, (0, 0, 0, 0), &mut entries).unwrap();
+        assert!(entries.is_empty());
+    }
+
+    #[test]
+    fn chunks_outside_the_file_fail() {
+        let size = HIERARCHY_NODE_SIZE as u64;
+        let data = node(NODE_TYPE_PROXY, 0, 0, u64::MAX - 8, size);
+        let mut entries = HashMap::new();
+        assert!(read_hierarchy_chunk(&data, 0, size, (0, 0, 0, 0), &mut entries).is_err());
+        assert!(read_hierarchy_chunk(&data, 16, size, (0, 0, 0, 0), &mut entries).is_err());
+    }
+}
@@ -0,0 +1,166 @@
+use std::path::Path;
+
+use super::format::PointcloudFormat;
+use super::types::{BoundingBox3D, HeaderIssue, PointBuffer, PointcloudMetadata, VlrInfo};
+use super::writer::Waveform;
+
+/// A pointcloud file the manager can index: every supported format
+/// streams its points through this interface into the octree builder.
+pub trait PointReader: Send {
+    /// Describe the file for the frontend
+    fn metadata(&self, id: &str, file_path: &str) -> PointcloudMetadata;
+
+    /// VLR/EVLR style header records, for formats that have them
+    fn vlr_infos(&self) -> Vec<VlrInfo> {
+        Vec::new()
+    }
+
+    /// Payloads of the LASF_Projection records by record ID, copied when writing LAS
+    fn projection_vlrs(&self) -> Vec<(u16, Vec<u8>)> {
+        Vec::new()
+    }
+
+    /// Whether GPS times are adjusted standard GPS time rather than GPS week time
+    fn standard_gps_time(&self) -> bool {
+        false
+    }
+
+    /// Waveform data the wave packets of the points refer to, copied when writing LAS
+    fn waveform(&self, _file_path: &str) -> Option<Waveform> {
+        None
+    }
+
+    /// Number of points the file declares (an estimate for formats without a count)
+    fn total_points(&self) -> u64;
+
+    /// Bounds declared by the file. None when they must be computed from the points.
+    fn bounds(&self) -> Option<BoundingBox3D>;
+
+    /// Coordinate resolution per axis, the tolerance when checking declared bounds
+    fn scale(&self) -> [f64; 3] {
+        [0.0; 3]
+    }
+
+    /// Optional attributes filled in the streamed points, see `point_attributes`
+    fn attribute_mask(&self) -> u32;
+
+    /// Stream all points in batches of at most `batch_size`. The callback gets each
+    /// batch with the number of points streamed before it; returning false stops.
+    fn stream_points(
+        &self,
+        batch_size: u64,
+        callback: &mut dyn FnMut(&PointBuffer, u64) -> bool,
+    ) -> Result<(), String>;
+
+    /// Problems found while streaming the points, reported with the
+    /// header validation once indexing finishes
+    fn stream_issues(&self) -> Vec<HeaderIssue> {
+        Vec::new()
+    }
+}
+
+/// Metadata for formats without LAS header fields: no VLRs, CRS or point record format
+pub fn base_metadata(
+    id: &str,
+    file_path: &str,
+    format: PointcloudFormat,
+    total_points: u64,
+    bounds: Option<BoundingBox3D>,
+) -> PointcloudMetadata {
+    let file_name = Path::new(file_path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("unknown")
+        .to_string();
+
+    PointcloudMetadata {
+        id: id.to_string(),
+        file_path: file_path.to_string(),
+        file_name,
+        format: format.name().into(),
+        total_points,
+        // Formats without declared bounds get them from the points once indexed
+        bounds: bounds.unwrap_or(BoundingBox3D {
+            min_x: 0.0, min_y: 0.0, min_z: 0.0,
+            max_x: 0.0, max_y: 0.0, max_z: 0.0,
+        }),
+        has_color: false,
+        has_intensity: false,
+        has_classification: false,
+        has_returns: false,
+        has_gps_time: false,
+        has_nir: false,
+        point_record_format: 0,
+        las_version: String::new(),
+        extra_attributes: Vec::new(),
+        crs: None,
+        color_depth: None,
+        validation: Vec::new(),
+    }
+}
+
+/// Range of color or intensity values in formats that do not fix it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueScale {
+    /// 0.0-1.0
+    Unit,
+    /// 0-255
+    Byte,
+    /// 0-65535
+    Word,
+    /// -2048..2047, the 12-bit signed intensity of PTS/PTX exports
+    Signed12,
+}
+
+impl ValueScale {
+    /// Pick the scale from sampled values. `fractional` tells whether any
+    /// sample had a fractional part, so 0/1 integers are not read as 0.0-1.0.
+    pub fn detect(min: f64, max: f64, fractional: bool) -> Self {
+        if (-2048.0..0.0).contains(&min) && max <= 2047.0 {
+            Self::Signed12
+        } else if max <= 1.0 && fractional {
+            Self::Unit
+        } else if max <= 255.0 {
+            Self::Byte
+        } else {
+            Self::Word
+        }
+    }
+
+    /// Map a value onto 0.0-1.0
+    fn normalize(self, value: f64) -> f64 {
+        let unit = match self {
+            Self::Unit => value,
+            Self::Byte => value / 255.0,
+            Self::Word => value / 65535.0,
+            Self::Signed12 => (value + 2048.0) / 4095.0,
+        };
+        if unit.is_nan() { 0.0 } else { unit.clamp(0.0, 1.0) }
+    }
+
+    pub fn to_u16(self, value: f64) -> u16 {
+        (self.normalize(value) * 65535.0).round() as u16
+    }
+}
+
+/// Write `contents` to a temporary file named after `name`, open it with
+/// `open` and read all its points, for the readers' tests
+#[cfg(test)]
+pub fn read_temp_file<R: PointReader>(
+    name: &str,
+    contents: &[u8],
+    open: impl FnOnce(&Path) -> Result<R, String>,
+) -> Result<Vec<super::types::PointRecord>, String> {
+    let path = std::env::temp_dir().join(format!("ops-reader-{}-{}", std::process::id(), name));
+    std::fs::write(&path, contents).unwrap();
+    let result = open(&path).and_then(|reader| {
+        let mut points = Vec::new();
+        reader.stream_points(100, &mut |batch, _| {
+            points.extend_from_slice(&batch.points);
+            true
+        })?;
+        Ok(points)
+    });
+    let _ = std::fs::remove_file(&path);
+    result
+}
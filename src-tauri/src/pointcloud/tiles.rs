@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use serde::Serialize;
+
+use super::octree::NodeSource;
+use super::types::{OctreeNodeInfo, PointChunk, PointcloudMetadata, TilesExportOptions};
+
+/// Nodes loaded from the source per batch
+const TILE_BATCH_SIZE: usize = 64;
+
+/// Folder next to tileset.json holding the tile contents
+const TILES_FOLDER: &str = "tiles";
+
+/// WGS84 ellipsoid
+const WGS84_A: f64 = 6_378_137.0;
+const WGS84_E2: f64 = 6.694_379_990_14e-3;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Tileset {
+    asset: Asset,
+    geometric_error: f64,
+    root: Tile,
+}
+
+#[derive(Serialize)]
+struct Asset {
+    version: &'static str,
+    generator: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Tile {
+    bounding_volume: BoundingVolume,
+    geometric_error: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    refine: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    transform: Option<[f64; 16]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<Content>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    children: Vec<Tile>,
+}
+
+#[derive(Serialize)]
+struct BoundingVolume {
+    /// Center, then the x, y and z half axes
+    #[serde(rename = "box")]
+    oriented_box: [f64; 12],
+}
+
+#[derive(Serialize)]
+struct Content {
+    uri: String,
+}
+
+/// Feature table of a .pnts tile
+#[derive(Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+struct FeatureTable {
+    points_length: u32,
+    rtc_center: [f64; 3],
+    position: BinaryRef,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rgb: Option<BinaryRef>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BinaryRef {
+    byte_offset: usize,
+}
+
+/// Per-point properties of a .pnts tile, for styling in the viewer
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct BatchTable {
+    intensity: BatchProperty,
+    classification: BatchProperty,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BatchProperty {
+    byte_offset: usize,
+    component_type: &'static str,
+    #[serde(rename = "type")]
+    kind: &'static str,
+}
+
+/// Write the octree as a 3D Tiles tileset: tileset.json in `output_dir` and a
+/// .pnts tile per node. Positions are stored relative to each tile's center
+/// (RTC_CENTER) and a tile's geometric error is its point spacing. The root
+/// tile places the cloud at `options.origin`, which is required. `progress`
+/// gets the points written so far and in all. Returns the points written.
+pub fn export_tiles(
+    source: &dyn NodeSource,
+    metadata: &PointcloudMetadata,
+    output_dir: &str,
+    options: &TilesExportOptions,
+    progress: &mut dyn FnMut(u64, u64),
+) -> Result<u64, String> {
+    let origin = options
+        .origin
+        .filter(|o| (-180.0..=180.0).contains(&o[0]) && (-90.0..=90.0).contains(&o[1]) && o[2].is_finite())
+        .ok_or("3D Tiles export needs the longitude, latitude and height to place the cloud at")?;
+    let nodes = source.nodes();
+    let root = nodes.first().ok_or("The octree has no nodes")?;
+    let total: u64 = nodes.iter().map(|n| n.point_count as u64).sum();
+
+    let tiles_dir = Path::new(output_dir).join(TILES_FOLDER);
+    fs::create_dir_all(&tiles_dir).map_err(|e| format!("Failed to create tiles folder: {}", e))?;
+
+    // Spacing of every node with points, which is also the tile's error
+    let mut spacings = HashMap::new();
+    let mut written = 0u64;
+    let ids: Vec<String> = nodes.iter().filter(|n| n.point_count > 0).map(|n| n.node_id.clone()).collect();
+    for batch in ids.chunks(TILE_BATCH_SIZE) {
+        for chunk in source.node_chunks(batch)? {
+            let path = tiles_dir.join(format!("{}.pnts", chunk.node_id));
+            fs::write(&path, encode_pnts(&chunk, metadata.has_color)?)
+                .map_err(|e| format!("Failed to write tile {}: {}", chunk.node_id, e))?;
+            written += chunk.point_count as u64;
+            spacings.insert(chunk.node_id.clone(), chunk.spacing as f64);
+        }
+        progress(written, total);
+    }
+
+    let mut children: HashMap<&str, Vec<&OctreeNodeInfo>> = HashMap::new();
+    for node in &nodes[1..] {
+        let parent = &node.node_id[..node.node_id.len() - 1];
+        children.entry(parent).or_default().push(node);
+    }
+
+    let mut root_tile = build_tile(root, &children, &spacings);
+    root_tile.refine = Some(if source.is_additive() { "ADD" } else { "REPLACE" });
+    root_tile.transform = Some(placement(origin, root.bounds.center()));
+
+    let tileset = Tileset {
+        asset: Asset { version: "1.0", generator: "Open Pointcloud Studio" },
+        // Error of showing nothing at all
+        geometric_error: root.bounds.max_extent().max(root_tile.geometric_error),
+        root: root_tile,
+    };
+    let json = serde_json::to_vec(&tileset).map_err(|e| format!("Failed to encode tileset: {}", e))?;
+    fs::write(Path::new(output_dir).join("tileset.json"), json)
+        .map_err(|e| format!("Failed to write tileset.json: {}", e))?;
+
+    Ok(written)
+}
+
+/// The tile of a node and its descendants. A tile's error is at least that of
+/// its children, as 3D Tiles requires; leaves have none.
+fn build_tile(
+    node: &OctreeNodeInfo,
+    children: &HashMap<&str, Vec<&OctreeNodeInfo>>,
+    spacings: &HashMap<String, f64>,
+) -> Tile {
+    let child_tiles: Vec<Tile> = children
+        .get(node.node_id.as_str())
+        .map(|nodes| nodes.iter().map(|c| build_tile(c, children, spacings)).collect())
+        .unwrap_or_default();
+
+    let spacing = spacings.get(&node.node_id).copied();
+    let geometric_error = if child_tiles.is_empty() {
+        0.0
+    } else {
+        let children_error = child_tiles.iter().map(|t| t.geometric_error).fold(0.0, f64::max);
+        spacing.unwrap_or(0.0).max(children_error)
+    };
+
+    let c = node.bounds.center();
+    let half = node.bounds.size().map(|s| s * 0.5);
+    Tile {
+        bounding_volume: BoundingVolume {
+            oriented_box: [c[0], c[1], c[2], half[0], 0.0, 0.0, 0.0, half[1], 0.0, 0.0, 0.0, half[2]],
+        },
+        geometric_error,
+        refine: None,
+        transform: None,
+        content: spacing.map(|_| Content { uri: format!("{}/{}.pnts", TILES_FOLDER, node.node_id) }),
+        children: child_tiles,
+    }
+}
+
+/// Encode a chunk as a .pnts tile. Chunk positions are already relative to
+/// its center, which becomes the tile's RTC_CENTER.
+fn encode_pnts(chunk: &PointChunk, with_color: bool) -> Result<Vec<u8>, String> {
+    let count = chunk.point_count as usize;
+
+    let mut features: Vec<u8> = chunk.positions.iter().flat_map(|v| v.to_le_bytes()).collect();
+    let rgb = with_color.then(|| {
+        let offset = features.len();
+        features.extend_from_slice(&chunk.colors);
+        BinaryRef { byte_offset: offset }
+    });
+    let feature_table = FeatureTable {
+        points_length: chunk.point_count,
+        rtc_center: chunk.center,
+        position: BinaryRef { byte_offset: 0 },
+        rgb,
+    };
+
+    let mut batch: Vec<u8> = chunk.intensities.iter().flat_map(|v| v.to_le_bytes()).collect();
+    batch.extend_from_slice(&chunk.classifications);
+    let batch_table = BatchTable {
+        intensity: BatchProperty { byte_offset: 0, component_type: "UNSIGNED_SHORT", kind: "SCALAR" },
+        classification: BatchProperty { byte_offset: count * 2, component_type: "UNSIGNED_BYTE", kind: "SCALAR" },
+    };
+
+    let mut feature_json =
+        serde_json::to_vec(&feature_table).map_err(|e| format!("Failed to encode feature table: {}", e))?;
+    let mut batch_json =
+        serde_json::to_vec(&batch_table).map_err(|e| format!("Failed to encode batch table: {}", e))?;
+
+    // Every section starts on an 8-byte boundary: JSON is padded with spaces,
+    // binary with zeros. The 28-byte header is followed by the feature table.
+    pad(&mut feature_json, b' ', 28);
+    pad(&mut features, 0, 0);
+    pad(&mut batch_json, b' ', 0);
+    pad(&mut batch, 0, 0);
+
+    let length = 28 + feature_json.len() + features.len() + batch_json.len() + batch.len();
+    let mut out = Vec::with_capacity(length);
+    out.extend_from_slice(b"pnts");
+    for v in [1, length, feature_json.len(), features.len(), batch_json.len(), batch.len()] {
+        out.extend_from_slice(&(v as u32).to_le_bytes());
+    }
+    out.extend_from_slice(&feature_json);
+    out.extend_from_slice(&features);
+    out.extend_from_slice(&batch_json);
+    out.extend_from_slice(&batch);
+    Ok(out)
+}
+
+/// Pad `bytes` so that it ends on an 8-byte boundary when it starts at `start`
+fn pad(bytes: &mut Vec<u8>, fill: u8, start: usize) {
+    let padding = (8 - (start + bytes.len()) % 8) % 8;
+    bytes.resize(bytes.len() + padding, fill);
+}
+
+/// Column-major transform from the cloud's coordinates to Earth-centered
+/// coordinates: the cloud's center moves to the geodetic `origin`, its axes
+/// become east, north and up there
+fn placement(origin: [f64; 3], center: [f64; 3]) -> [f64; 16] {
+    let (lon, lat, height) = (origin[0].to_radians(), origin[1].to_radians(), origin[2]);
+    let (sin_lon, cos_lon) = lon.sin_cos();
+    let (sin_lat, cos_lat) = lat.sin_cos();
+
+    let n = WGS84_A / (1.0 - WGS84_E2 * sin_lat * sin_lat).sqrt();
+    let position = [
+        (n + height) * cos_lat * cos_lon,
+        (n + height) * cos_lat * sin_lon,
+        (n * (1.0 - WGS84_E2) + height) * sin_lat,
+    ];
+    let east = [-sin_lon, cos_lon, 0.0];
+    let north = [-sin_lat * cos_lon, -sin_lat * sin_lon, cos_lat];
+    let up = [cos_lat * cos_lon, cos_lat * sin_lon, sin_lat];
+
+    let mut translation = [0.0; 3];
+    for i in 0..3 {
+        translation[i] = position[i] - east[i] * center[0] - north[i] * center[1] - up[i] * center[2];
+    }
+    [
+        east[0], east[1], east[2], 0.0,
+        north[0], north[1], north[2], 0.0,
+        up[0], up[1], up[2], 0.0,
+        translation[0], translation[1], translation[2], 1.0,
+    ]
+}
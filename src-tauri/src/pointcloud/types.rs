@@ -73,16 +73,252 @@ impl BoundingBox3D {
 }
 
 /// A single point record with all optional attributes
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy)]
 pub struct PointRecord {
     pub x: f64,
     pub y: f64,
     pub z: f64,
-    pub r: u8,
-    pub g: u8,
-    pub b: u8,
+    /// Colors are 16-bit, as LAS stores them
+    pub r: u16,
+    pub g: u16,
+    pub b: u16,
     pub intensity: u16,
     pub classification: u8,
+    pub return_number: u8,
+    pub number_of_returns: u8,
+    /// Classification and scan flags, see `point_flags`
+    pub flags: u8,
+    pub scanner_channel: u8,
+    /// Scan angle in degrees
+    pub scan_angle: f32,
+    pub user_data: u8,
+    pub point_source_id: u16,
+    pub gps_time: f64,
+    pub nir: u16,
+}
+
+impl Default for PointRecord {
+    fn default() -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            r: 0x8080,
+            g: 0x8080,
+            b: 0x8080,
+            intensity: 0,
+            classification: 0,
+            return_number: 1,
+            number_of_returns: 1,
+            flags: 0,
+            scanner_channel: 0,
+            scan_angle: 0.0,
+            user_data: 0,
+            point_source_id: 0,
+            gps_time: 0.0,
+            nir: 0,
+        }
+    }
+}
+
+/// Bit flags stored in `PointRecord::flags`.
+/// The low nibble matches the LAS 1.4 classification flags byte.
+pub mod point_flags {
+    pub const SYNTHETIC: u8 = 1 << 0;
+    pub const KEY_POINT: u8 = 1 << 1;
+    pub const WITHHELD: u8 = 1 << 2;
+    pub const OVERLAP: u8 = 1 << 3;
+    pub const SCAN_DIRECTION: u8 = 1 << 4;
+    pub const EDGE_OF_FLIGHT_LINE: u8 = 1 << 5;
+}
+
+/// Optional per-point attributes present in a cloud. Also sent as the
+/// `attribute_mask` of a `PointChunk` to say which arrays are filled.
+pub mod point_attributes {
+    pub const RETURNS: u32 = 1 << 0;
+    pub const FLAGS: u32 = 1 << 1;
+    pub const SCANNER_CHANNEL: u32 = 1 << 2;
+    pub const SCAN_ANGLE: u32 = 1 << 3;
+    pub const USER_DATA: u32 = 1 << 4;
+    pub const POINT_SOURCE_ID: u32 = 1 << 5;
+    pub const GPS_TIME: u32 = 1 << 6;
+    pub const NIR: u32 = 1 << 7;
+    pub const WAVE_PACKETS: u32 = 1 << 8;
+}
+
+/// LAS waveform packet descriptor fields
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct WavePacket {
+    pub descriptor_index: u8,
+    pub byte_offset: u64,
+    pub size: u32,
+    pub return_point_location: f32,
+    pub dx: f32,
+    pub dy: f32,
+    pub dz: f32,
+}
+
+/// Columns a `PointBuffer` carries besides its records
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PointColumns {
+    pub wave_packets: bool,
+    /// Number of custom attributes, see `PointcloudMetadata::extra_attributes`
+    pub extra_count: usize,
+}
+
+/// Points with the attributes only some clouds have kept beside them, one
+/// column per attribute, so records stay small and fixed-size. This is how
+/// readers stream points and how octree nodes hold them.
+#[derive(Debug, Clone, Default)]
+pub struct PointBuffer {
+    pub points: Vec<PointRecord>,
+    /// Waveform packet of each point (point formats 4, 5, 9 and 10 only)
+    pub wave_packets: Option<Vec<WavePacket>>,
+    /// One column per entry of `PointcloudMetadata::extra_attributes` (NaN = no data)
+    pub extra_attributes: Vec<Vec<f64>>,
+}
+
+impl PointBuffer {
+    pub fn new(columns: PointColumns) -> Self {
+        Self::with_capacity(columns, 0)
+    }
+
+    pub fn with_capacity(columns: PointColumns, capacity: usize) -> Self {
+        Self {
+            points: Vec::with_capacity(capacity),
+            wave_packets: columns.wave_packets.then(|| Vec::with_capacity(capacity)),
+            extra_attributes: (0..columns.extra_count).map(|_| Vec::with_capacity(capacity)).collect(),
+        }
+    }
+
+    pub fn columns(&self) -> PointColumns {
+        PointColumns {
+            wave_packets: self.wave_packets.is_some(),
+            extra_count: self.extra_attributes.len(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+        if let Some(packets) = &mut self.wave_packets {
+            packets.clear();
+        }
+        self.extra_attributes.iter_mut().for_each(Vec::clear);
+    }
+
+    /// Append point `index` of `other`. Columns `other` lacks get their default.
+    pub fn push_from(&mut self, other: &PointBuffer, index: usize) {
+        self.points.push(other.points[index]);
+        if let Some(packets) = &mut self.wave_packets {
+            packets.push(other.wave_packets.as_ref().map_or_else(WavePacket::default, |p| p[index]));
+        }
+        for (i, values) in self.extra_attributes.iter_mut().enumerate() {
+            values.push(other.extra_attributes.get(i).map_or(f64::NAN, |v| v[index]));
+        }
+    }
+
+    /// Append every point of `other`. Columns `other` lacks get their default.
+    pub fn extend_from(&mut self, other: &PointBuffer) {
+        self.points.extend_from_slice(&other.points);
+        if let Some(packets) = &mut self.wave_packets {
+            match &other.wave_packets {
+                Some(source) => packets.extend_from_slice(source),
+                None => packets.resize(self.points.len(), WavePacket::default()),
+            }
+        }
+        for (i, values) in self.extra_attributes.iter_mut().enumerate() {
+            match other.extra_attributes.get(i) {
+                Some(source) => values.extend_from_slice(source),
+                None => values.resize(self.points.len(), f64::NAN),
+            }
+        }
+    }
+
+    /// Copy of the points in `range`
+    pub fn slice(&self, range: std::ops::Range<usize>) -> PointBuffer {
+        PointBuffer {
+            points: self.points[range.clone()].to_vec(),
+            wave_packets: self.wave_packets.as_ref().map(|p| p[range.clone()].to_vec()),
+            extra_attributes: self.extra_attributes.iter().map(|v| v[range.clone()].to_vec()).collect(),
+        }
+    }
+
+    /// Exchange two points in every column
+    pub fn swap(&mut self, a: usize, b: usize) {
+        self.points.swap(a, b);
+        if let Some(packets) = &mut self.wave_packets {
+            packets.swap(a, b);
+        }
+        for values in &mut self.extra_attributes {
+            values.swap(a, b);
+        }
+    }
+
+    /// Remove the points at `indices`, which are in ascending order
+    pub fn remove_sorted(&mut self, indices: &[usize]) {
+        remove_sorted(&mut self.points, indices);
+        if let Some(packets) = &mut self.wave_packets {
+            remove_sorted(packets, indices);
+        }
+        for values in &mut self.extra_attributes {
+            remove_sorted(values, indices);
+        }
+    }
+}
+
+impl From<Vec<PointRecord>> for PointBuffer {
+    fn from(points: Vec<PointRecord>) -> Self {
+        Self { points, wave_packets: None, extra_attributes: Vec::new() }
+    }
+}
+
+fn remove_sorted<T>(values: &mut Vec<T>, indices: &[usize]) {
+    let mut removed = indices.iter().peekable();
+    let mut index = 0;
+    values.retain(|_| {
+        let keep = removed.next_if_eq(&&index).is_none();
+        index += 1;
+        keep
+    });
+}
+
+/// Custom per-point attribute declared in the LAS Extra Bytes VLR
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtraAttributeInfo {
+    pub name: String,
+    pub description: String,
+    pub data_type: String,
+    pub scale: f64,
+    pub offset: f64,
+    pub no_data: Option<f64>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+/// Coordinate reference system declared in the file's projection VLRs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrsInfo {
+    /// Top-level EPSG code (a compound code such as 7415 when present)
+    pub epsg: Option<u32>,
+    /// EPSG code of the horizontal (projected or geographic) component
+    pub horizontal_epsg: Option<u32>,
+    /// EPSG code of the vertical component
+    pub vertical_epsg: Option<u32>,
+    /// GeoTIFF model type of the horizontal component: 1 projected, 2 geographic, 3 geocentric
+    #[serde(default)]
+    pub model_type: Option<u16>,
+    pub name: Option<String>,
+    pub wkt: Option<String>,
+    /// "wkt" or "geotiff"
+    pub source: String,
 }
 
 /// Metadata about a loaded pointcloud
@@ -97,8 +333,204 @@ pub struct PointcloudMetadata {
     pub has_color: bool,
     pub has_intensity: bool,
     pub has_classification: bool,
+    pub has_returns: bool,
+    pub has_gps_time: bool,
+    pub has_nir: bool,
     pub point_record_format: u8,
     pub las_version: String,
+    pub extra_attributes: Vec<ExtraAttributeInfo>,
+    pub crs: Option<CrsInfo>,
+    /// How the 16-bit color fields are filled; None without color
+    pub color_depth: Option<ColorDepth>,
+    /// Problems found in the file header, see `HeaderIssue`
+    pub validation: Vec<HeaderIssue>,
+}
+
+/// Range of the values stored in the 16-bit LAS color fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColorDepth {
+    /// 0-255, as written by many scanners despite the spec
+    #[serde(rename = "8bit")]
+    Bit8,
+    /// 0-65535, as the LAS spec requires
+    #[serde(rename = "16bit")]
+    Bit16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IssueSeverity {
+    Warning,
+    Error,
+}
+
+/// A header field that is inconsistent with the rest of the file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeaderIssue {
+    pub field: String,
+    pub severity: IssueSeverity,
+    pub expected: String,
+    pub actual: String,
+    pub message: String,
+    /// Whether the file can still be read by trusting the point data over the header
+    pub recoverable: bool,
+}
+
+impl HeaderIssue {
+    pub fn error(
+        field: &str,
+        expected: impl ToString,
+        actual: impl ToString,
+        message: impl ToString,
+        recoverable: bool,
+    ) -> Self {
+        Self {
+            field: field.into(),
+            severity: IssueSeverity::Error,
+            expected: expected.to_string(),
+            actual: actual.to_string(),
+            message: message.to_string(),
+            recoverable,
+        }
+    }
+
+    pub fn warning(field: &str, expected: impl ToString, actual: impl ToString, message: impl ToString) -> Self {
+        Self {
+            field: field.into(),
+            severity: IssueSeverity::Warning,
+            expected: expected.to_string(),
+            actual: actual.to_string(),
+            message: message.to_string(),
+            recoverable: true,
+        }
+    }
+}
+
+/// Options passed to `pointcloud_open`
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct OpenOptions {
+    /// Read files whose header disagrees with the point data, deriving
+    /// the point count and bounds from the points themselves
+    #[serde(default)]
+    pub trust_data: bool,
+    /// Force the color scaling instead of detecting it from the points
+    #[serde(default)]
+    pub color_depth: Option<ColorDepth>,
+    /// Column layout of ASCII files (XYZ, CSV, TXT, PTS); detected when absent
+    #[serde(default)]
+    pub columns: Option<AsciiColumns>,
+    /// Build an additive octree, storing each point in one node only
+    /// instead of copying a sample of the children's points into parents
+    #[serde(default)]
+    pub additive: bool,
+}
+
+/// Zero-based column indices of the fields in an ASCII point file
+#[derive(Debug, Clone, Deserialize)]
+pub struct AsciiColumns {
+    pub x: usize,
+    pub y: usize,
+    pub z: usize,
+    #[serde(default)]
+    pub intensity: Option<usize>,
+    #[serde(default)]
+    pub red: Option<usize>,
+    #[serde(default)]
+    pub green: Option<usize>,
+    #[serde(default)]
+    pub blue: Option<usize>,
+    #[serde(default)]
+    pub classification: Option<usize>,
+    /// Field separator; detected from the first data line when absent
+    #[serde(default)]
+    pub delimiter: Option<char>,
+    /// Lines before the first point (header rows); detected when absent
+    #[serde(default)]
+    pub skip_lines: Option<usize>,
+}
+
+/// File format written by `pointcloud_export`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Las,
+    Laz,
+    /// Cloud Optimized Point Cloud: LAZ 1.4 organized as an octree
+    Copc,
+}
+
+/// Options passed to `pointcloud_export`
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    /// "1.2" or "1.4"; chosen from the cloud's attributes when absent
+    #[serde(default)]
+    pub las_version: Option<String>,
+    /// Point data record format; the smallest one holding the cloud's attributes when absent
+    #[serde(default)]
+    pub point_format: Option<u8>,
+    /// Coordinate resolution per axis; the source file's when absent
+    #[serde(default)]
+    pub scale: Option<[f64; 3]>,
+    /// Write only the points that pass this filter
+    #[serde(default)]
+    pub filter: Option<ExportFilter>,
+}
+
+/// Subset of a cloud to export. All given conditions must hold.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ExportFilter {
+    #[serde(default)]
+    pub bounds: Option<BoundingBox3D>,
+    #[serde(default)]
+    pub classifications: Option<Vec<u8>>,
+}
+
+impl ExportFilter {
+    pub fn accepts(&self, p: &PointRecord) -> bool {
+        if let Some(bounds) = &self.bounds {
+            if !bounds.contains(p.x, p.y, p.z) {
+                return false;
+            }
+        }
+        match &self.classifications {
+            Some(classes) => classes.contains(&p.classification),
+            None => true,
+        }
+    }
+}
+
+/// Options passed to `pointcloud_export_tiles`
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TilesExportOptions {
+    /// Longitude and latitude in degrees and ellipsoidal height to place the
+    /// cloud's center at, with its axes east, north and up. Required: cloud
+    /// coordinates are not reprojected, and viewers read the tileset as
+    /// Earth-centered coordinates.
+    #[serde(default)]
+    pub origin: Option<[f64; 3]>,
+}
+
+/// State of the on-disk octree cache
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheInfo {
+    pub directory: String,
+    pub size_bytes: u64,
+    pub limit_bytes: u64,
+    pub entries: u32,
+}
+
+/// A VLR or EVLR header entry, for inspecting file headers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VlrInfo {
+    pub user_id: String,
+    pub record_id: u16,
+    pub description: String,
+    /// Payload length in bytes (excluding the record header)
+    pub record_length: u64,
+    pub is_extended: bool,
+    /// Payload as text for textual records such as OGC WKT
+    pub text: Option<String>,
 }
 
 /// An octree node reference sent to the frontend
@@ -109,6 +541,8 @@ pub struct OctreeNodeInfo {
     pub level: u8,
     pub point_count: u32,
     pub has_children: bool,
+    /// Whether the node's children add points to it (true) or replace it
+    pub additive: bool,
 }
 
 /// A chunk of point data for rendering — positions as f32 relative to center, colors as u8
@@ -117,29 +551,81 @@ pub struct PointChunk {
     pub node_id: String,
     pub center: [f64; 3],
     pub level: u8,
+    /// Distance between the chunk's points: the sampling grid's cell size
+    /// for LOD nodes of a built octree, else estimated from the footprint
     pub spacing: f32,
     pub positions: Vec<f32>,
     pub colors: Vec<u8>,
     pub intensities: Vec<u16>,
     pub classifications: Vec<u8>,
+    /// Which of the optional arrays below are filled, see `point_attributes`
+    pub attribute_mask: u32,
+    pub return_numbers: Vec<u8>,
+    pub number_of_returns: Vec<u8>,
+    pub flags: Vec<u8>,
+    pub scanner_channels: Vec<u8>,
+    pub scan_angles: Vec<f32>,
+    pub user_data: Vec<u8>,
+    pub point_source_ids: Vec<u16>,
+    pub gps_times: Vec<f64>,
+    pub nir: Vec<u16>,
+    /// Waveform packets, one entry per point each (`WAVE_PACKETS`)
+    pub wave_descriptor_indices: Vec<u8>,
+    pub wave_byte_offsets: Vec<u64>,
+    pub wave_sizes: Vec<u32>,
+    pub wave_return_locations: Vec<f32>,
+    /// x, y, z of each point's waveform direction
+    pub wave_directions: Vec<f32>,
+    /// One array per entry of `PointcloudMetadata::extra_attributes` (NaN = no data)
+    pub extra_attributes: Vec<Vec<f32>>,
     pub point_count: u32,
 }
 
-/// Camera frustum sent from frontend for LOD selection
+/// How a camera projects the scene onto the screen
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CameraProjection {
+    #[default]
+    Perspective,
+    Orthographic,
+}
+
+/// Camera sent from frontend for LOD selection, in the cloud's coordinates
 #[derive(Debug, Clone, Deserialize)]
 pub struct CameraState {
     pub position: [f64; 3],
+    /// Point the camera looks at
     pub target: [f64; 3],
+    /// Up direction of the view; Z when absent
+    #[serde(default)]
+    pub up: Option<[f64; 3]>,
+    /// Vertical field of view in degrees, for a perspective camera
     pub fov: f64,
+    /// Width over height of the view
     pub aspect: f64,
+    /// Distance of the near clipping plane; no near plane when absent
+    #[serde(default)]
+    pub near: Option<f64>,
+    /// Distance of the far clipping plane; no far plane when absent
+    #[serde(default)]
+    pub far: Option<f64>,
+    #[serde(default)]
+    pub projection: CameraProjection,
+    /// Height of the view in world units, for an orthographic camera; what a
+    /// perspective camera sees at the target when absent
+    #[serde(default)]
+    pub ortho_height: Option<f64>,
     pub screen_height: f64,
 }
 
-/// Indexing progress
+/// Progress of a background task: indexing or export
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IndexProgress {
     pub progress: f64,
     pub phase: String,
     pub points_processed: u64,
     pub total_points: u64,
+    /// Problems that did not stop an export, such as source data the output cannot hold
+    #[serde(default)]
+    pub warnings: Vec<HeaderIssue>,
 }
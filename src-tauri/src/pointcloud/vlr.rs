@@ -0,0 +1,138 @@
+use super::types::VlrInfo;
+
+/// Size of a VLR header: reserved(2) + user_id(16) + record_id(2) + record_length(2) + description(32)
+const VLR_HEADER_SIZE: usize = 54;
+
+/// Size of an EVLR header: like a VLR header but with a 64-bit record length
+pub const EVLR_HEADER_SIZE: usize = 60;
+
+/// A variable length record located in the mapped file
+#[derive(Debug, Clone)]
+pub struct VlrRecord {
+    pub user_id: String,
+    pub record_id: u16,
+    pub description: String,
+    /// Byte offset of the payload within the file
+    pub data_offset: usize,
+    /// Payload length in bytes
+    pub data_len: usize,
+    /// Extended VLR stored after the point data (LAS 1.3 waveform / LAS 1.4 EVLR)
+    pub is_extended: bool,
+}
+
+impl VlrRecord {
+    /// Payload bytes of this record within the file data
+    pub fn data<'a>(&self, file: &'a [u8]) -> &'a [u8] {
+        &file[self.data_offset..self.data_offset + self.data_len]
+    }
+
+    pub fn is(&self, user_id: &str, record_id: u16) -> bool {
+        self.record_id == record_id && self.user_id == user_id
+    }
+
+    /// Describe this record for the frontend. Textual records (OGC WKT,
+    /// GeoTIFF ASCII params) include their text.
+    pub fn info(&self, file: &[u8]) -> VlrInfo {
+        let is_text = self.user_id == "LASF_Projection" && matches!(self.record_id, 2112 | 34737);
+        VlrInfo {
+            user_id: self.user_id.clone(),
+            record_id: self.record_id,
+            description: self.description.clone(),
+            record_length: self.data_len as u64,
+            is_extended: self.is_extended,
+            text: is_text.then(|| read_fixed_string(self.data(file))),
+        }
+    }
+}
+
+/// Decode a fixed-size, NUL-padded ASCII field
+pub fn read_fixed_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+}
+
+/// Encode a fixed-size, NUL-padded ASCII field, truncating long text
+pub fn write_fixed_string(text: &str, field: &mut [u8]) {
+    let len = text.len().min(field.len());
+    field[..len].copy_from_slice(&text.as_bytes()[..len]);
+    field[len..].fill(0);
+}
+
+/// Walk the regular VLR area that sits between the header and the point data.
+/// Stops at the first record that would run past `offset_to_points`.
+pub fn read_vlrs(data: &[u8], offset_to_points: usize) -> Vec<VlrRecord> {
+    if data.len() < 104 {
+        return Vec::new();
+    }
+
+    // VLRs start right after the header, whose size is stored in the header itself
+    let header_size = u16::from_le_bytes([data[94], data[95]]) as usize;
+    let num_vlrs = u32::from_le_bytes([data[100], data[101], data[102], data[103]]) as usize;
+    let limit = offset_to_points.min(data.len());
+
+    let mut vlrs = Vec::new();
+    let mut offset = header_size;
+    for _ in 0..num_vlrs {
+        if offset + VLR_HEADER_SIZE > limit {
+            break;
+        }
+
+        let record_length = u16::from_le_bytes([data[offset + 20], data[offset + 21]]) as usize;
+        let data_offset = offset + VLR_HEADER_SIZE;
+        if data_offset + record_length > limit {
+            break;
+        }
+
+        vlrs.push(VlrRecord {
+            user_id: read_fixed_string(&data[offset + 2..offset + 18]),
+            record_id: u16::from_le_bytes([data[offset + 18], data[offset + 19]]),
+            description: read_fixed_string(&data[offset + 22..offset + 54]),
+            data_offset,
+            data_len: record_length,
+            is_extended: false,
+        });
+
+        offset = data_offset + record_length;
+    }
+
+    vlrs
+}
+
+/// Walk `count` extended VLRs starting at byte offset `start` (after the point data).
+/// Records that run past the end of the file are dropped.
+pub fn read_evlrs(data: &[u8], start: u64, count: u32) -> Vec<VlrRecord> {
+    let mut evlrs = Vec::new();
+    let mut offset = match usize::try_from(start) {
+        Ok(o) => o,
+        Err(_) => return evlrs,
+    };
+
+    for _ in 0..count {
+        if offset > data.len().saturating_sub(EVLR_HEADER_SIZE) || data.len() < EVLR_HEADER_SIZE {
+            break;
+        }
+
+        let record_length = u64::from_le_bytes([
+            data[offset + 20], data[offset + 21], data[offset + 22], data[offset + 23],
+            data[offset + 24], data[offset + 25], data[offset + 26], data[offset + 27],
+        ]);
+        let data_offset = offset + EVLR_HEADER_SIZE;
+        let data_len = match usize::try_from(record_length) {
+            Ok(len) if data_offset.checked_add(len).is_some_and(|end| end <= data.len()) => len,
+            _ => break,
+        };
+
+        evlrs.push(VlrRecord {
+            user_id: read_fixed_string(&data[offset + 2..offset + 18]),
+            record_id: u16::from_le_bytes([data[offset + 18], data[offset + 19]]),
+            description: read_fixed_string(&data[offset + 28..offset + 60]),
+            data_offset,
+            data_len,
+            is_extended: true,
+        });
+
+        offset = data_offset + data_len;
+    }
+
+    evlrs
+}
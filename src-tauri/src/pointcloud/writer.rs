@@ -0,0 +1,751 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::extra_bytes::ExtraBytesLayout;
+use super::parser::{standard_record_length, PointcloudParser};
+use super::types::{point_flags, BoundingBox3D, ExtraAttributeInfo, PointBuffer};
+use super::vlr::write_fixed_string;
+
+/// Written to the generating software field of the header
+const GENERATING_SOFTWARE: &str = "Open Pointcloud Studio";
+
+/// Header sizes of LAS 1.2 and LAS 1.4
+const HEADER_SIZE_12: usize = 227;
+const HEADER_SIZE_14: usize = 375;
+
+/// Sizes of a VLR and an EVLR header
+const VLR_HEADER_SIZE: usize = 54;
+const EVLR_HEADER_SIZE: usize = 60;
+
+/// Global encoding bit saying GPS times are adjusted standard GPS time, not GPS week time
+pub const GLOBAL_ENCODING_GPS_STANDARD_TIME: u16 = 1;
+
+/// Global encoding bits saying where waveform data packets are stored
+pub const GLOBAL_ENCODING_WAVEFORM_INTERNAL: u16 = 1 << 1;
+pub const GLOBAL_ENCODING_WAVEFORM_EXTERNAL: u16 = 1 << 2;
+
+/// Global encoding bit saying the CRS is given as OGC WKT (LAS 1.4)
+const GLOBAL_ENCODING_WKT: u16 = 1 << 4;
+
+/// A variable length record to write
+#[derive(Debug, Clone)]
+pub struct Vlr {
+    pub user_id: String,
+    pub record_id: u16,
+    pub description: String,
+    pub data: Vec<u8>,
+}
+
+impl Vlr {
+    pub fn new(user_id: &str, record_id: u16, description: &str, data: Vec<u8>) -> Self {
+        Self {
+            user_id: user_id.to_string(),
+            record_id,
+            description: description.to_string(),
+            data,
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut out = vec![0u8; VLR_HEADER_SIZE];
+        write_fixed_string(&self.user_id, &mut out[2..18]);
+        out[18..20].copy_from_slice(&self.record_id.to_le_bytes());
+        out[20..22].copy_from_slice(&(self.data.len() as u16).to_le_bytes());
+        write_fixed_string(&self.description, &mut out[22..54]);
+        out.extend_from_slice(&self.data);
+        out
+    }
+
+    fn encode_extended(&self) -> Vec<u8> {
+        let mut out = vec![0u8; EVLR_HEADER_SIZE];
+        write_fixed_string(&self.user_id, &mut out[2..18]);
+        out[18..20].copy_from_slice(&self.record_id.to_le_bytes());
+        out[20..28].copy_from_slice(&(self.data.len() as u64).to_le_bytes());
+        write_fixed_string(&self.description, &mut out[28..60]);
+        out.extend_from_slice(&self.data);
+        out
+    }
+}
+
+/// Where a LAZ chunk written by `LasWriter::write_chunk` landed in the file
+#[derive(Debug, Clone, Copy)]
+pub struct ChunkSpan {
+    pub offset: u64,
+    pub byte_size: u64,
+    pub point_count: u64,
+}
+
+/// Waveform data the wave packets of the points refer to, copied from the source
+#[derive(Debug, Clone)]
+pub struct Waveform {
+    /// Payloads of the Waveform Packet Descriptor VLRs, by record ID (100-354)
+    pub descriptors: Vec<(u16, Vec<u8>)>,
+    pub data: WaveformData,
+}
+
+/// Where the waveform data of a source file is stored
+#[derive(Debug, Clone)]
+pub enum WaveformData {
+    /// The waveform data packet record of a LAS file: its byte range in
+    /// `path`, record header included, which packet offsets are relative to
+    Internal { path: PathBuf, offset: u64, len: u64 },
+    /// An external .wdp file, copied next to the output
+    External(PathBuf),
+}
+
+/// Layout of a LAS file to write
+#[derive(Debug, Clone)]
+pub struct LasLayout {
+    /// Minor version: 2 or 4
+    pub version_minor: u8,
+    /// Point data record format: 0-3, or any up to 10 for LAS 1.4
+    pub point_format: u8,
+    /// Write LAZ instead of LAS
+    pub compress: bool,
+    pub scale: [f64; 3],
+    pub offset: [f64; 3],
+    /// Stored as Extra Bytes after the standard fields of each record
+    pub extra_attributes: Vec<ExtraAttributeInfo>,
+    /// Payloads of the LASF_Projection records to write, by record ID
+    pub projection_vlrs: Vec<(u16, Vec<u8>)>,
+    /// Records written ahead of all others; COPC requires its info VLR first
+    pub leading_vlrs: Vec<Vlr>,
+    /// Compress each `write_chunk` call as its own variable-size LAZ chunk
+    pub variable_chunks: bool,
+    /// Required by the point formats with wave packets (4, 5, 9 and 10)
+    pub waveform: Option<Waveform>,
+    /// GPS times are adjusted standard GPS time rather than GPS week time
+    pub standard_gps_time: bool,
+}
+
+enum Sink {
+    Las(BufWriter<File>),
+    Laz(laz::ParLasZipCompressor<BufWriter<File>>),
+    Chunked(laz::LasZipCompressor<'static, BufWriter<File>>),
+}
+
+/// Streaming LAS/LAZ writer. Points are encoded as they arrive; the header,
+/// whose point counts and bounds depend on them, is written by `finish`.
+pub struct LasWriter {
+    sink: Sink,
+    layout: LasLayout,
+    extra_bytes: ExtraBytesLayout,
+    header_size: usize,
+    record_length: usize,
+    offset_to_points: u32,
+    number_of_vlrs: u32,
+    /// Chunks written so far; the size of the last is known once the point data ends
+    chunks: Vec<ChunkSpan>,
+    /// Offset after the point data, where EVLRs go, once it has ended
+    points_end: Option<u64>,
+    point_count: u64,
+    points_by_return: [u64; 15],
+    bounds: BoundingBox3D,
+    /// Reused between batches
+    buffer: Vec<u8>,
+}
+
+impl LasWriter {
+    /// Create the file and write everything up to the point data
+    pub fn create<P: AsRef<Path>>(path: P, layout: LasLayout) -> Result<Self, String> {
+        let format = layout.point_format;
+        if format > 10 {
+            return Err(format!("Cannot write point format {}", format));
+        }
+        if format >= 4 && layout.version_minor < 4 {
+            return Err(format!("Point format {} requires LAS 1.4", format));
+        }
+        // Point formats 6-10 require the CRS as OGC WKT
+        if format >= 6 && layout.projection_vlrs.iter().any(|(id, _)| *id == 34735) {
+            return Err(format!("Point format {} requires the CRS as OGC WKT, not GeoTIFF keys", format));
+        }
+        let has_wave_packets = PointcloudParser::record_layout(format).wave_packet.is_some();
+        if has_wave_packets != layout.waveform.is_some() {
+            return Err(match has_wave_packets {
+                true => format!("Point format {} requires waveform data, which the source lacks", format),
+                false => format!("Point format {} has no wave packets to keep the waveform data", format),
+            });
+        }
+
+        let extra_bytes = ExtraBytesLayout::from_infos(&layout.extra_attributes);
+        let record_length = standard_record_length(format) + extra_bytes.size();
+        let header_size = if layout.version_minor >= 4 { HEADER_SIZE_14 } else { HEADER_SIZE_12 };
+
+        let mut vlrs = layout.leading_vlrs.clone();
+        vlrs.extend(layout.projection_vlrs.iter().map(|(record_id, data)| {
+            Vlr::new("LASF_Projection", *record_id, projection_description(*record_id), data.clone())
+        }));
+        if let Some(waveform) = &layout.waveform {
+            vlrs.extend(waveform.descriptors.iter().map(|(record_id, data)| {
+                Vlr::new("LASF_Spec", *record_id, "Waveform packet descriptor", data.clone())
+            }));
+        }
+        if !extra_bytes.is_empty() {
+            vlrs.push(Vlr::new("LASF_Spec", 4, "Extra Bytes", extra_bytes.to_vlr()));
+        }
+        let laz_vlr = if layout.compress {
+            let items = laz::LazItemRecordBuilder::default_for_point_format_id(format, extra_bytes.size() as u16)
+                .map_err(|e| format!("Failed to describe LAZ point layout: {}", e))?;
+            let vlr = if layout.variable_chunks {
+                laz::LazVlrBuilder::new(items).with_variable_chunk_size().build()
+            } else {
+                laz::LazVlr::from_laz_items(items)
+            };
+            let mut data = Vec::new();
+            vlr.write_to(&mut data).map_err(|e| format!("Failed to encode LASzip VLR: {}", e))?;
+            vlrs.push(Vlr::new("laszip encoded", 22204, "LASzip compression", data));
+            Some(vlr)
+        } else {
+            None
+        };
+        vlrs.retain(|vlr| {
+            let fits = vlr.data.len() <= u16::MAX as usize;
+            if !fits {
+                eprintln!("Skipping VLR {} {}: payload too large for a VLR", vlr.user_id, vlr.record_id);
+            }
+            fits
+        });
+
+        if let Some(Waveform { data: WaveformData::External(source), .. }) = &layout.waveform {
+            fs::copy(source, path.as_ref().with_extension("wdp"))
+                .map_err(|e| format!("Failed to copy waveform data: {}", e))?;
+        }
+
+        // Opened for reading too: the end of variable-size chunks is read back
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path.as_ref())
+            .map_err(|e| format!("Failed to create file: {}", e))?;
+        let mut out = BufWriter::new(file);
+
+        // The header is written last; reserve its space
+        let mut head = vec![0u8; header_size];
+        for vlr in &vlrs {
+            head.extend_from_slice(&vlr.encode());
+        }
+        let offset_to_points = u32::try_from(head.len()).map_err(|_| "VLRs are too large".to_string())?;
+        out.write_all(&head).map_err(|e| format!("Failed to write header: {}", e))?;
+
+        let sink = match laz_vlr {
+            Some(vlr) if layout.variable_chunks => Sink::Chunked(
+                laz::LasZipCompressor::new(out, vlr)
+                    .map_err(|e| format!("Failed to create LAZ compressor: {}", e))?,
+            ),
+            Some(vlr) => Sink::Laz(
+                laz::ParLasZipCompressor::new(out, vlr)
+                    .map_err(|e| format!("Failed to create LAZ compressor: {}", e))?,
+            ),
+            None => Sink::Las(out),
+        };
+
+        Ok(Self {
+            sink,
+            layout,
+            extra_bytes,
+            header_size,
+            record_length,
+            offset_to_points,
+            number_of_vlrs: vlrs.len() as u32,
+            chunks: Vec::new(),
+            points_end: None,
+            point_count: 0,
+            points_by_return: [0; 15],
+            bounds: BoundingBox3D::new(),
+            buffer: Vec::new(),
+        })
+    }
+
+    /// Encode and write the points of a batch at `indices`
+    pub fn write_points<I>(&mut self, points: &PointBuffer, indices: I) -> Result<(), String>
+    where
+        I: IntoIterator<Item = usize>,
+    {
+        let buffer = self.encode_batch(points, indices)?;
+        let result = match &mut self.sink {
+            Sink::Las(out) => out.write_all(&buffer),
+            Sink::Laz(compressor) => compressor.compress_many(&buffer),
+            Sink::Chunked(_) => {
+                self.buffer = buffer;
+                return Err("Points of a chunked LAZ file are written by chunk".into());
+            }
+        };
+        self.buffer = buffer;
+        result.map_err(|e| format!("Failed to write points: {}", e))
+    }
+
+    /// Encode and compress points as one LAZ chunk of their own
+    pub fn write_chunk(&mut self, points: &PointBuffer) -> Result<(), String> {
+        if points.is_empty() {
+            return Ok(());
+        }
+        let buffer = self.encode_batch(points, 0..points.len())?;
+        let Sink::Chunked(compressor) = &mut self.sink else {
+            self.buffer = buffer;
+            return Err("The file is not written in variable-size chunks".into());
+        };
+
+        // A chunk is closed when the next one starts, so the last is closed by
+        // `done` and the chunk table does not end with an empty chunk
+        let offset = match self.chunks.last_mut() {
+            Some(previous) => {
+                let end = compressor
+                    .finish_current_chunk()
+                    .and_then(|_| compressor.get_mut().stream_position())
+                    .map_err(|e| format!("Failed to write points: {}", e))?;
+                previous.byte_size = end - previous.offset;
+                end
+            }
+            // The point data starts with the offset to the chunk table
+            None => self.offset_to_points as u64 + 8,
+        };
+        let result = compressor.compress_many(&buffer);
+        self.buffer = buffer;
+        result.map_err(|e| format!("Failed to write points: {}", e))?;
+
+        self.chunks.push(ChunkSpan { offset, byte_size: 0, point_count: points.len() as u64 });
+        Ok(())
+    }
+
+    fn encode_batch<I>(&mut self, points: &PointBuffer, indices: I) -> Result<Vec<u8>, String>
+    where
+        I: IntoIterator<Item = usize>,
+    {
+        let mut buffer = std::mem::take(&mut self.buffer);
+        buffer.clear();
+        for i in indices {
+            let start = buffer.len();
+            buffer.resize(start + self.record_length, 0);
+            if let Err(e) = self.encode_record(points, i, &mut buffer[start..]) {
+                self.buffer = buffer;
+                return Err(e);
+            }
+        }
+        Ok(buffer)
+    }
+
+    /// End the point data and return the offset after it, where EVLRs go.
+    /// No points can be written afterwards.
+    pub fn end_points(&mut self) -> Result<u64, String> {
+        if let Some(end) = self.points_end {
+            return Ok(end);
+        }
+        let end = match &mut self.sink {
+            Sink::Las(out) => out.stream_position(),
+            Sink::Laz(compressor) => {
+                compressor.done().map_err(|e| format!("Failed to finish point data: {}", e))?;
+                compressor.get_mut().stream_position()
+            }
+            Sink::Chunked(compressor) => end_chunks(compressor, self.offset_to_points as u64).map(|(end, table)| {
+                // The last chunk ends where the chunk table starts
+                if let Some(last) = self.chunks.last_mut() {
+                    last.byte_size = table.saturating_sub(last.offset);
+                }
+                end
+            }),
+        };
+        let end = end.map_err(|e| format!("Failed to finish point data: {}", e))?;
+        self.points_end = Some(end);
+        Ok(end)
+    }
+
+    /// Chunks written by `write_chunk`; sizes are complete after `end_points`
+    pub fn chunks(&self) -> &[ChunkSpan] {
+        &self.chunks
+    }
+
+    /// Replace the payload of a leading VLR, keeping its size, before `finish`
+    pub fn set_leading_vlr(&mut self, index: usize, data: Vec<u8>) -> Result<(), String> {
+        let vlr = self.layout.leading_vlrs.get_mut(index).ok_or("No such leading VLR")?;
+        if vlr.data.len() != data.len() {
+            return Err(format!("Leading VLR {} must stay {} bytes", vlr.record_id, vlr.data.len()));
+        }
+        vlr.data = data;
+        Ok(())
+    }
+
+    /// Flush the point data and write the final header. Returns the point count.
+    pub fn finish(self) -> Result<u64, String> {
+        self.finish_with_evlrs(&[])
+    }
+
+    /// Flush the point data, append the EVLRs (LAS 1.4) and write the final
+    /// header. Returns the point count.
+    pub fn finish_with_evlrs(mut self, evlrs: &[Vlr]) -> Result<u64, String> {
+        if self.layout.version_minor < 4 && self.point_count > u32::MAX as u64 {
+            return Err("Too many points for LAS 1.2; export as LAS 1.4".into());
+        }
+        if self.layout.version_minor < 4 && !evlrs.is_empty() {
+            return Err("EVLRs require LAS 1.4".into());
+        }
+        let evlr_start = self.end_points()?;
+
+        // Internal waveform data is the first EVLR, copied as is
+        let internal_waveform = match self.layout.waveform.as_ref().map(|w| &w.data) {
+            Some(WaveformData::Internal { path, offset, len }) => Some((path.clone(), *offset, *len)),
+            _ => None,
+        };
+        let evlr_count = evlrs.len() as u32 + internal_waveform.is_some() as u32;
+        let waveform_start = if internal_waveform.is_some() { evlr_start } else { 0 };
+        let mut head = self.header(if evlr_count == 0 { 0 } else { evlr_start }, evlr_count, waveform_start);
+        for vlr in &self.layout.leading_vlrs {
+            head.extend_from_slice(&vlr.encode());
+        }
+
+        let mut out = match self.sink {
+            Sink::Las(out) => out,
+            Sink::Laz(compressor) => compressor.into_inner(),
+            Sink::Chunked(compressor) => compressor.into_inner(),
+        };
+        if let Some((path, offset, len)) = internal_waveform {
+            copy_range(&path, offset, len, &mut out).map_err(|e| format!("Failed to copy waveform data: {}", e))?;
+        }
+        write_tail(&mut out, evlrs, &head).map_err(|e| format!("Failed to write header: {}", e))?;
+
+        Ok(self.point_count)
+    }
+
+    /// Coordinate as stored: scaled, offset and rounded to i32. Fails for
+    /// coordinates the scale and offset cannot reach.
+    fn quantize(&self, value: f64, axis: usize) -> Result<i32, String> {
+        let (scale, offset) = (self.layout.scale[axis], self.layout.offset[axis]);
+        let scaled = ((value - offset) / scale).round();
+        if !(i32::MIN as f64..=i32::MAX as f64).contains(&scaled) {
+            return Err(format!(
+                "Coordinate {} does not fit the LAS scale {} and offset {}",
+                value, scale, offset
+            ));
+        }
+        Ok(scaled as i32)
+    }
+
+    fn encode_record(&mut self, points: &PointBuffer, index: usize, rec: &mut [u8]) -> Result<(), String> {
+        let p = &points.points[index];
+        let format = self.layout.point_format;
+        let xyz = [self.quantize(p.x, 0)?, self.quantize(p.y, 1)?, self.quantize(p.z, 2)?];
+        for (axis, v) in xyz.iter().enumerate() {
+            rec[axis * 4..axis * 4 + 4].copy_from_slice(&v.to_le_bytes());
+        }
+        let stored = |axis: usize| xyz[axis] as f64 * self.layout.scale[axis] + self.layout.offset[axis];
+        self.bounds.expand(stored(0), stored(1), stored(2));
+
+        rec[12..14].copy_from_slice(&p.intensity.to_le_bytes());
+        let scan_direction = if p.flags & point_flags::SCAN_DIRECTION != 0 { 0x40 } else { 0 };
+        let edge = if p.flags & point_flags::EDGE_OF_FLIGHT_LINE != 0 { 0x80 } else { 0 };
+
+        if format >= 6 {
+            rec[14] = p.return_number.min(15) | (p.number_of_returns.min(15) << 4);
+            rec[15] = (p.flags & 0x0F) | ((p.scanner_channel & 0x03) << 4) | scan_direction | edge;
+            rec[16] = p.classification;
+            rec[17] = p.user_data;
+            // Scan angle in increments of 0.006 degrees
+            rec[18..20].copy_from_slice(&((p.scan_angle / 0.006).round() as i16).to_le_bytes());
+            rec[20..22].copy_from_slice(&p.point_source_id.to_le_bytes());
+            rec[22..30].copy_from_slice(&p.gps_time.to_le_bytes());
+        } else {
+            rec[14] = p.return_number.min(7) | (p.number_of_returns.min(7) << 3) | scan_direction | edge;
+            rec[15] = p.classification.min(31) | ((p.flags & 0x07) << 5);
+            rec[16] = p.scan_angle.round().clamp(-90.0, 90.0) as i8 as u8;
+            rec[17] = p.user_data;
+            rec[18..20].copy_from_slice(&p.point_source_id.to_le_bytes());
+            if matches!(format, 1 | 3) {
+                rec[20..28].copy_from_slice(&p.gps_time.to_le_bytes());
+            }
+        }
+
+        let layout = PointcloudParser::record_layout(format);
+        if let Some(co) = layout.color {
+            for (i, c) in [p.r, p.g, p.b].into_iter().enumerate() {
+                rec[co + i * 2..co + i * 2 + 2].copy_from_slice(&c.to_le_bytes());
+            }
+        }
+        if let Some(no) = layout.nir {
+            rec[no..no + 2].copy_from_slice(&p.nir.to_le_bytes());
+        }
+        if let (Some(wo), Some(packets)) = (layout.wave_packet, &points.wave_packets) {
+            let w = &packets[index];
+            rec[wo] = w.descriptor_index;
+            rec[wo + 1..wo + 9].copy_from_slice(&w.byte_offset.to_le_bytes());
+            rec[wo + 9..wo + 13].copy_from_slice(&w.size.to_le_bytes());
+            for (i, v) in [w.return_point_location, w.dx, w.dy, w.dz].into_iter().enumerate() {
+                rec[wo + 13 + i * 4..wo + 17 + i * 4].copy_from_slice(&v.to_le_bytes());
+            }
+        }
+
+        if !self.extra_bytes.is_empty() {
+            let value = |i: usize| points.extra_attributes.get(i).map_or(f64::NAN, |v| v[index]);
+            self.extra_bytes.encode(value, &mut rec[standard_record_length(format)..]);
+        }
+
+        self.point_count += 1;
+        if (1..=15).contains(&p.return_number) {
+            self.points_by_return[p.return_number as usize - 1] += 1;
+        }
+        Ok(())
+    }
+
+    fn header(&self, evlr_start: u64, evlr_count: u32, waveform_start: u64) -> Vec<u8> {
+        let mut h = vec![0u8; self.header_size];
+        let layout = &self.layout;
+        let is_14 = layout.version_minor >= 4;
+
+        h[0..4].copy_from_slice(b"LASF");
+        let mut global_encoding = 0;
+        if layout.standard_gps_time {
+            global_encoding |= GLOBAL_ENCODING_GPS_STANDARD_TIME;
+        }
+        let has_wkt = layout.projection_vlrs.iter().any(|(id, _)| *id == 2112);
+        if is_14 && (layout.point_format >= 6 || has_wkt) {
+            global_encoding |= GLOBAL_ENCODING_WKT;
+        }
+        match layout.waveform.as_ref().map(|w| &w.data) {
+            Some(WaveformData::Internal { .. }) => global_encoding |= GLOBAL_ENCODING_WAVEFORM_INTERNAL,
+            Some(WaveformData::External(_)) => global_encoding |= GLOBAL_ENCODING_WAVEFORM_EXTERNAL,
+            None => {}
+        }
+        h[6..8].copy_from_slice(&global_encoding.to_le_bytes());
+        h[24] = 1;
+        h[25] = layout.version_minor;
+        write_fixed_string("OTHER", &mut h[26..58]);
+        write_fixed_string(GENERATING_SOFTWARE, &mut h[58..90]);
+        let (day, year) = creation_date();
+        h[90..92].copy_from_slice(&day.to_le_bytes());
+        h[92..94].copy_from_slice(&year.to_le_bytes());
+        h[94..96].copy_from_slice(&(self.header_size as u16).to_le_bytes());
+        h[96..100].copy_from_slice(&self.offset_to_points.to_le_bytes());
+        h[100..104].copy_from_slice(&self.number_of_vlrs.to_le_bytes());
+        // Bit 7 marks LASzip compressed point data
+        h[104] = layout.point_format | if layout.compress { 0x80 } else { 0 };
+        h[105..107].copy_from_slice(&(self.record_length as u16).to_le_bytes());
+
+        // Legacy 32-bit counts stay 0 where they cannot hold the values (LAS 1.4)
+        let legacy = layout.point_format < 6 && self.point_count <= u32::MAX as u64;
+        if legacy {
+            h[107..111].copy_from_slice(&(self.point_count as u32).to_le_bytes());
+            for (i, &count) in self.points_by_return[..5].iter().enumerate() {
+                h[111 + i * 4..115 + i * 4].copy_from_slice(&(count as u32).to_le_bytes());
+            }
+        }
+
+        for i in 0..3 {
+            h[131 + i * 8..139 + i * 8].copy_from_slice(&layout.scale[i].to_le_bytes());
+            h[155 + i * 8..163 + i * 8].copy_from_slice(&layout.offset[i].to_le_bytes());
+        }
+        if self.point_count > 0 {
+            let b = &self.bounds;
+            for (i, v) in [b.max_x, b.min_x, b.max_y, b.min_y, b.max_z, b.min_z].iter().enumerate() {
+                h[179 + i * 8..187 + i * 8].copy_from_slice(&v.to_le_bytes());
+            }
+        }
+
+        if is_14 {
+            h[227..235].copy_from_slice(&waveform_start.to_le_bytes());
+            h[235..243].copy_from_slice(&evlr_start.to_le_bytes());
+            h[243..247].copy_from_slice(&evlr_count.to_le_bytes());
+            h[247..255].copy_from_slice(&self.point_count.to_le_bytes());
+            for (i, count) in self.points_by_return.iter().enumerate() {
+                h[255 + i * 8..263 + i * 8].copy_from_slice(&count.to_le_bytes());
+            }
+        }
+        h
+    }
+}
+
+/// Finish chunked LAZ data. Returns the offset after it and the offset of its
+/// chunk table, read back from the start of the point data.
+fn end_chunks(
+    compressor: &mut laz::LasZipCompressor<'static, BufWriter<File>>,
+    offset_to_points: u64,
+) -> std::io::Result<(u64, u64)> {
+    compressor.done()?;
+    let out = compressor.get_mut();
+    let end = out.stream_position()?;
+    out.flush()?;
+
+    let file = out.get_mut();
+    let mut table_offset = [0u8; 8];
+    file.seek(SeekFrom::Start(offset_to_points))?;
+    file.read_exact(&mut table_offset)?;
+    file.seek(SeekFrom::Start(end))?;
+    Ok((end, u64::from_le_bytes(table_offset)))
+}
+
+/// Append `len` bytes of the file at `path` from `offset`
+fn copy_range(path: &Path, offset: u64, len: u64, out: &mut BufWriter<File>) -> io::Result<()> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    let copied = io::copy(&mut file.take(len), out)?;
+    if copied < len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the source ends early"));
+    }
+    Ok(())
+}
+
+/// Append the EVLRs and write the header and leading VLRs over the reserved space
+fn write_tail(out: &mut BufWriter<File>, evlrs: &[Vlr], head: &[u8]) -> std::io::Result<()> {
+    for evlr in evlrs {
+        out.write_all(&evlr.encode_extended())?;
+    }
+    out.seek(SeekFrom::Start(0))?;
+    out.write_all(head)?;
+    out.flush()
+}
+
+fn projection_description(record_id: u16) -> &'static str {
+    match record_id {
+        2111 => "OGC math transform WKT",
+        2112 => "OGC coordinate system WKT",
+        34735 => "GeoKeyDirectoryTag",
+        34736 => "GeoDoubleParamsTag",
+        34737 => "GeoAsciiParamsTag",
+        _ => "",
+    }
+}
+
+/// Day of year (1-based) and year of today, for the header
+fn creation_date() -> (u16, u16) {
+    let mut days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() / 86_400)
+        .unwrap_or(0);
+    let mut year = 1970u64;
+    loop {
+        // Every fourth year is a leap year between 1901 and 2099
+        let length = if year & 3 == 0 { 366 } else { 365 };
+        if days < length {
+            break;
+        }
+        days -= length;
+        year += 1;
+    }
+    (days as u16 + 1, year as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointcloud::reader::PointReader;
+    use crate::pointcloud::types::{OpenOptions as ReadOptions, PointRecord};
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ops-writer-{}-{}", std::process::id(), name))
+    }
+
+    fn layout(version_minor: u8, point_format: u8, compress: bool) -> LasLayout {
+        LasLayout {
+            version_minor,
+            point_format,
+            compress,
+            scale: [0.001; 3],
+            offset: [1000.0, 2000.0, 0.0],
+            extra_attributes: Vec::new(),
+            projection_vlrs: Vec::new(),
+            leading_vlrs: Vec::new(),
+            variable_chunks: false,
+            waveform: None,
+            standard_gps_time: true,
+        }
+    }
+
+    fn sample_points(count: usize) -> PointBuffer {
+        let points: Vec<PointRecord> = (0..count)
+            .map(|i| PointRecord {
+                x: 1000.0 + i as f64 * 0.25,
+                y: 2000.0 + i as f64 * 0.5,
+                z: (i % 100) as f64 * 0.125,
+                r: (i * 257) as u16,
+                g: 0xffff - i as u16,
+                b: 0x1234,
+                intensity: (i * 3) as u16,
+                classification: (i % 20) as u8,
+                return_number: 1 + (i % 3) as u8,
+                number_of_returns: 3,
+                gps_time: 1.0e9 + i as f64 * 0.001,
+                point_source_id: 7,
+                ..PointRecord::default()
+            })
+            .collect();
+        PointBuffer::from(points)
+    }
+
+    fn read_back(path: &Path) -> Vec<PointRecord> {
+        let reader = PointcloudParser::open(path, &ReadOptions::default()).unwrap();
+        let mut points = Vec::new();
+        reader
+            .stream_points(1000, &mut |batch, _| {
+                points.extend_from_slice(&batch.points);
+                true
+            })
+            .unwrap();
+        points
+    }
+
+    fn round_trip(name: &str, layout: LasLayout) {
+        let path = temp_path(name);
+        let points = sample_points(5000);
+        let mut writer = LasWriter::create(&path, layout).unwrap();
+        writer.write_points(&points, 0..points.len()).unwrap();
+        assert_eq!(writer.finish().unwrap(), 5000);
+
+        let read = read_back(&path);
+        let _ = fs::remove_file(&path);
+        assert_eq!(read.len(), points.len());
+        for (a, b) in points.points.iter().zip(&read) {
+            assert!((a.x - b.x).abs() < 1e-6 && (a.y - b.y).abs() < 1e-6 && (a.z - b.z).abs() < 1e-6);
+            assert_eq!((a.r, a.g, a.b), (b.r, b.g, b.b));
+            assert_eq!((a.intensity, a.classification), (b.intensity, b.classification));
+            assert_eq!(a.point_source_id, b.point_source_id);
+            assert_eq!((a.return_number, a.number_of_returns), (b.return_number, b.number_of_returns));
+            assert_eq!(a.gps_time, b.gps_time);
+        }
+    }
+
+    #[test]
+    fn las_round_trip() {
+        round_trip("rt.las", layout(2, 3, false));
+        round_trip("rt14.las", layout(4, 7, false));
+    }
+
+    #[test]
+    fn laz_round_trip() {
+        round_trip("rt.laz", layout(2, 3, true));
+        round_trip("rt14.laz", layout(4, 7, true));
+    }
+
+    #[test]
+    fn laz_without_chunks_is_read_sequentially() {
+        // A single chunk is a point-wise stream once its chunk table offset
+        // is cut and the LASzip VLR says so
+        let path = temp_path("pointwise.laz");
+        let points = sample_points(5000);
+        let mut writer = LasWriter::create(&path, layout(2, 3, true)).unwrap();
+        writer.write_points(&points, 0..points.len()).unwrap();
+        writer.finish().unwrap();
+        let mut file = fs::read(&path).unwrap();
+        let offset_to_points = u32::from_le_bytes(file[96..100].try_into().unwrap()) as usize;
+        file.drain(offset_to_points..offset_to_points + 8);
+        let vlr = file.windows(14).position(|w| w == b"laszip encoded").unwrap() - 2;
+        file[vlr + 54..vlr + 56].copy_from_slice(&1u16.to_le_bytes());
+        fs::write(&path, file).unwrap();
+
+        let read = read_back(&path);
+        let _ = fs::remove_file(&path);
+        assert_eq!(read.len(), points.len());
+        assert!(points.points.iter().zip(&read).all(|(a, b)| (a.x - b.x).abs() < 1e-6 && a.gps_time == b.gps_time));
+    }
+
+    #[test]
+    fn coordinates_beyond_the_scale_fail() {
+        let path = temp_path("overflow.las");
+        let mut points = sample_points(1);
+        points.points[0].x = 1.0e10;
+        let mut writer = LasWriter::create(&path, layout(2, 3, false)).unwrap();
+        assert!(writer.write_points(&points, 0..1).is_err());
+        drop(writer);
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -2,6 +2,17 @@ import { useState, useRef, useEffect } from 'react';
 import { useAppStore, type UITheme } from '../../state/appStore';
 import './SettingsDialog.css';
 
+const isTauri = !!(window as any).__TAURI_INTERNALS__;
+
+const GB = 1024 ** 3;
+
+interface CacheInfo {
+  directory: string;
+  size_bytes: number;
+  limit_bytes: number;
+  entries: number;
+}
+
 const THEMES: { value: UITheme; label: string; swatches: string[] }[] = [
   { value: 'dark', label: 'Dark', swatches: ['#1a1a2e', '#16213e', '#e94560', '#eaeaea'] },
   { value: 'light', label: 'Light', swatches: ['#f5f5f5', '#ffffff', '#e94560', '#1f2937'] },
@@ -17,6 +28,60 @@ export function SettingsDialog({ onClose }: SettingsDialogProps) {
   const uiTheme = useAppStore((s) => s.uiTheme);
   const setUITheme = useAppStore((s) => s.setUITheme);
 
+  // Octree cache (desktop only)
+  const [cacheInfo, setCacheInfo] = useState<CacheInfo | null>(null);
+  const [cacheLimit, setCacheLimit] = useState('');
+  const [memoryBudget, setMemoryBudget] = useState<number | null>(null);
+  const [memoryBudgetInput, setMemoryBudgetInput] = useState('');
+
+  const refreshCacheInfo = async () => {
+    const { invoke } = await import('@tauri-apps/api/core');
+    const info = await invoke<CacheInfo>('pointcloud_get_cache_info');
+    setCacheInfo(info);
+    setCacheLimit((info.limit_bytes / GB).toFixed(0));
+  };
+
+  const refreshMemoryBudget = async () => {
+    const { invoke } = await import('@tauri-apps/api/core');
+    const budget = await invoke<number>('pointcloud_get_memory_budget');
+    setMemoryBudget(budget);
+    setMemoryBudgetInput((budget / GB).toFixed(0));
+  };
+
+  useEffect(() => {
+    if (!isTauri) return;
+    refreshCacheInfo().catch(() => setCacheInfo(null));
+    refreshMemoryBudget().catch(() => setMemoryBudget(null));
+  }, []);
+
+  const handleMemoryBudgetCommit = async () => {
+    const gb = parseFloat(memoryBudgetInput);
+    if (!Number.isFinite(gb) || gb <= 0) {
+      if (memoryBudget !== null) setMemoryBudgetInput((memoryBudget / GB).toFixed(0));
+      return;
+    }
+    const { invoke } = await import('@tauri-apps/api/core');
+    await invoke('pointcloud_set_memory_budget', { budgetBytes: Math.round(gb * GB) });
+    await refreshMemoryBudget();
+  };
+
+  const handleCacheLimitCommit = async () => {
+    const gb = parseFloat(cacheLimit);
+    if (!Number.isFinite(gb) || gb < 0) {
+      if (cacheInfo) setCacheLimit((cacheInfo.limit_bytes / GB).toFixed(0));
+      return;
+    }
+    const { invoke } = await import('@tauri-apps/api/core');
+    await invoke('pointcloud_set_cache_limit', { limitBytes: Math.round(gb * GB) });
+    await refreshCacheInfo();
+  };
+
+  const handleClearCache = async () => {
+    const { invoke } = await import('@tauri-apps/api/core');
+    await invoke('pointcloud_clear_cache');
+    await refreshCacheInfo();
+  };
+
   // Dragging state
   const dialogRef = useRef<HTMLDivElement>(null);
   const [offset, setOffset] = useState<{ x: number; y: number } | null>(null);
@@ -101,6 +166,46 @@ export function SettingsDialog({ onClose }: SettingsDialogProps) {
                 ))}
               </div>
             </div>
+
+            {memoryBudget !== null && (
+              <div className="settings-section">
+                <h3 className="settings-section-title">Indexing</h3>
+                <label className="settings-cache-row" title="Larger clouds are indexed on disk">
+                  <span>Memory budget (GB)</span>
+                  <input
+                    className="settings-cache-input"
+                    type="number"
+                    min={1}
+                    value={memoryBudgetInput}
+                    onChange={(e) => setMemoryBudgetInput(e.target.value)}
+                    onBlur={handleMemoryBudgetCommit}
+                    onKeyDown={(e) => { if (e.key === 'Enter') handleMemoryBudgetCommit(); }}
+                  />
+                </label>
+              </div>
+            )}
+
+            {cacheInfo && (
+              <div className="settings-section">
+                <h3 className="settings-section-title">Octree cache</h3>
+                <div className="settings-cache-row" title={cacheInfo.directory}>
+                  <span>{(cacheInfo.size_bytes / GB).toFixed(2)} GB in {cacheInfo.entries} files</span>
+                  <button className="settings-cache-button" onClick={handleClearCache}>Clear cache</button>
+                </div>
+                <label className="settings-cache-row">
+                  <span>Size limit (GB)</span>
+                  <input
+                    className="settings-cache-input"
+                    type="number"
+                    min={0}
+                    value={cacheLimit}
+                    onChange={(e) => setCacheLimit(e.target.value)}
+                    onBlur={handleCacheLimitCommit}
+                    onKeyDown={(e) => { if (e.key === 'Enter') handleCacheLimitCommit(); }}
+                  />
+                </label>
+              </div>
+            )}
           </div>
         </div>
 
@@ -94,6 +94,10 @@ export const Ribbon = memo(function Ribbon() {
                   { label: 'XYZ', onClick: () => actions.handleExport('xyz') },
                   { label: 'PTS', onClick: () => actions.handleExport('pts') },
                   { label: 'CSV', onClick: () => actions.handleExport('csv') },
+                  { label: 'LAS', onClick: () => actions.handleExportLas('las') },
+                  { label: 'LAZ', onClick: () => actions.handleExportLas('laz') },
+                  { label: 'COPC', onClick: () => actions.handleExportLas('copc') },
+                  { label: '3D Tiles', onClick: () => actions.handleExportTiles() },
                 ]}
               />
             </RibbonGroup>
@@ -8,6 +8,26 @@ import { exportToPLY, exportToXYZ, exportToPTS, exportToCSV, downloadFile } from
 import { translatePointcloud, scalePointcloud, thinPointcloud } from '../../../engine/pointcloud/PointcloudTransforms';
 import { formatPoints } from '../../../utils/format';
 
+/** Error prefix of `pointcloud_open` for files no Rust reader recognizes */
+const UNRECOGNIZED_FORMAT_ERROR = 'Unrecognized pointcloud format';
+
+/** A header finding of `pointcloud_open`, see `HeaderIssue` in the backend */
+interface HeaderIssue {
+  field: string;
+  severity: 'warning' | 'error';
+  expected: string;
+  actual: string;
+  message: string;
+}
+
+/** Tell the user about problems found in a file's header or points */
+function showValidationIssues(fileName: string, issues: HeaderIssue[]) {
+  if (issues.length === 0) return;
+  const lines = issues.map((issue) =>
+    `• ${issue.field}: ${issue.message} (expected ${issue.expected}, found ${issue.actual})`);
+  alert(`${fileName} has problems:\n\n${lines.join('\n')}`);
+}
+
 export function useRibbonActions() {
   const activePointcloudId = useAppStore((s) => s.activePointcloudId);
   const incrementTransformVersion = useAppStore((s) => s.incrementTransformVersion);
@@ -171,13 +191,103 @@ export function useRibbonActions() {
     }
   }, [activePointcloudId]);
 
+  // Clouds opened by the Rust backend are written there, reading the source file again
+  const nativePointcloud = (formatName: string) => {
+    if (!activePointcloudId) return null;
+    const pc = useAppStore.getState().pointclouds.find((p) => p.id === activePointcloudId);
+    if (!isTauri || !pc || getBrowserPointcloud(activePointcloudId)) {
+      alert(`${formatName} export is available for pointclouds opened by the native readers.`);
+      return null;
+    }
+    return pc;
+  };
+
+  // Start a backend export and poll it until it completes or fails
+  const runNativeExport = async (
+    pc: { id: string; fileName: string },
+    target: string,
+    command: string,
+    args: Record<string, unknown>,
+  ) => {
+    const { invoke } = await import('@tauri-apps/api/core');
+    try {
+      await invoke(command, { id: pc.id, ...args });
+      while (true) {
+        await new Promise((r) => setTimeout(r, 500));
+        const prog: any = await invoke('pointcloud_get_export_progress', { id: pc.id });
+        if (prog.phase === 'Complete') {
+          alert(`Exported ${formatPoints(prog.points_processed)} points to ${target}`);
+          // What the output format could not keep from the source
+          showValidationIssues(target, prog.warnings ?? []);
+          break;
+        }
+        if (prog.phase.startsWith('Error:')) {
+          throw new Error(prog.phase);
+        }
+      }
+    } catch (err) {
+      console.error('Export failed:', err);
+      alert(`Export failed: ${err instanceof Error ? err.message : String(err)}`);
+    }
+  };
+
+  const handleExportLas = useCallback(async (format: 'las' | 'laz' | 'copc') => {
+    const pc = nativePointcloud('LAS/LAZ/COPC');
+    if (!pc) return;
+
+    const { save } = await import('@tauri-apps/plugin-dialog');
+    const baseName = pc.fileName.replace(/\.[^.]+$/, '');
+    // COPC files are LAZ files and keep that extension
+    const extension = format === 'copc' ? 'copc.laz' : format;
+    const outputPath = await save({
+      defaultPath: `${baseName}.${extension}`,
+      filters: [{ name: format.toUpperCase(), extensions: [format === 'copc' ? 'laz' : format] }],
+    });
+    if (!outputPath) return;
+
+    await runNativeExport(pc, outputPath, 'pointcloud_export', { outputPath, options: { format } });
+  }, [activePointcloudId, isTauri]);
+
+  // The octree is written as a tileset.json with a .pnts tile per node
+  const handleExportTiles = useCallback(async () => {
+    const pc = nativePointcloud('3D Tiles');
+    if (!pc) return;
+
+    // Cloud coordinates are not reprojected: the tileset is placed on the globe
+    // at a geodetic origin, with the cloud's axes east, north and up there
+    const input = prompt(
+      'Place the cloud\'s center at (longitude, latitude in degrees, ellipsoidal height in meters):',
+      '0, 0, 0',
+    );
+    if (input === null) return;
+    const origin = input.split(',').map((v) => Number(v.trim()));
+    if (
+      origin.length !== 3
+      || origin.some((v) => !Number.isFinite(v))
+      || Math.abs(origin[0]) > 180
+      || Math.abs(origin[1]) > 90
+    ) {
+      alert('Enter the origin as longitude, latitude, height, e.g. 4.9, 52.37, 0');
+      return;
+    }
+
+    const { open } = await import('@tauri-apps/plugin-dialog');
+    const outputDir = await open({ directory: true, title: 'Folder for the 3D Tiles tileset' });
+    if (!outputDir || Array.isArray(outputDir)) return;
+
+    await runNativeExport(pc, outputDir, 'pointcloud_export_tiles', { outputDir, options: { origin } });
+  }, [activePointcloudId, isTauri]);
+
   const handleImport = useCallback(async () => {
     if (isTauri) {
       try {
         const { open } = await import('@tauri-apps/plugin-dialog');
         const result = await open({
           multiple: true,
-          filters: [{ name: 'Point Clouds', extensions: SUPPORTED_EXTENSIONS.map(e => e.slice(1)) }],
+          filters: [
+            { name: 'Point Clouds', extensions: SUPPORTED_EXTENSIONS.map(e => e.slice(1)) },
+            { name: 'Entwine / Potree (ept.json, metadata.json)', extensions: ['json'] },
+          ],
         });
         if (!result) return;
 
@@ -187,15 +297,59 @@ export function useRibbonActions() {
           const id = crypto.randomUUID();
           const fileName = filePath.split(/[/\\]/).pop() || 'unknown';
           const ext = fileName.substring(fileName.lastIndexOf('.')).toLowerCase();
-          const isNativeLAS = ext === '.las' || ext === '.laz';
+          const { invoke } = await import('@tauri-apps/api/core');
 
-          if (isNativeLAS) {
-            // LAS/LAZ: use Rust backend parser + octree
-            const { invoke } = await import('@tauri-apps/api/core');
+          // The backend identifies the format from the file content (EPT datasets and
+          // Potree 2.0 folders by their JSON metadata); files it does not recognize
+          // fall back to the frontend parser.
+          let meta: any = null;
+          try {
+            try {
+              meta = await invoke('pointcloud_open', { filePath });
+            } catch (err) {
+              // Recoverable header errors: offer to read the file trusting its point records
+              if (
+                !String(err).includes('trust_data')
+                || !confirm(`${fileName}: ${err}\n\nRead it anyway, trusting the point data over the header?`)
+              ) {
+                throw err;
+              }
+              meta = await invoke('pointcloud_open', { filePath, options: { trust_data: true } });
+            }
+          } catch (err) {
+            if (!String(err).includes(UNRECOGNIZED_FORMAT_ERROR)) {
+              console.error('Failed to open pointcloud:', err);
+              continue;
+            }
+          }
 
+          if (meta) {
+            // Formats with a Rust reader: use the backend parser + octree
             try {
-              const meta: any = await invoke('pointcloud_open', { filePath });
+              const openIssues: number = meta.validation?.length ?? 0;
+              showValidationIssues(fileName, meta.validation ?? []);
               const rustId = meta.id;
+              const crs = meta.crs
+                ? {
+                    epsg: meta.crs.epsg ?? null,
+                    horizontalEpsg: meta.crs.horizontal_epsg ?? null,
+                    verticalEpsg: meta.crs.vertical_epsg ?? null,
+                    name: meta.crs.name ?? null,
+                  }
+                : undefined;
+
+              // Warn when the new cloud's horizontal CRS differs from an already loaded one
+              const mismatch = useAppStore.getState().pointclouds.find((pc) =>
+                pc.crs?.horizontalEpsg != null
+                && crs?.horizontalEpsg != null
+                && pc.crs.horizontalEpsg !== crs.horizontalEpsg);
+              if (mismatch && crs) {
+                alert(
+                  `${fileName} is in EPSG:${crs.horizontalEpsg}${crs.name ? ` (${crs.name})` : ''}, `
+                  + `but ${mismatch.fileName} is in EPSG:${mismatch.crs!.horizontalEpsg}`
+                  + `${mismatch.crs!.name ? ` (${mismatch.crs!.name})` : ''}. The clouds will not line up.`,
+                );
+              }
 
               useAppStore.getState().addPointcloud({
                 id: rustId,
@@ -214,6 +368,8 @@ export function useRibbonActions() {
                 hasColor: meta.has_color,
                 hasIntensity: meta.has_intensity,
                 hasClassification: meta.has_classification,
+                colorDepth: meta.color_depth ?? undefined,
+                crs,
                 visible: true,
                 indexingProgress: 0,
                 indexingPhase: 'Reading points...',
@@ -232,6 +388,20 @@ export function useRibbonActions() {
                       prog.phase,
                     );
                     if (prog.progress >= 1.0 || prog.phase === 'Complete') {
+                      // Point count and bounds may have been refined from the points while indexing
+                      const final: any = await invoke('pointcloud_get_metadata', { id: rustId });
+                      // Indexing appends what it finds in the points, e.g. points outside the header bounds
+                      showValidationIssues(fileName, (final.validation ?? []).slice(openIssues));
+                      useAppStore.setState((s) => {
+                        const pc = s.pointclouds.find((p) => p.id === rustId);
+                        if (pc) {
+                          pc.totalPoints = final.total_points;
+                          pc.bounds = {
+                            minX: final.bounds.min_x, minY: final.bounds.min_y, minZ: final.bounds.min_z,
+                            maxX: final.bounds.max_x, maxY: final.bounds.max_y, maxZ: final.bounds.max_z,
+                          };
+                        }
+                      });
                       useAppStore.getState().updatePointcloudProgress(rustId, 1.0, 'Ready');
                       break;
                     }
@@ -382,6 +552,8 @@ export function useRibbonActions() {
     handleImport,
     handleFileInputChange,
     handleExport,
+    handleExportLas,
+    handleExportTiles,
     handleExportOBJ,
     // Transforms
     translateX, setTranslateX,
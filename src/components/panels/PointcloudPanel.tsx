@@ -3,14 +3,27 @@
  *
  * Shows list of loaded pointclouds with visibility toggles,
  * display settings (color mode, point size, point budget),
- * classification filter, and EDL toggle.
+ * classification filter, EDL toggle, and the active file's VLRs.
  */
 
-import { memo } from 'react';
+import { memo, useEffect, useState } from 'react';
 import { Eye, EyeOff, Trash2 } from 'lucide-react';
 import { useAppStore } from '../../state/appStore';
 import type { PointcloudColorMode } from '../../state/slices/pointcloudSlice';
 import { formatPoints } from '../../utils/format';
+import { getBrowserPointcloud } from '../../engine/pointcloud/BrowserPointcloudStore';
+
+const isTauri = !!(window as any).__TAURI_INTERNALS__;
+
+/** Variable length record of the source file, as returned by `pointcloud_get_vlrs` */
+interface VlrInfo {
+  user_id: string;
+  record_id: number;
+  description: string;
+  record_length: number;
+  is_extended: boolean;
+  text: string | null;
+}
 
 const ASPRS_CLASSIFICATIONS: { code: number; label: string }[] = [
   { code: 0, label: 'Never Classified' },
@@ -56,6 +69,24 @@ function PointcloudPanelInner() {
   const visibleClassifications = useAppStore((s) => s.visibleClassifications);
   const setVisibleClassifications = useAppStore((s) => s.setVisibleClassifications);
 
+  // VLRs of the active pointcloud (native readers only)
+  const [vlrs, setVlrs] = useState<VlrInfo[]>([]);
+  useEffect(() => {
+    setVlrs([]);
+    if (!isTauri || !activePointcloudId || getBrowserPointcloud(activePointcloudId)) return;
+    let cancelled = false;
+    (async () => {
+      const { invoke } = await import('@tauri-apps/api/core');
+      const list = await invoke<VlrInfo[]>('pointcloud_get_vlrs', { id: activePointcloudId });
+      if (!cancelled) setVlrs(list);
+    })().catch(() => {
+      if (!cancelled) setVlrs([]);
+    });
+    return () => {
+      cancelled = true;
+    };
+  }, [activePointcloudId]);
+
   const toggleClassification = (code: number) => {
     if (visibleClassifications.includes(code)) {
       setVisibleClassifications(visibleClassifications.filter((c) => c !== code));
@@ -217,6 +248,34 @@ function PointcloudPanelInner() {
           </div>
         </div>
       )}
+
+      {/* Variable Length Records */}
+      {vlrs.length > 0 && (
+        <div className="border-t border-cad-border mt-2 pt-2">
+          <div className="font-semibold text-cad-text-dim uppercase tracking-wide mb-1">
+            VLRs
+          </div>
+          <div className="flex flex-col gap-0.5 max-h-48 overflow-y-auto">
+            {vlrs.map((vlr, i) => (
+              <details key={i} className="px-1">
+                <summary className="cursor-pointer truncate" title={vlr.description}>
+                  <span className="text-cad-text-muted">
+                    {vlr.user_id} {vlr.record_id}
+                    {vlr.is_extended ? ' (EVLR)' : ''}
+                  </span>{' '}
+                  {vlr.description || '—'}
+                </summary>
+                <div className="pl-3 text-cad-text-muted">{vlr.record_length} bytes</div>
+                {vlr.text && (
+                  <pre className="pl-3 whitespace-pre-wrap break-all text-[10px] text-cad-text-muted">
+                    {vlr.text}
+                  </pre>
+                )}
+              </details>
+            ))}
+          </div>
+        </div>
+      )}
     </div>
   );
 }
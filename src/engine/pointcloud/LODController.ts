@@ -18,6 +18,8 @@ interface OctreeNodeInfo {
   level: number;
   point_count: number;
   has_children: boolean;
+  /** Whether the node's children add points to it (true) or replace it */
+  additive: boolean;
 }
 
 interface DecodedChunk {
@@ -30,8 +32,49 @@ interface DecodedChunk {
   colors: Uint8Array;
   intensities: Uint16Array;
   classifications: Uint8Array;
+  attribute_mask: number;
+  return_numbers?: Uint8Array;
+  number_of_returns?: Uint8Array;
+  flags?: Uint8Array;
+  scanner_channels?: Uint8Array;
+  scan_angles?: Float32Array;
+  user_data?: Uint8Array;
+  point_source_ids?: Uint16Array;
+  gps_times?: Float64Array;
+  nir?: Uint16Array;
+  /** Wave packets: descriptor index, waveform data location, return point location and direction */
+  wave_descriptor_indices?: Uint8Array;
+  wave_byte_offsets?: Float64Array;
+  wave_sizes?: Uint32Array;
+  wave_return_locations?: Float32Array;
+  wave_directions?: Float32Array;
+  /** One array per PointcloudMetadata.extra_attributes entry (NaN = no data) */
+  extra_attributes: Float32Array[];
 }
 
+/** Optional attribute bits in a chunk's attribute_mask (mirrors `point_attributes` in Rust) */
+export const POINT_ATTRIBUTES = {
+  RETURNS: 1 << 0,
+  FLAGS: 1 << 1,
+  SCANNER_CHANNEL: 1 << 2,
+  SCAN_ANGLE: 1 << 3,
+  USER_DATA: 1 << 4,
+  POINT_SOURCE_ID: 1 << 5,
+  GPS_TIME: 1 << 6,
+  NIR: 1 << 7,
+  WAVE_PACKETS: 1 << 8,
+} as const;
+
+/** Bits in the per-point flags array (mirrors `point_flags` in Rust) */
+export const POINT_FLAGS = {
+  SYNTHETIC: 1 << 0,
+  KEY_POINT: 1 << 1,
+  WITHHELD: 1 << 2,
+  OVERLAP: 1 << 3,
+  SCAN_DIRECTION: 1 << 4,
+  EDGE_OF_FLIGHT_LINE: 1 << 5,
+} as const;
+
 interface LoadedNode {
   nodeId: string;
   points: THREE.Points;
@@ -76,7 +119,7 @@ export class LODController {
   }
 
   /** Check if camera has moved since last update */
-  private hasCameraMoved(camera: THREE.PerspectiveCamera, pointBudget: number): boolean {
+  private hasCameraMoved(camera: THREE.PerspectiveCamera | THREE.OrthographicCamera, pointBudget: number): boolean {
     if (!this.cameraInitialized) {
       this.lastCameraPosition.copy(camera.position);
       this.lastCameraRotation.copy(camera.rotation);
@@ -102,7 +145,7 @@ export class LODController {
   }
 
   /** Update visible nodes based on current camera. Throttled to max 10Hz. */
-  async update(camera: THREE.PerspectiveCamera, pointBudget: number): Promise<void> {
+  async update(camera: THREE.PerspectiveCamera | THREE.OrthographicCamera, pointBudget: number): Promise<void> {
     if (this.disposed || this.isUpdating) return;
 
     const now = Date.now();
@@ -114,15 +157,29 @@ export class LODController {
     this.isUpdating = true;
 
     try {
+      // Camera frame in pointcloud coordinates: swap Y/Z back to Z-up
+      const direction = camera.getWorldDirection(new THREE.Vector3());
+      const up = camera.up.clone().applyQuaternion(camera.quaternion);
+      const target = camera.position.clone().add(direction);
+      const ortho = camera instanceof THREE.OrthographicCamera;
       const cameraState = {
         position: [
           camera.position.x + this.worldOffset[0],
           camera.position.z + this.worldOffset[1],
           camera.position.y + this.worldOffset[2],
         ],
-        target: [0, 0, 0],
-        fov: camera.fov,
-        aspect: camera.aspect,
+        target: [
+          target.x + this.worldOffset[0],
+          target.z + this.worldOffset[1],
+          target.y + this.worldOffset[2],
+        ],
+        up: [up.x, up.z, up.y],
+        fov: ortho ? 0 : camera.fov,
+        aspect: ortho ? (camera.right - camera.left) / (camera.top - camera.bottom) : camera.aspect,
+        near: camera.near,
+        far: camera.far,
+        projection: ortho ? 'orthographic' : 'perspective',
+        ortho_height: ortho ? (camera.top - camera.bottom) / camera.zoom : undefined,
         screen_height: window.innerHeight || 800,
       };
 
@@ -135,15 +192,6 @@ export class LODController {
 
       const visibleIds = new Set(visibleNodes.map((n) => n.node_id));
 
-      // Unload nodes that are no longer visible
-      for (const [nodeId, loaded] of this.loadedNodes) {
-        if (!visibleIds.has(nodeId)) {
-          this.scene.remove(loaded.points);
-          loaded.points.geometry.dispose();
-          this.loadedNodes.delete(nodeId);
-        }
-      }
-
       // Find nodes that need loading
       const toLoad = visibleNodes
         .filter((n) => !this.loadedNodes.has(n.node_id))
@@ -157,6 +205,16 @@ export class LODController {
         }
       }
 
+      // Unload nodes that are no longer visible once the new ones are in,
+      // so a node whose children replace it stays drawn until they are
+      for (const [nodeId, loaded] of this.loadedNodes) {
+        if (!visibleIds.has(nodeId)) {
+          this.scene.remove(loaded.points);
+          loaded.points.geometry.dispose();
+          this.loadedNodes.delete(nodeId);
+        }
+      }
+
       // Update last-used timestamp for visible nodes
       const timestamp = Date.now();
       for (const id of visibleIds) {
@@ -282,11 +340,22 @@ export class LODController {
  *     [4 bytes]                level (u32 LE)
  *     [4 bytes]                spacing (f32 LE)
  *     [4 bytes]                point_count (u32 LE)
+ *     [4 bytes]                attribute_mask (u32 LE, see POINT_ATTRIBUTES)
  *     [point_count * 12 bytes] positions: f32 LE (x,y,z)
  *     [point_count * 3 bytes]  colors: u8 (r,g,b)
  *     [point_count * 2 bytes]  intensities: u16 LE
  *     [point_count * 1 byte]   classifications: u8
  *     [0-3 bytes]              padding to 4-byte alignment
+ *     Optional arrays, in this order, each only if its mask bit is set and
+ *     each followed by padding to 4-byte alignment:
+ *       return_numbers u8, number_of_returns u8 (RETURNS), flags u8 (FLAGS),
+ *       scanner_channels u8 (SCANNER_CHANNEL), scan_angles f32 (SCAN_ANGLE),
+ *       user_data u8 (USER_DATA), point_source_ids u16 (POINT_SOURCE_ID),
+ *       gps_times f64 (GPS_TIME), nir u16 (NIR), then for WAVE_PACKETS:
+ *       wave_descriptor_indices u8, wave_byte_offsets u64, wave_sizes u32,
+ *       wave_return_locations f32, wave_directions f32 (x,y,z)
+ *     [4 bytes]                extra_count (u32 LE)
+ *     [extra_count * point_count * 4 bytes] extra attributes: f32 LE
  */
 function decodeBinaryChunks(buffer: ArrayBuffer): DecodedChunk[] {
   const view = new DataView(buffer);
@@ -325,6 +394,10 @@ function decodeBinaryChunks(buffer: ArrayBuffer): DecodedChunk[] {
     const pointCount = view.getUint32(offset, true);
     offset += 4;
 
+    // Attribute mask
+    const attributeMask = view.getUint32(offset, true);
+    offset += 4;
+
     // Positions: f32 LE — create a copy since the offset may not be aligned for Float32Array view
     const posBytes = pointCount * 12;
     const positions = new Float32Array(pointCount * 3);
@@ -353,6 +426,68 @@ function decodeBinaryChunks(buffer: ArrayBuffer): DecodedChunk[] {
     // Pad to 4-byte alignment
     offset = (offset + 3) & ~3;
 
+    const has = (bit: number) => (attributeMask & bit) !== 0;
+    const readU8 = (): Uint8Array => {
+      const arr = new Uint8Array(buffer.slice(offset, offset + pointCount));
+      offset = (offset + pointCount + 3) & ~3;
+      return arr;
+    };
+    const readU16 = (): Uint16Array => {
+      const arr = new Uint16Array(pointCount);
+      for (let j = 0; j < pointCount; j++) arr[j] = view.getUint16(offset + j * 2, true);
+      offset = (offset + pointCount * 2 + 3) & ~3;
+      return arr;
+    };
+    const readF32 = (count = pointCount): Float32Array => {
+      const arr = new Float32Array(count);
+      for (let j = 0; j < count; j++) arr[j] = view.getFloat32(offset + j * 4, true);
+      offset += count * 4;
+      return arr;
+    };
+    const readU32 = (): Uint32Array => {
+      const arr = new Uint32Array(pointCount);
+      for (let j = 0; j < pointCount; j++) arr[j] = view.getUint32(offset + j * 4, true);
+      offset += pointCount * 4;
+      return arr;
+    };
+    // u64 values as numbers: byte offsets stay far below 2^53
+    const readU64 = (): Float64Array => {
+      const arr = new Float64Array(pointCount);
+      for (let j = 0; j < pointCount; j++) arr[j] = Number(view.getBigUint64(offset + j * 8, true));
+      offset += pointCount * 8;
+      return arr;
+    };
+    const readF64 = (): Float64Array => {
+      const arr = new Float64Array(pointCount);
+      for (let j = 0; j < pointCount; j++) arr[j] = view.getFloat64(offset + j * 8, true);
+      offset += pointCount * 8;
+      return arr;
+    };
+
+    const returnNumbers = has(POINT_ATTRIBUTES.RETURNS) ? readU8() : undefined;
+    const numberOfReturns = has(POINT_ATTRIBUTES.RETURNS) ? readU8() : undefined;
+    const flags = has(POINT_ATTRIBUTES.FLAGS) ? readU8() : undefined;
+    const scannerChannels = has(POINT_ATTRIBUTES.SCANNER_CHANNEL) ? readU8() : undefined;
+    const scanAngles = has(POINT_ATTRIBUTES.SCAN_ANGLE) ? readF32() : undefined;
+    const userData = has(POINT_ATTRIBUTES.USER_DATA) ? readU8() : undefined;
+    const pointSourceIds = has(POINT_ATTRIBUTES.POINT_SOURCE_ID) ? readU16() : undefined;
+    const gpsTimes = has(POINT_ATTRIBUTES.GPS_TIME) ? readF64() : undefined;
+    const nir = has(POINT_ATTRIBUTES.NIR) ? readU16() : undefined;
+    const hasWave = has(POINT_ATTRIBUTES.WAVE_PACKETS);
+    const waveDescriptorIndices = hasWave ? readU8() : undefined;
+    const waveByteOffsets = hasWave ? readU64() : undefined;
+    const waveSizes = hasWave ? readU32() : undefined;
+    const waveReturnLocations = hasWave ? readF32() : undefined;
+    const waveDirections = hasWave ? readF32(pointCount * 3) : undefined;
+
+    // Extra Bytes attributes
+    const extraCount = view.getUint32(offset, true);
+    offset += 4;
+    const extraAttributes: Float32Array[] = [];
+    for (let e = 0; e < extraCount; e++) {
+      extraAttributes.push(readF32());
+    }
+
     chunks[i] = {
       node_id: nodeId,
       center: [cx, cy, cz],
@@ -363,6 +498,22 @@ function decodeBinaryChunks(buffer: ArrayBuffer): DecodedChunk[] {
       colors,
       intensities,
       classifications,
+      attribute_mask: attributeMask,
+      return_numbers: returnNumbers,
+      number_of_returns: numberOfReturns,
+      flags,
+      scanner_channels: scannerChannels,
+      scan_angles: scanAngles,
+      user_data: userData,
+      point_source_ids: pointSourceIds,
+      gps_times: gpsTimes,
+      nir,
+      wave_descriptor_indices: waveDescriptorIndices,
+      wave_byte_offsets: waveByteOffsets,
+      wave_sizes: waveSizes,
+      wave_return_locations: waveReturnLocations,
+      wave_directions: waveDirections,
+      extra_attributes: extraAttributes,
     };
   }
 
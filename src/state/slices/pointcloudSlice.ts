@@ -22,6 +22,15 @@ export interface PointcloudEntry {
   hasColor: boolean;
   hasIntensity: boolean;
   hasClassification: boolean;
+  /** Range of the stored color values, detected per file unless overridden on open (native LAS/LAZ only) */
+  colorDepth?: '8bit' | '16bit';
+  /** Coordinate reference system from the file's projection VLRs (native LAS/LAZ only) */
+  crs?: {
+    epsg: number | null;
+    horizontalEpsg: number | null;
+    verticalEpsg: number | null;
+    name: string | null;
+  };
   visible: boolean;
   indexingProgress: number;
   indexingPhase: string;